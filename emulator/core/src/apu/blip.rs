@@ -0,0 +1,194 @@
+use std::f64::consts::PI;
+
+// Band-limited step synthesis, same idea as blip_buf:
+// amplitude changes are stored as band-limited impulses at their exact clock time
+// and integrated back into a waveform when samples are read.
+const PHASES: usize = 32;
+const HALF_WIDTH: usize = 8;
+const KERNEL_WIDTH: usize = HALF_WIDTH * 2;
+
+#[derive(Debug, Clone)]
+pub struct BlipBuffer {
+    // output samples per input clock
+    factor: f64,
+    // position (in output samples) of clock 0 of the current frame
+    offset: f64,
+    buffer: Vec<f32>,
+    integrator: f32,
+    kernel: Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            factor: sample_rate / clock_rate,
+            offset: 0.0,
+            buffer: vec![0.0; 1024 + KERNEL_WIDTH],
+            integrator: 0.0,
+            kernel: build_kernel(),
+        }
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = sample_rate / clock_rate;
+    }
+
+    // Drops buffered deltas but keeps the frame timing
+    pub fn silence(&mut self) {
+        self.integrator = 0.0;
+        self.buffer.fill(0.0);
+    }
+
+    pub fn clear(&mut self) {
+        self.offset = 0.0;
+        self.integrator = 0.0;
+        self.buffer.fill(0.0);
+    }
+
+    // Adds an amplitude step of `delta` happening `time` clocks after the start of the frame
+    pub fn add_delta(&mut self, time: u32, delta: f32) {
+        if delta == 0.0 {
+            return;
+        }
+        let position = self.offset + time as f64 * self.factor;
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64) as usize;
+
+        if index + KERNEL_WIDTH > self.buffer.len() {
+            self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+        }
+        let taps = &self.kernel[phase.min(PHASES - 1)];
+        for (slot, tap) in self.buffer[index..index + KERNEL_WIDTH]
+            .iter_mut()
+            .zip(taps)
+        {
+            *slot += delta * tap;
+        }
+    }
+
+    // Ends the current frame after `clocks` clocks, making its samples available
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += clocks as f64 * self.factor;
+        let needed = self.offset as usize + KERNEL_WIDTH;
+        if needed > self.buffer.len() {
+            self.buffer.resize(needed, 0.0);
+        }
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub fn read_samples(&mut self, out: &mut Vec<f32>, count: usize) -> usize {
+        let count = count.min(self.samples_available());
+        for i in 0..count {
+            self.integrator += self.buffer[i];
+            out.push(self.integrator);
+        }
+
+        // Shift the remaining deltas to the start of the buffer
+        self.buffer.copy_within(count.., 0);
+        let len = self.buffer.len();
+        self.buffer[len - count..].fill(0.0);
+        self.offset -= count as f64;
+        count
+    }
+}
+
+fn build_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    // Blackman windowed sinc, one set of taps per sub-sample phase.
+    // Each set is normalized so a step always ends at exactly its delta.
+    let mut kernel = vec![[0.0; KERNEL_WIDTH]; PHASES];
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let fraction = phase as f64 / PHASES as f64;
+        let mut sum = 0.0;
+        let mut values = [0.0f64; KERNEL_WIDTH];
+        for (k, value) in values.iter_mut().enumerate() {
+            let x = k as f64 - (HALF_WIDTH as f64 - 1.0) - fraction;
+            let sinc = if x.abs() < 1e-9 {
+                1.0
+            } else {
+                // Cut off slightly below Nyquist to keep the transition band out of the audible range
+                let t = PI * x * 0.9;
+                t.sin() / t
+            };
+            let w = (x + HALF_WIDTH as f64) / KERNEL_WIDTH as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            *value = sinc * window.max(0.0);
+            sum += *value;
+        }
+        for (tap, value) in taps.iter_mut().zip(values) {
+            *tap = (value / sum) as f32;
+        }
+    }
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_settles_at_its_delta() {
+        let mut blip = BlipBuffer::new(1000.0, 1000.0);
+        blip.add_delta(10, 0.5);
+        blip.add_delta(40, -0.25);
+        blip.end_frame(100);
+
+        let mut out = Vec::new();
+        assert_eq!(blip.read_samples(&mut out, 100), 100);
+        assert!(out[0].abs() < 1e-6);
+        assert!((out[35] - 0.5).abs() < 1e-4);
+        assert!((out[99] - 0.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_every_phase_sums_to_one() {
+        for taps in build_kernel() {
+            assert!((taps.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_frame_clocks_become_samples() {
+        let mut blip = BlipBuffer::new(4_194_304.0, 48_000.0);
+        blip.end_frame(70224);
+        assert_eq!(blip.samples_available(), 803);
+
+        let mut out = Vec::new();
+        assert_eq!(blip.read_samples(&mut out, 500), 500);
+        assert_eq!(blip.samples_available(), 303);
+        // Only what is available is read
+        assert_eq!(blip.read_samples(&mut out, 1000), 303);
+        assert_eq!(out.len(), 803);
+        assert_eq!(blip.samples_available(), 0);
+    }
+
+    #[test]
+    fn test_deltas_carry_over_to_the_next_read() {
+        let mut blip = BlipBuffer::new(1000.0, 1000.0);
+        blip.add_delta(90, 1.0);
+        blip.end_frame(100);
+        let mut out = Vec::new();
+        blip.read_samples(&mut out, 100);
+        assert!(out[99] < 1.0);
+
+        blip.end_frame(100);
+        out.clear();
+        blip.read_samples(&mut out, 100);
+        assert!((out[99] - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_silence_drops_pending_deltas() {
+        let mut blip = BlipBuffer::new(1000.0, 1000.0);
+        blip.add_delta(10, 1.0);
+        blip.end_frame(50);
+        blip.silence();
+        assert_eq!(blip.samples_available(), 50);
+
+        let mut out = Vec::new();
+        blip.read_samples(&mut out, 50);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+    }
+}
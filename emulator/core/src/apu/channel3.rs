@@ -1,4 +1,4 @@
-use crate::bus::{self, io_address::IoRegister, Bus, MemoryInterface};
+use crate::bus::{self, io_address::IoRegister, MemoryInterface};
 #[derive(Debug, Clone)]
 pub struct Channel3 {
     frequency_timer: usize,
@@ -50,11 +50,10 @@ impl Channel3 {
     }
     fn get_sample<M: MemoryInterface>(&self, memory: &mut M) -> u8 {
         // 1 byte -> 2 samples
-        let wave_ram = memory.read_wave_ram();
-        let index = self.wave_position as usize / 2;
-        let byte = wave_ram[index];
+        let index = self.wave_position as u16 / 2;
+        let byte = memory.read_byte(IoRegister::WaveRamStart.address() + index);
         if self.wave_position % 2 == 0 {
-            byte & 0xF0 >> 4
+            (byte & 0xF0) >> 4
         } else {
             byte & 0xF
         }
@@ -32,7 +32,7 @@ impl Channel4 {
         if is_triggered {
             self.trigger(memory);
         }
-        self.period_timer -= 1;
+        self.period_timer = self.period_timer.wrapping_sub(1);
         if self.period_timer == 0 {
             self.period_timer = self.calculate_period(memory);
             let lfsr_bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
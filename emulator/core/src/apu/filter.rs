@@ -0,0 +1,50 @@
+// https://gbdev.io/pandocs/Audio_details.html#obscure-behavior
+// The output stage of the Game Boy goes through a capacitor that slowly
+// removes any DC offset coming from the DACs.
+const DMG_CHARGE_BASE: f64 = 0.999958;
+
+#[derive(Debug, Clone)]
+pub struct HighPassFilter {
+    capacitor: f32,
+    charge_factor: f32,
+}
+
+impl HighPassFilter {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            capacitor: 0.0,
+            charge_factor: DMG_CHARGE_BASE.powf(clock_rate / sample_rate) as f32,
+        }
+    }
+
+    pub fn apply(&mut self, input: f32) -> f32 {
+        let output = input - self.capacitor;
+        self.capacitor = input - output * self.charge_factor;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.capacitor = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dc_offset_decays() {
+        let mut filter = HighPassFilter::new(4_194_304.0, 48_000.0);
+        assert_eq!(filter.apply(1.0), 1.0);
+        let mut output = 1.0;
+        for _ in 0..48_000 {
+            output = filter.apply(1.0);
+        }
+        assert!(output.abs() < 0.01);
+
+        // A step down swings below zero by as much
+        assert!((filter.apply(0.0) + 1.0).abs() < 0.01);
+        filter.reset();
+        assert_eq!(filter.apply(0.0), 0.0);
+    }
+}
@@ -6,15 +6,58 @@ use channel3::Channel3;
 use channel4::Channel4;
 
 use crate::bus::{self, io_address::IoRegister, Bus, MemoryInterface};
+use blip::BlipBuffer;
+use filter::HighPassFilter;
+use recorder::{AudioCapture, CapturedAudio};
+use ring_buffer::AudioProducer;
 
-const SAMPLE_RATE: usize = 48_000;
 const CPU_FREQ: usize = 4_194_304;
-const CYCLES_PER_SAMPLE: usize = CPU_FREQ / SAMPLE_RATE;
+// Cycles between two blip frames (~1ms of audio)
+const BLIP_FRAME_CYCLES: u32 = 4096;
+// Dynamic rate control never moves the output rate further than this from nominal
+const MAX_RATE_DELTA: f64 = 0.005;
 
+mod blip;
 mod channel1;
 mod channel2;
 mod channel3;
 mod channel4;
+mod filter;
+pub mod recorder;
+pub mod ring_buffer;
+pub mod vgm;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleRate {
+    Hz44100,
+    Hz48000,
+    Hz96000,
+}
+impl SampleRate {
+    pub fn hz(self) -> u32 {
+        match self {
+            SampleRate::Hz44100 => 44_100,
+            SampleRate::Hz48000 => 48_000,
+            SampleRate::Hz96000 => 96_000,
+        }
+    }
+    pub fn from_hz(hz: u32) -> Option<Self> {
+        match hz {
+            44_100 => Some(SampleRate::Hz44100),
+            48_000 => Some(SampleRate::Hz48000),
+            96_000 => Some(SampleRate::Hz96000),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynthesisMode {
+    // Amplitude steps are band-limited at their exact cycle (no aliasing)
+    BandLimited,
+    // Channel outputs are read once per output sample
+    PointSampled,
+}
 
 #[derive(Debug, Clone)]
 pub struct APU {
@@ -26,9 +69,26 @@ pub struct APU {
     frame_sequencer_timer: usize,
     frame_sequencer_step: u8,
 
-    cycle_sample_counter: usize,
+    sample_rate: SampleRate,
+    pub synthesis: SynthesisMode,
+    // point sampling: fractional cycle position of the next sample
+    sample_clock: f64,
+    // band-limited: cycles since the start of the current blip frame
+    blip_clock: u32,
+    blip_left: BlipBuffer,
+    blip_right: BlipBuffer,
+    last_left: f32,
+    last_right: f32,
+    high_pass_left: HighPassFilter,
+    high_pass_right: HighPassFilter,
+    // Maximum ratio adjustment used by dynamic rate control, None when disabled
+    dynamic_rate_control: Option<f64>,
+    rate_ratio: f64,
+    capture: Option<AudioCapture>,
     samples: Vec<f32>,
     pub enabled: bool,
+    // Host side gain on top of NR50, used for fades
+    pub volume: f32,
 
     pub current_ch1_output: f32,
     pub current_ch2_output: f32,
@@ -40,7 +100,9 @@ pub struct APU {
     pub ch4_enabled: bool,
 }
 impl APU {
-    pub fn new() -> Self {
+    pub fn new(sample_rate: SampleRate) -> Self {
+        let clock_rate = CPU_FREQ as f64;
+        let output_rate = sample_rate.hz() as f64;
         Self {
             channel1: Channel1::new(),
             channel2: Channel2::new(),
@@ -48,9 +110,22 @@ impl APU {
             channel4: Channel4::new(),
             frame_sequencer_timer: 0,
             frame_sequencer_step: 0,
-            cycle_sample_counter: 0,
+            sample_rate,
+            synthesis: SynthesisMode::BandLimited,
+            sample_clock: 0.0,
+            blip_clock: 0,
+            blip_left: BlipBuffer::new(clock_rate, output_rate),
+            blip_right: BlipBuffer::new(clock_rate, output_rate),
+            last_left: 0.0,
+            last_right: 0.0,
+            high_pass_left: HighPassFilter::new(clock_rate, output_rate),
+            high_pass_right: HighPassFilter::new(clock_rate, output_rate),
+            dynamic_rate_control: None,
+            rate_ratio: 1.0,
+            capture: None,
             samples: Vec::new(),
             enabled: true,
+            volume: 1.0,
             ch1_enabled: true,
             ch2_enabled: true,
             ch3_enabled: true,
@@ -61,6 +136,20 @@ impl APU {
             current_ch4_output: 0.0,
         }
     }
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+    pub fn set_synthesis_mode(&mut self, mode: SynthesisMode) {
+        self.synthesis = mode;
+        self.sample_clock = 0.0;
+        self.blip_clock = 0;
+        self.blip_left.clear();
+        self.blip_right.clear();
+        self.last_left = 0.0;
+        self.last_right = 0.0;
+        self.high_pass_left.reset();
+        self.high_pass_right.reset();
+    }
     pub fn toggle_audio(&mut self) {
         self.enabled = !self.enabled;
     }
@@ -87,29 +176,146 @@ impl APU {
         self.channel3.tick(memory);
         self.channel4.tick(memory);
 
-        if self.cycle_sample_counter >= CYCLES_PER_SAMPLE {
-            self.cycle_sample_counter = 0;
-            self.generate_sample(memory);
+        match self.synthesis {
+            SynthesisMode::BandLimited => self.tick_band_limited(memory),
+            SynthesisMode::PointSampled => self.tick_point_sampled(memory),
         }
-        self.cycle_sample_counter += 1;
     }
+    // Interleaved stereo samples generated since the last call
     pub fn get_samples(&mut self) -> Vec<f32> {
-        const SAMPLE_BUFFER_SIZE: usize = 1600;
+        std::mem::take(&mut self.samples)
+    }
+    // Same as get_samples but keeps the allocation for the next frame
+    pub fn drain_samples(&mut self) -> std::vec::Drain<'_, f32> {
+        self.samples.drain(..)
+    }
+    pub fn pending_samples(&self) -> usize {
+        self.samples.len()
+    }
+    // Moves pending samples into the host ring buffer, samples that don't fit are dropped
+    // so latency can't build up.
+    pub fn push_samples(&mut self, producer: &mut AudioProducer) -> usize {
+        let written = producer.push_slice(&self.samples);
+        self.samples.clear();
+        written
+    }
+    pub fn set_dynamic_rate_control(&mut self, enabled: bool) {
+        self.dynamic_rate_control = if enabled { Some(MAX_RATE_DELTA) } else { None };
+        if !enabled {
+            self.set_rate_ratio(1.0);
+        }
+    }
+    pub fn dynamic_rate_control(&self) -> bool {
+        self.dynamic_rate_control.is_some()
+    }
+    /*
+    Dynamic rate control: https://docs.libretro.com/development/cores/dynamic-rate-control/
+    The output rate is nudged up when the host buffer is below half full and down when above,
+    so the buffer settles around half its capacity without audible pitch changes.
+    */
+    pub fn update_rate_control(&mut self, buffered: usize, capacity: usize) {
+        let Some(max_delta) = self.dynamic_rate_control else {
+            return;
+        };
+        if capacity == 0 {
+            return;
+        }
+        let capacity = capacity as f64;
+        let fill = (buffered as f64).min(capacity);
+        let ratio = 1.0 + max_delta * (capacity - 2.0 * fill) / capacity;
+        self.set_rate_ratio(ratio);
+    }
+    // Takes effect at the next blip frame so deltas within a frame stay consistent
+    fn set_rate_ratio(&mut self, ratio: f64) {
+        self.rate_ratio = ratio;
+    }
+    pub fn start_capture(&mut self, stems: bool) {
+        let sample_rate = self.sample_rate.hz() as f64;
+        self.capture = Some(AudioCapture::new(&self.blip_left, sample_rate, stems));
+    }
+    pub fn stop_capture(&mut self) {
+        self.capture = None;
+    }
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+    // Audio recorded since the last call, None when not capturing
+    pub fn take_captured(&mut self) -> Option<CapturedAudio> {
+        self.capture.as_mut().map(|capture| capture.take())
+    }
+    fn tick_band_limited<M: MemoryInterface>(&mut self, memory: &mut M) {
+        // Channel outputs only change on M-cycle boundaries
+        if self.blip_clock.is_multiple_of(4) {
+            let channels = self.mix(memory);
+            let (left, right) = sum_channels(&channels);
+            self.blip_left
+                .add_delta(self.blip_clock, left - self.last_left);
+            self.blip_right
+                .add_delta(self.blip_clock, right - self.last_right);
+            self.last_left = left;
+            self.last_right = right;
+            if let Some(capture) = &mut self.capture {
+                capture.add_stem_deltas(self.blip_clock, &channels);
+            }
+        }
+        self.blip_clock += 1;
 
-        // Preallocate to avoid repeated allocations
-        let mut samples = Vec::with_capacity(SAMPLE_BUFFER_SIZE);
+        if self.blip_clock >= BLIP_FRAME_CYCLES {
+            self.end_blip_frame();
+        }
+    }
+    fn end_blip_frame(&mut self) {
+        self.blip_left.end_frame(self.blip_clock);
+        self.blip_right.end_frame(self.blip_clock);
 
-        // Drain samples more efficiently
-        samples.extend(self.samples.drain(..).take(SAMPLE_BUFFER_SIZE));
+        let available = self
+            .blip_left
+            .samples_available()
+            .min(self.blip_right.samples_available());
+        let mut left = Vec::with_capacity(available);
+        let mut right = Vec::with_capacity(available);
+        self.blip_left.read_samples(&mut left, available);
+        self.blip_right.read_samples(&mut right, available);
 
-        // Pad with silence if needed
-        if samples.len() < SAMPLE_BUFFER_SIZE {
-            samples.resize(SAMPLE_BUFFER_SIZE, 0.0);
+        let output_rate = self.sample_rate.hz() as f64 * self.rate_ratio;
+        self.blip_left.set_rates(CPU_FREQ as f64, output_rate);
+        self.blip_right.set_rates(CPU_FREQ as f64, output_rate);
+        if let Some(capture) = &mut self.capture {
+            capture.end_stem_frame(self.blip_clock, available, output_rate);
         }
+        self.blip_clock = 0;
 
-        samples
+        for (left_sample, right_sample) in left.into_iter().zip(right) {
+            self.push_sample(left_sample, right_sample);
+        }
+    }
+    fn tick_point_sampled<M: MemoryInterface>(&mut self, memory: &mut M) {
+        let cycles_per_sample = CPU_FREQ as f64 / (self.sample_rate.hz() as f64 * self.rate_ratio);
+        self.sample_clock += 1.0;
+        if self.sample_clock >= cycles_per_sample {
+            self.sample_clock -= cycles_per_sample;
+            self.generate_sample(memory);
+        }
+    }
+    fn push_sample(&mut self, left: f32, right: f32) {
+        let left = self.high_pass_left.apply(left);
+        let right = self.high_pass_right.apply(right);
+        self.samples.push(left);
+        self.samples.push(right);
+        if let Some(capture) = &mut self.capture {
+            capture.push_mix(left, right);
+        }
     }
     fn generate_sample<M: MemoryInterface>(&mut self, memory: &mut M) {
+        let channels = self.mix(memory);
+        let (left_sample, right_sample) = sum_channels(&channels);
+        self.push_sample(left_sample, right_sample);
+        if let Some(capture) = &mut self.capture {
+            capture.push_stems(&channels);
+        }
+    }
+    // Left/right contribution of each channel after panning and master volume
+    fn mix<M: MemoryInterface>(&mut self, memory: &mut M) -> [(f32, f32); 4] {
         // Read panning and volume registers
         let nr50 = memory.read_byte(bus::io_address::IoRegister::Nr50.address());
         let nr51 = memory.read_byte(bus::io_address::IoRegister::Nr51.address());
@@ -117,9 +323,7 @@ impl APU {
 
         // Master volume
         if nr52 & 0x80 == 0 {
-            self.samples.push(0.0);
-            self.samples.push(0.0);
-            return;
+            return [(0.0, 0.0); 4];
         }
 
         let ch1_sample = if self.ch1_enabled {
@@ -143,51 +347,29 @@ impl APU {
             0.0
         };
 
-        // Panning for left and right channels
-        let mut left_amplitude = 0.0;
-        let mut right_amplitude = 0.0;
-        if nr51 & 0b00010000 != 0 {
-            left_amplitude += ch1_sample;
-        }
-        if nr51 & 0b00000001 != 0 {
-            right_amplitude += ch1_sample;
-        }
-        if nr51 & 0x20 != 0 {
-            left_amplitude += ch2_sample;
-        }
-        if nr51 & 0x02 != 0 {
-            right_amplitude += ch2_sample;
-        }
-        if nr51 & 0b01000000 != 0 {
-            left_amplitude += ch3_sample;
-        }
-        if nr51 & 0b00000100 != 0 {
-            right_amplitude += ch3_sample;
-        }
-        if nr51 & 0b10000000 != 0 {
-            left_amplitude += ch4_sample;
-        }
-        if nr51 & 0b00001000 != 0 {
-            right_amplitude += ch4_sample;
-        }
-        right_amplitude = right_amplitude / 4.0;
-        left_amplitude = left_amplitude / 4.0;
-
-        // Apply volume from NR50 register
-        let left_volume = (nr50 & 0x70) >> 4;
-        let right_volume = nr50 & 0x07;
+        // Apply volume from NR50 register (0 is 1/8, 7 is full volume)
+        let left_volume = (((nr50 & 0x70) >> 4) + 1) as f32 / 8.0 * self.volume;
+        let right_volume = ((nr50 & 0x07) + 1) as f32 / 8.0 * self.volume;
 
-        let left_sample = left_amplitude * left_volume as f32;
-        let right_sample = right_amplitude * right_volume as f32;
-
-        self.samples.push(left_sample);
-        self.samples.push(right_sample);
+        // Panning for left and right channels, bits 4-7 left, 0-3 right
+        let mut channels = [(0.0, 0.0); 4];
+        let samples = [ch1_sample, ch2_sample, ch3_sample, ch4_sample];
+        for (i, (channel, sample)) in channels.iter_mut().zip(samples).enumerate() {
+            if nr51 & (0x10 << i) != 0 {
+                channel.0 = sample / 4.0 * left_volume;
+            }
+            if nr51 & (0x01 << i) != 0 {
+                channel.1 = sample / 4.0 * right_volume;
+            }
+        }
 
         // debug
         self.current_ch1_output = ch1_sample;
         self.current_ch2_output = ch2_sample;
         self.current_ch3_output = ch3_sample;
         self.current_ch4_output = ch4_sample;
+
+        channels
     }
     fn update_lengths<M: MemoryInterface>(&mut self, memory: &M) {
         self.channel1.update_length(memory);
@@ -247,3 +429,9 @@ impl APU {
         self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
     }
 }
+
+fn sum_channels(channels: &[(f32, f32); 4]) -> (f32, f32) {
+    channels.iter().fold((0.0, 0.0), |(left, right), channel| {
+        (left + channel.0, right + channel.1)
+    })
+}
@@ -0,0 +1,281 @@
+use super::{blip::BlipBuffer, filter::HighPassFilter, APU, CPU_FREQ};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+// 16-bit PCM WAV, sizes are patched in when finished
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_bytes: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            data_bytes: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.data_bytes += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Interleaved stereo audio captured by the APU
+#[derive(Debug, Clone, Default)]
+pub struct CapturedAudio {
+    pub mix: Vec<f32>,
+    // One stereo stream per channel, same length as the mix
+    pub stems: Option<[Vec<f32>; 4]>,
+}
+
+/*
+Stems are synthesized exactly like the mix: each channel gets its own pair of blip buffers
+sharing the frame timing of the main ones, and its own high-pass filter.
+The four stems therefore stay sample-aligned with the mix.
+*/
+#[derive(Debug, Clone)]
+struct StemSynth {
+    // ch1 left, ch1 right, ch2 left...
+    blips: Vec<BlipBuffer>,
+    high_pass: Vec<HighPassFilter>,
+    last: [f32; 8],
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct AudioCapture {
+    captured: CapturedAudio,
+    stems: Option<StemSynth>,
+}
+
+impl AudioCapture {
+    pub(crate) fn new(timing: &BlipBuffer, sample_rate: f64, stems: bool) -> Self {
+        let stems = stems.then(|| {
+            let mut blip = timing.clone();
+            blip.silence();
+            StemSynth {
+                blips: vec![blip; 8],
+                high_pass: vec![HighPassFilter::new(CPU_FREQ as f64, sample_rate); 8],
+                last: [0.0; 8],
+            }
+        });
+        Self {
+            captured: CapturedAudio {
+                mix: Vec::new(),
+                stems: stems.as_ref().map(|_| Default::default()),
+            },
+            stems,
+        }
+    }
+
+    pub(crate) fn push_mix(&mut self, left: f32, right: f32) {
+        self.captured.mix.push(left);
+        self.captured.mix.push(right);
+    }
+
+    pub(crate) fn add_stem_deltas(&mut self, time: u32, channels: &[(f32, f32); 4]) {
+        let Some(stems) = &mut self.stems else {
+            return;
+        };
+        for (i, value) in channels.iter().flat_map(|(l, r)| [*l, *r]).enumerate() {
+            stems.blips[i].add_delta(time, value - stems.last[i]);
+            stems.last[i] = value;
+        }
+    }
+
+    pub(crate) fn end_stem_frame(&mut self, clocks: u32, count: usize, output_rate: f64) {
+        let (Some(stems), Some(captured)) = (&mut self.stems, &mut self.captured.stems) else {
+            return;
+        };
+        let mut read: Vec<Vec<f32>> = (0..8).map(|_| Vec::with_capacity(count)).collect();
+        for (blip, out) in stems.blips.iter_mut().zip(read.iter_mut()) {
+            blip.end_frame(clocks);
+            blip.read_samples(out, count);
+            blip.set_rates(CPU_FREQ as f64, output_rate);
+        }
+        for (channel, stem) in captured.iter_mut().enumerate() {
+            let (left, right) = (&read[channel * 2], &read[channel * 2 + 1]);
+            for (l, r) in left.iter().zip(right) {
+                stem.push(stems.high_pass[channel * 2].apply(*l));
+                stem.push(stems.high_pass[channel * 2 + 1].apply(*r));
+            }
+        }
+    }
+
+    // Point sampled mode, one sample per channel
+    pub(crate) fn push_stems(&mut self, channels: &[(f32, f32); 4]) {
+        let (Some(stems), Some(captured)) = (&mut self.stems, &mut self.captured.stems) else {
+            return;
+        };
+        for (channel, (left, right)) in channels.iter().enumerate() {
+            captured[channel].push(stems.high_pass[channel * 2].apply(*left));
+            captured[channel].push(stems.high_pass[channel * 2 + 1].apply(*right));
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> CapturedAudio {
+        CapturedAudio {
+            mix: std::mem::take(&mut self.captured.mix),
+            stems: self
+                .captured
+                .stems
+                .as_mut()
+                .map(|stems| stems.each_mut().map(std::mem::take)),
+        }
+    }
+}
+
+// Writes what the APU captures to <path> and optionally <path>_ch1.wav .. <path>_ch4.wav
+pub struct AudioRecorder {
+    mix: WavWriter<BufWriter<File>>,
+    stems: Option<Vec<WavWriter<BufWriter<File>>>>,
+}
+
+impl AudioRecorder {
+    pub fn start(apu: &mut APU, path: &Path, stems: bool) -> io::Result<Self> {
+        let sample_rate = apu.sample_rate().hz();
+        let mix = WavWriter::new(BufWriter::new(File::create(path)?), 2, sample_rate)?;
+        let stems = if stems {
+            let mut writers = Vec::with_capacity(4);
+            for channel in 1..=4 {
+                let file = File::create(stem_path(path, channel))?;
+                writers.push(WavWriter::new(BufWriter::new(file), 2, sample_rate)?);
+            }
+            Some(writers)
+        } else {
+            None
+        };
+
+        apu.start_capture(stems.is_some());
+        Ok(Self { mix, stems })
+    }
+
+    // Call regularly (e.g. every frame) to move captured samples to disk
+    pub fn record(&mut self, apu: &mut APU) -> io::Result<()> {
+        let Some(captured) = apu.take_captured() else {
+            return Ok(());
+        };
+        self.mix.write_samples(&captured.mix)?;
+        if let (Some(writers), Some(stems)) = (&mut self.stems, captured.stems) {
+            for (writer, stem) in writers.iter_mut().zip(stems) {
+                writer.write_samples(&stem)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stop(mut self, apu: &mut APU) -> io::Result<()> {
+        self.record(apu)?;
+        apu.stop_capture();
+        self.mix.finish()?;
+        for writer in self.stems.into_iter().flatten() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn stem_path(path: &Path, channel: u8) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}_ch{}.wav", stem, channel))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::gameboy_with_program;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_wav_header_sizes_are_patched() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 2, 48_000).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 24), 48_000);
+        // Byte rate and block align for 16-bit stereo
+        assert_eq!(u32_at(&bytes, 28), 48_000 * 4);
+        assert_eq!(bytes[32], 4);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 8);
+        // Out of range samples are clamped
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn test_stem_paths() {
+        assert_eq!(
+            stem_path(Path::new("out/song.wav"), 3),
+            PathBuf::from("out/song_ch3.wav")
+        );
+    }
+
+    #[test]
+    fn test_stems_stay_aligned_with_the_mix() {
+        // JR -2
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE]);
+        gameboy.apu.start_capture(true);
+        for _ in 0..3 {
+            gameboy.run_frame();
+        }
+
+        let captured = gameboy.apu.take_captured().unwrap();
+        assert!(!captured.mix.is_empty());
+        for stem in captured.stems.unwrap() {
+            assert_eq!(stem.len(), captured.mix.len());
+        }
+        gameboy.apu.stop_capture();
+        assert!(gameboy.apu.take_captured().is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::{
+    atomic::{AtomicU32, AtomicUsize, Ordering},
+    Arc,
+};
+
+// Single producer / single consumer ring of f32 samples.
+// The emulator thread owns the producer and the audio callback owns the consumer,
+// neither side ever blocks or allocates.
+#[derive(Debug)]
+struct Ring {
+    // f32 samples stored as their bit pattern
+    slots: Box<[AtomicU32]>,
+    // Monotonic positions, wrapped with the capacity on access
+    read: AtomicUsize,
+    write: AtomicUsize,
+}
+
+impl Ring {
+    fn len(&self) -> usize {
+        let write = self.write.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+        write.wrapping_sub(read)
+    }
+}
+
+#[derive(Debug)]
+pub struct AudioProducer {
+    ring: Arc<Ring>,
+}
+
+#[derive(Debug)]
+pub struct AudioConsumer {
+    ring: Arc<Ring>,
+}
+
+pub fn audio_ring_buffer(capacity: usize) -> (AudioProducer, AudioConsumer) {
+    let slots = (0..capacity.max(1))
+        .map(|_| AtomicU32::new(0))
+        .collect::<Vec<_>>()
+        .into_boxed_slice();
+    let ring = Arc::new(Ring {
+        slots,
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+    });
+    (
+        AudioProducer {
+            ring: Arc::clone(&ring),
+        },
+        AudioConsumer { ring },
+    )
+}
+
+impl AudioProducer {
+    // Pushes as many samples as fit, returns how many were written
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let capacity = self.ring.slots.len();
+        let write = self.ring.write.load(Ordering::Relaxed);
+        let read = self.ring.read.load(Ordering::Acquire);
+        let free = capacity - write.wrapping_sub(read);
+        let count = samples.len().min(free);
+
+        for (i, sample) in samples[..count].iter().enumerate() {
+            let index = write.wrapping_add(i) % capacity;
+            self.ring.slots[index].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.ring
+            .write
+            .store(write.wrapping_add(count), Ordering::Release);
+        count
+    }
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+}
+
+impl AudioConsumer {
+    // Fills `out` with available samples, returns how many were read
+    pub fn pop_slice(&mut self, out: &mut [f32]) -> usize {
+        let capacity = self.ring.slots.len();
+        let read = self.ring.read.load(Ordering::Relaxed);
+        let write = self.ring.write.load(Ordering::Acquire);
+        let count = out.len().min(write.wrapping_sub(read));
+
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            let index = read.wrapping_add(i) % capacity;
+            *sample = f32::from_bits(self.ring.slots[index].load(Ordering::Relaxed));
+        }
+        self.ring
+            .read
+            .store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_come_out_in_order() {
+        let (mut producer, mut consumer) = audio_ring_buffer(8);
+        assert!(consumer.is_empty());
+        assert_eq!(producer.push_slice(&[0.1, 0.2, 0.3]), 3);
+        assert_eq!(consumer.len(), 3);
+
+        let mut out = [0.0; 2];
+        assert_eq!(consumer.pop_slice(&mut out), 2);
+        assert_eq!(out, [0.1, 0.2]);
+        assert_eq!(producer.len(), 1);
+    }
+
+    #[test]
+    fn test_full_ring_drops_the_rest() {
+        let (mut producer, mut consumer) = audio_ring_buffer(4);
+        assert_eq!(producer.push_slice(&[1.0, 2.0, 3.0, 4.0, 5.0]), 4);
+        assert_eq!(producer.push_slice(&[6.0]), 0);
+
+        let mut out = [0.0; 8];
+        assert_eq!(consumer.pop_slice(&mut out), 4);
+        assert_eq!(out[..4], [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(consumer.pop_slice(&mut out), 0);
+    }
+
+    #[test]
+    fn test_positions_wrap_around() {
+        let (mut producer, mut consumer) = audio_ring_buffer(3);
+        let mut out = [0.0; 2];
+        for round in 0..10 {
+            let samples = [round as f32, -(round as f32)];
+            assert_eq!(producer.push_slice(&samples), 2);
+            assert_eq!(consumer.pop_slice(&mut out), 2);
+            assert_eq!(out, samples);
+        }
+        assert!(producer.is_empty());
+        assert_eq!(producer.capacity(), 3);
+    }
+
+    #[test]
+    fn test_threads_see_every_sample() {
+        let (mut producer, mut consumer) = audio_ring_buffer(64);
+        let writer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < 10_000 {
+                let samples: Vec<f32> = (next..next + 16).map(|i| i as f32).collect();
+                next += producer.push_slice(&samples);
+            }
+        });
+
+        let mut expected = 0;
+        let mut out = [0.0; 10];
+        while expected < 10_000 {
+            let count = consumer.pop_slice(&mut out);
+            for &sample in &out[..count] {
+                assert_eq!(sample, expected as f32);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+    }
+}
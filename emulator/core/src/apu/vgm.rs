@@ -0,0 +1,273 @@
+use crate::bus::{io_address::IoRegister, MemoryInterface};
+
+const VGM_SAMPLE_RATE: u64 = 44_100;
+const DMG_CLOCK: u64 = 4_194_304;
+const VGM_DATA_OFFSET: usize = 0x100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ApuWrite {
+    // T-cycles since the log started
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8,
+}
+
+/*
+Every write to the sound registers (FF10-FF3F) with its cycle timestamp.
+The register values at the start are kept so an export starts from the same state.
+*/
+#[derive(Debug, Clone)]
+pub struct ApuWriteLog {
+    pub writes: Vec<ApuWrite>,
+    initial_registers: [u8; 0x30],
+    cycle: u64,
+    loop_cycle: Option<u64>,
+    // Writes from here on were made by the instruction still running
+    first_pending: usize,
+}
+
+impl ApuWriteLog {
+    pub fn new<M: MemoryInterface>(memory: &M) -> Self {
+        let mut initial_registers = [0; 0x30];
+        for (i, register) in initial_registers.iter_mut().enumerate() {
+            *register = memory.read_byte(IoRegister::Nr10.address() + i as u16);
+        }
+        Self {
+            writes: Vec::new(),
+            initial_registers,
+            cycle: 0,
+            loop_cycle: None,
+            first_pending: 0,
+        }
+    }
+
+    pub fn record(&mut self, address: u16, value: u8) {
+        self.writes.push(ApuWrite {
+            cycle: self.cycle,
+            address,
+            value,
+        });
+    }
+
+    /*
+    Called after each instruction with its length. The CPU runs an instruction
+    at once, so its writes are moved to the M-cycles they take on hardware:
+    the last ones of the instruction, one write per M-cycle.
+    */
+    pub fn advance(&mut self, cycles: usize) {
+        let end = self.cycle + cycles as u64;
+        let first = self.first_pending.min(self.writes.len());
+        for (index, write) in self.writes[first..].iter_mut().rev().enumerate() {
+            write.cycle = end.saturating_sub(4 * (index as u64 + 1)).max(self.cycle);
+        }
+        self.first_pending = self.writes.len();
+        self.cycle = end;
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    // The exported VGM loops back to this point
+    pub fn mark_loop(&mut self) {
+        self.loop_cycle = Some(self.cycle);
+    }
+
+    pub fn loop_cycle(&self) -> Option<u64> {
+        self.loop_cycle
+    }
+
+    /*
+    VGM 1.61 https://vgmrips.net/wiki/VGM_Specification
+    0xB3 aa dd   GB DMG write, aa = register - 0xFF10
+    0x61 nnnn    wait n samples (44100 Hz)
+    0x62 / 0x63  wait 735 / 882 samples
+    0x7n         wait n+1 samples
+    0x66         end of sound data
+    */
+    pub fn to_vgm(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.write_initial_state(&mut data);
+
+        let mut current_sample = 0;
+        let mut loop_offset = None;
+        let mut loop_sample = 0;
+        let mut writes = self.writes.iter().peekable();
+        loop {
+            let next_cycle = writes.peek().map(|write| write.cycle).unwrap_or(self.cycle);
+            if let Some(loop_cycle) = self.loop_cycle {
+                if loop_offset.is_none() && loop_cycle <= next_cycle {
+                    let sample = cycle_to_sample(loop_cycle);
+                    write_wait(&mut data, sample - current_sample);
+                    current_sample = sample;
+                    loop_offset = Some(VGM_DATA_OFFSET + data.len());
+                    loop_sample = sample;
+                }
+            }
+
+            let sample = cycle_to_sample(next_cycle);
+            write_wait(&mut data, sample - current_sample);
+            current_sample = sample;
+
+            match writes.next() {
+                Some(write) => {
+                    data.extend_from_slice(&[0xB3, (write.address - 0xFF10) as u8, write.value])
+                }
+                None => break,
+            }
+        }
+        data.push(0x66);
+
+        let total_samples = current_sample;
+        let mut vgm = vec![0; VGM_DATA_OFFSET];
+        vgm[0x00..0x04].copy_from_slice(b"Vgm ");
+        let eof_offset = (VGM_DATA_OFFSET + data.len() - 0x04) as u32;
+        vgm[0x04..0x08].copy_from_slice(&eof_offset.to_le_bytes());
+        vgm[0x08..0x0C].copy_from_slice(&0x161u32.to_le_bytes());
+        vgm[0x18..0x1C].copy_from_slice(&(total_samples as u32).to_le_bytes());
+        if let Some(offset) = loop_offset {
+            vgm[0x1C..0x20].copy_from_slice(&((offset - 0x1C) as u32).to_le_bytes());
+            let loop_samples = (total_samples - loop_sample) as u32;
+            vgm[0x20..0x24].copy_from_slice(&loop_samples.to_le_bytes());
+        }
+        vgm[0x34..0x38].copy_from_slice(&((VGM_DATA_OFFSET - 0x34) as u32).to_le_bytes());
+        vgm[0x80..0x84].copy_from_slice(&(DMG_CLOCK as u32).to_le_bytes());
+        vgm.extend(data);
+        vgm
+    }
+
+    // Power on, wave RAM with channel 3 off, then the remaining registers.
+    // Trigger bits are cleared so notes playing when the log started are not restarted.
+    fn write_initial_state(&self, data: &mut Vec<u8>) {
+        let register = |address: IoRegister| (address.address() - 0xFF10) as usize;
+        let nr52 = register(IoRegister::Nr52);
+        let nr30 = register(IoRegister::Nr30);
+
+        data.extend_from_slice(&[0xB3, nr52 as u8, self.initial_registers[nr52] & 0x80]);
+        data.extend_from_slice(&[0xB3, nr30 as u8, 0x00]);
+        for offset in 0x20..0x30 {
+            data.extend_from_slice(&[0xB3, offset as u8, self.initial_registers[offset]]);
+        }
+        for offset in 0x00..nr52 {
+            let mut value = self.initial_registers[offset];
+            // NR14, NR24, NR34, NR44
+            if [0x04, 0x09, 0x0E, 0x13].contains(&offset) {
+                value &= 0x7F;
+            }
+            data.extend_from_slice(&[0xB3, offset as u8, value]);
+        }
+    }
+}
+
+fn cycle_to_sample(cycle: u64) -> u64 {
+    cycle * VGM_SAMPLE_RATE / DMG_CLOCK
+}
+
+fn write_wait(data: &mut Vec<u8>, mut samples: u64) {
+    while samples > 0 {
+        match samples {
+            735 => {
+                data.push(0x62);
+                samples = 0;
+            }
+            882 => {
+                data.push(0x63);
+                samples = 0;
+            }
+            1..=16 => {
+                data.push(0x70 + (samples - 1) as u8);
+                samples = 0;
+            }
+            _ => {
+                let wait = samples.min(0xFFFF);
+                data.push(0x61);
+                data.extend_from_slice(&(wait as u16).to_le_bytes());
+                samples -= wait;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::Gameboy;
+    use crate::test_util::{gameboy_with_program, PALETTE};
+
+    fn wait(samples: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_wait(&mut data, samples);
+        data
+    }
+
+    #[test]
+    fn test_wait_encoding() {
+        assert!(wait(0).is_empty());
+        assert_eq!(wait(1), [0x70]);
+        assert_eq!(wait(16), [0x7F]);
+        assert_eq!(wait(17), [0x61, 0x11, 0x00]);
+        assert_eq!(wait(735), [0x62]);
+        assert_eq!(wait(882), [0x63]);
+        assert_eq!(wait(0xFFFF + 735), [0x61, 0xFF, 0xFF, 0x62]);
+        assert_eq!(wait(0x20000), [0x61, 0xFF, 0xFF, 0x61, 0xFF, 0xFF, 0x71]);
+    }
+
+    #[test]
+    fn test_writes_take_the_last_m_cycles_of_their_instruction() {
+        // LDH ($12),A; LD HL,$FF13; LD (HL),A; LD ($FF14),A
+        let mut gameboy = gameboy_with_program(&[
+            0xE0, 0x12, 0x21, 0x13, 0xFF, 0x77, 0xEA, 0x14, 0xFF, 0x18, 0xFE,
+        ]);
+        // Past the jump to 0150
+        gameboy.tick();
+        gameboy.tick();
+        gameboy.start_apu_log();
+        for _ in 0..4 {
+            gameboy.tick();
+        }
+        let log = gameboy.stop_apu_log().unwrap();
+        let writes: Vec<(u16, u64)> = log
+            .writes
+            .iter()
+            .map(|write| (write.address, write.cycle))
+            .collect();
+        assert_eq!(writes, [(0xFF12, 8), (0xFF13, 28), (0xFF14, 44)]);
+        assert_eq!(log.cycle(), 48);
+    }
+
+    #[test]
+    fn test_vgm_export() {
+        let gameboy = Gameboy::new(PALETTE);
+        let mut log = ApuWriteLog::new(&gameboy.bus);
+        // 96 cycles is just over one 44100 Hz sample
+        log.advance(96);
+        log.record(0xFF12, 0xF0);
+        log.advance(1);
+        log.mark_loop();
+        log.advance(DMG_CLOCK as usize / 60);
+        log.record(0xFF26, 0x00);
+        log.advance(4);
+
+        let vgm = log.to_vgm();
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(vgm[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&vgm[0..4], b"Vgm ");
+        assert_eq!(u32_at(0x04) as usize, vgm.len() - 4);
+        assert_eq!(u32_at(0x08), 0x161);
+        assert_eq!(u32_at(0x34) as usize + 0x34, VGM_DATA_OFFSET);
+        assert_eq!(u32_at(0x80) as u64, DMG_CLOCK);
+        assert_eq!(*vgm.last().unwrap(), 0x66);
+
+        // Initial state: NR52, NR30, 16 wave RAM and 22 other registers
+        let commands = &vgm[VGM_DATA_OFFSET + 3 * (2 + 16 + 22)..];
+        assert_eq!(
+            commands,
+            [0x70, 0xB3, 0x02, 0xF0, 0x62, 0xB3, 0x16, 0x00, 0x66]
+        );
+        // The loop starts after the first write, 1 sample in
+        let loop_offset = u32_at(0x1C) as usize + 0x1C;
+        assert_eq!(vgm[loop_offset], 0x62);
+        assert_eq!(u32_at(0x18), 736);
+        assert_eq!(u32_at(0x20), 735);
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::ppu::compatibility::CompatibilityPalette;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CgbRegisters {
     pub vram_bank: u8,
@@ -16,6 +18,8 @@ pub struct CgbRegisters {
     dma_length: u16,
     pub dma_active: bool,
     pub hdma_active: bool,
+    // CGB running a DMG game, shades are colored with BG palette 0 and OBJ palettes 0-1
+    pub dmg_compatibility: bool,
 }
 
 impl Default for CgbRegisters {
@@ -33,6 +37,7 @@ impl Default for CgbRegisters {
             dma_length: 0,
             dma_active: false,
             hdma_active: false,
+            dmg_compatibility: false,
         }
     }
 }
@@ -144,6 +149,21 @@ impl CgbRegisters {
         }
     }
 
+    // Where the boot ROM leaves the colors for a DMG game
+    pub fn load_compatibility_palette(&mut self, palette: &CompatibilityPalette) {
+        let rgb555 = |color: u32| {
+            let r = (color >> 19) & 0x1F;
+            let g = (color >> 11) & 0x1F;
+            let b = (color >> 3) & 0x1F;
+            ((b << 10 | g << 5 | r) as u16).to_le_bytes()
+        };
+        for i in 0..4 {
+            self.bg_palette_ram[i * 2..i * 2 + 2].copy_from_slice(&rgb555(palette.bg[i]));
+            self.obj_palette_ram[i * 2..i * 2 + 2].copy_from_slice(&rgb555(palette.obj0[i]));
+            self.obj_palette_ram[8 + i * 2..10 + i * 2].copy_from_slice(&rgb555(palette.obj1[i]));
+        }
+    }
+
     pub fn get_vram_bank(&self) -> usize {
         self.vram_bank as usize
     }
@@ -190,7 +210,7 @@ impl CgbRegisters {
     }
 
     #[inline(always)]
-    fn convert_color(low: u8, high: u8) -> u32 {
+    pub(crate) fn convert_color(low: u8, high: u8) -> u32 {
         // Combine bytes into 15-bit RGB value
         let color = ((high as u16) << 8) | (low as u16);
 
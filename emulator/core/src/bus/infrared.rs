@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/*
+Infrared port, shared by the CGB RP register (FF56) and cartridges
+with their own IR LED and sensor (HuC1/HuC3).
+The host reads the LED and feeds back whether light is being received.
+*/
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Infrared {
+    // Light from the other side, set by the host
+    pub receiving: bool,
+    rp: u8,
+    cartridge_led: bool,
+}
+
+impl Infrared {
+    pub fn led(&self) -> bool {
+        self.rp & 0x01 != 0 || self.cartridge_led
+    }
+
+    /*
+    RP: bit 0 LED (write), bit 1 light received (read, 0 = receiving)
+    bits 6-7 read enable, must be 3 for bit 1 to work
+    */
+    pub fn read_rp(&self) -> u8 {
+        let receiving = self.rp & 0xC0 == 0xC0 && self.receiving;
+        self.rp & 0xC1 | 0x3C | if receiving { 0x00 } else { 0x02 }
+    }
+    pub fn write_rp(&mut self, value: u8) {
+        self.rp = value & 0xC1;
+    }
+
+    // Cartridge IR mode: reads 0xC1 while receiving light, 0xC0 otherwise
+    pub fn read_cartridge(&self) -> u8 {
+        0xC0 | self.receiving as u8
+    }
+    pub fn write_cartridge(&mut self, value: u8) {
+        self.cartridge_led = value & 0x01 != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rp() {
+        let mut infrared = Infrared::default();
+        assert_eq!(infrared.read_rp(), 0x3E);
+        infrared.write_rp(0xFF);
+        assert_eq!(infrared.read_rp(), 0xFF);
+        assert!(infrared.led());
+
+        // Bit 1 goes low while receiving, only with reading enabled
+        infrared.receiving = true;
+        assert_eq!(infrared.read_rp(), 0xFD);
+        infrared.write_rp(0x01);
+        assert_eq!(infrared.read_rp(), 0x3F);
+    }
+
+    #[test]
+    fn test_cartridge_port() {
+        let mut infrared = Infrared::default();
+        assert_eq!(infrared.read_cartridge(), 0xC0);
+        infrared.receiving = true;
+        assert_eq!(infrared.read_cartridge(), 0xC1);
+
+        infrared.write_cartridge(0x01);
+        assert!(infrared.led());
+        infrared.write_cartridge(0x00);
+        assert!(!infrared.led());
+    }
+}
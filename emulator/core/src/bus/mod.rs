@@ -1,13 +1,37 @@
 use cgb::CgbRegisters;
+use infrared::Infrared;
 use io_address::IoRegister;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    cartridge::{mbc0::Mbc0, mbc1::Mbc1, mbc3::Mbc3, mbc5::Mbc5, MbcType, MbcTypeState},
+    apu::vgm::ApuWriteLog,
+    cartridge::{
+        camera::Camera,
+        cartridge_header,
+        detect::{self, UnlicensedMapper},
+        gbs::{Gbs, GbsHeader},
+        huc1::Huc1,
+        huc3::Huc3,
+        mbc0::Mbc0,
+        mbc1::Mbc1,
+        mbc2::Mbc2,
+        mbc3::Mbc3,
+        mbc5::Mbc5,
+        mbc7::Mbc7,
+        mmm01::Mmm01,
+        rocket::Rocket,
+        sachen::Sachen,
+        wisdom_tree::WisdomTree,
+        MbcType, MbcTypeState, MbcTypeStateV1,
+    },
+    cheats::Cheats,
     joyp::Joypad,
+    model::HardwareModel,
+    sgb::{Sgb, SgbState},
 };
 
 pub mod cgb;
+pub mod infrared;
 pub mod io_address;
 
 pub trait MemoryInterface {
@@ -38,6 +62,9 @@ pub trait MemoryInterface {
 
     fn read_byte_vram_bank(&self, address: u16, bank: usize) -> u8;
     fn write_byte_vram_bank(&mut self, address: u16, value: u8, bank: usize);
+
+    // STAT as the PPU updates it, write_byte is a write from the game
+    fn set_stat(&mut self, value: u8);
 }
 
 #[derive(Clone, Debug)]
@@ -50,10 +77,19 @@ pub struct Bus {
     vram_banks: Vec<[u8; 0x2000]>,
     wram_banks: Vec<[u8; 0x1000]>,
     current_wram_bank: usize,
+    // FEA0-FEFF isn't memory on the emulated models, save states still keep these bytes
     debug: [u8; 0x100],
     pub mbc: MbcType,
     pub gb_mode: GameboyMode,
     pub cgb: cgb::CgbRegisters,
+    // Sound register writes, only collected while logging
+    pub apu_log: Option<ApuWriteLog>,
+    pub has_battery: bool,
+    // Game Genie and GameShark codes, not part of save states
+    pub cheats: Cheats,
+    pub infrared: Infrared,
+    pub sgb: Option<Sgb>,
+    pub model: HardwareModel,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Copy)]
@@ -72,9 +108,14 @@ impl MemoryInterface for Bus {
         self.vram_banks[bank][(address - 0x8000) as usize] = value;
     }
     #[inline(always)]
+    fn set_stat(&mut self, value: u8) {
+        self.io_registers[(IoRegister::Stat.address() - 0xFF01) as usize] = value;
+    }
+    #[inline(always)]
     fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.read_byte(address),
+            0xA000..=0xBFFF if self.mbc.infrared_selected() => self.infrared.read_cartridge(),
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.read_byte(address, self.cheats.patches()),
             0x8000..=0x9FFF => {
                 let bank = if self.gb_mode == GameboyMode::CGB {
                     self.cgb.get_vram_bank()
@@ -94,13 +135,17 @@ impl MemoryInterface for Bus {
             }
             0xE000..=0xFDFF => self.read_byte(address - 0x2000), // Echo RAM
             0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize],
-            0xFEA0..=0xFEFF => self.debug[(address - 0xFEA0) as usize],
-            0xFF00 => self.joypad.read(),
+            0xFEA0..=0xFEFF => self.model.read_unusable(address),
+            0xFF00 => match &self.sgb {
+                Some(sgb) => sgb.read_joypad(&self.joypad),
+                None => self.joypad.read(),
+            },
             0xFF4D | 0xFF4F | 0xFF55 | 0xFF68 | 0xFF69 | 0xFF6A | 0xFF6B | 0xFF70
                 if self.gb_mode == GameboyMode::CGB =>
             {
                 self.cgb.read_register(address)
             }
+            0xFF56 if self.gb_mode == GameboyMode::CGB => self.infrared.read_rp(),
             0xFF01..=0xFF7F => self.io_registers[(address - 0xFF01) as usize],
             0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
             0xFFFF => self.ie_register,
@@ -110,6 +155,7 @@ impl MemoryInterface for Bus {
     #[inline(always)]
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
+            0xA000..=0xBFFF if self.mbc.infrared_selected() => self.infrared.write_cartridge(value),
             0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.write_byte(address, value),
             0x8000..=0x9FFF => {
                 let bank = if self.gb_mode == GameboyMode::CGB {
@@ -130,13 +176,31 @@ impl MemoryInterface for Bus {
             }
             0xE000..=0xFDFF => self.write_byte(address - 0x2000, value), // Echo RAM
             0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
-            0xFEA0..=0xFEFF => self.debug[(address - 0xFEA0) as usize] = value,
-            0xFF00 => self.joypad.write(value),
+            0xFEA0..=0xFEFF => {}
+            0xFF00 => {
+                self.joypad.write(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
             0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF68 | 0xFF69 | 0xFF6A | 0xFF6B | 0xFF70
                 if self.gb_mode == GameboyMode::CGB =>
             {
                 self.cgb.write_register(address, value)
             }
+            0xFF56 if self.gb_mode == GameboyMode::CGB => self.infrared.write_rp(value),
+            0xFF10..=0xFF3F => {
+                if let Some(log) = &mut self.apu_log {
+                    log.record(address, value);
+                }
+                self.io_registers[(address - 0xFF01) as usize] = value
+            }
+            0xFF41 => {
+                if self.model.has_stat_write_bug() {
+                    self.stat_write_bug();
+                }
+                self.io_registers[(address - 0xFF01) as usize] = value
+            }
             0xFF01..=0xFF45 => self.io_registers[(address - 0xFF01) as usize] = value,
             0xFF46 => self.dma_oam_transfer(value),
             0xFF47..=0xFF7F => self.io_registers[(address - 0xFF01) as usize] = value,
@@ -179,6 +243,12 @@ impl Bus {
             mbc: MbcType::None,
             gb_mode: GameboyMode::DMG,
             cgb: cgb::CgbRegisters::default(),
+            apu_log: None,
+            cheats: Cheats::new(),
+            has_battery: false,
+            infrared: Infrared::default(),
+            sgb: None,
+            model: HardwareModel::Dmg,
         }
     }
 
@@ -206,6 +276,10 @@ impl Bus {
             debug: self.debug,
             mbc: self.mbc.save_state(),
             gb_mode: self.gb_mode.clone(),
+            infrared: self.infrared.clone(),
+            cgb: self.cgb.clone(),
+            sgb: self.sgb.as_ref().map(Sgb::save_state),
+            model: self.model,
         }
     }
 
@@ -243,6 +317,12 @@ impl Bus {
         self.debug = state.debug;
         self.mbc.load_state(state.mbc);
         self.gb_mode = state.gb_mode;
+        self.infrared = state.infrared;
+        self.cgb = state.cgb;
+        self.model = state.model;
+        if let (Some(sgb), Some(sgb_state)) = (&mut self.sgb, state.sgb) {
+            sgb.load_state(sgb_state);
+        }
     }
 
     pub fn check__gb_mode(&mut self, byte: u8) {
@@ -254,7 +334,17 @@ impl Bus {
     }
     #[inline]
     pub fn load_rom(&mut self, rom: &[u8]) {
-        let ram_size = match rom[0x149] {
+        // Mappers the cartridge type byte doesn't tell about
+        let unlicensed = detect::detect_unlicensed_mapper(rom);
+        let mmm01_rom;
+        let (rom, header) = if unlicensed == Some(UnlicensedMapper::Mmm01) {
+            mmm01_rom = detect::arrange_mmm01_rom(rom);
+            // The header of the menu describes the cartridge
+            (&mmm01_rom[..], &mmm01_rom[mmm01_rom.len() - 0x8000..])
+        } else {
+            (rom, rom)
+        };
+        let ram_size = match header[0x149] {
             0x00 => 0,       // No RAM
             0x02 => 0x2000,  // 8 KiB
             0x03 => 0x8000,  // 32 KiB
@@ -264,7 +354,7 @@ impl Bus {
         };
         println!("ram_size: {} bytes", ram_size);
         println!("rom_size: {} bytes", rom.len());
-        println!("mbctype: {:04X}", rom[0x147]);
+        println!("mbctype: {:04X}", header[0x147]);
         println!("gb_mode: {:?}", self.gb_mode);
         if self.gb_mode != GameboyMode::DMG {
             self.vram_banks = vec![[0; 0x2000], [0; 0x2000]];
@@ -273,16 +363,51 @@ impl Bus {
         }
         // power up sequence here?
 
+        self.has_battery = cartridge_header::has_battery(header[0x147]);
+
+        if let Some(mapper) = unlicensed {
+            println!("unlicensed mapper: {:?}", mapper);
+        }
         // Detect MBC type from ROM header
-        self.mbc = match rom[0x147] {
-            0x00 => MbcType::Mbc0(Mbc0::new(rom, ram_size)),
-            0x01..=0x03 => MbcType::Mbc1(Mbc1::new(rom, ram_size)),
-            0x0F | 0x10 => MbcType::Mbc3(Mbc3::new(rom, ram_size, true)), // RTC is present
-            0x11..=0x13 => MbcType::Mbc3(Mbc3::new(rom, ram_size, false)), // RTC is absent
-            0x19..=0x1E => MbcType::Mbc5(Mbc5::new(rom, ram_size)),
-
-            _ => panic!("Unsupported MBC type"),
+        self.mbc = match (unlicensed, header[0x147]) {
+            (Some(UnlicensedMapper::Mmm01), _) => MbcType::Mmm01(Mmm01::new(rom, ram_size)),
+            (Some(UnlicensedMapper::WisdomTree), _) => MbcType::WisdomTree(WisdomTree::new(rom)),
+            (Some(UnlicensedMapper::Sachen(kind)), _) => {
+                self.has_battery = false;
+                MbcType::Sachen(Sachen::new(rom, kind))
+            }
+            (Some(UnlicensedMapper::Rocket), _) => {
+                self.has_battery = false;
+                MbcType::Rocket(Rocket::new(rom))
+            }
+            (None, cartridge_type) => match cartridge_type {
+                0x00 => MbcType::Mbc0(Mbc0::new(rom, ram_size)),
+                0x01..=0x03 => MbcType::Mbc1(Mbc1::new(rom, ram_size)),
+                0x05 | 0x06 => MbcType::Mbc2(Mbc2::new(rom)),
+                0x0F | 0x10 => MbcType::Mbc3(Mbc3::new(rom, ram_size, true)), // RTC is present
+                0x11..=0x13 => MbcType::Mbc3(Mbc3::new(rom, ram_size, false)), // RTC is absent
+                0x19..=0x1B => MbcType::Mbc5(Mbc5::new(rom, ram_size, false)),
+                0x1C..=0x1E => MbcType::Mbc5(Mbc5::new(rom, ram_size, true)), // Rumble
+                0x22 => MbcType::Mbc7(Mbc7::new(rom)), // Accelerometer + EEPROM
+                0xFC => MbcType::Camera(Camera::new(rom)),
+                0xFE => MbcType::Huc3(Huc3::new(rom, ram_size)),
+                0xFF => MbcType::Huc1(Huc1::new(rom, ram_size)),
+
+                _ => panic!("Unsupported MBC type"),
+            },
         };
+        for game in self.mbc.multicart_games() {
+            println!("multicart game: {:02X} {}", game.bank, game.title);
+        }
+    }
+
+    pub fn load_gbs(&mut self, header: &GbsHeader, data: &[u8]) {
+        self.gb_mode = GameboyMode::DMG;
+        self.vram_banks = vec![[0; 0x2000]];
+        self.wram_banks = vec![[0; 0x1000], [0; 0x1000]];
+        self.current_wram_bank = 1;
+        self.mbc = MbcType::Gbs(Gbs::new(header, data));
+        self.has_battery = false;
     }
 
     #[inline]
@@ -295,6 +420,99 @@ impl Bus {
         }
     }
 
+    // The STAT line goes high for the write, a STAT interrupt if it wasn't already
+    fn stat_write_bug(&mut self) {
+        let lcd_on = self.read_byte(IoRegister::Lcdc.address()) & 0x80 != 0;
+        let stat = self.read_byte(IoRegister::Stat.address());
+        let mode = stat & 0x03;
+        let coincidence = stat & 0x04 != 0;
+        let line_was_high = (coincidence && stat & 0x40 != 0)
+            || (mode == 0 && stat & 0x08 != 0)
+            || (mode == 1 && stat & 0x10 != 0)
+            || (mode == 2 && stat & 0x20 != 0);
+        if lcd_on && (mode != 3 || coincidence) && !line_was_high {
+            let interrupt_flag = self.read_byte(IoRegister::If.address());
+            self.write_byte(IoRegister::If.address(), interrupt_flag | 0x02);
+        }
+    }
+
+    // Bank mapped at an address as the CPU sees it, 0 where nothing is banked
+    pub fn bank_at(&self, address: u16) -> usize {
+        let cgb = self.gb_mode == GameboyMode::CGB;
+        match address {
+            0x0000..=0x7FFF => self.mbc.rom_bank(address),
+            0x8000..=0x9FFF if cgb => self.cgb.get_vram_bank(),
+            0xD000..=0xDFFF if cgb => self.cgb.get_wram_bank(),
+            0xD000..=0xDFFF => 1,
+            0xE000..=0xFDFF => self.bank_at(address - 0x2000),
+            _ => 0,
+        }
+    }
+
+    // GameShark writes, at the start of every VBlank
+    pub(crate) fn apply_cheats(&mut self) {
+        for index in 0..self.cheats.writes().len() {
+            let freeze = self.cheats.writes()[index];
+            freeze.apply(self);
+        }
+    }
+
+    pub fn wram_bank_count(&self) -> usize {
+        self.wram_banks.len()
+    }
+    // Cartridge RAM as the game maps it at 0xA000, 0x2000 bytes per bank
+    pub fn cartridge_ram(&self) -> Option<&[u8]> {
+        match self.mbc {
+            MbcType::Mbc7(_) => None,
+            _ => self.mbc.ram(),
+        }
+    }
+
+    /*
+    RAM in a given bank, whatever bank is mapped right now: cartridge RAM,
+    WRAM (bank 0 at 0xC000, 1-7 at 0xD000, 0 maps 1 like SVBK) and HRAM.
+    None outside of RAM or past the banks the cartridge and model have.
+    */
+    pub fn read_banked(&self, bank: usize, address: u16) -> Option<u8> {
+        match address {
+            0xA000..=0xBFFF => self
+                .cartridge_ram()?
+                .get(bank * 0x2000 + (address - 0xA000) as usize)
+                .copied(),
+            0xC000..=0xCFFF => Some(self.wram_banks[0][(address - 0xC000) as usize]),
+            0xD000..=0xDFFF => self
+                .wram_banks
+                .get(bank.max(1))
+                .map(|wram| wram[(address - 0xD000) as usize]),
+            0xE000..=0xFDFF => self.read_banked(bank, address - 0x2000),
+            0xFF80..=0xFFFE => Some(self.hram[(address - 0xFF80) as usize]),
+            _ => None,
+        }
+    }
+    pub fn write_banked(&mut self, bank: usize, address: u16, value: u8) -> bool {
+        let byte = match address {
+            0xA000..=0xBFFF => self
+                .mbc
+                .ram_mut()
+                .and_then(|ram| ram.get_mut(bank * 0x2000 + (address - 0xA000) as usize)),
+            0xC000..=0xCFFF => Some(&mut self.wram_banks[0][(address - 0xC000) as usize]),
+            0xD000..=0xDFFF => self
+                .wram_banks
+                .get_mut(bank.max(1))
+                .map(|wram| &mut wram[(address - 0xD000) as usize]),
+            0xE000..=0xFDFF => return self.write_banked(bank, address - 0x2000, value),
+            0xFF80..=0xFFFE => Some(&mut self.hram[(address - 0xFF80) as usize]),
+            _ => None,
+        };
+        match byte {
+            Some(byte) => {
+                *byte = value;
+                true
+            }
+            None => false,
+        }
+    }
+
     #[inline(always)]
     pub fn read_cartridge_header(&self) -> [u8; 0x50] {
         let mut header = [0; 0x50];
@@ -323,4 +541,50 @@ pub struct BusState {
     pub debug: [u8; 0x100],
     pub mbc: MbcTypeState,
     pub gb_mode: GameboyMode,
+    pub infrared: Infrared,
+    pub cgb: CgbRegisters,
+    pub sgb: Option<SgbState>,
+    pub model: HardwareModel,
+}
+
+// Save state version 1, from before MBC5 rumble, infrared, CGB registers, the SGB and the model were saved
+#[derive(Deserialize)]
+pub(crate) struct BusStateV1 {
+    joypad: Joypad,
+    #[serde(with = "serde_arrays")]
+    oam: [u8; 0xA0],
+    #[serde(with = "serde_arrays")]
+    io_registers: [u8; 0x7F],
+    #[serde(with = "serde_arrays")]
+    hram: [u8; 0x7F],
+    ie_register: u8,
+    vram_data: Vec<u8>,
+    wram_data: Vec<u8>,
+    current_wram_bank: usize,
+    #[serde(with = "serde_arrays")]
+    debug: [u8; 0x100],
+    mbc: MbcTypeStateV1,
+    gb_mode: GameboyMode,
+}
+impl BusStateV1 {
+    // What wasn't saved stays as the bus has it now
+    pub(crate) fn upgrade(self, bus: &Bus) -> BusState {
+        BusState {
+            joypad: self.joypad,
+            oam: self.oam,
+            io_registers: self.io_registers,
+            hram: self.hram,
+            ie_register: self.ie_register,
+            vram_data: self.vram_data,
+            wram_data: self.wram_data,
+            current_wram_bank: self.current_wram_bank,
+            debug: self.debug,
+            mbc: self.mbc.into(),
+            gb_mode: self.gb_mode,
+            infrared: bus.infrared.clone(),
+            cgb: bus.cgb.clone(),
+            sgb: bus.sgb.as_ref().map(Sgb::save_state),
+            model: bus.model,
+        }
+    }
 }
@@ -0,0 +1,389 @@
+use serde::{Deserialize, Serialize};
+
+use crate::png;
+
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+const RAM_SIZE: usize = 0x20000;
+// Where a capture lands in RAM bank 0, 16x14 tiles
+const CAPTURE_ADDRESS: usize = 0x0100;
+const PHOTO_SIZE: usize = CAMERA_WIDTH * CAMERA_HEIGHT / 4;
+// Saved photos: 30 slots of 0x1000 bytes from RAM bank 1, the album order is in bank 0
+const PHOTO_SLOTS: usize = 30;
+const PHOTO_START: usize = 0x2000;
+const PHOTO_STRIDE: usize = 0x1000;
+const ALBUM_INDEX: usize = 0x11B2;
+
+// Sensor registers, A000-A035 while RAM bank 0x10 is selected
+const REGISTER_COUNT: usize = 0x36;
+const DITHER_MATRIX: usize = 0x06;
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    registers: [u8; REGISTER_COUNT],
+    // Cycles left until the running capture is written to RAM
+    capture_cycles: usize,
+    // Host image, 8 bit grayscale CAMERA_WIDTH x CAMERA_HEIGHT
+    image: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraState {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    registers: Vec<u8>,
+    capture_cycles: usize,
+}
+
+impl Camera {
+    /*
+    MAC-GBD mapper with the M64282FP image sensor
+    ROM BANK: Up to 64 banks of 16KB, bank 0 can be mapped at 4000-7FFF
+    RAM BANK: 16 banks of 8KB, bank 0x10 maps the sensor registers instead
+    RAM is readable while disabled, except during a capture
+    A000      bit 0 start capture / busy, bits 1-2 edge mode
+    A001      bits 0-4 gain, bits 5-7 edge extraction (all set = enhancement on)
+    A002-A003 exposure time, big endian
+    A004      bits 0-2 output bias, bit 3 invert, bits 4-6 edge enhancement ratio
+    A005      zero point calibration
+    A006-A035 4x4 dithering matrix, 3 thresholds per pixel, which sets the contrast
+     */
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            rom: rom.to_vec(),
+            ram: vec![0; RAM_SIZE],
+            ram_enabled: false,
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+            image: vec![0x80; CAMERA_WIDTH * CAMERA_HEIGHT],
+        }
+    }
+
+    pub fn save_state(&self) -> CameraState {
+        CameraState {
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram: self.ram.clone(),
+            ram_enabled: self.ram_enabled,
+            registers: self.registers.to_vec(),
+            capture_cycles: self.capture_cycles,
+        }
+    }
+    pub fn load_state(&mut self, state: CameraState) {
+        self.current_rom_bank = state.current_rom_bank;
+        self.current_ram_bank = state.current_ram_bank;
+        self.ram = state.ram;
+        self.ram_enabled = state.ram_enabled;
+        let length = self.registers.len().min(state.registers.len());
+        self.registers[..length].copy_from_slice(&state.registers[..length]);
+        self.capture_cycles = state.capture_cycles;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    // Any size of 8 bit grayscale, scaled to the sensor
+    pub fn set_image(&mut self, pixels: &[u8], width: usize, height: usize) {
+        if width == 0 || height == 0 || pixels.len() < width * height {
+            return;
+        }
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let source = (y * height / CAMERA_HEIGHT) * width + x * width / CAMERA_WIDTH;
+                self.image[y * CAMERA_WIDTH + x] = pixels[source];
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+        self.capture_cycles -= 1;
+        if self.capture_cycles == 0 {
+            self.capture();
+            self.registers[0] &= !0x01;
+        }
+    }
+
+    fn capturing(&self) -> bool {
+        self.capture_cycles > 0
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            // Other registers are write only
+            0xA000..=0xBFFF if self.current_ram_bank & 0x10 != 0 => match address & 0x7F {
+                0x00 => self.registers[0],
+                _ => 0x00,
+            },
+            0xA000..=0xBFFF if self.capturing() => 0x00,
+            0xA000..=0xBFFF => self.ram[self.ram_offset(address)],
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.current_rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.current_ram_bank = value & 0x1F,
+            0xA000..=0xBFFF if self.current_ram_bank & 0x10 != 0 => {
+                let register = (address & 0x7F) as usize;
+                match register {
+                    0x00 => {
+                        self.registers[0] = value & 0x07;
+                        if value & 0x01 != 0 && !self.capturing() {
+                            self.capture_cycles = self.capture_time();
+                        }
+                    }
+                    _ if register < REGISTER_COUNT => self.registers[register] = value,
+                    _ => {}
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled && !self.capturing() => {
+                let offset = self.ram_offset(address);
+                self.ram[offset] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        0x2000 * (self.current_ram_bank & 0x0F) as usize + (address - 0xA000) as usize
+    }
+
+    fn exposure(&self) -> u32 {
+        u16::from_be_bytes([self.registers[2], self.registers[3]]) as u32
+    }
+
+    // In T-cycles, the sensor runs at a quarter of the CPU clock
+    fn capture_time(&self) -> usize {
+        let edge_mode_cycles = if self.registers[1] & 0x80 != 0 {
+            0
+        } else {
+            512
+        };
+        (32446 + edge_mode_cycles + 16 * self.exposure() as usize) * 4
+    }
+
+    // Light level after gain and exposure, 0-255
+    fn sensor_value(&self, x: isize, y: isize) -> f32 {
+        let x = x.clamp(0, CAMERA_WIDTH as isize - 1) as usize;
+        let y = y.clamp(0, CAMERA_HEIGHT as isize - 1) as usize;
+        let light = self.image[y * CAMERA_WIDTH + x] as f32;
+        // Gain from about 0.8x to 3.2x
+        let gain = 0.8 * 2f32.powf((self.registers[1] & 0x1F) as f32 / 15.5);
+        light * gain * self.exposure() as f32 / 0x0800 as f32
+    }
+
+    // Runs the image through the sensor processing and stores it as tiles in RAM bank 0
+    fn capture(&mut self) {
+        const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+        let edge_enhancement = self.registers[1] & 0xE0 == 0xE0;
+        let edge_ratio = EDGE_RATIOS[((self.registers[4] >> 4) & 0x07) as usize];
+        let invert = self.registers[4] & 0x08 != 0;
+
+        let mut tiles = vec![0u8; PHOTO_SIZE];
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let (sx, sy) = (x as isize, y as isize);
+                let mut value = self.sensor_value(sx, sy);
+                if edge_enhancement {
+                    let neighbours = self.sensor_value(sx - 1, sy)
+                        + self.sensor_value(sx + 1, sy)
+                        + self.sensor_value(sx, sy - 1)
+                        + self.sensor_value(sx, sy + 1);
+                    value += (value * 4.0 - neighbours) * edge_ratio;
+                }
+                let mut value = value.clamp(0.0, 255.0) as u8;
+                if invert {
+                    value = 255 - value;
+                }
+
+                let matrix = DITHER_MATRIX + ((x & 3) + (y & 3) * 4) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let shade = if value < thresholds[0] {
+                    3
+                } else if value < thresholds[1] {
+                    2
+                } else if value < thresholds[2] {
+                    1
+                } else {
+                    0
+                };
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let index = tile * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                tiles[index] |= (shade & 0x01) << bit;
+                tiles[index + 1] |= ((shade >> 1) & 0x01) << bit;
+            }
+        }
+        self.ram[CAPTURE_ADDRESS..CAPTURE_ADDRESS + PHOTO_SIZE].copy_from_slice(&tiles);
+    }
+
+    // Photos kept in the album, in album order, as 8 bit grayscale
+    pub fn saved_photos(&self) -> Vec<Vec<u8>> {
+        let mut slots: Vec<(u8, usize)> = (0..PHOTO_SLOTS)
+            .filter_map(|slot| {
+                let order = self.ram[ALBUM_INDEX + slot];
+                (order != 0xFF).then_some((order, slot))
+            })
+            .collect();
+        slots.sort();
+        slots
+            .into_iter()
+            .map(|(_, slot)| {
+                let start = PHOTO_START + slot * PHOTO_STRIDE;
+                decode_photo(&self.ram[start..start + PHOTO_SIZE])
+            })
+            .collect()
+    }
+    pub fn saved_photos_png(&self) -> Vec<Vec<u8>> {
+        self.saved_photos()
+            .iter()
+            .map(|photo| png::encode_grayscale(CAMERA_WIDTH as u32, CAMERA_HEIGHT as u32, photo))
+            .collect()
+    }
+}
+
+// 2bpp tiles to 8 bit grayscale
+fn decode_photo(tiles: &[u8]) -> Vec<u8> {
+    const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+    let mut pixels = vec![0; CAMERA_WIDTH * CAMERA_HEIGHT];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % CAMERA_WIDTH, i / CAMERA_WIDTH);
+        let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+        let index = tile * 16 + (y % 8) * 2;
+        let bit = 7 - (x % 8);
+        let shade = (tiles[index] >> bit) & 0x01 | ((tiles[index + 1] >> bit) & 0x01) << 1;
+        *pixel = SHADES[shade as usize];
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Left half black, right half white, through 0.8x gain and 1x exposure
+    fn camera() -> Camera {
+        let mut camera = Camera::new(&vec![0; 4 * 0x4000]);
+        let image: Vec<u8> = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+            .map(|i| if i % CAMERA_WIDTH < 64 { 0x00 } else { 0xFF })
+            .collect();
+        camera.set_image(&image, CAMERA_WIDTH, CAMERA_HEIGHT);
+        camera.write_byte(0x4000, 0x10);
+        camera.write_byte(0xA001, 0x00);
+        camera.write_byte(0xA002, 0x08);
+        camera.write_byte(0xA003, 0x00);
+        for pixel in 0..16 {
+            camera.write_byte(0xA006 + pixel * 3, 0x40);
+            camera.write_byte(0xA007 + pixel * 3, 0x80);
+            camera.write_byte(0xA008 + pixel * 3, 0xC0);
+        }
+        camera
+    }
+
+    // First row of a captured tile
+    fn row(camera: &Camera, tile: usize) -> &[u8] {
+        &camera.ram()[CAPTURE_ADDRESS + tile * 16..][..2]
+    }
+
+    fn capture(camera: &mut Camera) {
+        camera.write_byte(0x4000, 0x10);
+        camera.write_byte(0xA000, 0x01);
+        assert_eq!(camera.read_byte(0xA000), 0x01);
+        camera.write_byte(0x4000, 0x00);
+        assert_eq!(camera.read_byte(0xA100), 0x00);
+        while camera.capturing() {
+            camera.tick();
+        }
+        camera.write_byte(0x4000, 0x10);
+        assert_eq!(camera.read_byte(0xA000), 0x00);
+        camera.write_byte(0x4000, 0x00);
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut camera = camera();
+        assert_eq!(camera.capture_time(), (32446 + 512 + 16 * 0x800) * 4);
+        capture(&mut camera);
+        // Black is shade 3, white over the last threshold shade 0
+        assert_eq!(row(&camera, 0), [0xFF, 0xFF]);
+        assert_eq!(row(&camera, 7), [0xFF, 0xFF]);
+        assert_eq!(row(&camera, 8), [0x00, 0x00]);
+        assert_eq!(row(&camera, 15), [0x00, 0x00]);
+
+        camera.write_byte(0x4000, 0x10);
+        camera.write_byte(0xA004, 0x08);
+        capture(&mut camera);
+        assert_eq!(row(&camera, 0), [0x00, 0x00]);
+        assert_eq!(row(&camera, 8), [0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_ram_needs_enabling_to_write() {
+        let mut camera = camera();
+        camera.write_byte(0x4000, 0x01);
+        camera.write_byte(0xA000, 0x12);
+        assert_eq!(camera.read_byte(0xA000), 0x00);
+        camera.write_byte(0x0000, 0x0A);
+        camera.write_byte(0xA000, 0x12);
+        assert_eq!(camera.read_byte(0xA000), 0x12);
+        assert_eq!(camera.ram()[0x2000], 0x12);
+    }
+
+    #[test]
+    fn test_saved_photos_in_album_order() {
+        let mut camera = camera();
+        capture(&mut camera);
+        let photo = camera.ram()[CAPTURE_ADDRESS..CAPTURE_ADDRESS + PHOTO_SIZE].to_vec();
+        let mut ram = camera.ram().to_vec();
+        ram[ALBUM_INDEX..ALBUM_INDEX + PHOTO_SLOTS].fill(0xFF);
+        // Slot 2 holds the photo and comes first, slot 0 is blank
+        ram[ALBUM_INDEX] = 1;
+        ram[ALBUM_INDEX + 2] = 0;
+        let slot = PHOTO_START + 2 * PHOTO_STRIDE;
+        ram[slot..slot + PHOTO_SIZE].copy_from_slice(&photo);
+        camera.load_ram(&ram);
+
+        let photos = camera.saved_photos();
+        assert_eq!(photos.len(), 2);
+        assert_eq!(photos[0][0], 0x00);
+        assert_eq!(photos[0][CAMERA_WIDTH - 1], 0xFF);
+        assert_eq!(photos[0][(CAMERA_HEIGHT - 1) * CAMERA_WIDTH + 63], 0x00);
+        assert!(photos[1].iter().all(|&pixel| pixel == 0xFF));
+
+        let pngs = camera.saved_photos_png();
+        assert_eq!(pngs.len(), 2);
+        assert_eq!(pngs[0][16..24], [0, 0, 0, 128, 0, 0, 0, 112]);
+    }
+}
@@ -19,6 +19,18 @@ pub enum CartridgeHeader {
     NewLicenseeCodeEnd = 0x145,
 }
 
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// True if the header starting at `base` (the cartridge start or a multicart sub game) has the logo
+pub fn has_nintendo_logo(rom: &[u8], base: usize) -> bool {
+    let start = base + CartridgeHeader::NintendoLogoStart as usize;
+    rom.get(start..start + NINTENDO_LOGO.len()) == Some(&NINTENDO_LOGO[..])
+}
+
 pub fn get_title(cartridge_data: &[u8]) -> String {
     let title_start = CartridgeHeader::TitleStart as usize;
     let title_end = CartridgeHeader::TitleEnd as usize;
@@ -44,6 +56,9 @@ pub fn get_cartridge_type(cartridge_data: &[u8]) -> String {
         0x05 => "MBC2",
         0x06 => "MBC2 + Battery",
         0x08 => "ROM + RAM",
+        0x0B => "MMM01",
+        0x0C => "MMM01 + RAM",
+        0x0D => "MMM01 + RAM + Battery",
         0x09 => "ROM + RAM + Battery",
         0x0F => "MBC3 + Timer + Battery",
         0x10 => "MBC3 + Timer + RAM + Battery",
@@ -59,6 +74,13 @@ pub fn get_cartridge_type(cartridge_data: &[u8]) -> String {
     }
     .to_string()
 }
+// Cartridge types whose RAM is kept alive by a battery
+pub fn has_battery(cartridge_type: u8) -> bool {
+    matches!(
+        cartridge_type,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFE | 0xFF
+    )
+}
 pub fn get_rom_size(cartridge_data: &[u8]) -> String {
     let rom_size_start = CartridgeHeader::ROMsize as usize;
     let rom_size_byte = cartridge_data[rom_size_start - 0x0100];
@@ -119,6 +141,13 @@ pub fn get_sgb_flag(cartridge_data: &[u8]) -> String {
     .to_string()
 }
 
+// The SGB only listens to command packets when the flag is set and the old licensee is 0x33
+pub fn supports_sgb(cartridge_data: &[u8]) -> bool {
+    let sgb_flag_byte = cartridge_data[CartridgeHeader::SGBFlag as usize - 0x100];
+    let old_licensee_code = cartridge_data[CartridgeHeader::OldLicenseeCode as usize - 0x100];
+    sgb_flag_byte == 0x03 && old_licensee_code == 0x33
+}
+
 pub fn get_mask_rom_version(cartridge_data: &[u8]) -> String {
     let rom_version_start = CartridgeHeader::ROMVersion as usize;
     let rom_version_byte = cartridge_data[rom_version_start - 0x100];
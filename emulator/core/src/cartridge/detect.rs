@@ -0,0 +1,193 @@
+use super::{
+    cartridge_header::{has_nintendo_logo, CartridgeHeader},
+    sachen::{self, SachenKind},
+};
+use crate::png::crc32;
+
+// Mappers that don't declare themselves in the cartridge type byte
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnlicensedMapper {
+    Mmm01,
+    WisdomTree,
+    Sachen(SachenKind),
+    Rocket,
+}
+
+// CRC-32 of whole dumps, for carts the heuristics below get wrong
+const KNOWN_ROMS: &[(u32, UnlicensedMapper)] = &[];
+
+pub fn detect_unlicensed_mapper(rom: &[u8]) -> Option<UnlicensedMapper> {
+    detect_with_known_roms(rom, KNOWN_ROMS)
+}
+
+// Known dumps first, otherwise guessed from the header and ROM contents
+fn detect_with_known_roms(
+    rom: &[u8],
+    known_roms: &[(u32, UnlicensedMapper)],
+) -> Option<UnlicensedMapper> {
+    let crc = crc32(rom);
+    if let Some(&(_, mapper)) = known_roms.iter().find(|&&(known, _)| known == crc) {
+        return Some(mapper);
+    }
+    let cartridge_type = rom[CartridgeHeader::CartridgeType as usize];
+    // MMM01 dumps usually start with the first game, the menu and its header are in the last 32KB
+    if rom.len() >= 0x10000 {
+        let menu = rom.len() - 0x8000;
+        let menu_type = rom[menu + CartridgeHeader::CartridgeType as usize];
+        if has_nintendo_logo(rom, menu) && matches!(menu_type, 0x0B..=0x0D) {
+            return Some(UnlicensedMapper::Mmm01);
+        }
+    }
+    if matches!(cartridge_type, 0x0B..=0x0D) {
+        return Some(UnlicensedMapper::Mmm01);
+    }
+    if has_nintendo_logo(rom, 0) {
+        // Wisdom Tree carts claim to be 32KB ROM only
+        let first_banks = &rom[..rom.len().min(0x8000)];
+        if cartridge_type == 0x00 && rom.len() > 0x8000 && contains(first_banks, b"WISDOM") {
+            return Some(UnlicensedMapper::WisdomTree);
+        }
+        return None;
+    }
+    if sachen::has_scrambled_logo(rom) {
+        // Only the MMC2 has the CGB flag set
+        let cgb_flag = rom[sachen::unscramble(0x0143) as usize];
+        let kind = if cgb_flag & 0x80 != 0 {
+            SachenKind::Mmc2
+        } else {
+            SachenKind::Mmc1
+        };
+        return Some(UnlicensedMapper::Sachen(kind));
+    }
+    if contains(&rom[..rom.len().min(0x8000)], b"ROCKET") {
+        return Some(UnlicensedMapper::Rocket);
+    }
+    None
+}
+
+// Some MMM01 dumps put the menu first, the mapper boots from the last 32KB
+pub fn arrange_mmm01_rom(rom: &[u8]) -> Vec<u8> {
+    let menu_first = matches!(rom[CartridgeHeader::CartridgeType as usize], 0x0B..=0x0D);
+    if menu_first && rom.len() > 0x8000 {
+        [&rom[0x8000..], &rom[..0x8000]].concat()
+    } else {
+        rom.to_vec()
+    }
+}
+
+fn contains(data: &[u8], pattern: &[u8]) -> bool {
+    data.windows(pattern.len()).any(|window| window == pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cartridge_header::NINTENDO_LOGO;
+    use super::*;
+    use crate::test_util::write_header;
+
+    fn rom(size: usize, cartridge_type: u8) -> Vec<u8> {
+        let mut rom = vec![0; size];
+        write_header(&mut rom, "", cartridge_type);
+        rom
+    }
+
+    #[test]
+    fn test_licensed_cartridges() {
+        assert_eq!(detect_unlicensed_mapper(&rom(0x8000, 0x00)), None);
+        let mut rom = rom(0x40000, 0x01);
+        rom[0x2000..0x2006].copy_from_slice(b"ROCKET");
+        assert_eq!(detect_unlicensed_mapper(&rom), None);
+    }
+
+    #[test]
+    fn test_mmm01() {
+        // Menu first
+        let menu_first = rom(0x20000, 0x0B);
+        assert_eq!(
+            detect_unlicensed_mapper(&menu_first),
+            Some(UnlicensedMapper::Mmm01)
+        );
+        // Menu last, after a game with an MBC1 header
+        let mut menu_last = rom(0x20000, 0x01);
+        write_header(&mut menu_last[0x18000..], "", 0x0D);
+        assert_eq!(
+            detect_unlicensed_mapper(&menu_last),
+            Some(UnlicensedMapper::Mmm01)
+        );
+
+        let arranged = arrange_mmm01_rom(&menu_first);
+        assert_eq!(arranged[0x18147], 0x0B);
+        assert_eq!(arranged[0x0147], 0x00);
+        assert_eq!(arrange_mmm01_rom(&menu_last), menu_last);
+    }
+
+    #[test]
+    fn test_wisdom_tree() {
+        let mut rom = rom(0x10000, 0x00);
+        rom[0x0150..0x0156].copy_from_slice(b"WISDOM");
+        assert_eq!(
+            detect_unlicensed_mapper(&rom),
+            Some(UnlicensedMapper::WisdomTree)
+        );
+        // A 32KB ROM only game has nothing to switch
+        assert_eq!(detect_unlicensed_mapper(&rom[..0x8000]), None);
+        // and the text has to be in the first 32KB
+        let mut late = vec![0; 0x10000];
+        late[..0x8000].copy_from_slice(&rom[..0x8000]);
+        late[0x0150] = 0;
+        late[0x9000..0x9006].copy_from_slice(b"WISDOM");
+        assert_eq!(detect_unlicensed_mapper(&late), None);
+    }
+
+    #[test]
+    fn test_sachen() {
+        let mut rom = vec![0; 0x10000];
+        for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom[sachen::unscramble(0x0184 + i as u16) as usize] = byte;
+        }
+        assert_eq!(
+            detect_unlicensed_mapper(&rom),
+            Some(UnlicensedMapper::Sachen(SachenKind::Mmc1))
+        );
+        rom[sachen::unscramble(0x0143) as usize] = 0x80;
+        assert_eq!(
+            detect_unlicensed_mapper(&rom),
+            Some(UnlicensedMapper::Sachen(SachenKind::Mmc2))
+        );
+    }
+
+    #[test]
+    fn test_rocket() {
+        let mut rom = vec![0; 0x20000];
+        rom[0x0104..0x010A].copy_from_slice(b"ROCKET");
+        assert_eq!(
+            detect_unlicensed_mapper(&rom),
+            Some(UnlicensedMapper::Rocket)
+        );
+        rom[0x0104..0x010A].fill(0);
+        assert_eq!(detect_unlicensed_mapper(&rom), None);
+    }
+
+    #[test]
+    fn test_known_rom_beats_the_heuristics() {
+        // Looks like a Wisdom Tree cart
+        let mut wisdom = rom(0x10000, 0x00);
+        wisdom[0x0150..0x0156].copy_from_slice(b"WISDOM");
+        let known_roms = [
+            (0x12345678, UnlicensedMapper::Mmm01),
+            (crc32(&wisdom), UnlicensedMapper::Rocket),
+        ];
+        assert_eq!(
+            detect_with_known_roms(&wisdom, &known_roms),
+            Some(UnlicensedMapper::Rocket)
+        );
+        // A licensed header is no reason to skip the lookup
+        let licensed = rom(0x8000, 0x01);
+        assert_eq!(
+            detect_with_known_roms(&licensed, &[(crc32(&licensed), UnlicensedMapper::Mmm01)]),
+            Some(UnlicensedMapper::Mmm01)
+        );
+        wisdom[0x0150] = 0;
+        assert_eq!(detect_with_known_roms(&wisdom, &known_roms), None);
+    }
+}
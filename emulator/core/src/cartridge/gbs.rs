@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+
+/*
+GBS file layout (https://ocremix.org/info/GBS_Format_Specification)
+0x00  3   "GBS"
+0x03  1   Version (1)
+0x04  1   Number of songs
+0x05  1   First song (1 based)
+0x06  2   Load address
+0x08  2   Init address
+0x0A  2   Play address
+0x0C  2   Stack pointer
+0x0E  1   Timer modulo
+0x0F  1   Timer control
+0x10  32  Title
+0x30  32  Author
+0x50  32  Copyright
+0x70      Code and data, loaded at the load address
+*/
+pub const GBS_HEADER_SIZE: usize = 0x70;
+
+// Synthesized code below the load address: CALL init, then wait for interrupts forever
+pub const GBS_DRIVER_ADDRESS: u16 = 0x0100;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GbsHeader {
+    pub version: u8,
+    pub song_count: u8,
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl GbsHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < GBS_HEADER_SIZE || &data[0..3] != b"GBS" {
+            return Err("Not a GBS file".to_string());
+        }
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let text = |offset: usize| {
+            let field = &data[offset..offset + 32];
+            let end = field.iter().position(|&c| c == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).trim().to_string()
+        };
+
+        let header = Self {
+            version: data[0x03],
+            song_count: data[0x04],
+            first_song: data[0x05],
+            load_address: word(0x06),
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+            title: text(0x10),
+            author: text(0x30),
+            copyright: text(0x50),
+        };
+
+        if header.version != 1 {
+            return Err(format!("Unsupported GBS version {}", header.version));
+        }
+        if header.song_count == 0 {
+            return Err("GBS file has no songs".to_string());
+        }
+        // The driver lives below the load address
+        if header.load_address < GBS_DRIVER_ADDRESS + 8 || header.load_address >= 0x8000 {
+            return Err(format!(
+                "Unsupported GBS load address {:04X}",
+                header.load_address
+            ));
+        }
+        Ok(header)
+    }
+
+    // PLAY is called from the timer interrupt instead of VBlank
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Gbs {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    current_rom_bank: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GbsState {
+    ram: Vec<u8>,
+    current_rom_bank: usize,
+}
+
+impl Gbs {
+    /*
+    The GBS data is placed at its load address in a flat ROM image.
+    ROM BANK: 0x0000-0x3FFF fixed, 0x4000-0x7FFF switched by writes to 0x2000-0x3FFF
+    RAM BANK: 1 bank 8KB
+    Memory below the load address is filled with:
+    RST vectors  -> JP load address + vector
+    VBlank/Timer -> CALL play, RETI (depending on the timer control)
+    0x0100       -> CALL init, EI, HALT, JR back to HALT
+     */
+    pub fn new(header: &GbsHeader, data: &[u8]) -> Self {
+        let code = &data[GBS_HEADER_SIZE..];
+        let load_address = header.load_address as usize;
+        let size = (load_address + code.len()).div_ceil(0x4000).max(2) * 0x4000;
+
+        let mut rom = vec![0xFF; size];
+        rom[load_address..load_address + code.len()].copy_from_slice(code);
+
+        // Interrupt vectors return right away unless used for PLAY
+        for vector in (0x40..=0x60).step_by(8) {
+            rom[vector] = 0xD9; // RETI
+        }
+        // RST vectors are relocated to the load address
+        for vector in (0x00..=0x38).step_by(8) {
+            let target = header.load_address + vector as u16;
+            rom[vector..vector + 3].copy_from_slice(&[0xC3, target as u8, (target >> 8) as u8]);
+        }
+        let play_vector = if header.uses_timer() { 0x50 } else { 0x40 };
+        let [play_low, play_high] = header.play_address.to_le_bytes();
+        rom[play_vector..play_vector + 4].copy_from_slice(&[0xCD, play_low, play_high, 0xD9]);
+
+        let driver = GBS_DRIVER_ADDRESS as usize;
+        let [init_low, init_high] = header.init_address.to_le_bytes();
+        rom[driver..driver + 8].copy_from_slice(&[
+            0xCD, init_low, init_high, // CALL init
+            0xFB,      // EI
+            0x76,      // HALT
+            0x00,      // NOP
+            0x18, 0xFC, // JR -4 (HALT)
+        ]);
+
+        Self {
+            rom,
+            ram: vec![0; 0x2000],
+            current_rom_bank: 1,
+        }
+    }
+
+    pub fn save_state(&self) -> GbsState {
+        GbsState {
+            ram: self.ram.clone(),
+            current_rom_bank: self.current_rom_bank,
+        }
+    }
+    pub fn load_state(&mut self, state: GbsState) {
+        self.ram = state.ram;
+        self.current_rom_bank = state.current_rom_bank;
+    }
+
+    // Fresh state for a new song
+    pub fn reset(&mut self) {
+        self.ram.fill(0);
+        self.current_rom_bank = 1;
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank,
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let offset = self.current_rom_bank * 0x4000 + (address as usize - 0x4000);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
+            0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize],
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x2000..=0x3FFF => {
+                let bank_count = self.rom.len() / 0x4000;
+                self.current_rom_bank = (value as usize).max(1) % bank_count;
+            }
+            0xA000..=0xBFFF => self.ram[(address - 0xA000) as usize] = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gbs_file(load_address: u16, code: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; GBS_HEADER_SIZE];
+        data[0..6].copy_from_slice(&[b'G', b'B', b'S', 1, 3, 2]);
+        data[0x06..0x08].copy_from_slice(&load_address.to_le_bytes());
+        data[0x08..0x0A].copy_from_slice(&0x0420u16.to_le_bytes());
+        data[0x0A..0x0C].copy_from_slice(&0x0430u16.to_le_bytes());
+        data[0x0C..0x0E].copy_from_slice(&0xDFFEu16.to_le_bytes());
+        data[0x0E] = 0xC0;
+        data[0x0F] = 0x04;
+        data[0x10..0x15].copy_from_slice(b"Title");
+        data[0x30..0x36].copy_from_slice(b"Author");
+        // Padded with spaces instead of zeros
+        data[0x50..0x70].copy_from_slice(&[b' '; 32]);
+        data[0x50..0x54].copy_from_slice(b"2024");
+        data.extend_from_slice(code);
+        data
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = GbsHeader::parse(&gbs_file(0x0400, &[])).unwrap();
+        assert_eq!(header.song_count, 3);
+        assert_eq!(header.first_song, 2);
+        assert_eq!(header.load_address, 0x0400);
+        assert_eq!(header.init_address, 0x0420);
+        assert_eq!(header.play_address, 0x0430);
+        assert_eq!(header.stack_pointer, 0xDFFE);
+        assert_eq!(header.timer_modulo, 0xC0);
+        assert!(header.uses_timer());
+        assert_eq!(header.title, "Title");
+        assert_eq!(header.author, "Author");
+        assert_eq!(header.copyright, "2024");
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_headers() {
+        assert!(GbsHeader::parse(b"GBS").is_err());
+
+        let mut data = gbs_file(0x0400, &[]);
+        data[0] = b'X';
+        assert_eq!(GbsHeader::parse(&data).unwrap_err(), "Not a GBS file");
+
+        let mut data = gbs_file(0x0400, &[]);
+        data[0x03] = 2;
+        assert!(GbsHeader::parse(&data).unwrap_err().contains("version 2"));
+
+        let mut data = gbs_file(0x0400, &[]);
+        data[0x04] = 0;
+        assert!(GbsHeader::parse(&data).is_err());
+
+        // No room for the driver below the code, or code outside ROM
+        assert!(GbsHeader::parse(&gbs_file(0x0104, &[])).is_err());
+        assert!(GbsHeader::parse(&gbs_file(0x8000, &[])).is_err());
+    }
+
+    #[test]
+    fn test_rom_layout() {
+        let data = gbs_file(0x0400, &[0xAA, 0xBB]);
+        let header = GbsHeader::parse(&data).unwrap();
+        let gbs = Gbs::new(&header, &data);
+
+        assert_eq!(gbs.read_byte(0x0400), 0xAA);
+        assert_eq!(gbs.read_byte(0x0401), 0xBB);
+        // RST 38 jumps to load address + 38
+        assert_eq!(
+            [gbs.read_byte(0x38), gbs.read_byte(0x39), gbs.read_byte(0x3A)],
+            [0xC3, 0x38, 0x04]
+        );
+        // The header asks for the timer, so PLAY is called from its vector
+        assert_eq!(
+            [0x50, 0x51, 0x52, 0x53].map(|address| gbs.read_byte(address)),
+            [0xCD, 0x30, 0x04, 0xD9]
+        );
+        assert_eq!(gbs.read_byte(0x40), 0xD9);
+        assert_eq!(
+            [0x100, 0x101, 0x102].map(|address| gbs.read_byte(address)),
+            [0xCD, 0x20, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_bank_switching() {
+        // Code running into a third bank
+        let mut code = vec![0; 0x8000];
+        code[0x4000 - 0x400] = 0x11;
+        code[0x8000 - 0x400] = 0x22;
+        let data = gbs_file(0x0400, &code);
+        let header = GbsHeader::parse(&data).unwrap();
+        let mut gbs = Gbs::new(&header, &data);
+
+        assert_eq!(gbs.read_byte(0x4000), 0x11);
+        gbs.write_byte(0x2000, 2);
+        assert_eq!(gbs.read_byte(0x4000), 0x22);
+        assert_eq!(gbs.rom_bank(0x4000), 2);
+        // Bank 0 maps bank 1
+        gbs.write_byte(0x2000, 0);
+        assert_eq!(gbs.rom_bank(0x4000), 1);
+
+        gbs.write_byte(0xA123, 0x5A);
+        assert_eq!(gbs.read_byte(0xA123), 0x5A);
+        gbs.reset();
+        assert_eq!(gbs.read_byte(0xA123), 0x00);
+    }
+}
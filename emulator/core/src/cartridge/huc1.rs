@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct Huc1 {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    infrared_selected: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Huc1State {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram: Vec<u8>,
+    infrared_selected: bool,
+}
+
+impl Huc1 {
+    /*
+    ROM BANK: Up to 64 banks of 16KB
+    RAM BANK: Up to 4 banks of 8KB, always enabled
+    0000-1FFF: 0x0E maps the IR port at A000-BFFF, anything else maps RAM
+     */
+    pub fn new(rom: &[u8], ram_size: usize) -> Self {
+        Self {
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            rom: rom.to_vec(),
+            ram: vec![0; ram_size],
+            infrared_selected: false,
+        }
+    }
+
+    pub fn save_state(&self) -> Huc1State {
+        Huc1State {
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram: self.ram.clone(),
+            infrared_selected: self.infrared_selected,
+        }
+    }
+    pub fn load_state(&mut self, state: Huc1State) {
+        self.current_rom_bank = state.current_rom_bank;
+        self.current_ram_bank = state.current_ram_bank;
+        self.ram = state.ram;
+        self.infrared_selected = state.infrared_selected;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    // A000-BFFF goes to the IR port, handled by the bus
+    pub fn infrared_selected(&self) -> bool {
+        self.infrared_selected
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        let offset = 0x2000 * self.current_ram_bank as usize + (address - 0xA000) as usize;
+        (!self.ram.is_empty()).then(|| offset % self.ram.len())
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.infrared_selected = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.current_rom_bank = (value & 0x3F).max(1),
+            0x4000..=0x5FFF => self.current_ram_bank = value & 0x03,
+            0xA000..=0xBFFF => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    #[test]
+    fn test_banking() {
+        let mut huc1 = Huc1::new(&banked_rom(64), 0x8000);
+        huc1.write_byte(0x2000, 0x3F);
+        assert_eq!(huc1.read_byte(0x4000), 0x3F);
+        huc1.write_byte(0x2000, 0x40);
+        assert_eq!(huc1.read_byte(0x4000), 1);
+
+        // RAM needs no enable
+        huc1.write_byte(0x4000, 0x03);
+        huc1.write_byte(0xA000, 0x12);
+        assert_eq!(huc1.ram()[0x6000], 0x12);
+        huc1.write_byte(0x4000, 0x00);
+        assert_eq!(huc1.read_byte(0xA000), 0x00);
+    }
+
+    #[test]
+    fn test_infrared_select() {
+        let mut huc1 = Huc1::new(&vec![0; 0x8000], 0x2000);
+        huc1.write_byte(0x0000, 0x0E);
+        assert!(huc1.infrared_selected());
+        huc1.write_byte(0x0000, 0x0A);
+        assert!(!huc1.infrared_selected());
+    }
+}
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+
+const CYCLES_PER_MINUTE: usize = 4_194_304 * 60;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+// Minutes and days as u16, then the 256 nibbles of RTC memory
+const RTC_SAVE_SIZE: usize = 4 + 0x100;
+
+/*
+The HuC3 RTC is a separate chip talked to with 1 byte commands,
+bits 4-6 command and bits 0-3 argument:
+1  read memory[address] into the response, address + 1
+3  write argument to memory[address], address + 1
+4  set address low nibble
+5  set address high nibble
+6  extended: 0 copy time to memory 00-06, 1 set time from memory 00-06,
+   2 status, E play the tone selected in memory 27
+Time in memory: 00-02 minutes of the day, 03-05 days, nibbles LSB first
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Huc3Rtc {
+    minutes: u16,
+    days: u16,
+    cycles: usize,
+    memory: Vec<u8>,
+    address: u8,
+    response: u8,
+}
+
+impl Huc3Rtc {
+    fn new() -> Self {
+        Self {
+            minutes: 0,
+            days: 0,
+            cycles: 0,
+            memory: vec![0; 0x100],
+            address: 0,
+            response: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles < CYCLES_PER_MINUTE {
+            return;
+        }
+        self.cycles = 0;
+        self.minutes += 1;
+        if self.minutes == MINUTES_PER_DAY {
+            self.minutes = 0;
+            self.days = (self.days + 1) & 0x0FFF;
+        }
+    }
+
+    // Returns the tone to play, if any
+    fn command(&mut self, value: u8) -> Option<u8> {
+        let argument = value & 0x0F;
+        let command = (value >> 4) & 0x07;
+        let mut response = argument;
+        match command {
+            0x1 => {
+                response = self.memory[self.address as usize] & 0x0F;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = self.address & 0xF0 | argument,
+            0x5 => self.address = self.address & 0x0F | argument << 4,
+            0x6 => match argument {
+                0x0 => {
+                    for i in 0..3 {
+                        self.memory[i] = (self.minutes >> (i * 4)) as u8 & 0x0F;
+                        self.memory[3 + i] = (self.days >> (i * 4)) as u8 & 0x0F;
+                    }
+                    self.memory[6] = 0;
+                }
+                0x1 => {
+                    let nibbles = |start: usize| {
+                        (0..3).fold(0u16, |value, i| {
+                            value | ((self.memory[start + i] as u16 & 0x0F) << (i * 4))
+                        })
+                    };
+                    self.minutes = nibbles(0) % MINUTES_PER_DAY;
+                    self.days = nibbles(3);
+                    self.cycles = 0;
+                }
+                // Ready
+                0x2 => response = 0x01,
+                0xE => {
+                    self.response = command << 4 | response;
+                    return Some(self.memory[0x27] & 0x0F);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        self.response = command << 4 | response;
+        None
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+        data.extend_from_slice(&self.minutes.to_le_bytes());
+        data.extend_from_slice(&self.days.to_le_bytes());
+        data.extend_from_slice(&self.memory);
+        data
+    }
+    fn load(&mut self, data: &[u8]) {
+        if data.len() < RTC_SAVE_SIZE {
+            return;
+        }
+        self.minutes = u16::from_le_bytes([data[0], data[1]]) % MINUTES_PER_DAY;
+        self.days = u16::from_le_bytes([data[2], data[3]]) & 0x0FFF;
+        self.memory.copy_from_slice(&data[4..RTC_SAVE_SIZE]);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Huc3 {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mode: u8,
+    pub rtc: Huc3Rtc,
+    // Tone requested by the game, taken by the host
+    tone: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Huc3State {
+    current_rom_bank: u8,
+    current_ram_bank: u8,
+    ram: Vec<u8>,
+    mode: u8,
+    rtc: Huc3Rtc,
+}
+
+impl Huc3 {
+    /*
+    ROM BANK: Up to 128 banks of 16KB
+    RAM BANK: Up to 4 banks of 8KB
+    0000-1FFF selects what A000-BFFF maps:
+    0x0 RAM read only   0xA RAM read/write
+    0xB RTC command     0xC RTC response
+    0xD RTC semaphore   0xE IR port (handled by the bus)
+     */
+    pub fn new(rom: &[u8], ram_size: usize) -> Self {
+        Self {
+            current_rom_bank: 1,
+            current_ram_bank: 0,
+            rom: rom.to_vec(),
+            ram: vec![0; ram_size],
+            mode: 0,
+            rtc: Huc3Rtc::new(),
+            tone: None,
+        }
+    }
+
+    pub fn save_state(&self) -> Huc3State {
+        Huc3State {
+            current_rom_bank: self.current_rom_bank,
+            current_ram_bank: self.current_ram_bank,
+            ram: self.ram.clone(),
+            mode: self.mode,
+            rtc: self.rtc.clone(),
+        }
+    }
+    pub fn load_state(&mut self, state: Huc3State) {
+        self.current_rom_bank = state.current_rom_bank;
+        self.current_ram_bank = state.current_ram_bank;
+        self.ram = state.ram;
+        self.mode = state.mode;
+        self.rtc = state.rtc;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    // Appended to the RAM in battery saves
+    pub fn rtc_save(&self) -> Vec<u8> {
+        self.rtc.save()
+    }
+    pub fn load_rtc_save(&mut self, data: &[u8]) {
+        self.rtc.load(data);
+    }
+    pub fn infrared_selected(&self) -> bool {
+        self.mode == 0x0E
+    }
+    pub fn take_tone(&mut self) -> Option<u8> {
+        self.tone.take()
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        let offset = 0x2000 * self.current_ram_bank as usize + (address - 0xA000) as usize;
+        (!self.ram.is_empty()).then(|| offset % self.ram.len())
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => match self.mode {
+                0x00 | 0x0A => match self.ram_offset(address) {
+                    Some(offset) => self.ram[offset],
+                    None => 0xFF,
+                },
+                0x0C => 0x80 | self.rtc.response,
+                // Commands complete right away
+                0x0D => 0xFF,
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.current_rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.current_ram_bank = value & 0x03,
+            0xA000..=0xBFFF => match self.mode {
+                0x0A => {
+                    if let Some(offset) = self.ram_offset(address) {
+                        self.ram[offset] = value;
+                    }
+                }
+                0x0B => {
+                    if let Some(tone) = self.rtc.command(value) {
+                        self.tone = Some(tone);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    fn huc3() -> Huc3 {
+        Huc3::new(&banked_rom(8), 0x8000)
+    }
+
+    // Sends an RTC command and reads back the response
+    fn command(huc3: &mut Huc3, value: u8) -> u8 {
+        huc3.write_byte(0x0000, 0x0B);
+        huc3.write_byte(0xA000, value);
+        huc3.write_byte(0x0000, 0x0C);
+        huc3.read_byte(0xA000)
+    }
+
+    fn set_address(huc3: &mut Huc3, address: u8) {
+        command(huc3, 0x40 | address & 0x0F);
+        command(huc3, 0x50 | address >> 4);
+    }
+
+    fn write_nibbles(huc3: &mut Huc3, address: u8, nibbles: &[u8]) {
+        set_address(huc3, address);
+        for &nibble in nibbles {
+            command(huc3, 0x30 | nibble);
+        }
+    }
+
+    fn read_nibbles(huc3: &mut Huc3, address: u8, count: usize) -> Vec<u8> {
+        set_address(huc3, address);
+        (0..count).map(|_| command(huc3, 0x10) & 0x0F).collect()
+    }
+
+    #[test]
+    fn test_rom_and_ram_banking() {
+        let mut huc3 = huc3();
+        huc3.write_byte(0x2000, 0x05);
+        assert_eq!(huc3.read_byte(0x4000), 5);
+        huc3.write_byte(0x2000, 0x00);
+        assert_eq!(huc3.read_byte(0x4000), 1);
+
+        // 0x0 maps RAM read only, 0xA read/write
+        huc3.write_byte(0x4000, 0x02);
+        huc3.write_byte(0xA000, 0x12);
+        assert_eq!(huc3.read_byte(0xA000), 0x00);
+        huc3.write_byte(0x0000, 0x0A);
+        huc3.write_byte(0xA000, 0x12);
+        huc3.write_byte(0x0000, 0x00);
+        assert_eq!(huc3.read_byte(0xA000), 0x12);
+        assert_eq!(huc3.ram()[0x4000], 0x12);
+
+        huc3.write_byte(0x0000, 0x0D);
+        assert_eq!(huc3.read_byte(0xA000), 0xFF);
+        huc3.write_byte(0x0000, 0x0E);
+        assert!(huc3.infrared_selected());
+    }
+
+    #[test]
+    fn test_rtc_memory() {
+        let mut huc3 = huc3();
+        write_nibbles(&mut huc3, 0x3E, &[0x1, 0x2, 0x3]);
+        assert_eq!(read_nibbles(&mut huc3, 0x3E, 3), [0x1, 0x2, 0x3]);
+        // The response echoes the command
+        assert_eq!(command(&mut huc3, 0x4A), 0xCA);
+        assert_eq!(command(&mut huc3, 0x62), 0xE1);
+    }
+
+    #[test]
+    fn test_rtc_time() {
+        let mut huc3 = huc3();
+        // 23:59 on day $123
+        write_nibbles(&mut huc3, 0x00, &[0xF, 0x9, 0x5, 0x3, 0x2, 0x1]);
+        command(&mut huc3, 0x61);
+        huc3.rtc.cycles = CYCLES_PER_MINUTE - 1;
+        huc3.rtc.tick();
+
+        write_nibbles(&mut huc3, 0x00, &[0; 6]);
+        command(&mut huc3, 0x60);
+        assert_eq!(
+            read_nibbles(&mut huc3, 0x00, 6),
+            [0x0, 0x0, 0x0, 0x4, 0x2, 0x1]
+        );
+
+        // Kept in the battery save
+        let save = huc3.rtc_save();
+        assert_eq!(save.len(), RTC_SAVE_SIZE);
+        let mut other = self::huc3();
+        other.load_rtc_save(&save);
+        assert_eq!(other.rtc.minutes, 0);
+        assert_eq!(other.rtc.days, 0x124);
+        assert_eq!(other.rtc.memory, huc3.rtc.memory);
+    }
+
+    #[test]
+    fn test_tone() {
+        let mut huc3 = huc3();
+        write_nibbles(&mut huc3, 0x27, &[0x3]);
+        assert_eq!(huc3.take_tone(), None);
+        command(&mut huc3, 0x6E);
+        assert_eq!(huc3.take_tone(), Some(3));
+        assert_eq!(huc3.take_tone(), None);
+    }
+}
@@ -30,6 +30,19 @@ impl Mbc0 {
         self.ram = state.ram;
     }
 
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    pub fn rom_bank(&self, address: u16) -> usize {
+        address as usize / 0x4000
+    }
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x7FFF => self.rom[address as usize],
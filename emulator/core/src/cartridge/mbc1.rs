@@ -1,4 +1,17 @@
 use serde::{Deserialize, Serialize};
+
+use super::cartridge_header::{get_title, has_nintendo_logo};
+
+// MBC1M games start every 256 KiB
+const MULTICART_GAME_SIZE: usize = 0x40000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MulticartGame {
+    // First ROM bank of the game
+    pub bank: u8,
+    pub title: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct Mbc1 {
     rom: Vec<u8>,
@@ -8,6 +21,7 @@ pub struct Mbc1 {
     mode: bool,
     ram_enabled: bool,
     is_multicart: bool,
+    multicart_games: Vec<MulticartGame>,
 }
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Mbc1State {
@@ -23,10 +37,13 @@ impl Mbc1 {
     /*
     ROM BANK: Up to 128 banks of 16KB
     RAM BANK: Up to 4 banks of 8KB
-    TODO: maybe MBC1M
+    MBC1M: 1 MiB multicarts wire the upper bank bits at bit 4 instead of bit 5,
+    so each 256 KiB game sees 16 banks. Detected by the Nintendo logo at the
+    start of more than one 256 KiB block.
     TODO: pass mooneye
      */
     pub fn new(rom: &[u8], ram_size: usize) -> Self {
+        let multicart_games = find_multicart_games(rom);
         Self {
             rom: rom.to_vec(),
             ram: vec![0; ram_size],
@@ -34,9 +51,17 @@ impl Mbc1 {
             current_ram_bank: 0,
             ram_enabled: false,
             mode: false,
-            is_multicart: false,
+            is_multicart: !multicart_games.is_empty(),
+            multicart_games,
         }
     }
+    pub fn is_multicart(&self) -> bool {
+        self.is_multicart
+    }
+    // Games found in a MBC1M compilation, empty for regular cartridges
+    pub fn multicart_games(&self) -> &[MulticartGame] {
+        &self.multicart_games
+    }
     pub fn save_state(&self) -> Mbc1State {
         Mbc1State {
             ram: self.ram.clone(),
@@ -53,22 +78,31 @@ impl Mbc1 {
         self.current_ram_bank = state.current_ram_bank;
         self.mode = state.mode;
         self.ram_enabled = state.ram_enabled;
-        self.is_multicart = state.is_multicart;
+        // The wiring comes from the ROM, states from before MBC1M detection say false
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            // Mode 1 can map a bank other than 0 at 0x0000 on large cartridges
+            0x0000..=0x3FFF if self.mode => self.get_zero_bank_number() as usize,
+            0x0000..=0x3FFF => 0,
+            _ => self.get_high_bank_number() as usize,
+        }
     }
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => {
-                if !self.mode {
-                    self.rom[address as usize]
-                } else {
-                    let zero_bank_number = self.get_zero_bank_number();
-                    self.rom[0x4000 * zero_bank_number as usize + address as usize]
-                }
-            }
+            0x0000..=0x3FFF => self.rom[0x4000 * self.rom_bank(address) + address as usize],
             0x4000..=0x7FFF => {
-                let high_bank_number = self.get_high_bank_number();
-
-                self.rom[0x4000 * high_bank_number as usize + (address - 0x4000) as usize]
+                self.rom[0x4000 * self.rom_bank(address) + (address - 0x4000) as usize]
             }
             0xA000..=0xBFFF => {
                 if !self.ram_enabled {
@@ -96,6 +130,11 @@ impl Mbc1 {
                 // enable ram
                 self.ram_enabled = value & 0x0F == 0xA;
             }
+            0x2000..=0x3FFF if self.is_multicart => {
+                // the zero check still sees all 5 bits, bit 4 is not wired to the ROM
+                let new_rom_bank_number = value & 0x1F;
+                self.current_rom_bank = new_rom_bank_number.max(1);
+            }
             0x2000..=0x3FFF => {
                 // select rom bank
                 let number_of_rom_banks = self.get_number_of_rom_banks();
@@ -139,6 +178,9 @@ impl Mbc1 {
         }
     }
     fn get_high_bank_number(&self) -> u8 {
+        if self.is_multicart {
+            return ((self.current_ram_bank & 0x03) << 4) | (self.current_rom_bank & 0x0F);
+        }
         let base_number =
             self.current_rom_bank & get_bit_mask(self.get_number_of_rom_banks() as u8);
         match self.get_number_of_rom_banks() {
@@ -160,6 +202,9 @@ impl Mbc1 {
     }
 
     fn get_zero_bank_number(&self) -> u8 {
+        if self.is_multicart {
+            return (self.current_ram_bank & 0x03) << 4;
+        }
         let number = 0b00000000;
         let number_of_rom_banks = self.get_number_of_rom_banks();
 
@@ -184,6 +229,26 @@ impl Mbc1 {
     }
 }
 
+fn find_multicart_games(rom: &[u8]) -> Vec<MulticartGame> {
+    if rom.len() != MULTICART_GAME_SIZE * 4 {
+        return Vec::new();
+    }
+    let games: Vec<MulticartGame> = (0..4)
+        .map(|game| game * MULTICART_GAME_SIZE)
+        .filter(|&base| has_nintendo_logo(rom, base))
+        .map(|base| MulticartGame {
+            bank: (base / 0x4000) as u8,
+            title: get_title(&rom[base + 0x100..]),
+        })
+        .collect();
+    // A regular 1 MiB game only has the logo in bank 0
+    if games.len() > 1 {
+        games
+    } else {
+        Vec::new()
+    }
+}
+
 fn get_bit_mask(number_rom_banks: u8) -> u8 {
     match number_rom_banks {
         128 => 0b00011111,
@@ -196,3 +261,112 @@ fn get_bit_mask(number_rom_banks: u8) -> u8 {
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{banked_rom, write_header};
+
+    // Bank 0 of every listed game has a header
+    fn rom(banks: usize, games: &[(usize, &str)]) -> Vec<u8> {
+        let mut rom = banked_rom(banks);
+        for &(base, title) in games {
+            write_header(&mut rom[base..], title, 0x01);
+        }
+        rom
+    }
+
+    #[test]
+    fn test_large_rom_banking() {
+        let mut mbc = Mbc1::new(&rom(128, &[(0, "BIG")]), 0);
+        assert!(!mbc.is_multicart());
+        mbc.write_byte(0x2000, 0x00);
+        assert_eq!(mbc.read_byte(0x4000), 1);
+        mbc.write_byte(0x2000, 0x1F);
+        assert_eq!(mbc.read_byte(0x4000), 0x1F);
+        // The RAM bank register is bits 5-6 of the ROM bank
+        mbc.write_byte(0x4000, 0x03);
+        assert_eq!(mbc.read_byte(0x4000), 0x7F);
+        assert_eq!(mbc.read_byte(0x0000), 0);
+        mbc.write_byte(0x6000, 0x01);
+        assert_eq!(mbc.read_byte(0x0000), 0x60);
+        // Bank 20 can't be selected, 21 is mapped instead
+        mbc.write_byte(0x4000, 0x01);
+        mbc.write_byte(0x2000, 0x00);
+        assert_eq!(mbc.read_byte(0x4000), 0x21);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = Mbc1::new(&rom(4, &[]), 0x8000);
+        mbc.write_byte(0xA000, 0x12);
+        assert_eq!(mbc.read_byte(0xA000), 0xFF);
+        mbc.write_byte(0x0000, 0x0A);
+        mbc.write_byte(0x6000, 0x01);
+        for bank in 0..4 {
+            mbc.write_byte(0x4000, bank);
+            mbc.write_byte(0xA123, 0x10 + bank);
+        }
+        mbc.write_byte(0x4000, 0x02);
+        assert_eq!(mbc.read_byte(0xA123), 0x12);
+        // Mode 0 always maps RAM bank 0
+        mbc.write_byte(0x6000, 0x00);
+        assert_eq!(mbc.read_byte(0xA123), 0x10);
+        assert_eq!(mbc.ram()[0x6123], 0x13);
+    }
+
+    #[test]
+    fn test_multicart_detection() {
+        let games = [(0, "MENU"), (0x40000, "GAME A"), (0xC0000, "GAME C")];
+        let mbc = Mbc1::new(&rom(64, &games), 0);
+        assert!(mbc.is_multicart());
+        let found: Vec<(u8, &str)> = mbc
+            .multicart_games()
+            .iter()
+            .map(|game| (game.bank, game.title.as_str()))
+            .collect();
+        assert_eq!(found, [(0x00, "MENU"), (0x10, "GAME A"), (0x30, "GAME C")]);
+
+        // A regular 1 MiB game only has a header in bank 0
+        assert!(!Mbc1::new(&rom(64, &[(0, "MENU")]), 0).is_multicart());
+        // and only 1 MiB cartridges are wired as MBC1M
+        let mut rom = rom(64, &games);
+        rom.truncate(0x80000);
+        assert!(!Mbc1::new(&rom, 0).is_multicart());
+    }
+
+    #[test]
+    fn test_multicart_banking() {
+        let games = [(0, "MENU"), (0x40000, "GAME A")];
+        let mut mbc = Mbc1::new(&rom(64, &games), 0);
+        // The upper bits start at bit 4, so each game sees 16 banks
+        mbc.write_byte(0x4000, 0x01);
+        mbc.write_byte(0x2000, 0x03);
+        assert_eq!(mbc.read_byte(0x4000), 0x13);
+        assert_eq!(mbc.rom_bank(0x4000), 0x13);
+        // Bit 4 isn't wired to the ROM but still counts for the zero check
+        mbc.write_byte(0x2000, 0x10);
+        assert_eq!(mbc.read_byte(0x4000), 0x10);
+        mbc.write_byte(0x2000, 0x00);
+        assert_eq!(mbc.read_byte(0x4000), 0x11);
+
+        assert_eq!(mbc.read_byte(0x0000), 0x00);
+        mbc.write_byte(0x6000, 0x01);
+        mbc.write_byte(0x4000, 0x03);
+        assert_eq!(mbc.read_byte(0x0000), 0x30);
+        assert_eq!(mbc.rom_bank(0x0000), 0x30);
+    }
+
+    #[test]
+    fn test_load_state_keeps_the_multicart_wiring() {
+        let games = [(0, "MENU"), (0x40000, "GAME A")];
+        let mut mbc = Mbc1::new(&rom(64, &games), 0);
+        let regular = Mbc1::new(&rom(64, &[(0, "MENU")]), 0);
+        mbc.load_state(regular.save_state());
+        assert!(mbc.is_multicart());
+        assert_eq!(mbc.multicart_games().len(), 2);
+        mbc.write_byte(0x4000, 0x01);
+        mbc.write_byte(0x2000, 0x03);
+        assert_eq!(mbc.read_byte(0x4000), 0x13);
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct Mbc2 {
+    current_rom_bank: u8,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    external_ram_enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Mbc2State {
+    current_rom_bank: u8,
+    ram: Vec<u8>,
+    external_ram_enabled: bool,
+}
+
+impl Mbc2 {
+    /*
+    ROM BANK: Up to 16 banks of 16KB
+    RAM: 512 x 4 bits built into the MBC, echoed across A000-BFFF
+    0000-3FFF writes: address bit 8 clear -> RAM enable, set -> ROM bank
+     */
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            current_rom_bank: 1,
+            rom: rom.to_vec(),
+            ram: vec![0; 0x200],
+            external_ram_enabled: false,
+        }
+    }
+
+    pub fn save_state(&self) -> Mbc2State {
+        Mbc2State {
+            current_rom_bank: self.current_rom_bank,
+            ram: self.ram.clone(),
+            external_ram_enabled: self.external_ram_enabled,
+        }
+    }
+    pub fn load_state(&mut self, state: Mbc2State) {
+        self.current_rom_bank = state.current_rom_bank;
+        self.ram = state.ram;
+        self.external_ram_enabled = state.external_ram_enabled;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        for (cell, value) in self.ram.iter_mut().zip(data) {
+            *cell = value & 0x0F;
+        }
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            // Only the lower nibble exists, the upper one reads as 1s
+            0xA000..=0xBFFF if self.external_ram_enabled => {
+                self.ram[(address & 0x1FF) as usize] | 0xF0
+            }
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF => {
+                if address & 0x0100 == 0 {
+                    // enable external ram
+                    self.external_ram_enabled = value & 0x0F == 0x0A;
+                } else {
+                    // rom bank 0 maps to 1
+                    self.current_rom_bank = (value & 0x0F).max(1);
+                }
+            }
+            0xA000..=0xBFFF if self.external_ram_enabled => {
+                self.ram[(address & 0x1FF) as usize] = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc2::new(&banked_rom(16));
+        assert_eq!(mbc.read_byte(0x4000), 1);
+        // Address bit 8 set selects the ROM bank, anywhere in 0000-3FFF
+        mbc.write_byte(0x2100, 0x05);
+        assert_eq!(mbc.read_byte(0x4000), 5);
+        mbc.write_byte(0x3FFF, 0xFF);
+        assert_eq!(mbc.read_byte(0x4000), 15);
+        mbc.write_byte(0x0100, 0x00);
+        assert_eq!(mbc.read_byte(0x4000), 1);
+        assert_eq!(mbc.read_byte(0x0000), 0);
+        // Bit 8 clear is the RAM enable, the bank stays
+        mbc.write_byte(0x2000, 0x07);
+        assert_eq!(mbc.read_byte(0x4000), 1);
+    }
+
+    #[test]
+    fn test_rom_bank_wraps_on_small_roms() {
+        let mut mbc = Mbc2::new(&banked_rom(4));
+        mbc.write_byte(0x2100, 0x06);
+        assert_eq!(mbc.rom_bank(0x4000), 2);
+        assert_eq!(mbc.read_byte(0x4000), 2);
+    }
+
+    #[test]
+    fn test_ram_is_4_bits_and_echoed() {
+        let mut mbc = Mbc2::new(&banked_rom(16));
+        mbc.write_byte(0xA000, 0x0C);
+        assert_eq!(mbc.read_byte(0xA000), 0xFF);
+
+        mbc.write_byte(0x0000, 0x0A);
+        mbc.write_byte(0xA000, 0x5C);
+        assert_eq!(mbc.read_byte(0xA000), 0xFC);
+        assert_eq!(mbc.read_byte(0xA200), 0xFC);
+        assert_eq!(mbc.read_byte(0xBE00), 0xFC);
+        mbc.write_byte(0xB1FF, 0x03);
+        assert_eq!(mbc.read_byte(0xA1FF), 0xF3);
+        assert_eq!(mbc.ram()[0x1FF], 0x03);
+
+        mbc.write_byte(0x00FF, 0x00);
+        assert_eq!(mbc.read_byte(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_load_ram_keeps_the_low_nibble() {
+        let mut mbc = Mbc2::new(&banked_rom(16));
+        mbc.load_ram(&[0xAB, 0x12]);
+        assert_eq!(mbc.ram()[..3], [0x0B, 0x02, 0x00]);
+    }
+}
@@ -29,7 +29,6 @@ impl Mbc3 {
     /*
     ROM BANK: Up to 128 banks of 16KB
     RAM BANK: Up to 4 banks of 8KB
-    TODO: battery save external ram
      */
     pub fn new(rom: &[u8], ram_size: usize, has_rtc: bool) -> Self {
         let rom_bank_count = rom.len() / 0x4000;
@@ -66,13 +65,27 @@ impl Mbc3 {
         self.rtc = state.rtc;
         self.current_rtc_register = state.current_rtc_register;
     }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.current_rom_bank as usize % self.rom_bank_count).max(1),
+        }
+    }
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x3FFF => self.rom[address as usize],
             0x4000..=0x7FFF => {
-                let bank = (self.current_rom_bank as usize % self.rom_bank_count).max(1);
-                let offset = bank * 0x4000 + (address as usize - 0x4000);
-                self.rom[offset]
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
             }
             0xA000..=0xBFFF => {
                 // rtc 1st then ram if no rtc
@@ -6,6 +6,11 @@ pub struct Mbc5 {
     rom: Vec<u8>,
     ram: Vec<u8>,
     external_ram_enabled: bool,
+    has_rumble: bool,
+    rumble: bool,
+    // Cycles the motor was on / elapsed since the host last polled
+    rumble_cycles: usize,
+    elapsed_cycles: usize,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -15,23 +20,78 @@ pub struct Mbc5State {
 
     ram: Vec<u8>,
     external_ram_enabled: bool,
+    rumble: bool,
+}
+// Save state version 1, from before rumble
+#[derive(Deserialize)]
+pub(crate) struct Mbc5StateV1 {
+    current_rom_bank: u16,
+    current_ram_bank: u8,
+    ram: Vec<u8>,
+    external_ram_enabled: bool,
+}
+impl From<Mbc5StateV1> for Mbc5State {
+    fn from(state: Mbc5StateV1) -> Self {
+        Self {
+            current_rom_bank: state.current_rom_bank,
+            current_ram_bank: state.current_ram_bank,
+            ram: state.ram,
+            external_ram_enabled: state.external_ram_enabled,
+            rumble: false,
+        }
+    }
 }
 
 impl Mbc5 {
     /*
     ROM BANK: Up to 512 banks of 16KB
     RAM BANK: Up to 16 banks of 8KB
+    Rumble carts (0x1C-0x1E) drive the motor with bit 3 of the RAM bank register,
+    leaving 8 RAM banks.
      */
-    // TODO: rumble?
-    pub fn new(rom: &[u8], ram_size: usize) -> Self {
+    pub fn new(rom: &[u8], ram_size: usize, has_rumble: bool) -> Self {
         Self {
             current_rom_bank: 1,
             current_ram_bank: 0,
             rom: rom.to_vec(),
             ram: vec![0; ram_size],
             external_ram_enabled: false,
+            has_rumble,
+            rumble: false,
+            rumble_cycles: 0,
+            elapsed_cycles: 0,
+        }
+    }
+    pub fn tick(&mut self) {
+        self.elapsed_cycles += 1;
+        if self.rumble {
+            self.rumble_cycles += 1;
         }
     }
+    pub fn has_rumble(&self) -> bool {
+        self.has_rumble
+    }
+    pub fn is_rumbling(&self) -> bool {
+        self.rumble
+    }
+    /*
+    Games pulse the motor to control its strength,
+    returns the fraction of time it was on since the last call (0.0 - 1.0)
+    */
+    pub fn take_rumble_strength(&mut self) -> f32 {
+        let strength = if self.elapsed_cycles == 0 {
+            if self.rumble {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            self.rumble_cycles as f32 / self.elapsed_cycles as f32
+        };
+        self.rumble_cycles = 0;
+        self.elapsed_cycles = 0;
+        strength
+    }
 
     pub fn save_state(&self) -> Mbc5State {
         Mbc5State {
@@ -39,6 +99,7 @@ impl Mbc5 {
             current_ram_bank: self.current_ram_bank,
             ram: self.ram.clone(),
             external_ram_enabled: self.external_ram_enabled,
+            rumble: self.rumble,
         }
     }
     pub fn load_state(&mut self, state: Mbc5State) {
@@ -46,6 +107,23 @@ impl Mbc5 {
         self.current_ram_bank = state.current_ram_bank;
         self.ram = state.ram;
         self.external_ram_enabled = state.external_ram_enabled;
+        self.rumble = state.rumble;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize,
+        }
     }
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
@@ -84,6 +162,11 @@ impl Mbc5 {
                 self.current_rom_bank =
                     (self.current_rom_bank & 0xFF) | ((value & 0x01) as u16) << 8;
             }
+            0x4000..=0x5FFF if self.has_rumble => {
+                // bit 3 is the motor, ram value 0-7
+                self.rumble = value & 0x08 != 0;
+                self.current_ram_bank = value & 0x07;
+            }
             0x4000..=0x5FFF => {
                 // set ram value 0-15
                 self.current_ram_bank = value & 0x0F;
@@ -102,3 +185,98 @@ impl Mbc5 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    fn mapped_bank(mbc: &Mbc5) -> u16 {
+        u16::from_le_bytes([mbc.read_byte(0x4000), mbc.read_byte(0x4001)])
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc5::new(&banked_rom(512), 0, false);
+        assert_eq!(mapped_bank(&mbc), 1);
+        mbc.write_byte(0x2000, 0xAB);
+        assert_eq!(mapped_bank(&mbc), 0xAB);
+        mbc.write_byte(0x3000, 0x01);
+        assert_eq!(mapped_bank(&mbc), 0x1AB);
+        mbc.write_byte(0x2FFF, 0x23);
+        assert_eq!(mapped_bank(&mbc), 0x123);
+        // Unlike MBC1, bank 0 can be mapped at 4000
+        mbc.write_byte(0x3000, 0x00);
+        mbc.write_byte(0x2000, 0x00);
+        assert_eq!(mapped_bank(&mbc), 0);
+        assert_eq!(mbc.rom_bank(0x4000), 0);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = Mbc5::new(&banked_rom(512), 0x20000, false);
+        mbc.write_byte(0x0000, 0x0A);
+        for bank in 0..16 {
+            mbc.write_byte(0x4000, bank);
+            mbc.write_byte(0xA000, bank);
+        }
+        mbc.write_byte(0x4000, 0x0F);
+        assert_eq!(mbc.read_byte(0xA000), 0x0F);
+        mbc.write_byte(0x4000, 0x08);
+        assert_eq!(mbc.read_byte(0xA000), 0x08);
+        assert!(!mbc.is_rumbling());
+
+        mbc.write_byte(0x0000, 0x00);
+        assert_eq!(mbc.read_byte(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_rumble_takes_ram_bank_bit_3() {
+        let mut mbc = Mbc5::new(&banked_rom(512), 0x10000, true);
+        assert!(mbc.has_rumble());
+        mbc.write_byte(0x0000, 0x0A);
+        mbc.write_byte(0x4000, 0x03);
+        mbc.write_byte(0xA000, 0x33);
+        mbc.write_byte(0x4000, 0x0B);
+        assert!(mbc.is_rumbling());
+        assert_eq!(mbc.read_byte(0xA000), 0x33);
+        mbc.write_byte(0x4000, 0x03);
+        assert!(!mbc.is_rumbling());
+    }
+
+    #[test]
+    fn test_rumble_strength() {
+        let mut mbc = Mbc5::new(&banked_rom(512), 0, true);
+        assert_eq!(mbc.take_rumble_strength(), 0.0);
+        mbc.write_byte(0x4000, 0x08);
+        assert_eq!(mbc.take_rumble_strength(), 1.0);
+
+        // On for a quarter of the time
+        for _ in 0..100 {
+            mbc.tick();
+        }
+        mbc.write_byte(0x4000, 0x00);
+        for _ in 0..300 {
+            mbc.tick();
+        }
+        assert_eq!(mbc.take_rumble_strength(), 0.25);
+        assert_eq!(mbc.take_rumble_strength(), 0.0);
+    }
+
+    #[test]
+    fn test_version_1_state_has_the_motor_off() {
+        let mut mbc = Mbc5::new(&banked_rom(512), 0x2000, true);
+        mbc.write_byte(0x4000, 0x08);
+        mbc.load_state(
+            Mbc5StateV1 {
+                current_rom_bank: 5,
+                current_ram_bank: 0,
+                ram: vec![0; 0x2000],
+                external_ram_enabled: false,
+            }
+            .into(),
+        );
+        assert!(!mbc.is_rumbling());
+        assert_eq!(mapped_bank(&mbc), 5);
+    }
+}
@@ -0,0 +1,511 @@
+use serde::{Deserialize, Serialize};
+
+// Accelerometer reading when flat, and the change per g of tilt
+const ACCELEROMETER_CENTER: f32 = 0x81D0 as f32;
+const ACCELEROMETER_PER_G: f32 = 0x70 as f32;
+
+// 93LC56 in 16 bit mode: 128 words
+const EEPROM_WORDS: usize = 128;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+enum EepromMode {
+    // Waiting for the start bit then shifting in opcode + address
+    Command,
+    // Shifting out the word at the address, continues with the next one
+    Read { address: u8, bits_left: u8 },
+    // Shifting in the 16 data bits of WRITE (Some) or WRAL (None)
+    Write { address: Option<u8>, bits_left: u8 },
+    // Command done, ignore clocks until CS goes low
+    Done,
+}
+
+/*
+93LC56 serial EEPROM, driven bit by bit through A080
+bit 7: CS, bit 6: CLK, bit 1: DI, bit 0: DO
+Commands are clocked in on the rising CLK edge while CS is high, MSB first:
+1 (start) + 2 bit opcode + 8 bit address (7 used)
+10 READ  -> a dummy 0 then 16 data bits
+01 WRITE -> 16 data bits
+11 ERASE
+00 11xxxxxx EWEN (enable writes)   00 00xxxxxx EWDS (disable writes)
+00 10xxxxxx ERAL (erase all)       00 01xxxxxx WRAL + 16 data bits (write all)
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Eeprom {
+    data: Vec<u8>,
+    cs: bool,
+    clk: bool,
+    di: bool,
+    do_bit: bool,
+    write_enabled: bool,
+    mode: EepromMode,
+    shift: u16,
+    bits: u8,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Self {
+            data: vec![0xFF; EEPROM_WORDS * 2],
+            cs: false,
+            clk: false,
+            di: false,
+            do_bit: true,
+            write_enabled: false,
+            mode: EepromMode::Command,
+            shift: 0,
+            bits: 0,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        (self.cs as u8) << 7 | (self.clk as u8) << 6 | (self.di as u8) << 1 | self.do_bit as u8
+    }
+
+    fn write(&mut self, value: u8) {
+        let cs = value & 0x80 != 0;
+        let clk = value & 0x40 != 0;
+        self.di = value & 0x02 != 0;
+
+        if !cs {
+            // Deselecting aborts anything in progress
+            self.mode = EepromMode::Command;
+            self.shift = 0;
+            self.bits = 0;
+        } else if clk && !self.clk {
+            self.clock();
+        }
+        self.cs = cs;
+        self.clk = clk;
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let index = (address as usize % EEPROM_WORDS) * 2;
+        u16::from_le_bytes([self.data[index], self.data[index + 1]])
+    }
+    fn set_word(&mut self, address: u8, value: u16) {
+        if self.write_enabled {
+            let index = (address as usize % EEPROM_WORDS) * 2;
+            self.data[index..index + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    // Rising edge of CLK
+    fn clock(&mut self) {
+        match self.mode.clone() {
+            EepromMode::Command => {
+                // Leading zeros before the start bit are ignored
+                if self.bits == 0 && !self.di {
+                    return;
+                }
+                self.shift = self.shift << 1 | self.di as u16;
+                self.bits += 1;
+                if self.bits == 11 {
+                    self.execute();
+                }
+            }
+            EepromMode::Read { address, bits_left } => {
+                let bit = 16 - bits_left;
+                self.do_bit = self.word(address) & (0x8000 >> bit) != 0;
+                self.mode = if bits_left > 1 {
+                    EepromMode::Read {
+                        address,
+                        bits_left: bits_left - 1,
+                    }
+                } else {
+                    EepromMode::Read {
+                        address: address.wrapping_add(1) % EEPROM_WORDS as u8,
+                        bits_left: 16,
+                    }
+                };
+            }
+            EepromMode::Write { address, bits_left } => {
+                self.shift = self.shift << 1 | self.di as u16;
+                if bits_left > 1 {
+                    self.mode = EepromMode::Write {
+                        address,
+                        bits_left: bits_left - 1,
+                    };
+                    return;
+                }
+                match address {
+                    Some(address) => self.set_word(address, self.shift),
+                    None => {
+                        for address in 0..EEPROM_WORDS as u8 {
+                            self.set_word(address, self.shift);
+                        }
+                    }
+                }
+                // Ready
+                self.do_bit = true;
+                self.mode = EepromMode::Done;
+            }
+            EepromMode::Done => {}
+        }
+    }
+
+    fn execute(&mut self) {
+        let opcode = (self.shift >> 8) & 0x03;
+        let address = self.shift as u8;
+        self.shift = 0;
+        self.mode = EepromMode::Done;
+        match opcode {
+            0b10 => {
+                // Dummy 0 before the data
+                self.do_bit = false;
+                self.mode = EepromMode::Read {
+                    address,
+                    bits_left: 16,
+                };
+            }
+            0b01 => {
+                self.mode = EepromMode::Write {
+                    address: Some(address),
+                    bits_left: 16,
+                }
+            }
+            0b11 => self.set_word(address, 0xFFFF),
+            _ => match address >> 6 {
+                0b11 => self.write_enabled = true,
+                0b00 => self.write_enabled = false,
+                0b10 => {
+                    for address in 0..EEPROM_WORDS as u8 {
+                        self.set_word(address, 0xFFFF);
+                    }
+                }
+                _ => {
+                    self.mode = EepromMode::Write {
+                        address: None,
+                        bits_left: 16,
+                    }
+                }
+            },
+        }
+        if self.mode == EepromMode::Done {
+            self.do_bit = true;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mbc7 {
+    current_rom_bank: u8,
+    rom: Vec<u8>,
+    ram_enabled_1: bool,
+    ram_enabled_2: bool,
+    eeprom: Eeprom,
+    // Tilt in g set by the host, positive x is right, positive y is down (towards the player)
+    tilt: (f32, f32),
+    accelerometer_latched: bool,
+    x_latch: u16,
+    y_latch: u16,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Mbc7State {
+    current_rom_bank: u8,
+    ram_enabled_1: bool,
+    ram_enabled_2: bool,
+    eeprom: Eeprom,
+    accelerometer_latched: bool,
+    x_latch: u16,
+    y_latch: u16,
+}
+
+impl Mbc7 {
+    /*
+    ROM BANK: Up to 128 banks of 16KB, bank 0 can be mapped at 4000-7FFF
+    No RAM, A000-AFFF is a register file once 0000 = 0x0A and 4000 = 0x40:
+    Ax0x  write 0x55 erases the accelerometer latch
+    Ax1x  write 0xAA after an erase latches the accelerometer
+    Ax2x  X low   Ax3x  X high
+    Ax4x  Y low   Ax5x  Y high
+    Ax6x  0x00    Ax7x  0xFF
+    Ax8x  EEPROM
+     */
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            current_rom_bank: 1,
+            rom: rom.to_vec(),
+            ram_enabled_1: false,
+            ram_enabled_2: false,
+            eeprom: Eeprom::new(),
+            tilt: (0.0, 0.0),
+            accelerometer_latched: false,
+            x_latch: 0x8000,
+            y_latch: 0x8000,
+        }
+    }
+
+    pub fn save_state(&self) -> Mbc7State {
+        Mbc7State {
+            current_rom_bank: self.current_rom_bank,
+            ram_enabled_1: self.ram_enabled_1,
+            ram_enabled_2: self.ram_enabled_2,
+            eeprom: self.eeprom.clone(),
+            accelerometer_latched: self.accelerometer_latched,
+            x_latch: self.x_latch,
+            y_latch: self.y_latch,
+        }
+    }
+    pub fn load_state(&mut self, state: Mbc7State) {
+        self.current_rom_bank = state.current_rom_bank;
+        self.ram_enabled_1 = state.ram_enabled_1;
+        self.ram_enabled_2 = state.ram_enabled_2;
+        self.eeprom = state.eeprom;
+        self.accelerometer_latched = state.accelerometer_latched;
+        self.x_latch = state.x_latch;
+        self.y_latch = state.y_latch;
+    }
+    // The EEPROM takes the place of battery backed RAM
+    pub fn ram(&self) -> &[u8] {
+        &self.eeprom.data
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.eeprom.data.len());
+        self.eeprom.data[..len].copy_from_slice(&data[..len]);
+    }
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x.clamp(-2.0, 2.0), y.clamp(-2.0, 2.0));
+    }
+
+    fn registers_enabled(&self) -> bool {
+        self.ram_enabled_1 && self.ram_enabled_2
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            0xA000..=0xAFFF if self.registers_enabled() => match (address >> 4) & 0x0F {
+                0x2 => self.x_latch as u8,
+                0x3 => (self.x_latch >> 8) as u8,
+                0x4 => self.y_latch as u8,
+                0x5 => (self.y_latch >> 8) as u8,
+                0x6 => 0x00,
+                0x8 => self.eeprom.read(),
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled_1 = value == 0x0A,
+            0x2000..=0x3FFF => self.current_rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled_2 = value == 0x40,
+            0xA000..=0xAFFF if self.registers_enabled() => match (address >> 4) & 0x0F {
+                0x0 if value == 0x55 => {
+                    self.accelerometer_latched = false;
+                    self.x_latch = 0x8000;
+                    self.y_latch = 0x8000;
+                }
+                0x1 if value == 0xAA && !self.accelerometer_latched => {
+                    // Tilting right lowers X, tilting down raises Y
+                    let (x, y) = self.tilt;
+                    self.x_latch = (ACCELEROMETER_CENTER - x * ACCELEROMETER_PER_G) as u16;
+                    self.y_latch = (ACCELEROMETER_CENTER + y * ACCELEROMETER_PER_G) as u16;
+                    self.accelerometer_latched = true;
+                }
+                0x8 => self.eeprom.write(value),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    const EWEN: u16 = 0b100_1100_0000;
+    const EWDS: u16 = 0b100_0000_0000;
+    const ERAL: u16 = 0b100_1000_0000;
+    const WRAL: u16 = 0b100_0100_0000;
+    const READ: u16 = 0b110_0000_0000;
+    const WRITE: u16 = 0b101_0000_0000;
+    const ERASE: u16 = 0b111_0000_0000;
+
+    fn mbc7() -> Mbc7 {
+        let mut mbc = Mbc7::new(&banked_rom(8));
+        mbc.write_byte(0x0000, 0x0A);
+        mbc.write_byte(0x4000, 0x40);
+        mbc
+    }
+
+    // One rising CLK edge with DI set to the bit, returns DO after it
+    fn clock(mbc: &mut Mbc7, bit: bool) -> bool {
+        let di = (bit as u8) << 1;
+        mbc.write_byte(0xA080, 0x80 | di);
+        mbc.write_byte(0xA080, 0xC0 | di);
+        mbc.read_byte(0xA080) & 0x01 != 0
+    }
+
+    fn send(mbc: &mut Mbc7, bits: u16, count: u8) {
+        for bit in (0..count).rev() {
+            clock(mbc, bits & (1 << bit) != 0);
+        }
+    }
+
+    fn deselect(mbc: &mut Mbc7) {
+        mbc.write_byte(0xA080, 0x00);
+    }
+
+    fn command(mbc: &mut Mbc7, command: u16) {
+        send(mbc, command, 11);
+        deselect(mbc);
+    }
+
+    fn write_word(mbc: &mut Mbc7, address: u8, value: u16) {
+        send(mbc, WRITE | address as u16, 11);
+        send(mbc, value, 16);
+        // Ready
+        assert_eq!(mbc.read_byte(0xA080) & 0x01, 1);
+        deselect(mbc);
+    }
+
+    fn read_words(mbc: &mut Mbc7, address: u8, count: usize) -> Vec<u16> {
+        send(mbc, READ | address as u16, 11);
+        // The dummy 0
+        assert_eq!(mbc.read_byte(0xA080) & 0x01, 0);
+        let words = (0..count)
+            .map(|_| (0..16).fold(0, |word, _| word << 1 | clock(mbc, false) as u16))
+            .collect();
+        deselect(mbc);
+        words
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = mbc7();
+        assert_eq!(mbc.read_byte(0x4000), 1);
+        mbc.write_byte(0x2000, 0x05);
+        assert_eq!(mbc.read_byte(0x4000), 5);
+        mbc.write_byte(0x2000, 0x00);
+        assert_eq!(mbc.read_byte(0x4000), 0);
+        // Wraps on the 8 banks there are
+        mbc.write_byte(0x2000, 0x0B);
+        assert_eq!(mbc.read_byte(0x4000), 3);
+    }
+
+    #[test]
+    fn test_registers_need_both_enables() {
+        let mut mbc = mbc7();
+        assert_eq!(mbc.read_byte(0xA060), 0x00);
+        mbc.write_byte(0x4000, 0x00);
+        assert_eq!(mbc.read_byte(0xA060), 0xFF);
+        assert_eq!(mbc.read_byte(0xA020), 0xFF);
+        mbc.write_byte(0x4000, 0x40);
+        mbc.write_byte(0x0000, 0x00);
+        assert_eq!(mbc.read_byte(0xA060), 0xFF);
+    }
+
+    #[test]
+    fn test_accelerometer_latch() {
+        let mut mbc = mbc7();
+        let latch = |mbc: &Mbc7| {
+            (
+                u16::from_le_bytes([mbc.read_byte(0xA020), mbc.read_byte(0xA030)]),
+                u16::from_le_bytes([mbc.read_byte(0xA040), mbc.read_byte(0xA050)]),
+            )
+        };
+        assert_eq!(latch(&mbc), (0x8000, 0x8000));
+
+        mbc.set_tilt(1.0, -0.5);
+        mbc.write_byte(0xA000, 0x55);
+        mbc.write_byte(0xA010, 0xAA);
+        assert_eq!(latch(&mbc), (0x81D0 - 0x70, 0x81D0 - 0x38));
+        // Only latches again after an erase
+        mbc.set_tilt(0.0, 0.0);
+        mbc.write_byte(0xA010, 0xAA);
+        assert_eq!(latch(&mbc), (0x81D0 - 0x70, 0x81D0 - 0x38));
+        mbc.write_byte(0xA000, 0x55);
+        assert_eq!(latch(&mbc), (0x8000, 0x8000));
+        mbc.write_byte(0xA010, 0xAA);
+        assert_eq!(latch(&mbc), (0x81D0, 0x81D0));
+
+        // Clamped to 2 g
+        mbc.set_tilt(-5.0, 5.0);
+        mbc.write_byte(0xA000, 0x55);
+        mbc.write_byte(0xA010, 0xAA);
+        assert_eq!(latch(&mbc), (0x81D0 + 0xE0, 0x81D0 + 0xE0));
+    }
+
+    #[test]
+    fn test_eeprom_write_and_read() {
+        let mut mbc = mbc7();
+        // Locked until EWEN
+        write_word(&mut mbc, 0x10, 0x1234);
+        assert_eq!(read_words(&mut mbc, 0x10, 1), [0xFFFF]);
+
+        command(&mut mbc, EWEN);
+        write_word(&mut mbc, 0x10, 0x1234);
+        write_word(&mut mbc, 0x11, 0xABCD);
+        // Reading carries on into the next words
+        assert_eq!(read_words(&mut mbc, 0x10, 3), [0x1234, 0xABCD, 0xFFFF]);
+        // Stored little endian in the save
+        assert_eq!(mbc.ram()[0x20..0x24], [0x34, 0x12, 0xCD, 0xAB]);
+
+        command(&mut mbc, ERASE | 0x10);
+        assert_eq!(read_words(&mut mbc, 0x10, 2), [0xFFFF, 0xABCD]);
+
+        command(&mut mbc, EWDS);
+        write_word(&mut mbc, 0x11, 0x0000);
+        assert_eq!(read_words(&mut mbc, 0x11, 1), [0xABCD]);
+    }
+
+    #[test]
+    fn test_eeprom_read_wraps_around() {
+        let mut mbc = mbc7();
+        command(&mut mbc, EWEN);
+        write_word(&mut mbc, 0x00, 0x0102);
+        write_word(&mut mbc, 0x7F, 0x7F7F);
+        assert_eq!(read_words(&mut mbc, 0x7F, 2), [0x7F7F, 0x0102]);
+    }
+
+    #[test]
+    fn test_eeprom_write_all_and_erase_all() {
+        let mut mbc = mbc7();
+        command(&mut mbc, EWEN);
+        send(&mut mbc, WRAL, 11);
+        send(&mut mbc, 0x5AA5, 16);
+        deselect(&mut mbc);
+        assert!(mbc.ram().chunks(2).all(|word| word == [0xA5, 0x5A]));
+
+        command(&mut mbc, ERAL);
+        assert!(mbc.ram().iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn test_eeprom_command_framing() {
+        let mut mbc = mbc7();
+        command(&mut mbc, EWEN);
+        // Zeros before the start bit are skipped
+        send(&mut mbc, 0, 5);
+        send(&mut mbc, WRITE | 0x20, 11);
+        send(&mut mbc, 0x4321, 16);
+        deselect(&mut mbc);
+        assert_eq!(read_words(&mut mbc, 0x20, 1), [0x4321]);
+
+        // Deselecting in the middle of the data drops the write
+        send(&mut mbc, WRITE | 0x20, 11);
+        send(&mut mbc, 0x00, 8);
+        deselect(&mut mbc);
+        assert_eq!(read_words(&mut mbc, 0x20, 1), [0x4321]);
+
+        // CLK edges without CS do nothing
+        mbc.write_byte(0xA080, 0x40);
+        mbc.write_byte(0xA080, 0x42);
+        assert_eq!(read_words(&mut mbc, 0x20, 1), [0x4321]);
+    }
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct Mmm01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mapped: bool,
+    ram_enabled: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    rom_bank_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_bank_mask: u8,
+    mbc1_mode: bool,
+    mbc1_mode_locked: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Mmm01State {
+    ram: Vec<u8>,
+    mapped: bool,
+    ram_enabled: bool,
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    rom_bank_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    ram_bank_mask: u8,
+    mbc1_mode: bool,
+    mbc1_mode_locked: bool,
+}
+
+impl Mmm01 {
+    /*
+    Multi game mapper. At power on the last 32KB (the menu) is mapped at 0000-7FFF,
+    the menu then sets up the outer banks of a game and maps it, which locks them.
+    0000-1FFF: bits 0-3 RAM enable (0x0A), bits 4-5 RAM bank mask, bit 6 map
+    2000-3FFF: bits 0-4 ROM bank low, bits 5-6 ROM bank mid
+    4000-5FFF: bits 0-1 RAM bank low, bits 2-3 RAM bank high, bits 4-5 ROM bank high,
+               bit 6 locks the MBC1 mode
+    6000-7FFF: bit 0 MBC1 mode, bits 2-5 ROM bank mask
+    Everything except the RAM enable, the unmasked ROM/RAM bank low bits and the
+    MBC1 mode can only be written before mapping.
+    The ROM bank mask fixes bits 1-4 of ROM bank low, giving games of 32KB to 512KB.
+     */
+    pub fn new(rom: &[u8], ram_size: usize) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            ram: vec![0; ram_size],
+            mapped: false,
+            ram_enabled: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_bank_mask: 0,
+            mbc1_mode: false,
+            mbc1_mode_locked: false,
+        }
+    }
+
+    pub fn save_state(&self) -> Mmm01State {
+        Mmm01State {
+            ram: self.ram.clone(),
+            mapped: self.mapped,
+            ram_enabled: self.ram_enabled,
+            rom_bank_low: self.rom_bank_low,
+            rom_bank_mid: self.rom_bank_mid,
+            rom_bank_high: self.rom_bank_high,
+            rom_bank_mask: self.rom_bank_mask,
+            ram_bank_low: self.ram_bank_low,
+            ram_bank_high: self.ram_bank_high,
+            ram_bank_mask: self.ram_bank_mask,
+            mbc1_mode: self.mbc1_mode,
+            mbc1_mode_locked: self.mbc1_mode_locked,
+        }
+    }
+    pub fn load_state(&mut self, state: Mmm01State) {
+        self.ram = state.ram;
+        self.mapped = state.mapped;
+        self.ram_enabled = state.ram_enabled;
+        self.rom_bank_low = state.rom_bank_low;
+        self.rom_bank_mid = state.rom_bank_mid;
+        self.rom_bank_high = state.rom_bank_high;
+        self.rom_bank_mask = state.rom_bank_mask;
+        self.ram_bank_low = state.ram_bank_low;
+        self.ram_bank_high = state.ram_bank_high;
+        self.ram_bank_mask = state.ram_bank_mask;
+        self.mbc1_mode = state.mbc1_mode;
+        self.mbc1_mode_locked = state.mbc1_mode_locked;
+    }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    // Bits of ROM bank low that are fixed once mapped
+    fn fixed_rom_bits(&self) -> u8 {
+        (self.rom_bank_mask << 1) & 0x1E
+    }
+    fn fixed_ram_bits(&self) -> u8 {
+        self.ram_bank_mask & 0x03
+    }
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / 0x4000).max(1)
+    }
+
+    fn rom_bank_0(&self) -> usize {
+        if !self.mapped {
+            return self.rom_bank_count().saturating_sub(2);
+        }
+        let outer = (self.rom_bank_mid as usize) << 5 | (self.rom_bank_high as usize) << 7;
+        (self.rom_bank_low & self.fixed_rom_bits()) as usize | outer
+    }
+    fn rom_bank_n(&self) -> usize {
+        if !self.mapped {
+            return self.rom_bank_count() - 1;
+        }
+        let mut low = self.rom_bank_low;
+        // Like MBC1, bank 0 of the game maps to 1
+        if low & !self.fixed_rom_bits() & 0x1F == 0 {
+            low |= 1;
+        }
+        let outer = (self.rom_bank_mid as usize) << 5 | (self.rom_bank_high as usize) << 7;
+        low as usize | outer
+    }
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = if self.mbc1_mode {
+            self.ram_bank_low | self.ram_bank_high << 2
+        } else {
+            self.ram_bank_high << 2
+        };
+        let offset = 0x2000 * bank as usize + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => self.rom_bank_0() % self.rom_bank_count(),
+            _ => self.rom_bank_n() % self.rom_bank_count(),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[0x4000 * self.rom_bank(address) + address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            0xA000..=0xBFFF => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if !self.mapped {
+                    self.ram_bank_mask = (value >> 4) & 0x03;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                let writable = if self.mapped {
+                    !self.fixed_rom_bits() & 0x1F
+                } else {
+                    0x1F
+                };
+                self.rom_bank_low = self.rom_bank_low & !writable | value & writable;
+                if !self.mapped {
+                    self.rom_bank_mid = (value >> 5) & 0x03;
+                }
+            }
+            0x4000..=0x5FFF => {
+                let writable = if self.mapped {
+                    !self.fixed_ram_bits() & 0x03
+                } else {
+                    0x03
+                };
+                self.ram_bank_low = self.ram_bank_low & !writable | value & writable;
+                if !self.mapped {
+                    self.ram_bank_high = (value >> 2) & 0x03;
+                    self.rom_bank_high = (value >> 4) & 0x03;
+                    self.mbc1_mode_locked = value & 0x40 != 0;
+                }
+            }
+            0x6000..=0x7FFF => {
+                if !self.mbc1_mode_locked {
+                    self.mbc1_mode = value & 0x01 != 0;
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 2) & 0x0F;
+                }
+            }
+            0xA000..=0xBFFF => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    fn mmm01() -> Mmm01 {
+        Mmm01::new(&banked_rom(64), 0x8000)
+    }
+
+    #[test]
+    fn test_boots_into_the_menu() {
+        let mut mmm01 = mmm01();
+        assert_eq!(mmm01.read_byte(0x0000), 62);
+        assert_eq!(mmm01.read_byte(0x4000), 63);
+        // Bank writes before mapping don't move the menu
+        mmm01.write_byte(0x2000, 0x05);
+        assert_eq!(mmm01.read_byte(0x4000), 63);
+    }
+
+    #[test]
+    fn test_mapped_game() {
+        let mut mmm01 = mmm01();
+        // A 64KB game at bank 32: mid bank 1, bits 2-4 of the bank fixed
+        mmm01.write_byte(0x2000, 0x20);
+        mmm01.write_byte(0x6000, 0x0E << 2);
+        mmm01.write_byte(0x0000, 0x40);
+        assert_eq!(mmm01.read_byte(0x0000), 32);
+        assert_eq!(mmm01.read_byte(0x4000), 33);
+
+        mmm01.write_byte(0x2000, 0x03);
+        assert_eq!(mmm01.read_byte(0x4000), 35);
+        // Fixed bits and the outer bank are locked
+        mmm01.write_byte(0x2000, 0x04);
+        assert_eq!(mmm01.read_byte(0x4000), 33);
+        mmm01.write_byte(0x2000, 0x7E);
+        assert_eq!(mmm01.read_byte(0x4000), 34);
+        assert_eq!(mmm01.read_byte(0x0000), 32);
+        // and so is the mapping
+        mmm01.write_byte(0x0000, 0x00);
+        assert_eq!(mmm01.read_byte(0x0000), 32);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut mmm01 = mmm01();
+        // RAM bank high 1 is bank 4, MBC1 mode adds in bank low
+        mmm01.write_byte(0x4000, 0x04);
+        mmm01.write_byte(0x0000, 0x4A);
+        mmm01.write_byte(0xA000, 0x12);
+        mmm01.write_byte(0x6000, 0x01);
+        mmm01.write_byte(0x4000, 0x03);
+        mmm01.write_byte(0xA000, 0x34);
+        // Banks 4 and 7 wrap around the 32KB there are
+        assert_eq!(mmm01.ram()[0x0000], 0x12);
+        assert_eq!(mmm01.ram()[0x6000], 0x34);
+        assert_eq!(mmm01.read_byte(0xA000), 0x34);
+
+        mmm01.write_byte(0x0000, 0x00);
+        assert_eq!(mmm01.read_byte(0xA000), 0xFF);
+    }
+}
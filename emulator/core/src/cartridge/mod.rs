@@ -1,29 +1,92 @@
+use crate::cheats::RomPatch;
+use camera::{Camera, CameraState};
+use gbs::{Gbs, GbsState};
+use huc1::{Huc1, Huc1State};
+use huc3::{Huc3, Huc3State};
 use mbc0::{Mbc0, Mbc0State};
-use mbc1::{Mbc1, Mbc1State};
+use mbc1::{Mbc1, Mbc1State, MulticartGame};
+use mbc2::{Mbc2, Mbc2State};
 use mbc3::{Mbc3, Mbc3State};
-use mbc5::{Mbc5, Mbc5State};
+use mbc5::{Mbc5, Mbc5State, Mbc5StateV1};
+use mbc7::{Mbc7, Mbc7State};
+use mmm01::{Mmm01, Mmm01State};
+use rocket::{Rocket, RocketState};
+use sachen::{Sachen, SachenState};
 use serde::{Deserialize, Serialize};
+use wisdom_tree::{WisdomTree, WisdomTreeState};
 
+pub mod camera;
 pub mod cartridge_header;
+pub mod detect;
+pub mod gbs;
+pub mod huc1;
+pub mod huc3;
 pub mod mbc0;
 pub mod mbc1;
+pub mod mbc2;
 pub mod mbc3;
 pub mod mbc5;
+pub mod mbc7;
+pub mod mmm01;
+pub mod rocket;
+pub mod sachen;
+pub mod wisdom_tree;
 #[derive(Clone, Debug)]
 pub enum MbcType {
     None,
     Mbc0(Mbc0),
     Mbc1(Mbc1),
+    Mbc2(Mbc2),
     Mbc3(Mbc3),
     Mbc5(Mbc5),
+    Mbc7(Mbc7),
+    Huc1(Huc1),
+    Huc3(Huc3),
+    Mmm01(Mmm01),
+    WisdomTree(WisdomTree),
+    Sachen(Sachen),
+    Rocket(Rocket),
+    Camera(Camera),
+    Gbs(Gbs),
 }
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum MbcTypeState {
+    // Save states encode the variant index, new mappers go at the end
     None,
     Mbc0(Mbc0State),
     Mbc1(Mbc1State),
     Mbc3(Mbc3State),
     Mbc5(Mbc5State),
+    Gbs(GbsState),
+    Mbc2(Mbc2State),
+    Mbc7(Mbc7State),
+    Huc1(Huc1State),
+    Huc3(Huc3State),
+    Mmm01(Mmm01State),
+    WisdomTree(WisdomTreeState),
+    Sachen(SachenState),
+    Rocket(RocketState),
+    Camera(CameraState),
+}
+// Save state version 1, the mappers there were then
+#[derive(Deserialize)]
+pub(crate) enum MbcTypeStateV1 {
+    None,
+    Mbc0(Mbc0State),
+    Mbc1(Mbc1State),
+    Mbc3(Mbc3State),
+    Mbc5(Mbc5StateV1),
+}
+impl From<MbcTypeStateV1> for MbcTypeState {
+    fn from(state: MbcTypeStateV1) -> Self {
+        match state {
+            MbcTypeStateV1::None => MbcTypeState::None,
+            MbcTypeStateV1::Mbc0(state) => MbcTypeState::Mbc0(state),
+            MbcTypeStateV1::Mbc1(state) => MbcTypeState::Mbc1(state),
+            MbcTypeStateV1::Mbc3(state) => MbcTypeState::Mbc3(state),
+            MbcTypeStateV1::Mbc5(state) => MbcTypeState::Mbc5(state.into()),
+        }
+    }
 }
 impl MbcType {
     pub fn save_state(&self) -> MbcTypeState {
@@ -31,8 +94,18 @@ impl MbcType {
             MbcType::None => MbcTypeState::None,
             MbcType::Mbc0(mbc) => MbcTypeState::Mbc0(mbc.save_state()),
             MbcType::Mbc1(mbc) => MbcTypeState::Mbc1(mbc.save_state()),
+            MbcType::Mbc2(mbc) => MbcTypeState::Mbc2(mbc.save_state()),
             MbcType::Mbc3(mbc) => MbcTypeState::Mbc3(mbc.save_state()),
             MbcType::Mbc5(mbc) => MbcTypeState::Mbc5(mbc.save_state()),
+            MbcType::Mbc7(mbc) => MbcTypeState::Mbc7(mbc.save_state()),
+            MbcType::Huc1(mbc) => MbcTypeState::Huc1(mbc.save_state()),
+            MbcType::Huc3(mbc) => MbcTypeState::Huc3(mbc.save_state()),
+            MbcType::Mmm01(mbc) => MbcTypeState::Mmm01(mbc.save_state()),
+            MbcType::WisdomTree(mbc) => MbcTypeState::WisdomTree(mbc.save_state()),
+            MbcType::Sachen(mbc) => MbcTypeState::Sachen(mbc.save_state()),
+            MbcType::Rocket(mbc) => MbcTypeState::Rocket(mbc.save_state()),
+            MbcType::Camera(mbc) => MbcTypeState::Camera(mbc.save_state()),
+            MbcType::Gbs(mbc) => MbcTypeState::Gbs(mbc.save_state()),
         }
     }
 
@@ -40,19 +113,72 @@ impl MbcType {
         match (self, state) {
             (MbcType::Mbc0(mbc), MbcTypeState::Mbc0(state)) => mbc.load_state(state),
             (MbcType::Mbc1(mbc), MbcTypeState::Mbc1(state)) => mbc.load_state(state),
+            (MbcType::Mbc2(mbc), MbcTypeState::Mbc2(state)) => mbc.load_state(state),
             (MbcType::Mbc3(mbc), MbcTypeState::Mbc3(state)) => mbc.load_state(state),
             (MbcType::Mbc5(mbc), MbcTypeState::Mbc5(state)) => mbc.load_state(state),
+            (MbcType::Mbc7(mbc), MbcTypeState::Mbc7(state)) => mbc.load_state(state),
+            (MbcType::Huc1(mbc), MbcTypeState::Huc1(state)) => mbc.load_state(state),
+            (MbcType::Huc3(mbc), MbcTypeState::Huc3(state)) => mbc.load_state(state),
+            (MbcType::Mmm01(mbc), MbcTypeState::Mmm01(state)) => mbc.load_state(state),
+            (MbcType::WisdomTree(mbc), MbcTypeState::WisdomTree(state)) => mbc.load_state(state),
+            (MbcType::Sachen(mbc), MbcTypeState::Sachen(state)) => mbc.load_state(state),
+            (MbcType::Rocket(mbc), MbcTypeState::Rocket(state)) => mbc.load_state(state),
+            (MbcType::Camera(mbc), MbcTypeState::Camera(state)) => mbc.load_state(state),
+            (MbcType::Gbs(mbc), MbcTypeState::Gbs(state)) => mbc.load_state(state),
             _ => {} // Handle mismatched types or None case
         }
     }
-    pub fn read_byte(&self, address: u16) -> u8 {
-        match self {
+    // Game Genie patches go over the ROM bytes the mapper returns
+    pub fn read_byte(&self, address: u16, patches: &[RomPatch]) -> u8 {
+        let value = match self {
             MbcType::None => 0xFF,
             MbcType::Mbc0(mbc) => mbc.read_byte(address),
             MbcType::Mbc1(mbc) => mbc.read_byte(address),
+            MbcType::Mbc2(mbc) => mbc.read_byte(address),
             MbcType::Mbc3(mbc) => mbc.read_byte(address),
             MbcType::Mbc5(mbc) => mbc.read_byte(address),
+            MbcType::Mbc7(mbc) => mbc.read_byte(address),
+            MbcType::Huc1(mbc) => mbc.read_byte(address),
+            MbcType::Huc3(mbc) => mbc.read_byte(address),
+            MbcType::Mmm01(mbc) => mbc.read_byte(address),
+            MbcType::WisdomTree(mbc) => mbc.read_byte(address),
+            MbcType::Sachen(mbc) => mbc.read_byte(address),
+            MbcType::Rocket(mbc) => mbc.read_byte(address),
+            MbcType::Camera(mbc) => mbc.read_byte(address),
+            MbcType::Gbs(mbc) => mbc.read_byte(address),
             _ => 0xFF,
+        };
+        if address < 0x8000 {
+            for patch in patches {
+                if patch.address == address && patch.compare.is_none_or(|compare| compare == value)
+                {
+                    return patch.value;
+                }
+            }
+        }
+        value
+    }
+    /*
+    16KB ROM bank the CPU sees at a 0x0000-0x7FFF address, as the index of
+    that bank in the ROM file. Every mapper implements it for the debugger.
+    */
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match self {
+            MbcType::None => 0,
+            MbcType::Mbc0(mbc) => mbc.rom_bank(address),
+            MbcType::Mbc1(mbc) => mbc.rom_bank(address),
+            MbcType::Mbc2(mbc) => mbc.rom_bank(address),
+            MbcType::Mbc3(mbc) => mbc.rom_bank(address),
+            MbcType::Mbc5(mbc) => mbc.rom_bank(address),
+            MbcType::Mbc7(mbc) => mbc.rom_bank(address),
+            MbcType::Huc1(mbc) => mbc.rom_bank(address),
+            MbcType::Huc3(mbc) => mbc.rom_bank(address),
+            MbcType::Mmm01(mbc) => mbc.rom_bank(address),
+            MbcType::WisdomTree(mbc) => mbc.rom_bank(address),
+            MbcType::Sachen(mbc) => mbc.rom_bank(address),
+            MbcType::Rocket(mbc) => mbc.rom_bank(address),
+            MbcType::Camera(mbc) => mbc.rom_bank(address),
+            MbcType::Gbs(mbc) => mbc.rom_bank(address),
         }
     }
     pub fn write_byte(&mut self, address: u16, value: u8) {
@@ -60,16 +186,135 @@ impl MbcType {
             MbcType::None => {}
             MbcType::Mbc0(mbc) => mbc.write_byte(address, value),
             MbcType::Mbc1(mbc) => mbc.write_byte(address, value),
+            MbcType::Mbc2(mbc) => mbc.write_byte(address, value),
             MbcType::Mbc3(mbc) => mbc.write_byte(address, value),
             MbcType::Mbc5(mbc) => mbc.write_byte(address, value),
+            MbcType::Mbc7(mbc) => mbc.write_byte(address, value),
+            MbcType::Huc1(mbc) => mbc.write_byte(address, value),
+            MbcType::Huc3(mbc) => mbc.write_byte(address, value),
+            MbcType::Mmm01(mbc) => mbc.write_byte(address, value),
+            MbcType::WisdomTree(mbc) => mbc.write_byte(address, value),
+            MbcType::Sachen(mbc) => mbc.write_byte(address, value),
+            MbcType::Rocket(mbc) => mbc.write_byte(address, value),
+            MbcType::Camera(mbc) => mbc.write_byte(address, value),
+            MbcType::Gbs(mbc) => mbc.write_byte(address, value),
             _ => {}
         }
     }
+    // Sub games of a MBC1M compilation
+    pub fn multicart_games(&self) -> &[MulticartGame] {
+        match self {
+            MbcType::Mbc1(mbc) => mbc.multicart_games(),
+            _ => &[],
+        }
+    }
+    // External RAM contents, what a battery keeps alive
+    pub fn ram(&self) -> Option<&[u8]> {
+        match self {
+            MbcType::Mbc0(mbc) => Some(mbc.ram()),
+            MbcType::Mbc1(mbc) => Some(mbc.ram()),
+            MbcType::Mbc2(mbc) => Some(mbc.ram()),
+            MbcType::Mbc3(mbc) => Some(mbc.ram()),
+            MbcType::Mbc5(mbc) => Some(mbc.ram()),
+            MbcType::Mbc7(mbc) => Some(mbc.ram()),
+            MbcType::Huc1(mbc) => Some(mbc.ram()),
+            MbcType::Huc3(mbc) => Some(mbc.ram()),
+            MbcType::Mmm01(mbc) => Some(mbc.ram()),
+            MbcType::Camera(mbc) => Some(mbc.ram()),
+            _ => None,
+        }
+    }
+    // MBC7 is left out, its EEPROM isn't mapped at 0xA000 byte for byte
+    pub fn ram_mut(&mut self) -> Option<&mut [u8]> {
+        match self {
+            MbcType::Mbc0(mbc) => Some(mbc.ram_mut()),
+            MbcType::Mbc1(mbc) => Some(mbc.ram_mut()),
+            MbcType::Mbc2(mbc) => Some(mbc.ram_mut()),
+            MbcType::Mbc3(mbc) => Some(mbc.ram_mut()),
+            MbcType::Mbc5(mbc) => Some(mbc.ram_mut()),
+            MbcType::Huc1(mbc) => Some(mbc.ram_mut()),
+            MbcType::Huc3(mbc) => Some(mbc.ram_mut()),
+            MbcType::Mmm01(mbc) => Some(mbc.ram_mut()),
+            MbcType::Camera(mbc) => Some(mbc.ram_mut()),
+            _ => None,
+        }
+    }
+    pub fn load_ram(&mut self, data: &[u8]) {
+        match self {
+            MbcType::Mbc0(mbc) => mbc.load_ram(data),
+            MbcType::Mbc1(mbc) => mbc.load_ram(data),
+            MbcType::Mbc2(mbc) => mbc.load_ram(data),
+            MbcType::Mbc3(mbc) => mbc.load_ram(data),
+            MbcType::Mbc5(mbc) => mbc.load_ram(data),
+            MbcType::Mbc7(mbc) => mbc.load_ram(data),
+            MbcType::Huc1(mbc) => mbc.load_ram(data),
+            MbcType::Huc3(mbc) => mbc.load_ram(data),
+            MbcType::Mmm01(mbc) => mbc.load_ram(data),
+            MbcType::Camera(mbc) => mbc.load_ram(data),
+            _ => {}
+        }
+    }
+    // Clock state stored after the RAM in battery saves
+    pub fn rtc_save(&self) -> Option<Vec<u8>> {
+        match self {
+            MbcType::Huc3(mbc) => Some(mbc.rtc_save()),
+            _ => None,
+        }
+    }
+    pub fn load_rtc_save(&mut self, data: &[u8]) {
+        if let MbcType::Huc3(mbc) = self {
+            mbc.load_rtc_save(data);
+        }
+    }
+    // A000-BFFF is mapped to the IR port instead of RAM
+    pub fn infrared_selected(&self) -> bool {
+        match self {
+            MbcType::Huc1(mbc) => mbc.infrared_selected(),
+            MbcType::Huc3(mbc) => mbc.infrared_selected(),
+            _ => false,
+        }
+    }
+    // HuC3 piezo tone requested since the last call
+    pub fn take_tone(&mut self) -> Option<u8> {
+        match self {
+            MbcType::Huc3(mbc) => mbc.take_tone(),
+            _ => None,
+        }
+    }
     pub fn tick(&mut self) {
-        if let MbcType::Mbc3(mbc) = self {
-            if let Some(rtc) = mbc.rtc.as_mut() {
-                rtc.tick();
+        match self {
+            MbcType::Mbc3(mbc) => {
+                if let Some(rtc) = mbc.rtc.as_mut() {
+                    rtc.tick();
+                }
             }
+            MbcType::Mbc5(mbc) if mbc.has_rumble() => mbc.tick(),
+            MbcType::Huc3(mbc) => mbc.rtc.tick(),
+            MbcType::Camera(mbc) => mbc.tick(),
+            _ => {}
+        }
+    }
+    pub fn has_rumble(&self) -> bool {
+        matches!(self, MbcType::Mbc5(mbc) if mbc.has_rumble())
+    }
+    pub fn is_rumbling(&self) -> bool {
+        match self {
+            MbcType::Mbc5(mbc) => mbc.is_rumbling(),
+            _ => false,
+        }
+    }
+    pub fn has_accelerometer(&self) -> bool {
+        matches!(self, MbcType::Mbc7(_))
+    }
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let MbcType::Mbc7(mbc) = self {
+            mbc.set_tilt(x, y);
+        }
+    }
+    pub fn take_rumble_strength(&mut self) -> f32 {
+        match self {
+            MbcType::Mbc5(mbc) => mbc.take_rumble_strength(),
+            _ => 0.0,
         }
     }
 }
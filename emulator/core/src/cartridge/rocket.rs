@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct Rocket {
+    rom: Vec<u8>,
+    current_rom_bank: u8,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RocketState {
+    current_rom_bank: u8,
+}
+
+impl Rocket {
+    /*
+    Rocket Games carts carry their own logo at 0104 and swap in the Nintendo one
+    for the boot ROM, which the emulator skips.
+    ROM BANK: Up to 128 banks of 16KB switched by writes to 2000-3FFF, 0 maps to 1
+    No RAM
+     */
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            current_rom_bank: 1,
+        }
+    }
+
+    pub fn save_state(&self) -> RocketState {
+        RocketState {
+            current_rom_bank: self.current_rom_bank,
+        }
+    }
+    pub fn load_state(&mut self, state: RocketState) {
+        self.current_rom_bank = state.current_rom_bank;
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.current_rom_bank as usize % (self.rom.len() / 0x4000).max(1),
+        }
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                self.rom[0x4000 * self.rom_bank(address) + (address as usize - 0x4000)]
+            }
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        if let 0x2000..=0x3FFF = address {
+            self.current_rom_bank = (value & 0x7F).max(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    #[test]
+    fn test_banking() {
+        let mut rocket = Rocket::new(&banked_rom(8));
+        assert_eq!(rocket.read_byte(0x4000), 1);
+        rocket.write_byte(0x2000, 0x06);
+        assert_eq!(rocket.read_byte(0x4000), 6);
+        rocket.write_byte(0x3FFF, 0x00);
+        assert_eq!(rocket.read_byte(0x4000), 1);
+        rocket.write_byte(0x2000, 0x0B);
+        assert_eq!(rocket.read_byte(0x4000), 3);
+        assert_eq!(rocket.read_byte(0x0000), 0);
+    }
+}
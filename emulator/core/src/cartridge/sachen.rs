@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+use super::cartridge_header::NINTENDO_LOGO;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SachenKind {
+    Mmc1,
+    // Adds a second lockout stage for the CGB boot ROM
+    Mmc2,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sachen {
+    rom: Vec<u8>,
+    pub kind: SachenKind,
+    base_bank: u8,
+    bank_mask: u8,
+    // Bank as written, before the outer bank is applied
+    unmasked_bank: u8,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SachenState {
+    base_bank: u8,
+    bank_mask: u8,
+    unmasked_bank: u8,
+}
+
+impl Sachen {
+    /*
+    ROM BANK: 256 banks of 16KB, an outer bank selects which bits come from the base bank
+    0000-1FFF: base bank (outer bank), only while the written bank has bits 4-5 set
+    2000-3FFF: ROM bank, 0 maps to 1
+    4000-5FFF: bank mask, same condition as the base bank
+    0000-3FFF maps base bank & mask, 4000-7FFF (bank & !mask) | (base bank & mask)
+    No RAM
+
+    The address lines of 0100-01FF are scrambled (A0 <-> A6, A1 <-> A4).
+    The real header is stored scrambled at 0180-01FF, and during the boot ROM logo check
+    the mapper adds 0x80 to these reads. The emulator starts after the boot ROM, so only
+    the scrambling is left to apply.
+     */
+    pub fn new(rom: &[u8], kind: SachenKind) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            kind,
+            base_bank: 0,
+            bank_mask: 0,
+            unmasked_bank: 1,
+        }
+    }
+
+    pub fn save_state(&self) -> SachenState {
+        SachenState {
+            base_bank: self.base_bank,
+            bank_mask: self.bank_mask,
+            unmasked_bank: self.unmasked_bank,
+        }
+    }
+    pub fn load_state(&mut self, state: SachenState) {
+        self.base_bank = state.base_bank;
+        self.bank_mask = state.bank_mask;
+        self.unmasked_bank = state.unmasked_bank;
+    }
+
+    fn registers_writable(&self) -> bool {
+        self.unmasked_bank & 0x30 == 0x30
+    }
+    fn read_rom(&self, bank: u8, offset: usize) -> u8 {
+        let bank_count = (self.rom.len() / 0x4000).max(1);
+        self.rom[0x4000 * (bank as usize % bank_count) + offset]
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => self.base_bank & self.bank_mask,
+            _ => (self.unmasked_bank & !self.bank_mask) | (self.base_bank & self.bank_mask),
+        };
+        bank as usize % (self.rom.len() / 0x4000).max(1)
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => {
+                let address = if address & 0xFF00 == 0x0100 {
+                    unscramble(address)
+                } else {
+                    address
+                };
+                self.read_rom(self.base_bank & self.bank_mask, address as usize)
+            }
+            0x4000..=0x7FFF => {
+                let bank =
+                    (self.unmasked_bank & !self.bank_mask) | (self.base_bank & self.bank_mask);
+                self.read_rom(bank, address as usize - 0x4000)
+            }
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF if self.registers_writable() => self.base_bank = value,
+            0x2000..=0x3FFF => self.unmasked_bank = value.max(1),
+            0x4000..=0x5FFF if self.registers_writable() => self.bank_mask = value,
+            _ => {}
+        }
+    }
+}
+
+// A0 <-> A6, A1 <-> A4
+pub fn unscramble(address: u16) -> u16 {
+    (address & 0xFFAC)
+        | (address & 0x40) >> 6
+        | (address & 0x10) >> 3
+        | (address & 0x02) << 3
+        | (address & 0x01) << 6
+}
+
+// The logo the boot ROM sees is the scrambled one at 0184-01B3
+pub fn has_scrambled_logo(rom: &[u8]) -> bool {
+    NINTENDO_LOGO.iter().enumerate().all(|(i, &byte)| {
+        let address = unscramble((0x0104 + i as u16) | 0x80) as usize;
+        rom.get(address) == Some(&byte)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    #[test]
+    fn test_unscramble() {
+        assert_eq!(unscramble(0x0101), 0x0140);
+        assert_eq!(unscramble(0x0140), 0x0101);
+        assert_eq!(unscramble(0x0102), 0x0110);
+        assert_eq!(unscramble(0x0110), 0x0102);
+        assert_eq!(unscramble(0x01AC), 0x01AC);
+        for address in 0x0100..0x0200 {
+            assert_eq!(unscramble(unscramble(address)), address);
+        }
+    }
+
+    #[test]
+    fn test_scrambled_logo() {
+        let mut rom = vec![0; 0x8000];
+        assert!(!has_scrambled_logo(&rom));
+        for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom[unscramble(0x0184 + i as u16) as usize] = byte;
+        }
+        assert!(has_scrambled_logo(&rom));
+        // Not where a regular cartridge has it
+        assert_ne!(rom[0x0104..0x0134], NINTENDO_LOGO);
+    }
+
+    #[test]
+    fn test_banking() {
+        let mut rom = banked_rom(256);
+        for bank in 0..256 {
+            rom[bank * 0x4000 + 0x0140] = !bank as u8;
+        }
+        let mut sachen = Sachen::new(&rom, SachenKind::Mmc1);
+        assert_eq!(sachen.read_byte(0x4000), 1);
+        // The outer bank registers are locked until the bank has bits 4-5 set
+        sachen.write_byte(0x0000, 0x10);
+        assert_eq!(sachen.base_bank, 0);
+        sachen.write_byte(0x2000, 0x30);
+        sachen.write_byte(0x0000, 0x10);
+        sachen.write_byte(0x4000, 0xF0);
+        sachen.write_byte(0x2000, 0x05);
+        assert_eq!(sachen.read_byte(0x0000), 0x10);
+        assert_eq!(sachen.read_byte(0x4000), 0x15);
+        assert_eq!(sachen.rom_bank(0x4000), 0x15);
+        sachen.write_byte(0x0000, 0x20);
+        assert_eq!(sachen.read_byte(0x0000), 0x10);
+
+        // 0100-01FF reads are scrambled
+        assert_eq!(sachen.read_byte(0x0101), !0x10);
+        sachen.write_byte(0x2000, 0x00);
+        assert_eq!(sachen.read_byte(0x4000), 0x11);
+    }
+}
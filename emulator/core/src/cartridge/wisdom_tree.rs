@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug)]
+pub struct WisdomTree {
+    rom: Vec<u8>,
+    current_rom_bank: u8,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WisdomTreeState {
+    current_rom_bank: u8,
+}
+
+impl WisdomTree {
+    /*
+    ROM BANK: Up to 256 banks of 32KB mapped at 0000-7FFF
+    Any write to 0000-7FFF switches the bank, the value is ignored and the
+    lower 8 bits of the address select the bank.
+    No RAM
+     */
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            current_rom_bank: 0,
+        }
+    }
+
+    pub fn save_state(&self) -> WisdomTreeState {
+        WisdomTreeState {
+            current_rom_bank: self.current_rom_bank,
+        }
+    }
+    pub fn load_state(&mut self, state: WisdomTreeState) {
+        self.current_rom_bank = state.current_rom_bank;
+    }
+
+    // The mapper switches 32KB at once, this still counts 16KB banks
+    pub fn rom_bank(&self, address: u16) -> usize {
+        let bank = self.current_rom_bank as usize % (self.rom.len() / 0x8000).max(1);
+        bank * 2 + address as usize / 0x4000
+    }
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => {
+                let bank_count = (self.rom.len() / 0x8000).max(1);
+                let bank = self.current_rom_bank as usize % bank_count;
+                self.rom
+                    .get(0x8000 * bank + address as usize)
+                    .copied()
+                    .unwrap_or(0xFF)
+            }
+            _ => 0xFF,
+        }
+    }
+    pub fn write_byte(&mut self, address: u16, _value: u8) {
+        if let 0x0000..=0x7FFF = address {
+            self.current_rom_bank = address as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::banked_rom;
+
+    #[test]
+    fn test_banking() {
+        // 4 banks of 32KB
+        let mut wisdom_tree = WisdomTree::new(&banked_rom(8));
+        assert_eq!(wisdom_tree.read_byte(0x4000), 1);
+        // The address picks the bank, not the value
+        wisdom_tree.write_byte(0x4002, 0x00);
+        assert_eq!(wisdom_tree.read_byte(0x0000), 4);
+        assert_eq!(wisdom_tree.read_byte(0x4000), 5);
+        assert_eq!(wisdom_tree.rom_bank(0x4000), 5);
+        wisdom_tree.write_byte(0x0007, 0x02);
+        assert_eq!(wisdom_tree.read_byte(0x0000), 6);
+    }
+}
@@ -0,0 +1,532 @@
+use crate::bus::{Bus, MemoryInterface};
+
+/*
+A RAM byte held at a value by writing it again at the start of every VBlank.
+With a bank it goes straight to that WRAM or cartridge RAM bank through
+Bus::write_banked, so it holds even while the game has another bank mapped.
+Without one it is written wherever the CPU sees the address.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RamFreeze {
+    pub bank: Option<usize>,
+    pub address: u16,
+    pub value: u8,
+}
+
+// A ROM byte replaced on every read, only while it holds compare when one is given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatKind {
+    GameGenie(RomPatch),
+    GameShark(RamFreeze),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    // As entered, upper case with Game Genie dashes: "00A-17B-C49", "01FF41C0"
+    pub code: String,
+    pub kind: CheatKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheatGroup {
+    pub name: String,
+    pub enabled: bool,
+    pub cheats: Vec<Cheat>,
+}
+
+/*
+Named groups of codes, switched on and off as a whole. The ROM patches and
+RAM writes of the enabled groups are collected whenever the groups change
+so the bus doesn't walk the groups on every ROM read.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Cheats {
+    groups: Vec<CheatGroup>,
+    patches: Vec<RomPatch>,
+    writes: Vec<RamFreeze>,
+}
+
+impl RamFreeze {
+    pub fn new(bank: Option<usize>, address: u16, value: u8) -> Self {
+        Self {
+            bank,
+            address,
+            value,
+        }
+    }
+
+    pub(crate) fn apply(&self, bus: &mut Bus) {
+        match self.bank {
+            Some(bank) => {
+                bus.write_banked(bank, self.address, self.value);
+            }
+            None => bus.write_byte(self.address, self.value),
+        }
+    }
+}
+
+impl Cheat {
+    // Game Genie "ABC-DEF" or "ABC-DEF-GHI", GameShark "ttvvllhh", dashes are optional
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let digits: Vec<u8> = text
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("Invalid cheat code \"{}\"", text))?;
+        match digits.len() {
+            6 | 9 => Self::game_genie(&digits),
+            8 => Self::game_shark(&digits),
+            _ => Err(format!("Invalid cheat code \"{}\"", text)),
+        }
+    }
+
+    // The GameShark code holding a RAM location at a value
+    pub fn from_freeze(freeze: &RamFreeze) -> Self {
+        let kind = match (freeze.bank, freeze.address) {
+            (Some(bank), 0xA000..=0xBFFF) => 0x80 | bank as u8,
+            (Some(bank), 0xD000..=0xDFFF) => 0x90 | bank as u8,
+            _ => 0x01,
+        };
+        let [low, high] = freeze.address.to_le_bytes();
+        Self {
+            code: format!("{:02X}{:02X}{:02X}{:02X}", kind, freeze.value, low, high),
+            kind: CheatKind::GameShark(*freeze),
+        }
+    }
+
+    /*
+    AB is the new value, the address is FCDE with F inverted. The optional
+    compare is GI rotated right by 2 and xored with $BA, H is a checksum
+    the Game Genie itself checks.
+    */
+    fn game_genie(digits: &[u8]) -> Result<Self, String> {
+        let address = ((digits[5] as u16 ^ 0xF) << 12)
+            | (digits[2] as u16) << 8
+            | (digits[3] as u16) << 4
+            | digits[4] as u16;
+        if address >= 0x8000 {
+            return Err(format!("Game Genie address ${:04X} isn't in ROM", address));
+        }
+        let compare =
+            (digits.len() == 9).then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+        let text: String = digits.iter().map(|digit| format!("{:X}", digit)).collect();
+        let code = match digits.len() {
+            9 => format!("{}-{}-{}", &text[..3], &text[3..6], &text[6..]),
+            _ => format!("{}-{}", &text[..3], &text[3..]),
+        };
+        Ok(Self {
+            code,
+            kind: CheatKind::GameGenie(RomPatch {
+                address,
+                value: digits[0] << 4 | digits[1],
+                compare,
+            }),
+        })
+    }
+
+    /*
+    tt is the type: 01 writes to whatever bank is mapped, 8x to cartridge
+    RAM bank x and 9x to CGB WRAM bank x at D000. vv is the value and the
+    address is hhll.
+    */
+    fn game_shark(digits: &[u8]) -> Result<Self, String> {
+        let byte = |index: usize| digits[index] << 4 | digits[index + 1];
+        let kind = byte(0);
+        let address = u16::from_le_bytes([byte(4), byte(6)]);
+        let bank = match (kind, address) {
+            (0x00 | 0x01, 0xA000..=0xDFFF | 0xFF80..=0xFFFE) => None,
+            (0x80..=0x8F, 0xA000..=0xBFFF) => Some((kind & 0x0F) as usize),
+            (0x90..=0x97, 0xD000..=0xDFFF) => Some((kind & 0x07) as usize),
+            _ => {
+                return Err(format!(
+                    "Unsupported GameShark code type {:02X} for ${:04X}",
+                    kind, address
+                ))
+            }
+        };
+        Ok(Self {
+            code: digits.iter().map(|digit| format!("{:X}", digit)).collect(),
+            kind: CheatKind::GameShark(RamFreeze::new(bank, address, byte(2))),
+        })
+    }
+
+    // Both change the same ROM or RAM byte
+    fn same_target(&self, other: &Cheat) -> bool {
+        match (self.kind, other.kind) {
+            (CheatKind::GameGenie(a), CheatKind::GameGenie(b)) => {
+                a.address == b.address && a.compare == b.compare
+            }
+            (CheatKind::GameShark(a), CheatKind::GameShark(b)) => {
+                a.address == b.address && a.bank == b.bank
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn groups(&self) -> &[CheatGroup] {
+        &self.groups
+    }
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+    pub(crate) fn patches(&self) -> &[RomPatch] {
+        &self.patches
+    }
+    pub(crate) fn writes(&self) -> &[RamFreeze] {
+        &self.writes
+    }
+
+    /*
+    Codes separated by spaces, commas or new lines, in place of the codes of a
+    group with the same name. Returns the group's index
+    */
+    pub fn add_group(&mut self, name: &str, codes: &str) -> Result<usize, String> {
+        // Either would end the name in the saved text
+        if name.contains(['#', '\n', '\r']) {
+            return Err(format!(
+                "Cheat group name \"{}\" can't contain # or line breaks",
+                name
+            ));
+        }
+        let cheats = codes
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|code| !code.is_empty())
+            .map(Cheat::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if cheats.is_empty() {
+            return Err("No cheat codes given".to_string());
+        }
+        let group = CheatGroup {
+            name: name.to_string(),
+            enabled: true,
+            cheats,
+        };
+        let index = match self.groups.iter().position(|other| other.name == name) {
+            Some(index) => {
+                self.groups[index] = group;
+                index
+            }
+            None => {
+                self.groups.push(group);
+                self.groups.len() - 1
+            }
+        };
+        self.update();
+        Ok(index)
+    }
+
+    // Into the group with that name, made if missing, in place of one changing the same byte
+    pub fn add_cheat(&mut self, group: &str, cheat: Cheat) {
+        let index = match self.groups.iter().position(|other| other.name == group) {
+            Some(index) => index,
+            None => {
+                self.groups.push(CheatGroup {
+                    name: group.to_string(),
+                    enabled: true,
+                    cheats: Vec::new(),
+                });
+                self.groups.len() - 1
+            }
+        };
+        let cheats = &mut self.groups[index].cheats;
+        cheats.retain(|other| !other.same_target(&cheat));
+        cheats.push(cheat);
+        self.update();
+    }
+
+    pub fn remove_cheat(&mut self, group: usize, cheat: usize) -> bool {
+        let Some(group) = self.groups.get_mut(group) else {
+            return false;
+        };
+        if cheat >= group.cheats.len() {
+            return false;
+        }
+        group.cheats.remove(cheat);
+        self.update();
+        true
+    }
+    pub fn remove_group(&mut self, index: usize) -> bool {
+        if index >= self.groups.len() {
+            return false;
+        }
+        self.groups.remove(index);
+        self.update();
+        true
+    }
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        let Some(group) = self.groups.get_mut(index) else {
+            return false;
+        };
+        group.enabled = enabled;
+        self.update();
+        true
+    }
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.update();
+    }
+
+    /*
+    Saved per ROM as text: a group starts with "+Name" when enabled or
+    "-Name" when not, followed by one code per line
+    */
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for group in &self.groups {
+            let state = if group.enabled { '+' } else { '-' };
+            text.push_str(&format!("{}{}\n", state, group.name));
+            for cheat in &group.cheats {
+                text.push_str(&format!("{}\n", cheat.code));
+            }
+        }
+        text
+    }
+
+    // Replaces all groups, # starts a comment. Returns how many codes were read
+    pub fn load_text(&mut self, text: &str) -> Result<usize, String> {
+        let mut groups: Vec<CheatGroup> = Vec::new();
+        let mut count = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if let Some(state) = line.chars().next().filter(|c| matches!(c, '+' | '-')) {
+                groups.push(CheatGroup {
+                    name: line[1..].trim().to_string(),
+                    enabled: state == '+',
+                    cheats: Vec::new(),
+                });
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let cheat = Cheat::parse(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            if groups.is_empty() {
+                groups.push(CheatGroup {
+                    name: "Cheats".to_string(),
+                    enabled: true,
+                    cheats: Vec::new(),
+                });
+            }
+            groups.last_mut().unwrap().cheats.push(cheat);
+            count += 1;
+        }
+        self.groups = groups;
+        self.update();
+        Ok(count)
+    }
+
+    fn update(&mut self) {
+        self.patches.clear();
+        self.writes.clear();
+        for cheat in self
+            .groups
+            .iter()
+            .filter(|group| group.enabled)
+            .flat_map(|group| &group.cheats)
+        {
+            match cheat.kind {
+                CheatKind::GameGenie(patch) => self.patches.push(patch),
+                CheatKind::GameShark(freeze) => self.writes.push(freeze),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::gameboy_with_program;
+
+    fn patch(code: &str) -> RomPatch {
+        match Cheat::parse(code).unwrap().kind {
+            CheatKind::GameGenie(patch) => patch,
+            kind => panic!("{:?}", kind),
+        }
+    }
+    fn freeze(code: &str) -> RamFreeze {
+        match Cheat::parse(code).unwrap().kind {
+            CheatKind::GameShark(freeze) => freeze,
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_game_genie() {
+        assert_eq!(
+            patch("00A-17B-C49"),
+            RomPatch {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            }
+        );
+        assert_eq!(
+            patch("3E0-16F"),
+            RomPatch {
+                address: 0x0016,
+                value: 0x3E,
+                compare: None,
+            }
+        );
+        // GI = $EA rotates and xors back to a compare of 0
+        assert_eq!(patch("3E0-16F-E0A").compare, Some(0x00));
+
+        let cheat = Cheat::parse(" 00a17bc49 ").unwrap();
+        assert_eq!(cheat.code, "00A-17B-C49");
+        assert_eq!(Cheat::parse("3e016f").unwrap().code, "3E0-16F");
+        // F = 7 makes the address $8000
+        assert!(Cheat::parse("000-007").is_err());
+    }
+
+    #[test]
+    fn test_game_shark() {
+        assert_eq!(freeze("01FF41C0"), RamFreeze::new(None, 0xC041, 0xFF));
+        assert_eq!(freeze("000380FF"), RamFreeze::new(None, 0xFF80, 0x03));
+        assert_eq!(freeze("81030AA0"), RamFreeze::new(Some(1), 0xA00A, 0x03));
+        assert_eq!(freeze("920510D0"), RamFreeze::new(Some(2), 0xD010, 0x05));
+        assert_eq!(Cheat::parse("01ff41c0").unwrap().code, "01FF41C0");
+
+        // ROM, the wrong area for the type and unknown types
+        assert!(Cheat::parse("01000080").is_err());
+        assert!(Cheat::parse("810000C0").is_err());
+        assert!(Cheat::parse("980010D0").is_err());
+        assert!(Cheat::parse("A10000C0").is_err());
+
+        assert!(Cheat::parse("1234").is_err());
+        assert!(Cheat::parse("ZZZ-ZZZ").is_err());
+        assert!(Cheat::parse("").is_err());
+    }
+
+    #[test]
+    fn test_from_freeze() {
+        for code in ["01FF41C0", "81030AA0", "920510D0"] {
+            let cheat = Cheat::parse(code).unwrap();
+            let CheatKind::GameShark(freeze) = cheat.kind else {
+                panic!("{}", code);
+            };
+            assert_eq!(Cheat::from_freeze(&freeze), cheat);
+        }
+        // WRAM bank 0 and HRAM have no banked type
+        let cheat = Cheat::from_freeze(&RamFreeze::new(Some(0), 0xFF90, 0x12));
+        assert_eq!(cheat.code, "011290FF");
+    }
+
+    #[test]
+    fn test_groups() {
+        let mut cheats = Cheats::new();
+        assert_eq!(cheats.add_group("Lives", "00A-17B-C49, 01FF41C0"), Ok(0));
+        assert_eq!(cheats.add_group("Time", "010900D0"), Ok(1));
+        assert_eq!(cheats.patches().len(), 1);
+        assert_eq!(cheats.writes().len(), 2);
+
+        // Same name, new codes in the same place
+        assert_eq!(cheats.add_group("Lives", "01FE41C0"), Ok(0));
+        assert_eq!(cheats.groups().len(), 2);
+        assert_eq!(cheats.groups()[0].cheats.len(), 1);
+        assert!(cheats.patches().is_empty());
+
+        assert!(cheats.add_group("Bad#name", "01FE41C0").is_err());
+        assert!(cheats.add_group("Two\nlines", "01FE41C0").is_err());
+        assert!(cheats.add_group("Empty", " , ").is_err());
+        assert!(cheats.add_group("Lives", "01FE41C0 nonsense").is_err());
+        assert_eq!(cheats.groups()[0].cheats[0].code, "01FE41C0");
+
+        assert!(cheats.set_enabled(0, false));
+        assert_eq!(cheats.writes(), [RamFreeze::new(None, 0xD000, 0x09)]);
+        assert!(!cheats.set_enabled(5, true));
+    }
+
+    #[test]
+    fn test_add_and_remove_cheats() {
+        let mut cheats = Cheats::new();
+        cheats.add_cheat("Found", Cheat::parse("01FF41C0").unwrap());
+        cheats.add_cheat("Found", Cheat::parse("01FF42C0").unwrap());
+        // Replaces the one for the same byte
+        cheats.add_cheat("Found", Cheat::parse("010141C0").unwrap());
+        let codes: Vec<&str> = cheats.groups()[0]
+            .cheats
+            .iter()
+            .map(|cheat| cheat.code.as_str())
+            .collect();
+        assert_eq!(codes, ["01FF42C0", "010141C0"]);
+
+        assert!(cheats.remove_cheat(0, 1));
+        assert!(!cheats.remove_cheat(0, 1));
+        assert_eq!(cheats.writes().len(), 1);
+        assert!(cheats.remove_group(0));
+        assert!(cheats.is_empty());
+        assert!(cheats.writes().is_empty());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut cheats = Cheats::new();
+        cheats.add_group("Lives", "00A-17B-C49 01FF41C0").unwrap();
+        cheats.add_group("Time", "3E0-16F").unwrap();
+        cheats.set_enabled(1, false);
+        let text = cheats.to_text();
+        assert_eq!(text, "+Lives\n00A-17B-C49\n01FF41C0\n-Time\n3E0-16F\n");
+
+        let mut loaded = Cheats::new();
+        assert_eq!(loaded.load_text(&text), Ok(3));
+        assert_eq!(loaded.groups(), cheats.groups());
+        assert_eq!(loaded.patches(), cheats.patches());
+        assert_eq!(loaded.writes(), cheats.writes());
+    }
+
+    #[test]
+    fn test_load_text() {
+        let mut cheats = Cheats::new();
+        let text = "# Saved cheats\n01FF41C0 # no group yet\n\n- Off \n3E0-16F\n";
+        assert_eq!(cheats.load_text(text), Ok(2));
+        let groups = cheats.groups();
+        assert_eq!(groups[0].name, "Cheats");
+        assert!(groups[0].enabled);
+        assert_eq!(groups[1].name, "Off");
+        assert!(!groups[1].enabled);
+        assert!(cheats.patches().is_empty());
+
+        // A bad line keeps what was loaded before
+        let error = cheats
+            .load_text("+Group\n01FF41C0\nnonsense\n")
+            .unwrap_err();
+        assert!(error.starts_with("Line 3: "));
+        assert_eq!(cheats.groups().len(), 2);
+    }
+
+    #[test]
+    fn test_applied_by_the_bus() {
+        // JR -2
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE]);
+
+        // The ROM holds 0 at $0016, so only the patch comparing with 0 applies
+        let cheats = &mut gameboy.bus.cheats;
+        cheats.add_group("Compare", "3E0-16F-C49").unwrap();
+        assert_eq!(gameboy.bus.read_byte(0x0016), 0x00);
+        let cheats = &mut gameboy.bus.cheats;
+        cheats.add_group("Compare", "3E0-16F-E0A").unwrap();
+        assert_eq!(gameboy.bus.read_byte(0x0016), 0x3E);
+
+        // Written again every VBlank, whichever bank is mapped
+        let cheats = &mut gameboy.bus.cheats;
+        cheats.add_group("Freeze", "014200C0 920510D0").unwrap();
+        gameboy.run_frame();
+        assert_eq!(gameboy.bus.read_byte(0xC000), 0x42);
+        assert_eq!(gameboy.bus.read_banked(2, 0xD010), None);
+    }
+}
@@ -0,0 +1,805 @@
+use std::{cell::RefCell, fmt};
+
+use crate::{
+    bus::{cgb::CgbRegisters, io_address::IoRegister, Bus, GameboyMode, MemoryInterface},
+    cpu::CPU,
+};
+
+/*
+Breakpoints, watchpoints and a call stack for the Gameboy::run_until_break and
+step_* functions. Nothing here runs during tick/run_frame, so a game played
+without the debugger pays nothing for it.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    // Calls and interrupts taken while stepping, innermost last
+    pub call_stack: Vec<CallFrame>,
+    next_id: u32,
+    // Opcode of the last instruction run by the debugger, None while halted
+    pub(crate) last_opcode: Option<u8>,
+    // PC of the breakpoint the last run stopped at, the next run starts by running it
+    pub(crate) resume_from: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// "a == $10", "hl >= 0xC000", "sp < 57344"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Read or write
+    Access,
+}
+
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: u32,
+    // None breaks in any bank
+    pub bank: Option<usize>,
+    pub address: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    // Times the address was reached with the condition true
+    pub hit_count: u32,
+    // Hits to let through before stopping
+    pub ignore_count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub id: u32,
+    // Inclusive range, a single IO register is start == end
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub hit_count: u32,
+    pub ignore_count: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CallFrame {
+    // First instruction of the called routine or interrupt handler
+    pub target: u16,
+    pub bank: usize,
+    pub return_address: u16,
+    // SP right after the return address was pushed
+    pub sp: u16,
+    pub interrupt: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    // Stopped before running the instruction at address
+    Breakpoint {
+        id: u32,
+        bank: usize,
+        address: u16,
+    },
+    // Stopped after the instruction that made the access
+    Watchpoint {
+        id: u32,
+        address: u16,
+        value: u8,
+        write: bool,
+    },
+    // step_* finished normally
+    Step,
+    // run_until_break reached the end of a frame
+    FrameComplete,
+    // step_over/step_out ran too long without getting back
+    Timeout,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WatchHit {
+    index: usize,
+    address: u16,
+    value: u8,
+    write: bool,
+}
+
+impl Register {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let register = match name.to_ascii_lowercase().as_str() {
+            "a" => Register::A,
+            "f" => Register::F,
+            "b" => Register::B,
+            "c" => Register::C,
+            "d" => Register::D,
+            "e" => Register::E,
+            "h" => Register::H,
+            "l" => Register::L,
+            "af" => Register::AF,
+            "bc" => Register::BC,
+            "de" => Register::DE,
+            "hl" => Register::HL,
+            "sp" => Register::SP,
+            "pc" => Register::PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Register::A => "a",
+            Register::F => "f",
+            Register::B => "b",
+            Register::C => "c",
+            Register::D => "d",
+            Register::E => "e",
+            Register::H => "h",
+            Register::L => "l",
+            Register::AF => "af",
+            Register::BC => "bc",
+            Register::DE => "de",
+            Register::HL => "hl",
+            Register::SP => "sp",
+            Register::PC => "pc",
+        }
+    }
+    pub fn read(&self, cpu: &CPU) -> u16 {
+        let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+        match self {
+            Register::A => cpu.a as u16,
+            Register::F => cpu.f.bits() as u16,
+            Register::B => cpu.b as u16,
+            Register::C => cpu.c as u16,
+            Register::D => cpu.d as u16,
+            Register::E => cpu.e as u16,
+            Register::H => cpu.h as u16,
+            Register::L => cpu.l as u16,
+            Register::AF => pair(cpu.a, cpu.f.bits()),
+            Register::BC => pair(cpu.b, cpu.c),
+            Register::DE => pair(cpu.d, cpu.e),
+            Register::HL => pair(cpu.h, cpu.l),
+            Register::SP => cpu.sp,
+            Register::PC => cpu.pc,
+        }
+    }
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap_or("==")
+    }
+    fn compare(&self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (position, symbol, comparison) = Comparison::ALL
+            .iter()
+            .find_map(|(symbol, comparison)| {
+                text.find(symbol)
+                    .map(|position| (position, *symbol, *comparison))
+            })
+            .ok_or_else(|| format!("No comparison in condition \"{}\"", text))?;
+        let register = text[..position].trim();
+        let register = Register::from_name(register)
+            .ok_or_else(|| format!("Unknown register \"{}\"", register))?;
+        let value = parse_number(text[position + symbol.len()..].trim())?;
+        Ok(Self {
+            register,
+            comparison,
+            value,
+        })
+    }
+    pub fn matches(&self, cpu: &CPU) -> bool {
+        self.comparison.compare(self.register.read(cpu), self.value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ${:X}",
+            self.register.name(),
+            self.comparison.symbol(),
+            self.value
+        )
+    }
+}
+
+impl WatchKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "read" | "r" => Some(WatchKind::Read),
+            "write" | "w" => Some(WatchKind::Write),
+            "access" | "rw" => Some(WatchKind::Access),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        }
+    }
+    fn matches(&self, write: bool) -> bool {
+        match self {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+// "$C000", "0xC000", "C000h" are hex, plain digits are decimal
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let hex = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_suffix('h'));
+    match hex {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("Invalid number \"{}\"", text))
+}
+
+// Register names as in hardware.inc, with or without the r prefix: "LCDC", "rSTAT"
+pub fn io_register_address(name: &str) -> Option<u16> {
+    let name = name.strip_prefix('r').unwrap_or(name);
+    let register = match name.to_ascii_uppercase().as_str() {
+        "P1" | "JOYP" => IoRegister::Joyp,
+        "SB" => IoRegister::Sb,
+        "SC" => IoRegister::Sc,
+        "DIV" => IoRegister::Div,
+        "TIMA" => IoRegister::Tima,
+        "TMA" => IoRegister::Tma,
+        "TAC" => IoRegister::Tac,
+        "IF" => IoRegister::If,
+        "IE" => IoRegister::Ie,
+        "NR10" => IoRegister::Nr10,
+        "NR11" => IoRegister::Nr11,
+        "NR12" => IoRegister::Nr12,
+        "NR13" => IoRegister::Nr13,
+        "NR14" => IoRegister::Nr14,
+        "NR21" => IoRegister::Nr21,
+        "NR22" => IoRegister::Nr22,
+        "NR23" => IoRegister::Nr23,
+        "NR24" => IoRegister::Nr24,
+        "NR30" => IoRegister::Nr30,
+        "NR31" => IoRegister::Nr31,
+        "NR32" => IoRegister::Nr32,
+        "NR33" => IoRegister::Nr33,
+        "NR34" => IoRegister::Nr34,
+        "NR41" => IoRegister::Nr41,
+        "NR42" => IoRegister::Nr42,
+        "NR43" => IoRegister::Nr43,
+        "NR44" => IoRegister::Nr44,
+        "NR50" => IoRegister::Nr50,
+        "NR51" => IoRegister::Nr51,
+        "NR52" => IoRegister::Nr52,
+        "LCDC" => IoRegister::Lcdc,
+        "STAT" => IoRegister::Stat,
+        "SCY" => IoRegister::Scy,
+        "SCX" => IoRegister::Scx,
+        "LY" => IoRegister::Ly,
+        "LYC" => IoRegister::Lyc,
+        "DMA" => IoRegister::Dma,
+        "BGP" => IoRegister::Bgp,
+        "OBP0" => IoRegister::Obp0,
+        "OBP1" => IoRegister::Obp1,
+        "WY" => IoRegister::Wy,
+        "WX" => IoRegister::Wx,
+        "KEY1" => IoRegister::Key1,
+        "VBK" => IoRegister::Vbk,
+        "HDMA1" => IoRegister::Hdma1,
+        "HDMA2" => IoRegister::Hdma2,
+        "HDMA3" => IoRegister::Hdma3,
+        "HDMA4" => IoRegister::Hdma4,
+        "HDMA5" => IoRegister::Hdma5,
+        "RP" => IoRegister::Rp,
+        "BCPS" => IoRegister::Bcps,
+        "BCPD" => IoRegister::Bcpd,
+        "OCPS" => IoRegister::Ocps,
+        "OCPD" => IoRegister::Ocpd,
+        "OPRI" => IoRegister::Opri,
+        "SVBK" => IoRegister::Svbk,
+        "PCM12" => IoRegister::Pcm12,
+        "PCM34" => IoRegister::Pcm34,
+        _ => return None,
+    };
+    Some(register.address())
+}
+
+// Bytes of CALL and RST instructions, None for everything else
+pub fn call_length(opcode: u8) -> Option<u16> {
+    match opcode {
+        0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC => Some(3),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Some(1),
+        _ => None,
+    }
+}
+pub fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8)
+}
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(
+        &mut self,
+        bank: Option<usize>,
+        address: u16,
+        condition: Option<Condition>,
+    ) -> u32 {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            bank,
+            address,
+            condition,
+            enabled: true,
+            hit_count: 0,
+            ignore_count: 0,
+        });
+        id
+    }
+    pub fn add_watchpoint(
+        &mut self,
+        start: u16,
+        end: u16,
+        kind: WatchKind,
+        condition: Option<Condition>,
+    ) -> u32 {
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            start: start.min(end),
+            end: start.max(end),
+            kind,
+            condition,
+            enabled: true,
+            hit_count: 0,
+            ignore_count: 0,
+        });
+        id
+    }
+    pub fn add_io_watchpoint(&mut self, register: IoRegister, kind: WatchKind) -> u32 {
+        let address = register.address();
+        self.add_watchpoint(address, address, kind, None)
+    }
+    // Breakpoints and watchpoints share ids
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+    pub fn set_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        self.update(id, |found_enabled, _, _| *found_enabled = enabled)
+    }
+    pub fn set_ignore_count(&mut self, id: u32, count: u32) -> bool {
+        self.update(id, |_, _, ignore_count| *ignore_count = count)
+    }
+    pub fn reset_hit_count(&mut self, id: u32) -> bool {
+        self.update(id, |_, hit_count, _| *hit_count = 0)
+    }
+    pub fn hit_count(&self, id: u32) -> Option<u32> {
+        let breakpoint = self.breakpoints.iter().find(|b| b.id == id);
+        let watchpoint = self.watchpoints.iter().find(|w| w.id == id);
+        breakpoint
+            .map(|b| b.hit_count)
+            .or_else(|| watchpoint.map(|w| w.hit_count))
+    }
+
+    fn take_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+    // enabled, hit count and ignore count of either kind
+    fn update(&mut self, id: u32, change: impl FnOnce(&mut bool, &mut u32, &mut u32)) -> bool {
+        if let Some(b) = self.breakpoints.iter_mut().find(|b| b.id == id) {
+            change(&mut b.enabled, &mut b.hit_count, &mut b.ignore_count);
+            return true;
+        }
+        if let Some(w) = self.watchpoints.iter_mut().find(|w| w.id == id) {
+            change(&mut w.enabled, &mut w.hit_count, &mut w.ignore_count);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn check_breakpoints(&mut self, bank: usize, cpu: &CPU) -> Option<StopReason> {
+        let address = cpu.pc;
+        let mut stop = None;
+        for breakpoint in &mut self.breakpoints {
+            if !breakpoint.enabled
+                || breakpoint.address != address
+                || breakpoint.bank.is_some_and(|b| b != bank)
+                || !breakpoint.condition.is_none_or(|c| c.matches(cpu))
+            {
+                continue;
+            }
+            breakpoint.hit_count += 1;
+            if stop.is_none() && breakpoint.hit_count > breakpoint.ignore_count {
+                self.resume_from = Some(address);
+                stop = Some(StopReason::Breakpoint {
+                    id: breakpoint.id,
+                    bank,
+                    address,
+                });
+            }
+        }
+        stop
+    }
+
+    pub(crate) fn check_watch_hits(&mut self, hits: &[WatchHit], cpu: &CPU) -> Option<StopReason> {
+        let mut stop = None;
+        for hit in hits {
+            let watchpoint = &mut self.watchpoints[hit.index];
+            if !watchpoint.condition.is_none_or(|c| c.matches(cpu)) {
+                continue;
+            }
+            watchpoint.hit_count += 1;
+            if stop.is_none() && watchpoint.hit_count > watchpoint.ignore_count {
+                stop = Some(StopReason::Watchpoint {
+                    id: watchpoint.id,
+                    address: hit.address,
+                    value: hit.value,
+                    write: hit.write,
+                });
+            }
+        }
+        stop
+    }
+
+    /*
+    Follows calls and interrupts from SP: frames whose return address was popped
+    are dropped, then a taken CALL/RST or an interrupt dispatch adds one.
+    Games that move SP by hand just lose the frames above it.
+    */
+    pub(crate) fn update_call_stack(&mut self, pc: u16, sp: u16, cpu: &CPU, bus: &Bus) {
+        while self
+            .call_stack
+            .last()
+            .is_some_and(|frame| frame.sp < cpu.sp)
+        {
+            self.call_stack.pop();
+        }
+        if cpu.sp >= sp {
+            return;
+        }
+        let call = self.last_opcode.and_then(call_length);
+        let interrupt = INTERRUPT_VECTORS.contains(&cpu.pc);
+        let return_address = match call {
+            Some(length) if !interrupt => pc.wrapping_add(length),
+            _ if interrupt => bus.read_word(cpu.sp),
+            _ => return,
+        };
+        self.call_stack.push(CallFrame {
+            target: cpu.pc,
+            bank: bus.bank_at(cpu.pc),
+            return_address,
+            sp: cpu.sp,
+            interrupt,
+        });
+    }
+}
+
+/*
+The bus as the CPU sees it while watchpoints are set, accesses by the PPU,
+APU and DMA don't go through here. Reads of the instruction's own bytes are
+opcode fetches, not data reads, and are left out.
+*/
+pub(crate) struct WatchedMemory<'a> {
+    bus: &'a mut Bus,
+    watchpoints: &'a [Watchpoint],
+    pc: u16,
+    hits: RefCell<Vec<WatchHit>>,
+}
+
+impl<'a> WatchedMemory<'a> {
+    pub fn new(bus: &'a mut Bus, watchpoints: &'a [Watchpoint], pc: u16) -> Self {
+        Self {
+            bus,
+            watchpoints,
+            pc,
+            hits: RefCell::new(Vec::new()),
+        }
+    }
+    pub fn into_hits(self) -> Vec<WatchHit> {
+        self.hits.into_inner()
+    }
+    fn record(&self, address: u16, value: u8, write: bool) {
+        for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+            if watchpoint.enabled
+                && (watchpoint.start..=watchpoint.end).contains(&address)
+                && watchpoint.kind.matches(write)
+            {
+                self.hits.borrow_mut().push(WatchHit {
+                    index,
+                    address,
+                    value,
+                    write,
+                });
+            }
+        }
+    }
+}
+
+impl MemoryInterface for WatchedMemory<'_> {
+    fn read_byte(&self, address: u16) -> u8 {
+        let value = self.bus.read_byte(address);
+        if address.wrapping_sub(self.pc) >= 3 {
+            self.record(address, value, false);
+        }
+        value
+    }
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.record(address, value, true);
+        self.bus.write_byte(address, value);
+    }
+    fn read_wave_ram(&self) -> [u8; 16] {
+        self.bus.read_wave_ram()
+    }
+    fn gb_mode(&self) -> GameboyMode {
+        self.bus.gb_mode()
+    }
+    fn cgb(&self) -> &CgbRegisters {
+        self.bus.cgb()
+    }
+    fn read_byte_vram_bank(&self, address: u16, bank: usize) -> u8 {
+        self.bus.read_byte_vram_bank(address, bank)
+    }
+    fn write_byte_vram_bank(&mut self, address: u16, value: u8, bank: usize) {
+        self.bus.write_byte_vram_bank(address, value, bank)
+    }
+    fn set_stat(&mut self, value: u8) {
+        self.bus.set_stat(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gameboy::Gameboy, test_util::gameboy_with_program};
+
+    fn gameboy() -> Gameboy {
+        let program = [
+            &[0x3E, 0x05][..],   // 0150: LD A, $05
+            &[0xCD, 0x60, 0x01], // 0152: CALL $0160
+            &[0xEA, 0x00, 0xC0], // 0155: LD ($C000), A
+            &[0x18, 0xF8],       // 0158: JR $0152
+            &[0x00; 6],          // 015A: NOP padding
+            &[0x3C, 0xC9],       // 0160: INC A; RET
+        ]
+        .concat();
+        gameboy_with_program(&program)
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        let condition = Condition::parse("a == $10").unwrap();
+        assert_eq!(condition.register, Register::A);
+        assert_eq!(condition.comparison, Comparison::Equal);
+        assert_eq!(condition.value, 0x10);
+        assert_eq!(condition.to_string(), "a == $10");
+
+        let condition = Condition::parse("HL >= 0xC000").unwrap();
+        assert_eq!(condition.register, Register::HL);
+        assert_eq!(condition.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(condition.value, 0xC000);
+
+        let condition = Condition::parse("sp<57344").unwrap();
+        assert_eq!(condition.comparison, Comparison::Less);
+        assert_eq!(condition.value, 0xE000);
+
+        assert!(Condition::parse("a 10").is_err());
+        assert!(Condition::parse("x == 1").is_err());
+        assert!(Condition::parse("a == $1G").is_err());
+    }
+
+    #[test]
+    fn test_numbers_and_io_names() {
+        assert_eq!(parse_number("$FF40"), Ok(0xFF40));
+        assert_eq!(parse_number("0xff40"), Ok(0xFF40));
+        assert_eq!(parse_number("C000h"), Ok(0xC000));
+        assert_eq!(parse_number("100"), Ok(100));
+        assert!(parse_number("70000").is_err());
+        assert_eq!(io_register_address("rSTAT"), Some(0xFF41));
+        assert_eq!(io_register_address("lcdc"), Some(0xFF40));
+        assert_eq!(io_register_address("JOYP"), Some(0xFF00));
+        assert_eq!(io_register_address("FOO"), None);
+    }
+
+    #[test]
+    fn test_ids_are_shared() {
+        let mut debugger = Debugger::new();
+        let breakpoint = debugger.add_breakpoint(None, 0x150, None);
+        let watchpoint = debugger.add_watchpoint(0xC010, 0xC000, WatchKind::Write, None);
+        assert_ne!(breakpoint, watchpoint);
+        assert_eq!(debugger.watchpoints[0].start, 0xC000);
+        assert_eq!(debugger.watchpoints[0].end, 0xC010);
+
+        assert!(debugger.set_enabled(watchpoint, false));
+        assert!(!debugger.watchpoints[0].enabled);
+        assert!(debugger.remove(breakpoint));
+        assert!(!debugger.remove(breakpoint));
+        assert_eq!(debugger.hit_count(watchpoint), Some(0));
+        assert_eq!(debugger.hit_count(breakpoint), None);
+    }
+
+    #[test]
+    fn test_breakpoint_resumes() {
+        let mut gameboy = gameboy();
+        let id = gameboy.debugger.add_breakpoint(None, 0x160, None);
+        let stop = StopReason::Breakpoint {
+            id,
+            bank: 0,
+            address: 0x160,
+        };
+        assert_eq!(gameboy.run_until_break(), stop);
+        assert_eq!(gameboy.cpu.a, 5);
+        let frame = gameboy.debugger.call_stack[0];
+        assert_eq!(frame.target, 0x160);
+        assert_eq!(frame.return_address, 0x155);
+        assert!(!frame.interrupt);
+
+        // Running again starts with the instruction it stopped at
+        assert_eq!(gameboy.run_until_break(), stop);
+        assert_eq!(gameboy.cpu.a, 6);
+        assert_eq!(gameboy.debugger.hit_count(id), Some(2));
+        assert_eq!(gameboy.debugger.call_stack.len(), 1);
+
+        gameboy.debugger.set_enabled(id, false);
+        assert_eq!(gameboy.run_until_break(), StopReason::FrameComplete);
+    }
+
+    #[test]
+    fn test_conditions_and_ignore_counts() {
+        let mut gameboy = gameboy();
+        let condition = Condition::parse("a == 8").unwrap();
+        let id = gameboy
+            .debugger
+            .add_breakpoint(None, 0x155, Some(condition));
+        assert!(matches!(
+            gameboy.run_until_break(),
+            StopReason::Breakpoint { address: 0x155, .. }
+        ));
+        assert_eq!(gameboy.cpu.a, 8);
+        assert_eq!(gameboy.debugger.hit_count(id), Some(1));
+
+        let mut gameboy = self::gameboy();
+        let id = gameboy.debugger.add_breakpoint(None, 0x160, None);
+        gameboy.debugger.set_ignore_count(id, 2);
+        gameboy.run_until_break();
+        assert_eq!(gameboy.cpu.a, 7);
+        assert_eq!(gameboy.debugger.hit_count(id), Some(3));
+
+        // In another bank it never stops
+        let mut gameboy = self::gameboy();
+        gameboy.debugger.add_breakpoint(Some(1), 0x160, None);
+        assert_eq!(gameboy.run_until_break(), StopReason::FrameComplete);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut gameboy = gameboy();
+        gameboy
+            .debugger
+            .add_watchpoint(0xC000, 0xC000, WatchKind::Read, None);
+        let id = gameboy
+            .debugger
+            .add_watchpoint(0xC000, 0xC000, WatchKind::Write, None);
+        // Stops after the write
+        assert_eq!(
+            gameboy.run_until_break(),
+            StopReason::Watchpoint {
+                id,
+                address: 0xC000,
+                value: 6,
+                write: true,
+            }
+        );
+        assert_eq!(gameboy.cpu.pc, 0x158);
+        assert_eq!(gameboy.debugger.watchpoints[0].hit_count, 0);
+
+        let mut gameboy = self::gameboy();
+        let id = gameboy
+            .debugger
+            .add_io_watchpoint(IoRegister::Ly, WatchKind::Access);
+        assert_eq!(gameboy.debugger.watchpoints[0].start, 0xFF44);
+        assert_eq!(gameboy.run_until_break(), StopReason::FrameComplete);
+        assert_eq!(gameboy.debugger.hit_count(id), Some(0));
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut gameboy = gameboy();
+        assert_eq!(gameboy.step_instruction(), StopReason::Step);
+        assert_eq!(gameboy.cpu.pc, 0x101);
+        gameboy.step_instruction();
+        assert_eq!(gameboy.cpu.pc, 0x150);
+        gameboy.step_instruction();
+        assert_eq!(gameboy.cpu.pc, 0x152);
+
+        // Over the call, the routine still runs
+        assert_eq!(gameboy.step_over(), StopReason::Step);
+        assert_eq!(gameboy.cpu.pc, 0x155);
+        assert_eq!(gameboy.cpu.a, 6);
+        assert!(gameboy.debugger.call_stack.is_empty());
+
+        gameboy.step_instruction();
+        gameboy.step_instruction();
+        gameboy.step_instruction();
+        assert_eq!(gameboy.cpu.pc, 0x160);
+        assert_eq!(gameboy.debugger.call_stack.len(), 1);
+        assert_eq!(gameboy.step_out(), StopReason::Step);
+        assert_eq!(gameboy.cpu.pc, 0x155);
+        assert_eq!(gameboy.cpu.a, 7);
+        assert!(gameboy.debugger.call_stack.is_empty());
+    }
+}
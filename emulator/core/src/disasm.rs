@@ -0,0 +1,602 @@
+use std::fmt;
+
+use crate::{
+    bus::{Bus, MemoryInterface},
+    symbols::SymbolTable,
+};
+
+/*
+SM83 disassembler for the debuggers and the trace logger. Instructions are
+decoded from the current memory map, so banked code reads as whatever bank
+is switched in right now. Text output follows RGBDS syntax.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    // Bank mapped at address when it was decoded, see Bus::bank_at
+    pub bank: usize,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    pub length: u8,
+    // T cycles, for conditional instructions when the condition fails
+    pub cycles: u8,
+    // T cycles of a conditional jump, call or return that is taken
+    pub cycles_taken: Option<u8>,
+    // Where JR, JP, CALL and RST go, None when it depends on registers (JP HL, RET)
+    pub target: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    // a, hl, sp, af
+    Register(&'static str),
+    // [hl], [hl+], [bc], [c]
+    Indirect(&'static str),
+    Condition(&'static str),
+    Immediate8(u8),
+    Immediate16(u16),
+    // add sp, e8
+    Signed(i8),
+    // ld hl, sp + e8
+    SpOffset(i8),
+    // [n16]
+    Address(u16),
+    // ldh [n8], written as the full $FFxx address
+    HighAddress(u8),
+    // JR/JP/CALL destination
+    Target(u16),
+    // rst vector
+    Vector(u8),
+    // bit number of BIT, RES and SET
+    Bit(u8),
+}
+
+const REGISTERS: [Operand; 8] = [
+    Operand::Register("b"),
+    Operand::Register("c"),
+    Operand::Register("d"),
+    Operand::Register("e"),
+    Operand::Register("h"),
+    Operand::Register("l"),
+    Operand::Indirect("hl"),
+    Operand::Register("a"),
+];
+const PAIRS: [&str; 4] = ["bc", "de", "hl", "sp"];
+const STACK_PAIRS: [&str; 4] = ["bc", "de", "hl", "af"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ACCUMULATOR: [&str; 4] = ["bc", "de", "hl+", "hl-"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const ROTATES: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const MISC: [&str; 8] = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"];
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(name) | Operand::Condition(name) => write!(f, "{}", name),
+            Operand::Indirect(name) => write!(f, "[{}]", name),
+            Operand::Immediate8(value) => write!(f, "${:02X}", value),
+            Operand::Immediate16(value) | Operand::Target(value) => write!(f, "${:04X}", value),
+            Operand::Signed(value) => write!(f, "{}", value),
+            Operand::SpOffset(value) if *value < 0 => write!(f, "sp - {}", value.unsigned_abs()),
+            Operand::SpOffset(value) => write!(f, "sp + {}", value),
+            Operand::Address(address) => write!(f, "[${:04X}]", address),
+            Operand::HighAddress(offset) => write!(f, "[$FF{:02X}]", offset),
+            Operand::Vector(vector) => write!(f, "${:02X}", vector),
+            Operand::Bit(bit) => write!(f, "{}", bit),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    // "01:4000", the bank:address form of RGBDS .sym files
+    pub fn location(&self) -> String {
+        format_location(self.bank, self.address)
+    }
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length as u16)
+    }
+    pub fn is_call(&self) -> bool {
+        matches!(self.mnemonic, "call" | "rst")
+    }
+    pub fn is_return(&self) -> bool {
+        matches!(self.mnemonic, "ret" | "reti")
+    }
+    pub fn is_conditional(&self) -> bool {
+        self.cycles_taken.is_some()
+    }
+    // Like Display, with labels for targets and addresses in the banks mapped now
+    pub fn text_with_symbols(&self, bus: &Bus, symbols: &SymbolTable) -> String {
+        if symbols.is_empty() {
+            return self.to_string();
+        }
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|operand| {
+                let label = |address: u16| symbols.label(bus, address);
+                match *operand {
+                    Operand::Target(address) | Operand::Immediate16(address) => {
+                        label(address).map(str::to_string)
+                    }
+                    Operand::Address(address) => label(address).map(|name| format!("[{}]", name)),
+                    Operand::HighAddress(offset) => {
+                        label(0xFF00 | offset as u16).map(|name| format!("[{}]", name))
+                    }
+                    _ => None,
+                }
+                .unwrap_or_else(|| operand.to_string())
+            })
+            .collect();
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        } else {
+            format!("{} {}", self.mnemonic, operands.join(", "))
+        }
+    }
+    // "CD 50 01"
+    pub fn bytes_text(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub fn format_location(bank: usize, address: u16) -> String {
+    format!("{:02X}:{:04X}", bank, address)
+}
+
+// Decodes the instruction at address as the CPU would see it now
+pub fn decode(bus: &Bus, address: u16) -> Instruction {
+    let bytes = [
+        bus.read_byte(address),
+        bus.read_byte(address.wrapping_add(1)),
+        bus.read_byte(address.wrapping_add(2)),
+    ];
+    let mut instruction = decode_bytes(address, &bytes);
+    instruction.bank = bus.bank_at(address);
+    instruction
+}
+
+// count instructions in a row from address
+pub fn disassemble(bus: &Bus, address: u16, count: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = address;
+    for _ in 0..count {
+        let instruction = decode(bus, address);
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/*
+Decodes from raw bytes (up to three, missing ones read as 0), e.g. straight
+from a ROM file. The opcode is split into its x/y/z/p/q octal fields,
+the way the SM83 groups its instruction set.
+*/
+pub fn decode_bytes(address: u16, bytes: &[u8]) -> Instruction {
+    let byte = |index: usize| bytes.get(index).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let n8 = byte(1);
+    let n16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let relative = address.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 0x07;
+    let z = opcode & 0x07;
+    let p = (y >> 1) as usize;
+    let q = y & 0x01;
+    let r = |index: u8| REGISTERS[index as usize];
+    // [hl] operands take an extra memory access
+    let hl_cycles = |index: u8, cycles: u8, extra: u8| {
+        if index == 6 {
+            cycles + extra
+        } else {
+            cycles
+        }
+    };
+
+    let mut operands = Vec::new();
+    let mut cycles_taken = None;
+    let mut target = None;
+    let (mnemonic, length, cycles) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("nop", 1, 4),
+            1 => {
+                operands = vec![Operand::Address(n16), Operand::Register("sp")];
+                ("ld", 3, 20)
+            }
+            2 => ("stop", 2, 4),
+            3 => {
+                operands = vec![Operand::Target(relative)];
+                target = Some(relative);
+                ("jr", 2, 12)
+            }
+            _ => {
+                operands = vec![
+                    Operand::Condition(CONDITIONS[y as usize - 4]),
+                    Operand::Target(relative),
+                ];
+                target = Some(relative);
+                cycles_taken = Some(12);
+                ("jr", 2, 8)
+            }
+        },
+        (0, 1) if q == 0 => {
+            operands = vec![Operand::Register(PAIRS[p]), Operand::Immediate16(n16)];
+            ("ld", 3, 12)
+        }
+        (0, 1) => {
+            operands = vec![Operand::Register("hl"), Operand::Register(PAIRS[p])];
+            ("add", 1, 8)
+        }
+        (0, 2) => {
+            let memory = Operand::Indirect(ACCUMULATOR[p]);
+            operands = if q == 0 {
+                vec![memory, Operand::Register("a")]
+            } else {
+                vec![Operand::Register("a"), memory]
+            };
+            ("ld", 1, 8)
+        }
+        (0, 3) => {
+            operands = vec![Operand::Register(PAIRS[p])];
+            (if q == 0 { "inc" } else { "dec" }, 1, 8)
+        }
+        (0, 4) | (0, 5) => {
+            operands = vec![r(y)];
+            (if z == 4 { "inc" } else { "dec" }, 1, hl_cycles(y, 4, 8))
+        }
+        (0, 6) => {
+            operands = vec![r(y), Operand::Immediate8(n8)];
+            ("ld", 2, hl_cycles(y, 8, 4))
+        }
+        (0, 7) => (MISC[y as usize], 1, 4),
+        (1, 6) if y == 6 => ("halt", 1, 4),
+        (1, _) => {
+            operands = vec![r(y), r(z)];
+            ("ld", 1, hl_cycles(y, hl_cycles(z, 4, 4), 4))
+        }
+        (2, _) => {
+            operands = alu_operands(y, r(z));
+            (ALU[y as usize], 1, hl_cycles(z, 4, 4))
+        }
+        (3, 0) => match y {
+            0..=3 => {
+                operands = vec![Operand::Condition(CONDITIONS[y as usize])];
+                cycles_taken = Some(20);
+                ("ret", 1, 8)
+            }
+            4 => {
+                operands = vec![Operand::HighAddress(n8), Operand::Register("a")];
+                ("ldh", 2, 12)
+            }
+            5 => {
+                operands = vec![Operand::Register("sp"), Operand::Signed(n8 as i8)];
+                ("add", 2, 16)
+            }
+            6 => {
+                operands = vec![Operand::Register("a"), Operand::HighAddress(n8)];
+                ("ldh", 2, 12)
+            }
+            _ => {
+                operands = vec![Operand::Register("hl"), Operand::SpOffset(n8 as i8)];
+                ("ld", 2, 12)
+            }
+        },
+        (3, 1) if q == 0 => {
+            operands = vec![Operand::Register(STACK_PAIRS[p])];
+            ("pop", 1, 12)
+        }
+        (3, 1) => match p {
+            0 => ("ret", 1, 16),
+            1 => ("reti", 1, 16),
+            2 => {
+                operands = vec![Operand::Register("hl")];
+                ("jp", 1, 4)
+            }
+            _ => {
+                operands = vec![Operand::Register("sp"), Operand::Register("hl")];
+                ("ld", 1, 8)
+            }
+        },
+        (3, 2) => match y {
+            0..=3 => {
+                operands = vec![
+                    Operand::Condition(CONDITIONS[y as usize]),
+                    Operand::Target(n16),
+                ];
+                target = Some(n16);
+                cycles_taken = Some(16);
+                ("jp", 3, 12)
+            }
+            4 => {
+                operands = vec![Operand::Indirect("c"), Operand::Register("a")];
+                ("ldh", 1, 8)
+            }
+            5 => {
+                operands = vec![Operand::Address(n16), Operand::Register("a")];
+                ("ld", 3, 16)
+            }
+            6 => {
+                operands = vec![Operand::Register("a"), Operand::Indirect("c")];
+                ("ldh", 1, 8)
+            }
+            _ => {
+                operands = vec![Operand::Register("a"), Operand::Address(n16)];
+                ("ld", 3, 16)
+            }
+        },
+        (3, 3) if y == 0 => {
+            operands = vec![Operand::Target(n16)];
+            target = Some(n16);
+            ("jp", 3, 16)
+        }
+        (3, 3) if y == 1 => return decode_prefixed(address, n8),
+        (3, 3) if y == 6 => ("di", 1, 4),
+        (3, 3) if y == 7 => ("ei", 1, 4),
+        (3, 4) if y < 4 => {
+            operands = vec![
+                Operand::Condition(CONDITIONS[y as usize]),
+                Operand::Target(n16),
+            ];
+            target = Some(n16);
+            cycles_taken = Some(24);
+            ("call", 3, 12)
+        }
+        (3, 5) if q == 0 => {
+            operands = vec![Operand::Register(STACK_PAIRS[p])];
+            ("push", 1, 16)
+        }
+        (3, 5) if p == 0 => {
+            operands = vec![Operand::Target(n16)];
+            target = Some(n16);
+            ("call", 3, 24)
+        }
+        (3, 6) => {
+            operands = alu_operands(y, Operand::Immediate8(n8));
+            (ALU[y as usize], 2, 8)
+        }
+        (3, 7) => {
+            operands = vec![Operand::Vector(y * 8)];
+            target = Some(y as u16 * 8);
+            ("rst", 1, 16)
+        }
+        // The 11 unused opcodes lock up the CPU
+        _ => {
+            operands = vec![Operand::Immediate8(opcode)];
+            ("db", 1, 4)
+        }
+    };
+
+    Instruction {
+        address,
+        bank: 0,
+        bytes: (0..length as usize).map(byte).collect(),
+        mnemonic,
+        operands,
+        length,
+        cycles,
+        cycles_taken,
+        target,
+    }
+}
+
+// RGBDS keeps the "a, " for add, adc and sbc and drops it for the rest
+fn alu_operands(operation: u8, operand: Operand) -> Vec<Operand> {
+    match operation {
+        0 | 1 | 3 => vec![Operand::Register("a"), operand],
+        _ => vec![operand],
+    }
+}
+
+fn decode_prefixed(address: u16, opcode: u8) -> Instruction {
+    let y = (opcode >> 3) & 0x07;
+    let z = opcode & 0x07;
+    let register = REGISTERS[z as usize];
+    let (mnemonic, operands) = match opcode >> 6 {
+        0 => (ROTATES[y as usize], vec![register]),
+        1 => ("bit", vec![Operand::Bit(y), register]),
+        2 => ("res", vec![Operand::Bit(y), register]),
+        _ => ("set", vec![Operand::Bit(y), register]),
+    };
+    // BIT only reads [hl], the others write it back
+    let cycles = match (opcode >> 6, z) {
+        (1, 6) => 12,
+        (_, 6) => 16,
+        _ => 8,
+    };
+    Instruction {
+        address,
+        bank: 0,
+        bytes: vec![0xCB, opcode],
+        mnemonic,
+        operands,
+        length: 2,
+        cycles,
+        cycles_taken: None,
+        target: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Length and not taken T cycles of every unprefixed opcode, CB decodes as CB 00
+    const LENGTHS: [u8; 256] = [
+        1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1, //
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, //
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, //
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //
+        1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 2, 3, 3, 2, 1, //
+        1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 1, 2, 1, //
+        2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, //
+        2, 1, 1, 1, 1, 1, 2, 1, 2, 1, 3, 1, 1, 1, 2, 1, //
+    ];
+    const CYCLES: [u8; 256] = [
+        4, 12, 8, 8, 4, 4, 8, 4, 20, 8, 8, 8, 4, 4, 8, 4, //
+        4, 12, 8, 8, 4, 4, 8, 4, 12, 8, 8, 8, 4, 4, 8, 4, //
+        8, 12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4, //
+        8, 12, 8, 8, 12, 12, 12, 4, 8, 8, 8, 8, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4, //
+        8, 12, 12, 16, 12, 16, 8, 16, 8, 16, 12, 8, 12, 24, 8, 16, //
+        8, 12, 12, 4, 12, 16, 8, 16, 8, 16, 12, 4, 12, 4, 8, 16, //
+        12, 12, 8, 4, 4, 16, 8, 16, 16, 4, 16, 4, 4, 4, 8, 16, //
+        12, 12, 8, 4, 4, 16, 8, 16, 12, 8, 16, 4, 4, 4, 8, 16, //
+    ];
+    const ILLEGAL: [u8; 11] = [
+        0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+    ];
+
+    fn text(bytes: &[u8]) -> String {
+        decode_bytes(0x150, bytes).to_string()
+    }
+
+    #[test]
+    fn test_lengths_and_cycles() {
+        for opcode in 0..=255u8 {
+            let instruction = decode_bytes(0x150, &[opcode, 0x34, 0x12]);
+            let index = opcode as usize;
+            assert_eq!(instruction.length, LENGTHS[index], "{:02X}", opcode);
+            assert_eq!(instruction.cycles, CYCLES[index], "{:02X}", opcode);
+            assert_eq!(instruction.bytes.len(), LENGTHS[index] as usize);
+            assert_eq!(instruction.bytes[0], opcode);
+        }
+    }
+
+    #[test]
+    fn test_illegal_opcodes() {
+        for opcode in 0..=255u8 {
+            let instruction = decode_bytes(0, &[opcode]);
+            assert_eq!(
+                instruction.mnemonic == "db",
+                ILLEGAL.contains(&opcode),
+                "{:02X}",
+                opcode
+            );
+        }
+        let instruction = decode_bytes(0, &[0xDD, 0x12, 0x34]);
+        assert_eq!(instruction.to_string(), "db $DD");
+        assert_eq!(instruction.bytes, [0xDD]);
+    }
+
+    #[test]
+    fn test_conditional_timing() {
+        for opcode in 0..=255u8 {
+            let taken = match opcode {
+                0x20 | 0x28 | 0x30 | 0x38 => Some(12),
+                0xC0 | 0xC8 | 0xD0 | 0xD8 => Some(20),
+                0xC2 | 0xCA | 0xD2 | 0xDA => Some(16),
+                0xC4 | 0xCC | 0xD4 | 0xDC => Some(24),
+                _ => None,
+            };
+            let instruction = decode_bytes(0, &[opcode]);
+            assert_eq!(instruction.cycles_taken, taken, "{:02X}", opcode);
+            assert_eq!(instruction.is_conditional(), taken.is_some());
+        }
+    }
+
+    #[test]
+    fn test_prefixed() {
+        for opcode in 0..=255u8 {
+            let instruction = decode_bytes(0x150, &[0xCB, opcode]);
+            assert_eq!(instruction.length, 2);
+            assert_eq!(instruction.bytes, [0xCB, opcode]);
+            let mnemonic = match opcode >> 6 {
+                0 => ROTATES[(opcode >> 3) as usize & 7],
+                1 => "bit",
+                2 => "res",
+                _ => "set",
+            };
+            assert_eq!(instruction.mnemonic, mnemonic, "CB {:02X}", opcode);
+            let cycles = match (opcode >> 6, opcode & 7) {
+                (1, 6) => 12,
+                (_, 6) => 16,
+                _ => 8,
+            };
+            assert_eq!(instruction.cycles, cycles, "CB {:02X}", opcode);
+        }
+        assert_eq!(text(&[0xCB, 0x37]), "swap a");
+        assert_eq!(text(&[0xCB, 0x7E]), "bit 7, [hl]");
+        assert_eq!(text(&[0xCB, 0x80]), "res 0, b");
+        assert_eq!(text(&[0xCB, 0xFD]), "set 7, l");
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(&[0x00]), "nop");
+        assert_eq!(text(&[0x08, 0x00, 0xC0]), "ld [$C000], sp");
+        assert_eq!(text(&[0x22]), "ld [hl+], a");
+        assert_eq!(text(&[0x3A]), "ld a, [hl-]");
+        assert_eq!(text(&[0x36, 0x7F]), "ld [hl], $7F");
+        assert_eq!(text(&[0x76]), "halt");
+        assert_eq!(text(&[0x88]), "adc a, b");
+        assert_eq!(text(&[0xAF]), "xor a");
+        assert_eq!(text(&[0xFE, 0x90]), "cp $90");
+        assert_eq!(text(&[0xE0, 0x40]), "ldh [$FF40], a");
+        assert_eq!(text(&[0xF2]), "ldh a, [c]");
+        assert_eq!(text(&[0xE8, 0xFE]), "add sp, -2");
+        assert_eq!(text(&[0xF8, 0xFE]), "ld hl, sp - 2");
+        assert_eq!(text(&[0xF8, 0x05]), "ld hl, sp + 5");
+        assert_eq!(text(&[0xF1]), "pop af");
+        assert_eq!(text(&[0xE9]), "jp hl");
+        assert_eq!(text(&[0xD9]), "reti");
+        // Bytes past the end read as 0
+        assert_eq!(text(&[0x01]), "ld bc, $0000");
+    }
+
+    #[test]
+    fn test_targets() {
+        let instruction = decode_bytes(0x150, &[0x18, 0xFE]);
+        assert_eq!(instruction.to_string(), "jr $0150");
+        assert_eq!(instruction.target, Some(0x150));
+        assert_eq!(decode_bytes(0x150, &[0x20, 0x10]).target, Some(0x162));
+        assert_eq!(text(&[0x38, 0x00]), "jr c, $0152");
+
+        let instruction = decode_bytes(0x150, &[0xCC, 0x00, 0x40]);
+        assert_eq!(instruction.to_string(), "call z, $4000");
+        assert_eq!(instruction.target, Some(0x4000));
+        assert!(instruction.is_call());
+        assert_eq!(instruction.next_address(), 0x153);
+        assert_eq!(instruction.bytes_text(), "CC 00 40");
+
+        let instruction = decode_bytes(0x150, &[0xFF]);
+        assert_eq!(instruction.to_string(), "rst $38");
+        assert_eq!(instruction.target, Some(0x38));
+        assert!(instruction.is_call());
+
+        assert_eq!(decode_bytes(0x150, &[0xE9]).target, None);
+        assert!(decode_bytes(0x150, &[0xC0]).is_return());
+        assert_eq!(format_location(1, 0x4000), "01:4000");
+    }
+}
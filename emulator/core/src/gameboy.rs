@@ -1,9 +1,23 @@
 use crate::{
-    apu::APU,
-    bus::{io_address::IoRegister, Bus, BusState, GameboyMode, MemoryInterface},
+    apu::{vgm::ApuWriteLog, SampleRate, APU},
+    bus::{io_address::IoRegister, Bus, BusState, BusStateV1, GameboyMode, MemoryInterface},
+    cartridge::{
+        cartridge_header,
+        gbs::{GbsHeader, GBS_DRIVER_ADDRESS},
+        MbcType,
+    },
     cpu::{flags::Flags, CPUState, CPU},
-    ppu::{self, PPUState, PPU},
+    debugger::{self, Debugger, StopReason, WatchedMemory},
+    model::HardwareModel,
+    ppu::{
+        self,
+        compatibility::{self, CompatibilityPalette, PaletteCombo},
+        PPUState, PPUStateV1, PPU,
+    },
+    sgb::{FrameSize, Sgb, SGB_HEIGHT, SGB_WIDTH},
+    symbols::SymbolTable,
     timer::{Timer, TimerState},
+    trace::TraceLogger,
 };
 use bincode;
 use serde::{Deserialize, Serialize};
@@ -13,6 +27,14 @@ use std::{
     time::{Duration, Instant},
 };
 
+const CYCLES_PER_FRAME: usize = 70224;
+/*
+Save states start with this and a version byte. Version 1 states, from
+before the header existed, are the bare bincode of SerializableGameboyV1.
+*/
+const SAVE_STATE_MAGIC: &[u8; 4] = b"SBsv";
+const SAVE_STATE_VERSION: u8 = 2;
+
 #[derive(Clone, Serialize, Deserialize)]
 struct SerializableGameboy {
     cpu_state: CPUState,
@@ -20,6 +42,13 @@ struct SerializableGameboy {
     ppu_state: PPUState,
     bus_data: BusState,
 }
+#[derive(Deserialize)]
+struct SerializableGameboyV1 {
+    cpu_state: CPUState,
+    timer_state: TimerState,
+    ppu_state: PPUStateV1,
+    bus_data: BusStateV1,
+}
 #[derive(Clone, Debug)]
 pub struct Gameboy {
     pub cpu: CPU,
@@ -27,15 +56,26 @@ pub struct Gameboy {
     pub ppu: PPU,
     pub bus: Bus,
     pub apu: APU,
+    pub debugger: Debugger,
+    pub trace: Option<TraceLogger>,
+    // Labels for the debugger, kept across ROM loads
+    pub symbols: SymbolTable,
+    // Model picked by the user, None picks it from the cartridge header
+    requested_model: Option<HardwareModel>,
+    // GBC colors for DMG games, runs them on a CGB when no model is picked
+    colorize: bool,
 }
 
 impl Gameboy {
     pub fn new(palette: [u32; 4]) -> Self {
+        Self::with_sample_rate(palette, SampleRate::Hz48000)
+    }
+    pub fn with_sample_rate(palette: [u32; 4], sample_rate: SampleRate) -> Self {
         let bus = Bus::new();
         let timer = Timer::new();
         let cpu = CPU::new();
         let ppu = PPU::new(palette);
-        let apu = APU::new();
+        let apu = APU::new(sample_rate);
 
         Self {
             cpu,
@@ -43,6 +83,11 @@ impl Gameboy {
             bus,
             ppu,
             apu,
+            debugger: Debugger::new(),
+            trace: None,
+            symbols: SymbolTable::new(),
+            requested_model: None,
+            colorize: false,
         }
     }
     pub fn save_state(&self) -> Result<Vec<u8>, std::io::Error> {
@@ -53,15 +98,38 @@ impl Gameboy {
             bus_data: self.bus.save_state(),
         };
 
-        bincode::serialize(&serializable_state)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Serialization failed"))
+        let mut state = SAVE_STATE_MAGIC.to_vec();
+        state.push(SAVE_STATE_VERSION);
+        bincode::serialize_into(&mut state, &serializable_state)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Serialization failed"))?;
+        Ok(state)
     }
 
     pub fn load_state(&mut self, state: Vec<u8>) -> Result<(), std::io::Error> {
-        let serializable_state: SerializableGameboy =
-            bincode::deserialize(&state).map_err(|_| {
-                std::io::Error::new(std::io::ErrorKind::Other, "Deserialization failed")
-            })?;
+        let failed =
+            |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let serializable_state: SerializableGameboy = match state.strip_prefix(SAVE_STATE_MAGIC) {
+            Some([SAVE_STATE_VERSION, data @ ..]) => bincode::deserialize(data)
+                .map_err(|_| failed("Deserialization failed".to_string()))?,
+            Some([version, ..]) => {
+                return Err(failed(format!(
+                    "Save state version {} isn't supported, this emulator reads up to version {}",
+                    version, SAVE_STATE_VERSION
+                )))
+            }
+            Some([]) => return Err(failed("Save state is empty".to_string())),
+            None => {
+                let old: SerializableGameboyV1 = bincode::deserialize(&state).map_err(|_| {
+                    failed("Not a save state, or one from an unsupported version".to_string())
+                })?;
+                SerializableGameboy {
+                    cpu_state: old.cpu_state,
+                    timer_state: old.timer_state,
+                    ppu_state: old.ppu_state.into(),
+                    bus_data: old.bus_data.upgrade(&self.bus),
+                }
+            }
+        };
 
         self.bus.load_state(serializable_state.bus_data);
         self.cpu.load_state(serializable_state.cpu_state);
@@ -71,17 +139,42 @@ impl Gameboy {
         Ok(())
     }
     pub fn reset(&mut self) {
+        if let Some(sgb) = &mut self.bus.sgb {
+            *sgb = Sgb::new(sgb.commands_enabled());
+        }
+        self.debugger.call_stack.clear();
+        self.debugger.resume_from = None;
         self.set_power_up_sequence();
     }
 
     pub fn tick(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            trace.log(&self.cpu, &self.bus, &self.symbols);
+        }
         self.cpu.tick(&mut self.bus);
+        self.tick_components();
+    }
+    // Everything but the CPU, for the cycles of the last instruction
+    fn tick_components(&mut self) {
         for _ in 0..self.cpu.cycles {
             self.timer.tick(&mut self.bus);
+            let in_vblank = self.ppu.is_new_frame();
             self.ppu.tick(&mut self.bus);
+            if !in_vblank && self.ppu.is_new_frame() {
+                if let Some(sgb) = &mut self.bus.sgb {
+                    sgb.end_frame(self.ppu.get_shade_buffer());
+                }
+                if let Some(trace) = &mut self.trace {
+                    trace.end_frame();
+                }
+                self.bus.apply_cheats();
+            }
             self.bus.mbc.tick();
             self.apu.tick(&mut self.bus);
         }
+        if let Some(log) = &mut self.bus.apu_log {
+            log.advance(self.cpu.cycles);
+        }
     }
     pub fn run_frame(&mut self) {
         // Run one frame worth of emulation
@@ -93,16 +186,359 @@ impl Gameboy {
         }
     }
 
+    /*
+    Debugger runs, each returns why it stopped. run_until_break stops at the end
+    of a frame at the latest so a frontend can keep drawing, step_over/step_out
+    give up after a second of emulated time.
+    */
+    pub fn run_until_break(&mut self) -> StopReason {
+        self.run_until(true, |_| false)
+    }
+    pub fn step_instruction(&mut self) -> StopReason {
+        self.debug_tick(false).unwrap_or(StopReason::Step)
+    }
+    // Runs a CALL or RST until it returns, other instructions are a single step
+    pub fn step_over(&mut self) -> StopReason {
+        let pc = self.cpu.pc;
+        let sp = self.cpu.sp;
+        match debugger::call_length(self.bus.read_byte(pc)) {
+            Some(length) if !self.cpu.halt => {
+                let return_address = pc.wrapping_add(length);
+                self.run_until(false, |gameboy| {
+                    gameboy.cpu.pc == return_address && gameboy.cpu.sp >= sp
+                })
+            }
+            _ => self.step_instruction(),
+        }
+    }
+    // Runs until the current routine returns to its caller
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.cpu.sp;
+        self.run_until(false, |gameboy| {
+            gameboy
+                .debugger
+                .last_opcode
+                .is_some_and(debugger::is_return)
+                && gameboy.cpu.sp > sp
+        })
+    }
+
+    fn run_until(&mut self, one_frame: bool, done: impl Fn(&Gameboy) -> bool) -> StopReason {
+        let (max_cycles, limit_reason) = if one_frame {
+            (CYCLES_PER_FRAME, StopReason::FrameComplete)
+        } else {
+            (CYCLES_PER_FRAME * 60, StopReason::Timeout)
+        };
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            let in_vblank = self.ppu.is_new_frame();
+            if let Some(reason) = self.debug_tick(true) {
+                return reason;
+            }
+            cycles += self.cpu.cycles;
+            if done(self) {
+                return StopReason::Step;
+            }
+            if one_frame && !in_vblank && self.ppu.is_new_frame() {
+                break;
+            }
+        }
+        limit_reason
+    }
+
+    /*
+    One instruction with breakpoints, watchpoints and the call stack. The
+    breakpoint a run stopped at doesn't stop the next one again, so it can continue
+    */
+    fn debug_tick(&mut self, check_breakpoints: bool) -> Option<StopReason> {
+        let pc = self.cpu.pc;
+        let sp = self.cpu.sp;
+        let resuming = self.debugger.resume_from.take() == Some(pc);
+        if check_breakpoints && !resuming && !self.cpu.halt {
+            let bank = self.bus.bank_at(pc);
+            if let Some(reason) = self.debugger.check_breakpoints(bank, &self.cpu) {
+                return Some(reason);
+            }
+        }
+        self.debugger.last_opcode = (!self.cpu.halt).then(|| self.bus.read_byte(pc));
+        if let Some(trace) = &mut self.trace {
+            trace.log(&self.cpu, &self.bus, &self.symbols);
+        }
+
+        let hits = if self.debugger.watchpoints.is_empty() {
+            self.cpu.tick(&mut self.bus);
+            Vec::new()
+        } else {
+            let mut memory = WatchedMemory::new(&mut self.bus, &self.debugger.watchpoints, pc);
+            self.cpu.tick(&mut memory);
+            memory.into_hits()
+        };
+        self.tick_components();
+
+        self.debugger
+            .update_call_stack(pc, sp, &self.cpu, &self.bus);
+        self.debugger.check_watch_hits(&hits, &self.cpu)
+    }
+
+    /*
+    Audio as timing master: instead of running whole frames paced by a sleep,
+    the host asks for as many stereo samples as its audio buffer needs.
+    Returns true when a video frame was completed on the way.
+    Bounded to one frame of cycles in case the APU produces nothing (muted).
+    */
+    pub fn run_for_samples(&mut self, count: usize) -> bool {
+        let cycles_per_frame = 70224;
+        let target = self.apu.pending_samples() + count * 2;
+        let mut cycles = 0;
+        let mut frame_completed = false;
+        while self.apu.pending_samples() < target && cycles < cycles_per_frame {
+            let in_vblank = self.ppu.is_new_frame();
+            self.tick();
+            cycles += self.cpu.cycles;
+            if !in_vblank && self.ppu.is_new_frame() {
+                frame_completed = true;
+            }
+        }
+        frame_completed
+    }
+
     pub fn load_rom(&mut self, rom: &[u8]) {
-        self.bus.check__gb_mode(rom[0x143]);
-        self.set_power_up_sequence();
+        let requested = self
+            .requested_model
+            .or(self.colorize.then_some(HardwareModel::Cgb));
+        let model = HardwareModel::for_rom(rom, requested);
+        self.bus.model = model;
+        // CGB features only on a CGB, and only for games that ask for them
+        self.bus
+            .check__gb_mode(if model.is_cgb() { rom[0x143] } else { 0x00 });
+        self.bus.sgb = model
+            .is_sgb()
+            .then(|| Sgb::new(cartridge_header::supports_sgb(&rom[0x100..])));
+
+        // A CGB running a DMG game colors it with the compatibility palettes
+        self.bus.cgb.dmg_compatibility = model.is_cgb() && self.bus.gb_mode == GameboyMode::DMG;
+        if self.bus.cgb.dmg_compatibility {
+            // A combo held at boot wins over the title
+            let palette = match PaletteCombo::from_keys(self.bus.joypad.keys) {
+                Some(combo) => combo.palette(),
+                None => compatibility::palette_for_rom(rom),
+            };
+            self.bus.cgb.load_compatibility_palette(&palette);
+        }
         self.bus.load_rom(rom);
+        // Cheats are per ROM, the frontend loads the new game's
+        self.bus.cheats.clear();
+        self.debugger.call_stack.clear();
+        self.debugger.resume_from = None;
+        self.set_power_up_sequence();
+    }
+    // Takes effect on the next load_rom, None picks the model from the cartridge header
+    pub fn set_model(&mut self, model: Option<HardwareModel>) {
+        self.requested_model = model;
+    }
+    pub fn model(&self) -> HardwareModel {
+        self.bus.model
+    }
+    pub fn is_sgb(&self) -> bool {
+        self.bus.sgb.is_some()
+    }
+    pub fn frame_buffer(&self, size: FrameSize) -> &[u32] {
+        match (&self.bus.sgb, size) {
+            (Some(sgb), FrameSize::Screen) => sgb.screen(),
+            (Some(sgb), FrameSize::Sgb) => sgb.frame(),
+            (None, _) => self.ppu.get_frame_buffer(),
+        }
+    }
+    // Width and height of frame_buffer(size)
+    pub fn frame_dimensions(&self, size: FrameSize) -> (usize, usize) {
+        match (&self.bus.sgb, size) {
+            (Some(_), FrameSize::Sgb) => (SGB_WIDTH, SGB_HEIGHT),
+            _ => (ppu::SCREEN_WIDTH as usize, ppu::SCREEN_HEIGHT as usize),
+        }
+    }
+    // Keys of SGB players 2-4 (1 based), in the same format as the joypad keys
+    pub fn set_player_keys(&mut self, player: usize, keys: u8) {
+        match (&mut self.bus.sgb, player) {
+            (_, 1) => self.bus.joypad.update_keys(keys),
+            (Some(sgb), 2..=4) => sgb.player_keys[player - 2] = keys,
+            _ => {}
+        }
+    }
+    // Players requested with MLT_REQ
+    pub fn player_count(&self) -> u8 {
+        self.bus.sgb.as_ref().map_or(1, |sgb| sgb.player_count())
+    }
+    // GBC colorization of DMG games, takes effect on the next load_rom unless set_model picked one
+    pub fn set_colorization(&mut self, enabled: bool) {
+        self.colorize = enabled;
+    }
+    // DMG game colorized by a CGB
+    pub fn is_colorized(&self) -> bool {
+        self.bus.cgb.dmg_compatibility && self.bus.gb_mode == GameboyMode::DMG
+    }
+    // Picks other colors while colorized, e.g. one of the button combos
+    pub fn set_compatibility_palette(&mut self, palette: &CompatibilityPalette) {
+        if self.is_colorized() {
+            self.bus.cgb.load_compatibility_palette(palette);
+        }
+    }
+    pub fn has_rumble(&self) -> bool {
+        self.bus.mbc.has_rumble()
+    }
+    // Rumble motor state right now
+    pub fn is_rumbling(&self) -> bool {
+        self.bus.mbc.is_rumbling()
+    }
+    // How much the motor was on since the last call (0.0 - 1.0), poll once per frame
+    pub fn rumble_strength(&mut self) -> f32 {
+        self.bus.mbc.take_rumble_strength()
+    }
+    // MBC7 cartridges (Kirby Tilt 'n' Tumble)
+    pub fn has_accelerometer(&self) -> bool {
+        self.bus.mbc.has_accelerometer()
+    }
+    // Tilt in g, positive x tilts right and positive y tilts down (towards the player)
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.bus.mbc.set_tilt(x, y);
+    }
+    // Game Boy Camera
+    pub fn has_camera(&self) -> bool {
+        matches!(self.bus.mbc, MbcType::Camera(_))
+    }
+    // 8 bit grayscale of any size, used for the next captures
+    pub fn set_camera_image(&mut self, pixels: &[u8], width: usize, height: usize) {
+        if let MbcType::Camera(camera) = &mut self.bus.mbc {
+            camera.set_image(pixels, width, height);
+        }
+    }
+    // Photos saved in the camera album, as PNG files
+    pub fn camera_photos_png(&self) -> Vec<Vec<u8>> {
+        match &self.bus.mbc {
+            MbcType::Camera(camera) => camera.saved_photos_png(),
+            _ => Vec::new(),
+        }
+    }
+    // Battery backed cartridge RAM, None when the cartridge has no battery
+    pub fn save_battery(&self) -> Option<Vec<u8>> {
+        if !self.bus.has_battery {
+            return None;
+        }
+        let mut battery = self.bus.mbc.ram()?.to_vec();
+        if let Some(rtc) = self.bus.mbc.rtc_save() {
+            battery.extend(rtc);
+        }
+        Some(battery)
+    }
+    pub fn load_battery(&mut self, data: &[u8]) {
+        if self.bus.has_battery {
+            let ram_size = self
+                .bus
+                .mbc
+                .ram()
+                .map_or(0, |ram| ram.len())
+                .min(data.len());
+            self.bus.mbc.load_ram(&data[..ram_size]);
+            self.bus.mbc.load_rtc_save(&data[ram_size..]);
+        }
+    }
+    // IR LED of the CGB RP port or the cartridge, and light coming back from the other side
+    pub fn infrared_led(&self) -> bool {
+        self.bus.infrared.led()
+    }
+    pub fn set_infrared_receiving(&mut self, receiving: bool) {
+        self.bus.infrared.receiving = receiving;
+    }
+    // Tone number a HuC3 cartridge asked its speaker to play
+    pub fn take_cartridge_tone(&mut self) -> Option<u8> {
+        self.bus.mbc.take_tone()
+    }
+    // Starts collecting sound register writes for a VGM export
+    pub fn start_apu_log(&mut self) {
+        self.bus.apu_log = Some(ApuWriteLog::new(&self.bus));
+    }
+    pub fn mark_apu_loop(&mut self) {
+        if let Some(log) = &mut self.bus.apu_log {
+            log.mark_loop();
+        }
+    }
+    pub fn stop_apu_log(&mut self) -> Option<ApuWriteLog> {
+        self.bus.apu_log.take()
+    }
+    // Instruction trace, see TraceLogger for the triggers and size limit
+    pub fn start_trace(&mut self, trace: TraceLogger) {
+        self.trace = Some(trace);
+    }
+    pub fn stop_trace(&mut self) -> Option<TraceLogger> {
+        self.trace.take()
+    }
+    pub fn load_gbs(&mut self, data: &[u8]) -> Result<GbsHeader, String> {
+        let header = GbsHeader::parse(data)?;
+        self.bus.load_gbs(&header, data);
+        self.set_power_up_sequence();
+        Ok(header)
+    }
+    /*
+    Starts a GBS song (0 based): clears RAM, sets up the timer and interrupts from the header
+    and jumps to the driver which calls INIT with the song number in A.
+    PLAY is then called from the VBlank or timer interrupt vector.
+    */
+    pub fn start_gbs_song(&mut self, header: &GbsHeader, song: u8) {
+        if let MbcType::Gbs(gbs) = &mut self.bus.mbc {
+            gbs.reset();
+        }
+        for address in 0xC000..=0xDFFF {
+            self.bus.write_byte(address, 0);
+        }
+        for address in 0xFF80..=0xFFFE {
+            self.bus.write_byte(address, 0);
+        }
+
+        // Sound on, every channel silent
+        self.bus.write_byte(IoRegister::Nr52.address(), 0x80);
+        for address in IoRegister::Nr10.address()..=IoRegister::Nr51.address() {
+            self.bus.write_byte(address, 0);
+        }
+        self.bus.write_byte(IoRegister::Nr50.address(), 0x77);
+        self.bus.write_byte(IoRegister::Nr51.address(), 0xFF);
+
+        self.bus
+            .write_byte(IoRegister::Tma.address(), header.timer_modulo);
+        self.bus
+            .write_byte(IoRegister::Tac.address(), header.timer_control & 0x07);
+        self.bus.write_byte(IoRegister::If.address(), 0);
+        let interrupt = if header.uses_timer() { 0x04 } else { 0x01 };
+        self.bus.write_byte(IoRegister::Ie.address(), interrupt);
+
+        self.cpu = CPU::new();
+        self.cpu.a = song;
+        self.cpu.sp = header.stack_pointer;
+        self.cpu.pc = GBS_DRIVER_ADDRESS;
     }
     pub fn set_power_up_sequence(&mut self) {
         match self.bus.gb_mode() {
             GameboyMode::DMG => self.set_power_up_sequence_dmg(),
             GameboyMode::CGB => self.set_power_up_sequence_cgb(),
         }
+        self.set_model_registers();
+    }
+    // What differs between models of the same mode, from the boot ROM of each
+    fn set_model_registers(&mut self) {
+        let header: Vec<u8> = (0..0x150)
+            .map(|address| self.bus.read_byte(address))
+            .collect();
+        let cgb_mode = self.bus.gb_mode == GameboyMode::CGB;
+        let registers = self.bus.model.boot_registers(&header, cgb_mode);
+        self.cpu.a = registers.a;
+        self.cpu.f = Flags::from_bits_truncate(registers.f);
+        self.cpu.b = registers.b;
+        self.cpu.c = registers.c;
+        self.cpu.d = registers.d;
+        self.cpu.e = registers.e;
+        self.cpu.h = registers.h;
+        self.cpu.l = registers.l;
+        self.bus
+            .write_byte(IoRegister::Div.address(), registers.div);
+        self.bus.set_stat(registers.stat);
     }
     fn set_power_up_sequence_dmg(&mut self) {
         // Set initial GB state after boot
@@ -122,7 +558,8 @@ impl Gameboy {
         self.cpu.ime = false;
 
         // Hardware Registers
-        self.bus.write_byte(IoRegister::Joyp.address(), 0xCF);
+        // Straight to the joypad, on an SGB both lines low would be a reset pulse
+        self.bus.joypad.write(0xCF);
         self.bus.write_byte(IoRegister::Sb.address(), 0x00);
         self.bus.write_byte(IoRegister::Sc.address(), 0x7E);
         self.bus.write_byte(IoRegister::Div.address(), 0xAB);
@@ -156,7 +593,7 @@ impl Gameboy {
 
         // LCD Registers
         self.bus.write_byte(IoRegister::Lcdc.address(), 0x91);
-        self.bus.write_byte(IoRegister::Stat.address(), 0x85);
+        self.bus.set_stat(0x85);
         self.bus.write_byte(IoRegister::Scy.address(), 0x00);
         self.bus.write_byte(IoRegister::Scx.address(), 0x00);
         self.bus.write_byte(IoRegister::Ly.address(), 0x00);
@@ -188,7 +625,8 @@ impl Gameboy {
         self.cpu.ime = false;
 
         // Hardware Registers
-        self.bus.write_byte(IoRegister::Joyp.address(), 0xCF);
+        // Straight to the joypad, on an SGB both lines low would be a reset pulse
+        self.bus.joypad.write(0xCF);
         self.bus.write_byte(IoRegister::Sb.address(), 0x00);
         self.bus.write_byte(IoRegister::Sc.address(), 0x7F);
         self.bus.write_byte(IoRegister::Div.address(), 0xAB); // ??
@@ -222,7 +660,7 @@ impl Gameboy {
 
         // LCD Registers
         self.bus.write_byte(IoRegister::Lcdc.address(), 0x91);
-        self.bus.write_byte(IoRegister::Stat.address(), 0x85); //??
+        self.bus.set_stat(0x85); //??
         self.bus.write_byte(IoRegister::Scy.address(), 0x00);
         self.bus.write_byte(IoRegister::Scx.address(), 0x00);
         self.bus.write_byte(IoRegister::Ly.address(), 0x00); //??
@@ -271,3 +709,60 @@ impl Gameboy {
         self.bus.cgb.hdma_active = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::gameboy_with_program;
+
+    fn gameboy() -> Gameboy {
+        // INC A; INC B; JR -4
+        gameboy_with_program(&[0x3C, 0x04, 0x18, 0xFC])
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        let mut gameboy = gameboy();
+        for _ in 0..1000 {
+            gameboy.tick();
+        }
+        let state = gameboy.save_state().unwrap();
+        assert_eq!(state[..4], *SAVE_STATE_MAGIC);
+        assert_eq!(state[4], SAVE_STATE_VERSION);
+
+        let mut other = self::gameboy();
+        other.load_state(state.clone()).unwrap();
+        assert_eq!(other.save_state().unwrap(), state);
+
+        // Both carry on the same from there
+        for _ in 0..1000 {
+            gameboy.tick();
+            other.tick();
+        }
+        assert_eq!(other.save_state().unwrap(), gameboy.save_state().unwrap());
+    }
+
+    #[test]
+    fn test_load_state_rejects_newer_versions_and_garbage() {
+        let mut gameboy = gameboy();
+        let mut state = gameboy.save_state().unwrap();
+        state[4] = SAVE_STATE_VERSION + 1;
+        let error = gameboy.load_state(state).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Save state version {} isn't supported, this emulator reads up to version {}",
+                SAVE_STATE_VERSION + 1,
+                SAVE_STATE_VERSION
+            )
+        );
+
+        let error = gameboy.load_state(b"SBsv".to_vec()).unwrap_err();
+        assert_eq!(error.to_string(), "Save state is empty");
+        let error = gameboy.load_state(vec![0xFF; 16]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Not a save state, or one from an unsupported version"
+        );
+    }
+}
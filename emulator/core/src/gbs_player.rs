@@ -0,0 +1,155 @@
+use crate::{cartridge::gbs::GbsHeader, gameboy::Gameboy};
+
+// Frames per second of the DMG (4194304 / 70224)
+const FRAME_RATE: f64 = 59.7275;
+
+/*
+Plays the songs of a GBS file on a Gameboy.
+Songs have no natural end, an optional length and fade out (in frames)
+is applied through the APU volume for rendering songs to files.
+*/
+#[derive(Clone, Debug)]
+pub struct GbsPlayer {
+    pub header: GbsHeader,
+    current_song: u8,
+    frames_played: usize,
+    length_frames: Option<usize>,
+    fade_frames: usize,
+}
+
+impl GbsPlayer {
+    pub fn load(gameboy: &mut Gameboy, data: &[u8]) -> Result<Self, String> {
+        let header = gameboy.load_gbs(data)?;
+        let first_song = header.first_song.saturating_sub(1);
+        let mut player = Self {
+            header,
+            current_song: 0,
+            frames_played: 0,
+            length_frames: None,
+            fade_frames: 0,
+        };
+        player.play_song(gameboy, first_song);
+        Ok(player)
+    }
+
+    // 0 based, wraps around the song count
+    pub fn play_song(&mut self, gameboy: &mut Gameboy, song: u8) {
+        self.current_song = song % self.header.song_count;
+        self.frames_played = 0;
+        gameboy.apu.volume = 1.0;
+        gameboy.start_gbs_song(&self.header, self.current_song);
+    }
+    pub fn next_song(&mut self, gameboy: &mut Gameboy) {
+        let song = (self.current_song as u16 + 1) % self.header.song_count as u16;
+        self.play_song(gameboy, song as u8);
+    }
+    pub fn previous_song(&mut self, gameboy: &mut Gameboy) {
+        let song = (self.current_song as u16 + self.header.song_count as u16 - 1)
+            % self.header.song_count as u16;
+        self.play_song(gameboy, song as u8);
+    }
+    pub fn current_song(&self) -> u8 {
+        self.current_song
+    }
+
+    // Length excludes the fade, None plays forever
+    pub fn set_length(&mut self, length_seconds: Option<f64>, fade_seconds: f64) {
+        self.length_frames = length_seconds.map(|seconds| (seconds * FRAME_RATE) as usize);
+        self.fade_frames = (fade_seconds * FRAME_RATE) as usize;
+    }
+    // Total frames of the song including the fade, None when endless
+    pub fn total_frames(&self) -> Option<usize> {
+        self.length_frames.map(|length| length + self.fade_frames)
+    }
+    pub fn is_finished(&self) -> bool {
+        self.total_frames()
+            .is_some_and(|total| self.frames_played >= total)
+    }
+
+    pub fn run_frame(&mut self, gameboy: &mut Gameboy) {
+        if let Some(length) = self.length_frames {
+            gameboy.apu.volume = if self.frames_played < length {
+                1.0
+            } else if self.fade_frames == 0 {
+                0.0
+            } else {
+                let fade_position = (self.frames_played - length) as f32 / self.fade_frames as f32;
+                (1.0 - fade_position).max(0.0)
+            };
+        }
+        gameboy.run_frame();
+        self.frames_played += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemoryInterface;
+    use crate::test_util::PALETTE;
+
+    /*
+    INIT stores the song number at C000, PLAY counts its calls at C001,
+    called from VBlank
+    */
+    fn gbs_file() -> Vec<u8> {
+        let mut data = vec![0; 0x70];
+        data[0..6].copy_from_slice(&[b'G', b'B', b'S', 1, 3, 1]);
+        data[0x06..0x08].copy_from_slice(&0x0400u16.to_le_bytes());
+        data[0x08..0x0A].copy_from_slice(&0x0400u16.to_le_bytes());
+        data[0x0A..0x0C].copy_from_slice(&0x0410u16.to_le_bytes());
+        data[0x0C..0x0E].copy_from_slice(&0xDFFEu16.to_le_bytes());
+        let mut code = vec![0; 0x20];
+        code[0x00..0x04].copy_from_slice(&[0xEA, 0x00, 0xC0, 0xC9]);
+        code[0x10..0x15].copy_from_slice(&[0x21, 0x01, 0xC0, 0x34, 0xC9]);
+        data.extend_from_slice(&code);
+        data
+    }
+
+    #[test]
+    fn test_init_gets_the_song_and_play_runs_every_frame() {
+        let mut gameboy = Gameboy::new(PALETTE);
+        let mut player = GbsPlayer::load(&mut gameboy, &gbs_file()).unwrap();
+        assert_eq!(player.current_song(), 0);
+
+        for _ in 0..10 {
+            player.run_frame(&mut gameboy);
+        }
+        assert_eq!(gameboy.bus.read_byte(0xC000), 0);
+        let calls = gameboy.bus.read_byte(0xC001);
+        assert!((9..=10).contains(&calls), "PLAY ran {} times", calls);
+
+        player.play_song(&mut gameboy, 2);
+        player.run_frame(&mut gameboy);
+        assert_eq!(gameboy.bus.read_byte(0xC000), 2);
+        assert!(gameboy.bus.read_byte(0xC001) <= 1);
+    }
+
+    #[test]
+    fn test_song_selection_wraps() {
+        let mut gameboy = Gameboy::new(PALETTE);
+        let mut player = GbsPlayer::load(&mut gameboy, &gbs_file()).unwrap();
+        player.previous_song(&mut gameboy);
+        assert_eq!(player.current_song(), 2);
+        player.next_song(&mut gameboy);
+        assert_eq!(player.current_song(), 0);
+        player.play_song(&mut gameboy, 4);
+        assert_eq!(player.current_song(), 1);
+    }
+
+    #[test]
+    fn test_fade_out() {
+        let mut gameboy = Gameboy::new(PALETTE);
+        let mut player = GbsPlayer::load(&mut gameboy, &gbs_file()).unwrap();
+        assert_eq!(player.total_frames(), None);
+
+        player.set_length(Some(2.0 / FRAME_RATE), 4.0 / FRAME_RATE);
+        assert_eq!(player.total_frames(), Some(6));
+        let mut volumes = Vec::new();
+        while !player.is_finished() {
+            player.run_frame(&mut gameboy);
+            volumes.push(gameboy.apu.volume);
+        }
+        assert_eq!(volumes, [1.0, 1.0, 1.0, 0.75, 0.5, 0.25]);
+    }
+}
@@ -50,16 +50,20 @@ impl Joypad {
     }
 
     pub fn read(&self) -> u8 {
+        self.read_keys(self.keys)
+    }
+    // The register as seen with another set of keys, e.g. SGB multiplayer
+    pub fn read_keys(&self, keys: u8) -> u8 {
         let mut result = 0xCF; // Bit 6 and 7 are always set. Buttons released
 
         let direction_select = self.register & 0x10 == 0;
         let action_select = self.register & 0x20 == 0;
 
         if direction_select {
-            result &= !(0x0F & !self.keys);
+            result &= !(0x0F & !keys);
         }
         if action_select {
-            result &= !(0x0F & !(self.keys >> 4));
+            result &= !(0x0F & !(keys >> 4));
         }
 
         result
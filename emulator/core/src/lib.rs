@@ -1,10 +1,22 @@
 pub mod apu;
 pub mod bus;
 pub mod cartridge;
+pub mod cheats;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
 pub mod gameboy;
+pub mod gbs_player;
 pub mod joyp;
+pub mod model;
+pub mod png;
 pub mod ppu;
+pub mod ram_search;
+pub mod sgb;
+pub mod symbols;
 pub mod test;
 pub mod test2;
+#[cfg(test)]
+pub(crate) mod test_util;
 pub mod timer;
+pub mod trace;
@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ppu::compatibility::{is_nintendo, title_checksum};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum HardwareModel {
+    // Early DMG boot ROM
+    Dmg0,
+    Dmg,
+    // Game Boy Pocket/Light
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    // Game Boy Advance
+    Agb,
+}
+
+// CPU registers, DIV and STAT as the boot ROM leaves them
+#[derive(Clone, Copy, Debug)]
+pub struct BootRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub div: u8,
+    pub stat: u8,
+}
+
+impl HardwareModel {
+    pub const ALL: [HardwareModel; 7] = [
+        HardwareModel::Dmg0,
+        HardwareModel::Dmg,
+        HardwareModel::Mgb,
+        HardwareModel::Sgb,
+        HardwareModel::Sgb2,
+        HardwareModel::Cgb,
+        HardwareModel::Agb,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HardwareModel::Dmg0 => "dmg0",
+            HardwareModel::Dmg => "dmg",
+            HardwareModel::Mgb => "mgb",
+            HardwareModel::Sgb => "sgb",
+            HardwareModel::Sgb2 => "sgb2",
+            HardwareModel::Cgb => "cgb",
+            HardwareModel::Agb => "agb",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|model| model.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, HardwareModel::Cgb | HardwareModel::Agb)
+    }
+    pub fn is_sgb(&self) -> bool {
+        matches!(self, HardwareModel::Sgb | HardwareModel::Sgb2)
+    }
+
+    /*
+    Writing STAT on the DMG family enables every STAT source for a cycle, so a
+    write outside of mode 3 or while LY=LYC requests a STAT interrupt.
+    Games like Road Rash and Zerd no Densetsu depend on it, the CGB fixed it.
+    */
+    pub fn has_stat_write_bug(&self) -> bool {
+        !self.is_cgb()
+    }
+
+    // FEA0-FEFF isn't memory: 00 on the DMG family, the CGB-E and AGB repeat the address' high nibble
+    pub fn read_unusable(&self, address: u16) -> u8 {
+        if self.is_cgb() {
+            let nibble = (address as u8) & 0xF0;
+            nibble | nibble >> 4
+        } else {
+            0x00
+        }
+    }
+
+    /*
+    The model a cartridge gets without a choice from the user: header byte 0x143
+    bit 7 means CGB features, 0xC0 means the game doesn't run on older models.
+    CGB only games are moved to a CGB, CGB compatible ones can be forced to DMG mode.
+    */
+    pub fn for_rom(rom: &[u8], requested: Option<HardwareModel>) -> Self {
+        let cgb_flag = rom[0x143];
+        match requested {
+            Some(model) if cgb_flag == 0xC0 && !model.is_cgb() => {
+                println!(
+                    "CGB only game, running on a CGB instead of {}",
+                    model.name()
+                );
+                HardwareModel::Cgb
+            }
+            Some(model) => model,
+            None if cgb_flag & 0x80 != 0 => HardwareModel::Cgb,
+            None => HardwareModel::Dmg,
+        }
+    }
+
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+    pub fn boot_registers(&self, rom: &[u8], cgb_mode: bool) -> BootRegisters {
+        let header_checksum = rom[0x14D];
+        // Z, then H and C unless the header checksum is 0
+        let dmg_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+        let dmg = BootRegisters {
+            a: 0x01,
+            f: dmg_flags,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            div: 0xAB,
+            stat: 0x85,
+        };
+        let sgb = BootRegisters {
+            f: 0x00,
+            c: 0x14,
+            e: 0x00,
+            h: 0xC0,
+            l: 0x60,
+            ..dmg
+        };
+        let cgb = if cgb_mode {
+            BootRegisters {
+                a: 0x11,
+                f: 0x80,
+                b: 0x00,
+                c: 0x00,
+                d: 0xFF,
+                e: 0x56,
+                h: 0x00,
+                l: 0x0D,
+                ..dmg
+            }
+        } else {
+            // The boot ROM leaves the title checksum it used for the palette in B
+            let b = if is_nintendo(rom) {
+                title_checksum(rom)
+            } else {
+                0
+            };
+            // and HL pointing into the logo tilemap for two of the titles
+            let [h, l] = match b {
+                0x43 | 0x58 => [0x99, 0x1A],
+                _ => [0x00, 0x7C],
+            };
+            BootRegisters {
+                a: 0x11,
+                f: 0x80,
+                b,
+                c: 0x00,
+                d: 0x00,
+                e: 0x08,
+                h,
+                l,
+                ..dmg
+            }
+        };
+
+        match self {
+            HardwareModel::Dmg0 => BootRegisters {
+                f: 0x00,
+                b: 0xFF,
+                e: 0xC1,
+                h: 0x84,
+                l: 0x03,
+                div: 0x18,
+                stat: 0x81,
+                ..dmg
+            },
+            HardwareModel::Dmg => dmg,
+            // A = 0xFF is how games tell a Pocket or a SGB2 apart
+            HardwareModel::Mgb => BootRegisters { a: 0xFF, ..dmg },
+            HardwareModel::Sgb => sgb,
+            HardwareModel::Sgb2 => BootRegisters { a: 0xFF, ..sgb },
+            HardwareModel::Cgb => cgb,
+            // The AGB boot ROM ends with INC B, games check bit 0 of B for a GBA
+            HardwareModel::Agb => {
+                let b = cgb.b.wrapping_add(1);
+                let zero = if b == 0 { 0x80 } else { 0x00 };
+                let half_carry = if b & 0x0F == 0 { 0x20 } else { 0x00 };
+                BootRegisters {
+                    b,
+                    f: zero | half_carry | cgb.f & 0x10,
+                    ..cgb
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{io_address::IoRegister, MemoryInterface};
+    use crate::test_util::{gameboy_with_rom, write_header};
+
+    fn rom(title: &str, cgb_flag: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        write_header(&mut rom, title, 0x00);
+        rom[0x143] = cgb_flag;
+        // Nintendo
+        rom[0x14B] = 0x01;
+        rom[0x14D] = 0x12;
+        rom
+    }
+
+    // AF, BC, DE, HL
+    fn pairs(registers: BootRegisters) -> [u16; 4] {
+        [
+            [registers.a, registers.f],
+            [registers.b, registers.c],
+            [registers.d, registers.e],
+            [registers.h, registers.l],
+        ]
+        .map(u16::from_be_bytes)
+    }
+
+    #[test]
+    fn test_model_for_rom() {
+        let dmg_game = rom("", 0x00);
+        let cgb_game = rom("", 0x80);
+        let cgb_only = rom("", 0xC0);
+        assert_eq!(HardwareModel::for_rom(&dmg_game, None), HardwareModel::Dmg);
+        assert_eq!(HardwareModel::for_rom(&cgb_game, None), HardwareModel::Cgb);
+        assert_eq!(
+            HardwareModel::for_rom(&cgb_game, Some(HardwareModel::Dmg)),
+            HardwareModel::Dmg
+        );
+        assert_eq!(
+            HardwareModel::for_rom(&cgb_only, Some(HardwareModel::Sgb)),
+            HardwareModel::Cgb
+        );
+        assert_eq!(
+            HardwareModel::for_rom(&cgb_only, Some(HardwareModel::Agb)),
+            HardwareModel::Agb
+        );
+        for model in HardwareModel::ALL {
+            assert_eq!(HardwareModel::from_name(model.name()), Some(model));
+        }
+        assert_eq!(HardwareModel::from_name("SGB2"), Some(HardwareModel::Sgb2));
+        assert_eq!(HardwareModel::from_name("gba"), None);
+    }
+
+    #[test]
+    fn test_dmg_boot_registers() {
+        let rom = rom("", 0x00);
+        let registers = |model: HardwareModel| pairs(model.boot_registers(&rom, false));
+        assert_eq!(
+            registers(HardwareModel::Dmg0),
+            [0x0100, 0xFF13, 0x00C1, 0x8403]
+        );
+        assert_eq!(
+            registers(HardwareModel::Dmg),
+            [0x01B0, 0x0013, 0x00D8, 0x014D]
+        );
+        assert_eq!(
+            registers(HardwareModel::Mgb),
+            [0xFFB0, 0x0013, 0x00D8, 0x014D]
+        );
+        assert_eq!(
+            registers(HardwareModel::Sgb),
+            [0x0100, 0x0014, 0x0000, 0xC060]
+        );
+        assert_eq!(
+            registers(HardwareModel::Sgb2),
+            [0xFF00, 0x0014, 0x0000, 0xC060]
+        );
+        // H and C only come from a header checksum that isn't 0
+        let mut zero_checksum = rom.clone();
+        zero_checksum[0x14D] = 0;
+        assert_eq!(
+            HardwareModel::Dmg.boot_registers(&zero_checksum, false).f,
+            0x80
+        );
+    }
+
+    #[test]
+    fn test_cgb_boot_registers() {
+        let rom = rom("", 0x80);
+        assert_eq!(
+            pairs(HardwareModel::Cgb.boot_registers(&rom, true)),
+            [0x1180, 0x0000, 0xFF56, 0x000D]
+        );
+        // INC B after the CGB values
+        assert_eq!(
+            pairs(HardwareModel::Agb.boot_registers(&rom, true)),
+            [0x1100, 0x0100, 0xFF56, 0x000D]
+        );
+    }
+
+    #[test]
+    fn test_cgb_boot_registers_in_dmg_mode() {
+        let registers = |title: &str, model: HardwareModel| {
+            pairs(model.boot_registers(&rom(title, 0x00), false))
+        };
+        // B is the title checksum
+        assert_eq!(
+            registers("TETRIS", HardwareModel::Cgb),
+            [0x1180, 0xDB00, 0x0008, 0x007C]
+        );
+        // and HL points into the logo tilemap for $43 and $58
+        assert_eq!(
+            registers("C", HardwareModel::Cgb),
+            [0x1180, 0x4300, 0x0008, 0x991A]
+        );
+        assert_eq!(
+            registers("X", HardwareModel::Cgb),
+            [0x1180, 0x5800, 0x0008, 0x991A]
+        );
+        // Other licensees aren't looked up
+        let mut other = rom("TETRIS", 0x00);
+        other[0x14B] = 0x08;
+        assert_eq!(
+            pairs(HardwareModel::Cgb.boot_registers(&other, false)),
+            [0x1180, 0x0000, 0x0008, 0x007C]
+        );
+        // INC B wrapping sets Z and H
+        let wrap = "\u{7F}\u{7F}\u{1}";
+        assert_eq!(
+            registers(wrap, HardwareModel::Agb),
+            [0x11A0, 0x0000, 0x0008, 0x007C]
+        );
+    }
+
+    #[test]
+    fn test_colorization_and_model_choice() {
+        let rom = rom("TETRIS", 0x00);
+        let mut gameboy = gameboy_with_rom(&rom);
+        assert_eq!(gameboy.model(), HardwareModel::Dmg);
+        // DMG games go to a CGB for its colors
+        gameboy.set_colorization(true);
+        gameboy.load_rom(&rom);
+        assert_eq!(gameboy.model(), HardwareModel::Cgb);
+        // unless a model is picked
+        gameboy.set_model(Some(HardwareModel::Sgb));
+        gameboy.load_rom(&rom);
+        assert_eq!(gameboy.model(), HardwareModel::Sgb);
+    }
+
+    #[test]
+    fn test_stat_write_bug() {
+        let stat_interrupt_after_write = |model: HardwareModel, old_stat: u8, new_stat: u8| {
+            let mut gameboy = gameboy_with_rom(&rom("", 0x80));
+            let bus = &mut gameboy.bus;
+            bus.model = model;
+            bus.write_byte(IoRegister::Lcdc.address(), 0x91);
+            bus.write_byte(IoRegister::If.address(), 0x00);
+            bus.set_stat(old_stat);
+            bus.write_byte(IoRegister::Stat.address(), new_stat);
+            bus.read_byte(IoRegister::If.address()) & 0x02 != 0
+        };
+        // HBlank, VBlank and OAM scan
+        for mode in 0..3 {
+            assert!(stat_interrupt_after_write(HardwareModel::Dmg, mode, 0x00));
+        }
+        assert!(stat_interrupt_after_write(HardwareModel::Sgb2, 0x00, 0x00));
+        // Drawing, unless LY=LYC
+        assert!(!stat_interrupt_after_write(HardwareModel::Dmg, 0x03, 0x00));
+        assert!(stat_interrupt_after_write(HardwareModel::Mgb, 0x07, 0x00));
+        // No edge when an enabled source already holds the line high
+        assert!(!stat_interrupt_after_write(HardwareModel::Dmg, 0x08, 0x00));
+        assert!(!stat_interrupt_after_write(HardwareModel::Cgb, 0x00, 0x00));
+        assert!(!stat_interrupt_after_write(HardwareModel::Agb, 0x01, 0x00));
+    }
+
+    #[test]
+    fn test_no_stat_interrupt_after_boot() {
+        let gameboy = gameboy_with_rom(&rom("", 0x00));
+        assert_eq!(gameboy.bus.read_byte(IoRegister::If.address()), 0xE1);
+    }
+
+    #[test]
+    fn test_ppu_stat_updates_are_not_writes() {
+        let mut program = rom("", 0x00);
+        // JR -2
+        program[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let mut gameboy = gameboy_with_rom(&program);
+        for _ in 0..20_000 {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.bus.read_byte(IoRegister::If.address()) & 0x02, 0);
+    }
+
+    #[test]
+    fn test_unusable_memory() {
+        let mut gameboy = gameboy_with_rom(&rom("", 0x00));
+        gameboy.bus.write_byte(0xFEA0, 0x12);
+        assert_eq!(gameboy.bus.read_byte(0xFEA0), 0x00);
+        assert_eq!(gameboy.bus.read_byte(0xFEFF), 0x00);
+        gameboy.bus.model = HardwareModel::Agb;
+        assert_eq!(gameboy.bus.read_byte(0xFEA0), 0xAA);
+        assert_eq!(gameboy.bus.read_byte(0xFEB7), 0xBB);
+        assert_eq!(gameboy.bus.read_byte(0xFEFF), 0xFF);
+    }
+}
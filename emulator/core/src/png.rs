@@ -0,0 +1,157 @@
+/*
+Minimal PNG writer, no compression (stored deflate blocks).
+Enough for small images like camera photos without pulling in a crate.
+*/
+pub fn encode_grayscale(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    // Each row starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity((width as usize + 1) * height as usize);
+    for row in pixels.chunks(width as usize).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8 bit grayscale, no interlace
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+// CRC-32 (IEEE), over each chunk's type and data, also used to look up ROM dumps
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (type, data) of each chunk, checking the lengths and CRCs
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc32(&rest[4..8 + length]), crc);
+            chunks.push((kind, rest[8..8 + length].to_vec()));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    // Undoes zlib_stored, checking the block headers and the Adler-32
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        assert_eq!(u16::from_be_bytes([0x78, 0x01]) % 31, 0);
+        let mut data = Vec::new();
+        let mut rest = &zlib[2..];
+        loop {
+            let last = rest[0] == 1;
+            let length = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !length);
+            data.extend_from_slice(&rest[5..5 + length as usize]);
+            rest = &rest[5 + length as usize..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_encode_grayscale() {
+        let png = encode_grayscale(3, 2, &[0x00, 0x55, 0xAA, 0xFF, 0x80, 0x01]);
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 3, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+        assert_eq!(
+            inflate_stored(&chunks[1].1),
+            [0, 0x00, 0x55, 0xAA, 0, 0xFF, 0x80, 0x01]
+        );
+        assert!(chunks[2].1.is_empty());
+        assert_eq!(png[png.len() - 4..], 0xAE426082u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_large_images_take_several_blocks() {
+        let pixels: Vec<u8> = (0..300 * 300).map(|i| (i % 251) as u8).collect();
+        let chunks = chunks(&encode_grayscale(300, 300, &pixels));
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw.len(), 301 * 300);
+        for (row, pixels) in raw.chunks(301).zip(pixels.chunks(300)) {
+            assert_eq!(row[0], 0);
+            assert_eq!(row[1..], *pixels);
+        }
+        // 0xFFFF bytes, then the rest in a final block
+        assert_eq!(chunks[1].1[2], 0);
+        assert_eq!(chunks[1].1[2 + 5 + 0xFFFF], 1);
+    }
+
+    #[test]
+    fn test_empty_data() {
+        assert!(inflate_stored(&zlib_stored(&[])).is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+/*
+Output stage options for how colors reach the host screen.
+The CGB LCD was far less saturated than a modern screen, so RGB555 expanded
+linearly looks too bright and colorful. Games were drawn for that LCD.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorCorrection {
+    // Linear RGB555 to RGB888
+    None,
+    // Channel mixing of the CGB LCD, as measured for higan
+    CgbLcd,
+    // Darker GBA LCD with its gamma
+    Gba,
+    // Milder mixing that keeps white white, for today's screens
+    ModernBalanced,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameBlending {
+    None,
+    // Average of this frame and the last one, for sprites flickered every other frame
+    Mix,
+    // Slow LCD response, old frames fade out over a few frames
+    Ghosting,
+}
+
+impl ColorCorrection {
+    pub const ALL: [ColorCorrection; 4] = [
+        ColorCorrection::None,
+        ColorCorrection::CgbLcd,
+        ColorCorrection::Gba,
+        ColorCorrection::ModernBalanced,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorCorrection::None => "none",
+            ColorCorrection::CgbLcd => "cgb",
+            ColorCorrection::Gba => "gba",
+            ColorCorrection::ModernBalanced => "modern",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|correction| correction.name().eq_ignore_ascii_case(name))
+    }
+
+    // 5 bit channels to 0xAARRGGBB
+    pub fn convert(&self, r: u8, g: u8, b: u8) -> u32 {
+        let (r, g, b) = match self {
+            ColorCorrection::None => (
+                (r << 3 | r >> 2) as f32,
+                (g << 3 | g >> 2) as f32,
+                (b << 3 | b >> 2) as f32,
+            ),
+            ColorCorrection::CgbLcd => {
+                let (r, g, b) = (r as u32, g as u32, b as u32);
+                (
+                    ((r * 26 + g * 4 + b * 2).min(960) >> 2) as f32,
+                    ((g * 24 + b * 8).min(960) >> 2) as f32,
+                    ((r * 6 + g * 4 + b * 22).min(960) >> 2) as f32,
+                )
+            }
+            ColorCorrection::Gba => {
+                let linear = |channel: u8| (channel as f32 / 31.0).powf(4.0);
+                let (r, g, b) = (linear(r), linear(g), linear(b));
+                let output = |value: f32| (value / 255.0).powf(1.0 / 2.2) * 255.0 * 255.0 / 280.0;
+                (
+                    output(255.0 * r + 50.0 * g),
+                    output(10.0 * r + 230.0 * g + 30.0 * b),
+                    output(50.0 * r + 10.0 * g + 220.0 * b),
+                )
+            }
+            ColorCorrection::ModernBalanced => {
+                let (r, g, b) = (r as f32 / 31.0, g as f32 / 31.0, b as f32 / 31.0);
+                // Rows add up to 1, then a slight gamma lift for the mixed midtones
+                let output = |value: f32| value.powf(1.0 / 1.1) * 255.0;
+                (
+                    output(0.86 * r + 0.10 * g + 0.04 * b),
+                    output(0.04 * r + 0.88 * g + 0.08 * b),
+                    output(0.08 * r + 0.06 * g + 0.86 * b),
+                )
+            }
+        };
+        let channel = |value: f32| value.round().clamp(0.0, 255.0) as u32;
+        0xFF00_0000 | channel(r) << 16 | channel(g) << 8 | channel(b)
+    }
+
+    // Every RGB555 color, indexed like CGB palette RAM, empty when there is nothing to correct
+    pub fn table(&self) -> Vec<u32> {
+        if *self == ColorCorrection::None {
+            return Vec::new();
+        }
+        (0..0x8000u16)
+            .map(|color| {
+                let r = (color & 0x1F) as u8;
+                let g = ((color >> 5) & 0x1F) as u8;
+                let b = ((color >> 10) & 0x1F) as u8;
+                self.convert(r, g, b)
+            })
+            .collect()
+    }
+}
+
+impl FrameBlending {
+    pub const ALL: [FrameBlending; 3] = [
+        FrameBlending::None,
+        FrameBlending::Mix,
+        FrameBlending::Ghosting,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameBlending::None => "none",
+            FrameBlending::Mix => "mix",
+            FrameBlending::Ghosting => "ghosting",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|blending| blending.name().eq_ignore_ascii_case(name))
+    }
+
+    // Blends a finished frame into what was shown before
+    pub fn blend(&self, frame: &[u32], previous: &mut [u32], output: &mut [u32]) {
+        match self {
+            FrameBlending::None => output.copy_from_slice(frame),
+            FrameBlending::Mix => {
+                for ((out, &new), old) in output.iter_mut().zip(frame).zip(previous.iter()) {
+                    *out = mix(new, *old, 128);
+                }
+                previous.copy_from_slice(frame);
+            }
+            FrameBlending::Ghosting => {
+                for (out, &new) in output.iter_mut().zip(frame) {
+                    *out = mix(new, *out, 160);
+                }
+            }
+        }
+    }
+}
+
+// weight / 256 of a, the rest of b, per channel
+fn mix(a: u32, b: u32, weight: u32) -> u32 {
+    let channel = |shift: u32| {
+        let a = (a >> shift) & 0xFF;
+        let b = (b >> shift) & 0xFF;
+        ((a * weight + b * (256 - weight)) >> 8) << shift
+    };
+    0xFF00_0000 & a | channel(16) | channel(8) | channel(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear() {
+        let none = ColorCorrection::None;
+        assert_eq!(none.convert(0, 0, 0), 0xFF000000);
+        assert_eq!(none.convert(31, 31, 31), 0xFFFFFFFF);
+        assert_eq!(none.convert(31, 16, 1), 0xFFFF8408);
+        assert!(none.table().is_empty());
+    }
+
+    #[test]
+    fn test_corrections() {
+        // The CGB LCD never gets to full white and bleeds red into blue
+        assert_eq!(ColorCorrection::CgbLcd.convert(31, 31, 31), 0xFFF0F0F0);
+        assert_eq!(ColorCorrection::CgbLcd.convert(31, 0, 0), 0xFFC9002E);
+        assert_eq!(ColorCorrection::Gba.convert(31, 31, 31), 0xFFFCEEF2);
+        assert_eq!(ColorCorrection::Gba.convert(0, 0, 0), 0xFF000000);
+        assert_eq!(
+            ColorCorrection::ModernBalanced.convert(31, 31, 31),
+            0xFFFFFFFF
+        );
+        assert_eq!(ColorCorrection::ModernBalanced.convert(0, 0, 0), 0xFF000000);
+    }
+
+    #[test]
+    fn test_table_is_indexed_like_palette_ram() {
+        let correction = ColorCorrection::CgbLcd;
+        let table = correction.table();
+        assert_eq!(table.len(), 0x8000);
+        assert_eq!(table[0x001F], correction.convert(31, 0, 0));
+        assert_eq!(table[0x03E0], correction.convert(0, 31, 0));
+        assert_eq!(table[0x7C00], correction.convert(0, 0, 31));
+        assert_eq!(table[0x7FFF], correction.convert(31, 31, 31));
+    }
+
+    #[test]
+    fn test_names() {
+        for correction in ColorCorrection::ALL {
+            assert_eq!(
+                ColorCorrection::from_name(correction.name()),
+                Some(correction)
+            );
+        }
+        for blending in FrameBlending::ALL {
+            assert_eq!(FrameBlending::from_name(blending.name()), Some(blending));
+        }
+        assert_eq!(
+            ColorCorrection::from_name("GBA"),
+            Some(ColorCorrection::Gba)
+        );
+        assert_eq!(FrameBlending::from_name("blur"), None);
+    }
+
+    #[test]
+    fn test_blending() {
+        let white = [0xFFFFFFFF; 2];
+        let black = [0xFF000000; 2];
+        let mut previous = black;
+        let mut output = [0; 2];
+
+        FrameBlending::None.blend(&white, &mut previous, &mut output);
+        assert_eq!(output, white);
+        assert_eq!(previous, black);
+
+        FrameBlending::Mix.blend(&white, &mut previous, &mut output);
+        assert_eq!(output, [0xFF7F7F7F; 2]);
+        assert_eq!(previous, white);
+        FrameBlending::Mix.blend(&white, &mut previous, &mut output);
+        assert_eq!(output, white);
+
+        // Ghosting fades from what was shown
+        output = black;
+        FrameBlending::Ghosting.blend(&white, &mut previous, &mut output);
+        assert_eq!(output, [0xFF9F9F9F; 2]);
+        FrameBlending::Ghosting.blend(&white, &mut previous, &mut output);
+        assert_eq!(output, [0xFFDBDBDB; 2]);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::cartridge::cartridge_header::CartridgeHeader;
+
+/*
+Colors a CGB gives DMG games. The boot ROM picks them from a checksum of the title
+(Nintendo games only, the 4th title letter tells apart games with the same checksum)
+or from a button combo held while the logo is shown, and leaves them in
+BG palette 0 and OBJ palettes 0-1, which the PPU then uses with BGP/OBP0/OBP1.
+https://gbdev.io/pandocs/Power_Up_Sequence.html#compatibility-palettes
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompatibilityPalette {
+    pub bg: [u32; 4],
+    pub obj0: [u32; 4],
+    pub obj1: [u32; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteCombo {
+    Up,
+    UpA,
+    UpB,
+    Left,
+    LeftA,
+    LeftB,
+    Down,
+    DownA,
+    DownB,
+    Right,
+    RightA,
+    RightB,
+}
+
+/*
+Tables from the CGB boot ROM. Nintendo games are looked up by title checksum,
+entries from FIRST_DUPLICATE on share their checksum with another title and
+only match with the 4th title letter below. Each entry picks a combination of
+three palettes, unknown titles and other licensees get combination 0.
+*/
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0xBF, 0x0D, 0xF4, 0xB3, 0x46,
+];
+const FIRST_DUPLICATE: usize = 65;
+const DUPLICATE_FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+const COMBINATION_PER_TITLE: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 34, 23, 18, 29, 28,
+];
+
+// 30 palettes of 4 RGB555 colors, one after the other
+const COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, 0x639F, 0x4279, 0x15B0, 0x04CB, 0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, 0x7FFF, 0x421F, 0x1CF2, 0x0000, 0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000, 0x7FFF, 0x03EF, 0x01D6, 0x0000, 0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000, 0x67FF, 0x77AC, 0x1A13, 0x2D6B, 0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, 0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, 0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF, 0x7FFF, 0x01DF, 0x0112, 0x0000, 0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000, 0x299F, 0x001A, 0x000C, 0x0000, 0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120, 0x7FFF, 0x7EEB, 0x001F, 0x7C00, 0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000, 0x03FF, 0x001F, 0x000C, 0x0000, 0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF, 0x7FFF, 0x7E8C, 0x7C00, 0x0000, 0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+// OBJ0, OBJ1 and BG palettes of a combination
+const fn palettes(obj0: u8, obj1: u8, bg: u8) -> [u8; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+/*
+As offsets into COLORS. A few combinations start their palettes at a color
+that isn't the first of a palette, the boot ROM reads 4 colors from there.
+*/
+const COMBINATIONS: [[u8; 3]; 51] = [
+    palettes(4, 4, 29),
+    palettes(18, 18, 18),
+    palettes(20, 20, 20),
+    palettes(24, 24, 24),
+    palettes(9, 9, 9),
+    palettes(0, 0, 0),
+    palettes(27, 27, 27),
+    palettes(5, 5, 5),
+    palettes(12, 12, 12),
+    palettes(26, 26, 26),
+    palettes(16, 8, 8),
+    palettes(4, 28, 28),
+    palettes(4, 2, 2),
+    palettes(3, 4, 4),
+    palettes(4, 29, 29),
+    palettes(28, 4, 28),
+    palettes(2, 17, 2),
+    palettes(16, 16, 8),
+    palettes(4, 4, 7),
+    palettes(4, 4, 18),
+    palettes(4, 4, 20),
+    palettes(19, 19, 9),
+    [15, 15, 44],
+    palettes(17, 17, 2),
+    palettes(4, 4, 2),
+    palettes(4, 4, 3),
+    palettes(28, 28, 0),
+    palettes(3, 3, 0),
+    palettes(0, 0, 1),
+    palettes(18, 22, 18),
+    palettes(20, 22, 20),
+    palettes(24, 22, 24),
+    palettes(16, 22, 8),
+    palettes(17, 4, 13),
+    [111, 0, 56],
+    [111, 16, 60],
+    palettes(19, 22, 9),
+    palettes(16, 28, 10),
+    palettes(4, 23, 28),
+    palettes(17, 22, 2),
+    palettes(4, 0, 2),
+    palettes(4, 28, 3),
+    palettes(28, 3, 0),
+    palettes(3, 28, 4),
+    palettes(21, 28, 4),
+    palettes(3, 28, 0),
+    palettes(25, 3, 28),
+    palettes(0, 28, 8),
+    palettes(4, 3, 28),
+    palettes(28, 3, 6),
+    palettes(4, 28, 29),
+];
+
+impl CompatibilityPalette {
+    // One of the boot ROM's palette combinations
+    pub fn from_combination(index: usize) -> Self {
+        let [obj0, obj1, bg] = COMBINATIONS[index].map(|start| {
+            let start = start as usize;
+            let mut colors = [0; 4];
+            for (color, &rgb555) in colors.iter_mut().zip(&COLORS[start..start + 4]) {
+                let [r, g, b] = [0, 5, 10].map(|shift| ((rgb555 >> shift) & 0x1F) as u32);
+                *color = [r, g, b]
+                    .iter()
+                    .fold(0, |rgb, &c| rgb << 8 | c << 3 | c >> 2);
+            }
+            colors
+        });
+        Self { bg, obj0, obj1 }
+    }
+}
+
+impl PaletteCombo {
+    pub const ALL: [PaletteCombo; 12] = [
+        PaletteCombo::Up,
+        PaletteCombo::UpA,
+        PaletteCombo::UpB,
+        PaletteCombo::Left,
+        PaletteCombo::LeftA,
+        PaletteCombo::LeftB,
+        PaletteCombo::Down,
+        PaletteCombo::DownA,
+        PaletteCombo::DownB,
+        PaletteCombo::Right,
+        PaletteCombo::RightA,
+        PaletteCombo::RightB,
+    ];
+
+    pub fn palette(&self) -> CompatibilityPalette {
+        let combination = match self {
+            PaletteCombo::Up => 5,
+            PaletteCombo::UpA => 43,
+            PaletteCombo::UpB => 28,
+            PaletteCombo::Left => 48,
+            PaletteCombo::LeftA => 40,
+            PaletteCombo::LeftB => 7,
+            PaletteCombo::Down => 8,
+            PaletteCombo::DownA => 3,
+            PaletteCombo::DownB => 49,
+            PaletteCombo::Right => 1,
+            PaletteCombo::RightA => 0,
+            PaletteCombo::RightB => 6,
+        };
+        CompatibilityPalette::from_combination(combination)
+    }
+
+    // Joypad keys as in Joypad::keys (0 = pressed), None without a direction held
+    pub fn from_keys(keys: u8) -> Option<Self> {
+        let pressed = !keys;
+        let button = match (pressed & 0x10 != 0, pressed & 0x20 != 0) {
+            (true, _) => 1,
+            (false, true) => 2,
+            (false, false) => 0,
+        };
+        let direction = match pressed & 0x0F {
+            d if d & 0x04 != 0 => 0,
+            d if d & 0x02 != 0 => 1,
+            d if d & 0x08 != 0 => 2,
+            d if d & 0x01 != 0 => 3,
+            _ => return None,
+        };
+        Some(Self::ALL[direction * 3 + button])
+    }
+
+    // "up", "up-a", "left-b"...
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|combo| combo.name().eq_ignore_ascii_case(name))
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteCombo::Up => "up",
+            PaletteCombo::UpA => "up-a",
+            PaletteCombo::UpB => "up-b",
+            PaletteCombo::Left => "left",
+            PaletteCombo::LeftA => "left-a",
+            PaletteCombo::LeftB => "left-b",
+            PaletteCombo::Down => "down",
+            PaletteCombo::DownA => "down-a",
+            PaletteCombo::DownB => "down-b",
+            PaletteCombo::Right => "right",
+            PaletteCombo::RightA => "right-a",
+            PaletteCombo::RightB => "right-b",
+        }
+    }
+}
+
+pub fn title_checksum(rom: &[u8]) -> u8 {
+    let start = CartridgeHeader::TitleStart as usize;
+    rom[start..start + 16]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// Only Nintendo games are looked up by title
+pub fn is_nintendo(rom: &[u8]) -> bool {
+    let new_licensee = CartridgeHeader::NewLicenseeCodeStart as usize;
+    match rom[CartridgeHeader::OldLicenseeCode as usize] {
+        0x01 => true,
+        0x33 => &rom[new_licensee..new_licensee + 2] == b"01",
+        _ => false,
+    }
+}
+
+// What the boot ROM picks for a DMG game when no button combo is held
+pub fn palette_for_rom(rom: &[u8]) -> CompatibilityPalette {
+    CompatibilityPalette::from_combination(combination_for_rom(rom))
+}
+
+fn combination_for_rom(rom: &[u8]) -> usize {
+    if !is_nintendo(rom) {
+        return 0;
+    }
+    let checksum = title_checksum(rom);
+    let fourth_letter = rom[CartridgeHeader::TitleStart as usize + 3];
+    TITLE_CHECKSUMS
+        .iter()
+        .enumerate()
+        .find(|&(index, &sum)| {
+            sum == checksum
+                && (index < FIRST_DUPLICATE
+                    || DUPLICATE_FOURTH_LETTERS[index - FIRST_DUPLICATE] == fourth_letter)
+        })
+        .map_or(0, |(index, _)| COMBINATION_PER_TITLE[index] as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_header;
+
+    fn rom(title: &str, old_licensee: u8, new_licensee: &[u8; 2]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        write_header(&mut rom, title, 0x00);
+        rom[CartridgeHeader::OldLicenseeCode as usize] = old_licensee;
+        let new_start = CartridgeHeader::NewLicenseeCodeStart as usize;
+        rom[new_start..new_start + 2].copy_from_slice(new_licensee);
+        rom
+    }
+
+    fn nintendo(title: &str) -> Vec<u8> {
+        rom(title, 0x01, b"00")
+    }
+
+    #[test]
+    fn test_tables_line_up() {
+        assert_eq!(
+            TITLE_CHECKSUMS.len() - FIRST_DUPLICATE,
+            DUPLICATE_FOURTH_LETTERS.len()
+        );
+        for combination in COMBINATIONS {
+            assert!(combination
+                .iter()
+                .all(|&start| start as usize + 4 <= COLORS.len()));
+        }
+        assert!(COMBINATION_PER_TITLE
+            .iter()
+            .all(|&combination| (combination as usize) < COMBINATIONS.len()));
+    }
+
+    #[test]
+    fn test_title_lookup() {
+        assert_eq!(title_checksum(&nintendo("TETRIS")), 0xDB);
+        assert_eq!(combination_for_rom(&nintendo("TETRIS")), 3);
+        assert_eq!(combination_for_rom(&nintendo("POKEMON RED")), 13);
+        assert_eq!(combination_for_rom(&nintendo("POKEMON BLUE")), 11);
+        assert_eq!(combination_for_rom(&nintendo("ZELDA")), 44);
+        assert_eq!(combination_for_rom(&nintendo("NOT A REAL GAME")), 0);
+    }
+
+    #[test]
+    fn test_fourth_letter_tells_duplicates_apart() {
+        // Both sum to $46
+        assert_eq!(title_checksum(&nintendo("SUPER MARIOLAND")), 0x46);
+        assert_eq!(title_checksum(&nintendo("METROID2")), 0x46);
+        assert_eq!(combination_for_rom(&nintendo("SUPER MARIOLAND")), 22);
+        assert_eq!(combination_for_rom(&nintendo("METROID2")), 46);
+        // Same sum, a 4th letter none of them has
+        assert_eq!(title_checksum(&nintendo("METXIID2")), 0x46);
+        assert_eq!(combination_for_rom(&nintendo("METXIID2")), 0);
+    }
+
+    #[test]
+    fn test_only_nintendo_games_are_looked_up() {
+        assert!(is_nintendo(&rom("TETRIS", 0x01, b"00")));
+        assert!(is_nintendo(&rom("TETRIS", 0x33, b"01")));
+        assert!(!is_nintendo(&rom("TETRIS", 0x33, b"08")));
+        assert!(!is_nintendo(&rom("TETRIS", 0x08, b"01")));
+        assert_eq!(combination_for_rom(&rom("TETRIS", 0x33, b"01")), 3);
+        assert_eq!(combination_for_rom(&rom("TETRIS", 0x33, b"08")), 0);
+        assert_eq!(
+            palette_for_rom(&rom("TETRIS", 0x08, b"00")),
+            PaletteCombo::RightA.palette()
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        // Right+A, what unknown games get, with 5 bits widened as c << 3 | c >> 2
+        let palette = CompatibilityPalette::from_combination(0);
+        assert_eq!(palette.bg, [0xFFFFFF, 0x7BFF31, 0x0063C6, 0x000000]);
+        assert_eq!(palette.obj0, [0xFFFFFF, 0xFF8484, 0x943939, 0x000000]);
+        assert_eq!(palette.obj1, palette.obj0);
+        // Left+B is grayscale
+        let gray = [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000];
+        let palette = PaletteCombo::LeftB.palette();
+        assert_eq!([palette.bg, palette.obj0, palette.obj1], [gray; 3]);
+    }
+
+    #[test]
+    fn test_combos() {
+        assert_eq!(PaletteCombo::from_keys(0xFF), None);
+        // Up is bit 2, A bit 4, B bit 5
+        assert_eq!(PaletteCombo::from_keys(!0x04), Some(PaletteCombo::Up));
+        assert_eq!(PaletteCombo::from_keys(!0x14), Some(PaletteCombo::UpA));
+        assert_eq!(PaletteCombo::from_keys(!0x22), Some(PaletteCombo::LeftB));
+        assert_eq!(PaletteCombo::from_keys(!0x38), Some(PaletteCombo::DownA));
+        assert_eq!(PaletteCombo::from_keys(!0x01), Some(PaletteCombo::Right));
+        assert_eq!(PaletteCombo::from_keys(!0x10), None);
+        for combo in PaletteCombo::ALL {
+            assert_eq!(PaletteCombo::from_name(combo.name()), Some(combo));
+        }
+        assert_eq!(PaletteCombo::from_name("Down-B"), Some(PaletteCombo::DownB));
+        assert_eq!(PaletteCombo::from_name("sideways"), None);
+    }
+}
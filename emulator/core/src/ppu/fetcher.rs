@@ -1,4 +1,4 @@
-use super::pixelfifo::PixelFifo;
+use super::{indexed::Layer, pixelfifo::PixelFifo};
 use crate::bus::{io_address::IoRegister, GameboyMode, MemoryInterface};
 use serde::{Deserialize, Serialize};
 
@@ -195,7 +195,10 @@ impl Fetcher {
 
         // Push pixels to FIFO with appropriate attributes
         for color in pixels {
-            let pixel = super::pixelfifo::Pixel::new_bg(memory, color, self.tile_attrs);
+            let mut pixel = super::pixelfifo::Pixel::new_bg(memory, color, self.tile_attrs);
+            if self.is_window_fetch {
+                pixel.layer = Layer::Window;
+            }
             pixel_fifo.bg_fifo.push_back(pixel);
         }
 
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use super::{pixelfifo::PixelFifo, Sprite};
+use super::{pixelfifo::PixelFifo, Sprite, SpriteV1};
 use crate::{
     bus::{self, io_address::IoRegister, GameboyMode, MemoryInterface},
     gameboy::Gameboy,
@@ -16,6 +16,30 @@ pub struct SpriteFetcher {
     pub remaining_pixels: u8,
     pub sprite: Sprite,
 }
+// Save state version 1
+#[derive(Deserialize)]
+pub(crate) struct SpriteFetcherV1 {
+    step: u8,
+    tile_number: u8,
+    tile_data_low: u8,
+    tile_data_high: u8,
+    active: bool,
+    remaining_pixels: u8,
+    sprite: SpriteV1,
+}
+impl From<SpriteFetcherV1> for SpriteFetcher {
+    fn from(state: SpriteFetcherV1) -> Self {
+        Self {
+            step: state.step,
+            tile_number: state.tile_number,
+            tile_data_low: state.tile_data_low,
+            tile_data_high: state.tile_data_high,
+            active: state.active,
+            remaining_pixels: state.remaining_pixels,
+            sprite: state.sprite.into(),
+        }
+    }
+}
 impl SpriteFetcher {
     pub fn new() -> Self {
         Self {
@@ -131,8 +155,9 @@ impl SpriteFetcher {
             // Only override existing pixels if the new pixel is not transparent
             if color != 0 {
                 if let Some(existing_pixel) = pixel_fifo.sprite_fifo.get_mut(bit) {
-                    let new_pixel =
+                    let mut new_pixel =
                         super::pixelfifo::Pixel::new_sprite(memory, color, self.sprite.flags);
+                    new_pixel.oam_index = self.sprite.oam_index;
 
                     match memory.gb_mode() {
                         bus::GameboyMode::DMG => {
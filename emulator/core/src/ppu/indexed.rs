@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+// Which part of the picture a pixel came from, objects by their DMG palette (OAM bit 4)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Layer {
+    #[default]
+    Background,
+    Window,
+    Obj0,
+    Obj1,
+}
+
+/*
+What a pixel was drawn from instead of its final color, so palettes can be
+swapped after the fact and tools can tell the layers apart.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IndexedPixel {
+    pub layer: Layer,
+    // Color number 0-3 from the tile data
+    pub color: u8,
+    // DMG: color after BGP/OBP0/OBP1, CGB: same as color
+    pub shade: u8,
+    // CGB palette 0-7, on a DMG 0 for the background and OBP0/OBP1 for objects
+    pub palette: u8,
+    // Entry 0-39 of the object that won
+    pub oam_index: Option<u8>,
+}
+
+impl IndexedPixel {
+    /*
+    One u32 per pixel for the frontends:
+    bits 0-1 color, 2-3 shade, 4-6 palette, 8-9 layer, 16-23 OAM index (0xFF without an object)
+    */
+    pub fn pack(&self) -> u32 {
+        let layer = match self.layer {
+            Layer::Background => 0,
+            Layer::Window => 1,
+            Layer::Obj0 => 2,
+            Layer::Obj1 => 3,
+        };
+        (self.color as u32 & 0x03)
+            | (self.shade as u32 & 0x03) << 2
+            | (self.palette as u32 & 0x07) << 4
+            | layer << 8
+            | (self.oam_index.unwrap_or(0xFF) as u32) << 16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::gameboy_with_program;
+
+    #[test]
+    fn test_pack() {
+        let pixel = IndexedPixel {
+            layer: Layer::Obj1,
+            color: 2,
+            shade: 1,
+            palette: 5,
+            oam_index: Some(39),
+        };
+        assert_eq!(pixel.pack(), 39 << 16 | 3 << 8 | 5 << 4 | 1 << 2 | 2);
+        assert_eq!(IndexedPixel::default().pack(), 0xFF << 16);
+    }
+
+    #[test]
+    fn test_layers_and_shades() {
+        let program = [
+            &[0xAF, 0xE0, 0x40][..],       // XOR A; LDH ($40),A
+            &[0x3E, 0xFF][..],             // LD A,$FF
+            &[0x21, 0x10, 0x80][..],       // LD HL,$8010
+            &[0x06, 0x08][..],             // LD B,8
+            &[0x22, 0x36, 0x00, 0x23][..], // LD (HL+),A; LD (HL),0; INC HL
+            &[0x05, 0x20, 0xF9][..],       // DEC B; JR NZ,-7
+            &[0x3E, 0x01][..],             // LD A,1
+            &[0xEA, 0x00, 0x98][..],       // LD ($9800),A
+            &[0x21, 0x00, 0xFE][..],       // LD HL,$FE00
+            &[0x36, 0x10, 0x23][..],       // Y 16
+            &[0x36, 0x10, 0x23][..],       // X 16
+            &[0x36, 0x01, 0x23][..],       // tile 1
+            &[0x36, 0x10][..],             // OBP1
+            &[0x3E, 0xE4, 0xE0, 0x47][..], // BGP
+            &[0x3E, 0x1B, 0xE0, 0x49][..], // OBP1 reversed
+            &[0x3E, 0x93, 0xE0, 0x40][..], // LCD and objects on
+            &[0x18, 0xFE][..],
+        ]
+        .concat();
+        let mut gameboy = gameboy_with_program(&program);
+        assert!(gameboy.ppu.get_indexed_buffer().is_empty());
+        gameboy.ppu.set_indexed_output(true);
+        for _ in 0..3 {
+            gameboy.run_frame();
+        }
+
+        let buffer = gameboy.ppu.get_indexed_buffer();
+        assert_eq!(buffer.len(), 160 * 144);
+        // Tile 1 is color 1 all over, in the top left background cell
+        assert_eq!(
+            buffer[0],
+            IndexedPixel {
+                layer: Layer::Background,
+                color: 1,
+                shade: 1,
+                palette: 0,
+                oam_index: None,
+            }
+        );
+        // and object 0 at (8, 0)
+        assert_eq!(
+            buffer[8],
+            IndexedPixel {
+                layer: Layer::Obj1,
+                color: 1,
+                shade: 2,
+                palette: 1,
+                oam_index: Some(0),
+            }
+        );
+        assert_eq!(buffer[16].layer, Layer::Background);
+    }
+}
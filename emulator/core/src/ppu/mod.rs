@@ -1,13 +1,18 @@
+pub mod color;
+pub mod compatibility;
 pub mod fetcher;
 pub mod fetcher_sprites;
 mod helper;
+pub mod indexed;
 pub mod pixelfifo;
 
 use crate::bus::{io_address::IoRegister, Bus, GameboyMode, MemoryInterface};
+use color::{ColorCorrection, FrameBlending};
 use fetcher::Fetcher;
-use fetcher_sprites::SpriteFetcher;
+use fetcher_sprites::{SpriteFetcher, SpriteFetcherV1};
 use helper::{should_add_sprite, should_fetch_sprite};
-use pixelfifo::{ColorValue, PixelFifo};
+use indexed::IndexedPixel;
+use pixelfifo::{ColorValue, PixelFifo, PixelFifoV1};
 use serde::{Deserialize, Serialize};
 use std::{
     cell::{Ref, RefCell},
@@ -16,8 +21,8 @@ use std::{
     vec,
 };
 
-const SCREEN_WIDTH: u8 = 160;
-const SCREEN_HEIGHT: u8 = 144;
+pub const SCREEN_WIDTH: u8 = 160;
+pub const SCREEN_HEIGHT: u8 = 144;
 const CYCLES_PER_SCANLINE: usize = 456;
 const X_POSITION_COUNTER_MAX: u16 = 160;
 const SCANLINE_Y_COUNTER_MAX: u8 = 153;
@@ -38,6 +43,16 @@ pub struct PPU {
     pub mode_cycles: usize,
 
     buffer: Vec<u32>,
+    // Shades 0-3 after the DMG palettes, for the SGB
+    shades: Vec<u8>,
+    color_correction: ColorCorrection,
+    // Corrected color for every RGB555 value, empty without correction
+    color_table: Vec<u32>,
+    frame_blending: FrameBlending,
+    previous_frame: Vec<u32>,
+    blended_frame: Vec<u32>,
+    // What every pixel was drawn from, empty unless turned on
+    indexed: Vec<IndexedPixel>,
 
     sprite_buffer: Vec<Sprite>,
     fetcher: Fetcher,
@@ -69,6 +84,42 @@ pub struct PPUState {
     pub debug_config: DebugConfig,
 }
 
+// Save state version 1
+#[derive(Deserialize)]
+pub(crate) struct PPUStateV1 {
+    mode: PPUMode,
+    mode_cycles: usize,
+    sprite_buffer: Vec<SpriteV1>,
+    fetcher: Fetcher,
+    sprite_fetcher: SpriteFetcherV1,
+    pixel_fifo: PixelFifoV1,
+    window_triggered_this_frame: bool,
+    previous_stat_conditions: u8,
+    x_render_counter: i16,
+    window_line_counter_incremented_this_scanline: bool,
+    new_frame: bool,
+    debug_config: DebugConfig,
+}
+impl From<PPUStateV1> for PPUState {
+    fn from(state: PPUStateV1) -> Self {
+        Self {
+            mode: state.mode,
+            mode_cycles: state.mode_cycles,
+            sprite_buffer: state.sprite_buffer.into_iter().map(Sprite::from).collect(),
+            fetcher: state.fetcher,
+            sprite_fetcher: state.sprite_fetcher.into(),
+            pixel_fifo: state.pixel_fifo.into(),
+            window_triggered_this_frame: state.window_triggered_this_frame,
+            previous_stat_conditions: state.previous_stat_conditions,
+            x_render_counter: state.x_render_counter,
+            window_line_counter_incremented_this_scanline: state
+                .window_line_counter_incremented_this_scanline,
+            new_frame: state.new_frame,
+            debug_config: state.debug_config,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Copy)]
 pub struct DebugConfig {
     pub sprite_debug_enabled: bool,
@@ -81,6 +132,26 @@ pub struct Sprite {
     pub x_pos: u8,
     pub tile_number: u8,
     pub flags: u8,
+    pub oam_index: u8,
+}
+// Save state version 1, from before sprites kept their OAM entry
+#[derive(Deserialize)]
+pub(crate) struct SpriteV1 {
+    y_pos: u8,
+    x_pos: u8,
+    tile_number: u8,
+    flags: u8,
+}
+impl From<SpriteV1> for Sprite {
+    fn from(sprite: SpriteV1) -> Self {
+        Self {
+            y_pos: sprite.y_pos,
+            x_pos: sprite.x_pos,
+            tile_number: sprite.tile_number,
+            flags: sprite.flags,
+            oam_index: 0,
+        }
+    }
 }
 impl Sprite {
     pub fn new() -> Self {
@@ -89,6 +160,7 @@ impl Sprite {
             x_pos: 0,
             tile_number: 0,
             flags: 0,
+            oam_index: 0,
         }
     }
 }
@@ -104,6 +176,13 @@ impl PPU {
         Self {
             palette: palette,
             buffer: vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+            shades: vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+            color_correction: ColorCorrection::None,
+            color_table: Vec::new(),
+            frame_blending: FrameBlending::None,
+            previous_frame: vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+            blended_frame: vec![0; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize],
+            indexed: Vec::new(),
             mode: PPUMode::OAM_SCAN,
             mode_cycles: 0,
             sprite_buffer: Vec::with_capacity(10),
@@ -186,8 +265,58 @@ impl PPU {
             && self.x_render_counter as i16 >= (wx.wrapping_sub(7)) as i16
     }
 
+    // True from VBlank start until the next frame begins
+    pub fn is_new_frame(&self) -> bool {
+        self.new_frame
+    }
     pub fn get_frame_buffer(&self) -> &[u32] {
-        &self.buffer
+        match self.frame_blending {
+            FrameBlending::None => &self.buffer,
+            _ => &self.blended_frame,
+        }
+    }
+    pub fn get_shade_buffer(&self) -> &[u8] {
+        &self.shades
+    }
+    pub fn color_correction(&self) -> ColorCorrection {
+        self.color_correction
+    }
+    // Only CGB colors are corrected, DMG shades come from the user palette
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.color_correction = correction;
+        self.color_table = correction.table();
+    }
+    pub fn frame_blending(&self) -> FrameBlending {
+        self.frame_blending
+    }
+    pub fn set_frame_blending(&mut self, blending: FrameBlending) {
+        self.frame_blending = blending;
+        self.previous_frame.copy_from_slice(&self.buffer);
+        self.blended_frame.copy_from_slice(&self.buffer);
+    }
+    fn correct_color(&self, rgba: u32) -> u32 {
+        if self.color_table.is_empty() {
+            return rgba;
+        }
+        // Back to RGB555, the expansion keeps the 5 bits on top of each channel
+        let r = (rgba >> 19) & 0x1F;
+        let g = (rgba >> 11) & 0x1F;
+        let b = (rgba >> 3) & 0x1F;
+        self.color_table[(r | g << 5 | b << 10) as usize]
+    }
+    pub fn is_indexed_output_enabled(&self) -> bool {
+        !self.indexed.is_empty()
+    }
+    pub fn set_indexed_output(&mut self, enabled: bool) {
+        self.indexed = if enabled {
+            vec![IndexedPixel::default(); SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize]
+        } else {
+            Vec::new()
+        };
+    }
+    // Same layout as the frame buffer, empty when indexed output is off
+    pub fn get_indexed_buffer(&self) -> &[IndexedPixel] {
+        &self.indexed
     }
     pub fn reset_scanline(&mut self) {
         self.mode_cycles = 0;
@@ -257,6 +386,7 @@ impl PPU {
             x_pos: memory.read_byte(address + 1),
             tile_number: memory.read_byte(address + 2),
             flags: memory.read_byte(address + 3),
+            oam_index: ((address - 0xFE00) / 4) as u8,
         }
     }
 
@@ -298,7 +428,9 @@ impl PPU {
             .pixel_fifo
             .is_paused(self.sprite_fetcher.active, self.fetcher.pause)
         {
-            if let Some(color_value) = self.pixel_fifo.pop_pixel(memory, &mut self.fetcher) {
+            if let Some((color_value, indexed)) =
+                self.pixel_fifo.pop_pixel(memory, &mut self.fetcher)
+            {
                 let ly = self.get_io_register(memory, IoRegister::Ly);
 
                 if self.x_render_counter >= 0
@@ -309,11 +441,17 @@ impl PPU {
                         ly as usize * SCREEN_WIDTH as usize + self.x_render_counter as usize;
 
                     let final_color = match color_value {
-                        ColorValue::Dmg(color_index) => self.palette[color_index as usize & 0x03],
-                        ColorValue::Cgb(rgba) => rgba,
+                        ColorValue::Dmg(color_index) => {
+                            self.shades[buffer_index] = color_index & 0x03;
+                            self.palette[color_index as usize & 0x03]
+                        }
+                        ColorValue::Cgb(rgba) => self.correct_color(rgba),
                     };
 
                     self.buffer[buffer_index] = final_color;
+                    if let Some(pixel) = self.indexed.get_mut(buffer_index) {
+                        *pixel = indexed;
+                    }
                 }
                 self.fetcher.x_pos_counter += 1;
                 self.x_render_counter += 1;
@@ -369,6 +507,13 @@ impl PPU {
         self.set_io_register(memory, IoRegister::If, if_register | 0b0000_0001);
         // update window per frame
         self.new_frame = true;
+        if self.frame_blending != FrameBlending::None {
+            self.frame_blending.blend(
+                &self.buffer,
+                &mut self.previous_frame,
+                &mut self.blended_frame,
+            );
+        }
     }
     fn get_io_register<M: MemoryInterface>(&self, memory: &mut M, register: IoRegister) -> u8 {
         memory.read_byte(register.address())
@@ -445,6 +590,6 @@ impl PPU {
 
         // Ensure bit 7 is always set
         stat |= 0b1000_0000;
-        self.set_io_register(memory, IoRegister::Stat, stat);
+        memory.set_stat(stat);
     }
 }
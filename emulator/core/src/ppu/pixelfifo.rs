@@ -1,5 +1,6 @@
 use super::{
     fetcher::{self, Fetcher},
+    indexed::{IndexedPixel, Layer},
     Sprite,
 };
 use crate::bus::{io_address::IoRegister, Bus, GameboyMode, MemoryInterface};
@@ -12,6 +13,16 @@ pub struct Pixel {
     pub bg_priority: bool,
     pub palette: u8, // For CGB: 0-7, For DMG: 0 = OBP0, 1 = OBP1
     pub cgb_attrs: Option<CgbAttributes>,
+    pub layer: Layer,
+    pub oam_index: u8,
+}
+// Save state version 1, from before pixels kept their layer and object
+#[derive(Deserialize)]
+pub(crate) struct PixelV1 {
+    color: u8,
+    bg_priority: bool,
+    palette: u8,
+    cgb_attrs: Option<CgbAttributes>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Copy)]
@@ -27,6 +38,8 @@ impl Pixel {
                 bg_priority: false,
                 palette: 0,
                 cgb_attrs: None,
+                layer: Layer::Background,
+                oam_index: 0,
             },
             GameboyMode::CGB => Self {
                 color,
@@ -35,17 +48,26 @@ impl Pixel {
                 cgb_attrs: Some(CgbAttributes {
                     sprite_priority: false,
                 }),
+                layer: Layer::Background,
+                oam_index: 0,
             },
         }
     }
 
     pub fn new_sprite<M: MemoryInterface>(memory: &M, color: u8, attrs: u8) -> Self {
+        let layer = if attrs & 0x10 != 0 {
+            Layer::Obj1
+        } else {
+            Layer::Obj0
+        };
         match memory.gb_mode() {
             GameboyMode::DMG => Self {
                 color,
                 bg_priority: attrs & 0x80 != 0,
                 palette: if (attrs & 0x10) != 0 { 1 } else { 0 },
                 cgb_attrs: None,
+                layer,
+                oam_index: 0,
             },
             GameboyMode::CGB => Self {
                 color,
@@ -54,6 +76,8 @@ impl Pixel {
                 cgb_attrs: Some(CgbAttributes {
                     sprite_priority: attrs & 0x80 != 0,
                 }),
+                layer,
+                oam_index: 0,
             },
         }
     }
@@ -65,6 +89,52 @@ pub struct PixelFifo {
     pub sprite_fifo: VecDeque<Pixel>,
     fine_scroll_applied: bool,
 }
+// Save state version 1
+#[derive(Deserialize)]
+pub(crate) struct PixelFifoV1 {
+    bg_fifo: VecDeque<PixelV1>,
+    sprite_fifo: VecDeque<PixelV1>,
+    fine_scroll_applied: bool,
+}
+
+impl PixelV1 {
+    fn upgrade(self, layer: Layer) -> Pixel {
+        Pixel {
+            color: self.color,
+            bg_priority: self.bg_priority,
+            palette: self.palette,
+            cgb_attrs: self.cgb_attrs,
+            layer,
+            oam_index: 0,
+        }
+    }
+}
+
+impl From<PixelFifoV1> for PixelFifo {
+    fn from(state: PixelFifoV1) -> Self {
+        // DMG objects get their layer back from the palette, the rest can't tell
+        let sprite_layer = |pixel: &PixelV1| match (pixel.cgb_attrs, pixel.palette) {
+            (None, 1) => Layer::Obj1,
+            _ => Layer::Obj0,
+        };
+        Self {
+            bg_fifo: state
+                .bg_fifo
+                .into_iter()
+                .map(|pixel| pixel.upgrade(Layer::Background))
+                .collect(),
+            sprite_fifo: state
+                .sprite_fifo
+                .into_iter()
+                .map(|pixel| {
+                    let layer = sprite_layer(&pixel);
+                    pixel.upgrade(layer)
+                })
+                .collect(),
+            fine_scroll_applied: state.fine_scroll_applied,
+        }
+    }
+}
 
 impl PixelFifo {
     pub fn new() -> Self {
@@ -85,7 +155,7 @@ impl PixelFifo {
         &mut self,
         memory: &M,
         fetcher: &mut Fetcher,
-    ) -> Option<ColorValue> {
+    ) -> Option<(ColorValue, IndexedPixel)> {
         if self.bg_fifo.is_empty() {
             return None;
         }
@@ -95,12 +165,24 @@ impl PixelFifo {
         let sprite_pixel = self.sprite_fifo.pop_front();
 
         match memory.gb_mode() {
-            GameboyMode::DMG => self
-                .mix_dmg_pixels(memory, bg_pixel, sprite_pixel)
-                .map(ColorValue::Dmg),
+            GameboyMode::DMG => {
+                let pixel = self.mix_dmg_pixels(memory, bg_pixel, sprite_pixel)?;
+                if !memory.cgb().dmg_compatibility {
+                    return Some((ColorValue::Dmg(pixel.shade), pixel));
+                }
+                let rgb = match pixel.oam_index {
+                    Some(_) => memory.cgb().get_obj_color(pixel.palette, pixel.shade),
+                    None => memory.cgb().get_bg_color(0, pixel.shade),
+                };
+                Some((ColorValue::Cgb(rgb), pixel))
+            }
             GameboyMode::CGB => {
-                let rgb = self.mix_cgb_pixels(memory, bg_pixel, sprite_pixel);
-                Some(ColorValue::Cgb(rgb))
+                let pixel = self.mix_cgb_pixels(memory, bg_pixel, sprite_pixel);
+                let rgb = match pixel.oam_index {
+                    Some(_) => memory.cgb().get_obj_color(pixel.palette, pixel.color),
+                    None => memory.cgb().get_bg_color(pixel.palette, pixel.color),
+                };
+                Some((ColorValue::Cgb(rgb), pixel))
             }
         }
     }
@@ -110,7 +192,7 @@ impl PixelFifo {
         memory: &M,
         bg_pixel: Pixel,
         sprite_pixel: Option<Pixel>,
-    ) -> Option<u8> {
+    ) -> Option<IndexedPixel> {
         let lcdc = memory.read_byte(IoRegister::Lcdc.address());
         let mut final_color = bg_pixel.color;
 
@@ -119,7 +201,14 @@ impl PixelFifo {
         }
 
         let bgp = memory.read_byte(IoRegister::Bgp.address());
-        final_color = (bgp >> (final_color * 2)) & 0x03;
+        let mut pixel = IndexedPixel {
+            layer: bg_pixel.layer,
+            color: final_color,
+            shade: (bgp >> (final_color * 2)) & 0x03,
+            palette: 0,
+            oam_index: None,
+        };
+        final_color = pixel.shade;
 
         if let Some(sprite) = sprite_pixel {
             if lcdc & 0x02 != 0 && sprite.color != 0 {
@@ -129,12 +218,18 @@ impl PixelFifo {
                     } else {
                         memory.read_byte(IoRegister::Obp0.address())
                     };
-                    final_color = (obp >> (sprite.color * 2)) & 0x03;
+                    pixel = IndexedPixel {
+                        layer: sprite.layer,
+                        color: sprite.color,
+                        shade: (obp >> (sprite.color * 2)) & 0x03,
+                        palette: sprite.palette,
+                        oam_index: Some(sprite.oam_index),
+                    };
                 }
             }
         }
 
-        Some(final_color)
+        Some(pixel)
     }
 
     fn mix_cgb_pixels<M: MemoryInterface>(
@@ -142,20 +237,27 @@ impl PixelFifo {
         memory: &M,
         bg_pixel: Pixel,
         sprite_pixel: Option<Pixel>,
-    ) -> u32 {
+    ) -> IndexedPixel {
         let lcdc = memory.read_byte(IoRegister::Lcdc.address());
+        let indexed = |pixel: Pixel, oam_index: Option<u8>| IndexedPixel {
+            layer: pixel.layer,
+            color: pixel.color,
+            shade: pixel.color,
+            palette: pixel.palette,
+            oam_index,
+        };
 
         // If LCDC bit 0 is clear (master priority off), sprites show over black background
         if lcdc & 0x01 == 0 {
             if let Some(sprite) = sprite_pixel {
                 if sprite.color != 0 {
-                    return memory.cgb().get_obj_color(sprite.palette, sprite.color);
+                    return indexed(sprite, Some(sprite.oam_index));
                 }
             }
         }
 
         // Get the background color first
-        let bg_color = memory.cgb().get_bg_color(bg_pixel.palette, bg_pixel.color);
+        let bg_color = indexed(bg_pixel, None);
 
         // If sprites are disabled or no sprite pixel, return background
         if lcdc & 0x02 == 0 || sprite_pixel.is_none() {
@@ -169,7 +271,7 @@ impl PixelFifo {
             return bg_color;
         }
 
-        let sprite_color = memory.cgb().get_obj_color(sprite.palette, sprite.color);
+        let sprite_color = indexed(sprite, Some(sprite.oam_index));
 
         // CGB Priority Rules:
         // 1. If BG color is 0, sprite always shows
@@ -229,3 +331,44 @@ pub enum ColorValue {
     Dmg(u8),
     Cgb(u32),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(palette: u8, cgb_attrs: Option<CgbAttributes>) -> PixelV1 {
+        PixelV1 {
+            color: 2,
+            bg_priority: false,
+            palette,
+            cgb_attrs,
+        }
+    }
+
+    #[test]
+    fn test_version_1_fifo_gets_layers() {
+        let cgb = Some(CgbAttributes {
+            sprite_priority: false,
+        });
+        let fifo: PixelFifo = PixelFifoV1 {
+            bg_fifo: [pixel(0, None), pixel(3, cgb)].into(),
+            sprite_fifo: [pixel(0, None), pixel(1, None), pixel(1, cgb)].into(),
+            fine_scroll_applied: true,
+        }
+        .into();
+        let layers = |pixels: &VecDeque<Pixel>| -> Vec<Layer> {
+            pixels.iter().map(|pixel| pixel.layer).collect()
+        };
+        assert_eq!(
+            layers(&fifo.bg_fifo),
+            [Layer::Background, Layer::Background]
+        );
+        // DMG objects by OBP0/OBP1, CGB palette 1 isn't OBP1
+        assert_eq!(
+            layers(&fifo.sprite_fifo),
+            [Layer::Obj0, Layer::Obj1, Layer::Obj0]
+        );
+        assert_eq!(fifo.bg_fifo[1].palette, 3);
+        assert!(fifo.fine_scroll_applied);
+    }
+}
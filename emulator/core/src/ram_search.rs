@@ -0,0 +1,364 @@
+use crate::{bus::Bus, cheats::RamFreeze, disasm};
+
+// Cheat group the frontends put frozen search results in
+pub const FREEZE_GROUP: &str = "RAM search";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueSize {
+    Byte,
+    // Little endian, the byte at the address is the low one
+    Word,
+}
+
+// How a candidate's value now compares to the one it had at the last snapshot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    // Equal to a value, -1 and $FF both match an 8 bit $FF
+    Value(i32),
+}
+
+// A RAM location still in the running and its value at the last snapshot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate {
+    pub bank: usize,
+    pub address: u16,
+    pub previous: i32,
+}
+
+/*
+Cheat finder over the RAM a game can keep state in: WRAM in all its banks,
+cartridge RAM and HRAM. Locations are (bank, address) pairs read through
+Bus::read_banked, so CGB WRAM banks and cartridge RAM banks are searched
+whichever bank the game has mapped. Every filter compares against the
+previous snapshot and then takes a new one.
+*/
+#[derive(Clone, Debug)]
+pub struct RamSearch {
+    size: ValueSize,
+    signed: bool,
+    candidates: Vec<Candidate>,
+}
+
+impl ValueSize {
+    pub fn bytes(&self) -> usize {
+        match self {
+            ValueSize::Byte => 1,
+            ValueSize::Word => 2,
+        }
+    }
+    // "8" or "16" bits
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "8" => Some(ValueSize::Byte),
+            "16" => Some(ValueSize::Word),
+            _ => None,
+        }
+    }
+    fn mask(&self) -> i32 {
+        match self {
+            ValueSize::Byte => 0xFF,
+            ValueSize::Word => 0xFFFF,
+        }
+    }
+}
+
+impl SearchFilter {
+    // "unchanged" (or "equal"), "changed", "increased", "decreased", or a value
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        match text.to_ascii_lowercase().as_str() {
+            "unchanged" | "equal" | "=" => return Ok(SearchFilter::Unchanged),
+            "changed" | "!=" => return Ok(SearchFilter::Changed),
+            "increased" | ">" => return Ok(SearchFilter::Increased),
+            "decreased" | "<" => return Ok(SearchFilter::Decreased),
+            _ => {}
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = match digits
+            .strip_prefix('$')
+            .or_else(|| digits.strip_prefix("0x"))
+        {
+            Some(hex) => i32::from_str_radix(hex, 16),
+            None => digits.parse(),
+        }
+        .map_err(|_| format!("Unknown search filter \"{}\"", text))?;
+        Ok(SearchFilter::Value(if negative { -value } else { value }))
+    }
+
+    fn matches(&self, previous: i32, value: i32, mask: i32) -> bool {
+        match *self {
+            SearchFilter::Unchanged => value == previous,
+            SearchFilter::Changed => value != previous,
+            SearchFilter::Increased => value > previous,
+            SearchFilter::Decreased => value < previous,
+            SearchFilter::Value(wanted) => value & mask == wanted & mask,
+        }
+    }
+}
+
+impl Candidate {
+    // "02:D123"
+    pub fn location(&self) -> String {
+        disasm::format_location(self.bank, self.address)
+    }
+}
+
+impl RamSearch {
+    // Starts with every location as a candidate and their values as the snapshot
+    pub fn new(bus: &Bus, size: ValueSize, signed: bool) -> Self {
+        let mut search = Self {
+            size,
+            signed,
+            candidates: Vec::new(),
+        };
+        search.reset(bus);
+        search
+    }
+
+    pub fn size(&self) -> ValueSize {
+        self.size
+    }
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    pub fn reset(&mut self, bus: &Bus) {
+        self.candidates.clear();
+        // A word may not run past the end of its bank
+        let last = self.size.bytes() - 1;
+        for (bank, start, length) in ram_banks(bus) {
+            for address in start..start + (length - last) as u16 {
+                if let Some(value) = self.value(bus, bank, address) {
+                    self.candidates.push(Candidate {
+                        bank,
+                        address,
+                        previous: value,
+                    });
+                }
+            }
+        }
+    }
+
+    // Value at a location right now, as this search reads it
+    pub fn value(&self, bus: &Bus, bank: usize, address: u16) -> Option<i32> {
+        let low = bus.read_banked(bank, address)?;
+        Some(match (self.size, self.signed) {
+            (ValueSize::Byte, false) => low as i32,
+            (ValueSize::Byte, true) => low as i8 as i32,
+            (ValueSize::Word, signed) => {
+                let word = u16::from_le_bytes([low, bus.read_banked(bank, address + 1)?]);
+                if signed {
+                    word as i16 as i32
+                } else {
+                    word as i32
+                }
+            }
+        })
+    }
+
+    // Keeps the candidates that match, returns how many are left
+    pub fn filter(&mut self, bus: &Bus, filter: SearchFilter) -> usize {
+        let mask = self.size.mask();
+        let mut candidates = std::mem::take(&mut self.candidates);
+        candidates.retain_mut(|candidate| {
+            let Some(value) = self.value(bus, candidate.bank, candidate.address) else {
+                return false;
+            };
+            let keep = filter.matches(candidate.previous, value, mask);
+            candidate.previous = value;
+            keep
+        });
+        self.candidates = candidates;
+        self.candidates.len()
+    }
+
+    // Freeze cheats holding a candidate at a value, one per byte
+    pub fn freeze(&self, candidate: &Candidate, value: i32) -> Vec<RamFreeze> {
+        let bytes = (value & self.size.mask()).to_le_bytes();
+        (0..self.size.bytes())
+            .map(|offset| {
+                RamFreeze::new(
+                    Some(candidate.bank),
+                    candidate.address + offset as u16,
+                    bytes[offset],
+                )
+            })
+            .collect()
+    }
+}
+
+// (bank, first address, length) of every RAM bank the cartridge and model have
+fn ram_banks(bus: &Bus) -> Vec<(usize, u16, usize)> {
+    let mut banks = vec![(0, 0xC000, 0x1000)];
+    for bank in 1..bus.wram_bank_count() {
+        banks.push((bank, 0xD000, 0x1000));
+    }
+    let cartridge_ram = bus.cartridge_ram().map_or(0, |ram| ram.len());
+    for (bank, start) in (0..cartridge_ram).step_by(0x2000).enumerate() {
+        banks.push((bank, 0xA000, (cartridge_ram - start).min(0x2000)));
+    }
+    banks.push((0, 0xFF80, 0x7F));
+    banks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cartridge::cartridge_header::CartridgeHeader,
+        gameboy::Gameboy,
+        test_util::{gameboy_with_rom, write_header},
+    };
+
+    // A DMG with its RAM cleared, plus 4 banks of cartridge RAM with MBC1
+    fn gameboy(cartridge_ram: bool) -> Gameboy {
+        let mut rom = vec![0; 0x8000];
+        if cartridge_ram {
+            write_header(&mut rom, "", 0x03);
+            rom[CartridgeHeader::RAMsize as usize] = 0x03;
+        }
+        gameboy_with_rom(&rom)
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(SearchFilter::parse("equal"), Ok(SearchFilter::Unchanged));
+        assert_eq!(SearchFilter::parse(" Changed "), Ok(SearchFilter::Changed));
+        assert_eq!(SearchFilter::parse(">"), Ok(SearchFilter::Increased));
+        assert_eq!(SearchFilter::parse("<"), Ok(SearchFilter::Decreased));
+        assert_eq!(SearchFilter::parse("100"), Ok(SearchFilter::Value(100)));
+        assert_eq!(SearchFilter::parse("$FF"), Ok(SearchFilter::Value(0xFF)));
+        assert_eq!(SearchFilter::parse("-0x10"), Ok(SearchFilter::Value(-16)));
+        assert!(SearchFilter::parse("more").is_err());
+        assert_eq!(ValueSize::from_name("16"), Some(ValueSize::Word));
+        assert_eq!(ValueSize::from_name("32"), None);
+    }
+
+    #[test]
+    fn test_locations() {
+        let gameboy = gameboy(false);
+        let search = RamSearch::new(&gameboy.bus, ValueSize::Byte, false);
+        assert_eq!(search.len(), 0x2000 + 0x7F);
+        let last = search.candidates().last().unwrap();
+        assert_eq!(last.location(), "00:FFFE");
+
+        // Words don't run past the end of a bank
+        let search = RamSearch::new(&gameboy.bus, ValueSize::Word, false);
+        assert_eq!(search.len(), 0x2000 + 0x7F - 3);
+        assert!(!search.candidates().iter().any(|c| c.address == 0xCFFF));
+
+        let gameboy = self::gameboy(true);
+        let search = RamSearch::new(&gameboy.bus, ValueSize::Byte, false);
+        assert_eq!(search.len(), 0x2000 + 0x7F + 0x8000);
+    }
+
+    #[test]
+    fn test_filter_bytes() {
+        let mut gameboy = gameboy(false);
+        let bus = &mut gameboy.bus;
+        bus.write_banked(0, 0xC100, 5);
+        bus.write_banked(1, 0xD200, 5);
+        let mut search = RamSearch::new(bus, ValueSize::Byte, false);
+
+        bus.write_banked(0, 0xC100, 6);
+        bus.write_banked(1, 0xD200, 4);
+        assert_eq!(search.filter(bus, SearchFilter::Changed), 2);
+        assert_eq!(search.filter(bus, SearchFilter::Unchanged), 2);
+        bus.write_banked(0, 0xC100, 7);
+        assert_eq!(search.filter(bus, SearchFilter::Increased), 1);
+        let candidate = search.candidates()[0];
+        assert_eq!(candidate.location(), "00:C100");
+        assert_eq!(candidate.previous, 7);
+        assert_eq!(search.filter(bus, SearchFilter::Value(8)), 0);
+        assert!(search.is_empty());
+
+        let mut search = RamSearch::new(bus, ValueSize::Byte, false);
+        bus.write_banked(1, 0xD200, 3);
+        assert_eq!(search.filter(bus, SearchFilter::Decreased), 1);
+        assert_eq!(search.candidates()[0].location(), "01:D200");
+    }
+
+    #[test]
+    fn test_filter_signed() {
+        let mut gameboy = gameboy(false);
+        let bus = &mut gameboy.bus;
+        bus.write_banked(0, 0xFF90, 0x7F);
+        let mut unsigned = RamSearch::new(bus, ValueSize::Byte, false);
+        let mut signed = RamSearch::new(bus, ValueSize::Byte, true);
+        bus.write_banked(0, 0xFF90, 0x80);
+        assert_eq!(unsigned.filter(bus, SearchFilter::Increased), 1);
+        assert_eq!(signed.filter(bus, SearchFilter::Decreased), 1);
+        assert_eq!(signed.candidates()[0].previous, -128);
+
+        // -1 and $FF are the same byte either way
+        bus.write_banked(0, 0xFF90, 0xFF);
+        assert_eq!(unsigned.filter(bus, SearchFilter::Value(-1)), 1);
+        assert_eq!(signed.filter(bus, SearchFilter::Value(0xFF)), 1);
+    }
+
+    #[test]
+    fn test_filter_words() {
+        let mut gameboy = gameboy(false);
+        let bus = &mut gameboy.bus;
+        bus.write_banked(0, 0xC010, 0xFF);
+        let mut search = RamSearch::new(bus, ValueSize::Word, false);
+        assert_eq!(search.value(bus, 0, 0xC010), Some(0x00FF));
+
+        // $00FF to $0100, the low byte goes down
+        bus.write_banked(0, 0xC010, 0x00);
+        bus.write_banked(0, 0xC011, 0x01);
+        assert_eq!(search.filter(bus, SearchFilter::Increased), 2);
+        assert_eq!(search.filter(bus, SearchFilter::Value(0x100)), 1);
+        assert_eq!(search.candidates()[0].address, 0xC010);
+
+        bus.write_banked(1, 0xD000, 0xFF);
+        bus.write_banked(1, 0xD001, 0x7F);
+        let mut search = RamSearch::new(bus, ValueSize::Word, true);
+        bus.write_banked(1, 0xD000, 0x00);
+        bus.write_banked(1, 0xD001, 0x80);
+        assert_eq!(search.filter(bus, SearchFilter::Value(-32768)), 1);
+        assert_eq!(search.candidates()[0].location(), "01:D000");
+    }
+
+    #[test]
+    fn test_cartridge_ram_and_freeze() {
+        let mut gameboy = gameboy(true);
+        let bus = &mut gameboy.bus;
+        let mut search = RamSearch::new(bus, ValueSize::Word, false);
+        bus.write_banked(2, 0xA123, 0x34);
+        bus.write_banked(2, 0xA124, 0x12);
+        assert_eq!(search.filter(bus, SearchFilter::Value(0x1234)), 1);
+        let candidate = search.candidates()[0];
+        assert_eq!(candidate.location(), "02:A123");
+
+        let freezes = search.freeze(&candidate, 0x10FFFF);
+        assert_eq!(
+            freezes,
+            [
+                RamFreeze::new(Some(2), 0xA123, 0xFF),
+                RamFreeze::new(Some(2), 0xA124, 0xFF),
+            ]
+        );
+        let byte = RamSearch::new(bus, ValueSize::Byte, true);
+        assert_eq!(
+            byte.freeze(&candidate, -2),
+            [RamFreeze::new(Some(2), 0xA123, 0xFE)]
+        );
+    }
+}
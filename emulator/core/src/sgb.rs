@@ -0,0 +1,772 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{bus::cgb::CgbRegisters, joyp::Joypad};
+
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+// Top left of the game screen inside the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+// One palette per 8x8 cell of the screen
+const ATTRIBUTE_WIDTH: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_HEIGHT: usize = SCREEN_HEIGHT / 8;
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT / 4;
+const SYSTEM_PALETTES: usize = 512;
+// VRAM transfers send 4KB, the first 256 tiles shown on screen
+const TRANSFER_SIZE: usize = 0x1000;
+// Frames between a *_TRN command and the screen that is read
+const TRANSFER_DELAY: u8 = 2;
+// Border: 32x28 tile map followed by palettes 4-7
+const BORDER_MAP_SIZE: usize = 0x800;
+const BORDER_DATA_SIZE: usize = BORDER_MAP_SIZE + 4 * 16 * 2;
+const BORDER_TILES_SIZE: usize = 256 * 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameSize {
+    // 160x144, colorized when running as a SGB
+    Screen,
+    // 256x224 with the border, same as Screen without a SGB
+    Sgb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Transfer {
+    Palettes,
+    // Tiles 0x00-0x7F or 0x80-0xFF
+    BorderTiles(usize),
+    BorderMap,
+    Attributes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Mask {
+    None,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sgb {
+    commands_enabled: bool,
+    // Packet being shifted in through P14/P15
+    packet: [u8; PACKET_SIZE],
+    packet_bits: usize,
+    receiving: bool,
+    pulse_ready: bool,
+    // Packets of the current command
+    command: Vec<u8>,
+    previous_lines: u8,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>,
+    attributes: Vec<u8>,
+    attribute_files: Vec<u8>,
+    mask: Mask,
+    transfer: Option<(Transfer, u8)>,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+
+    player_count: u8,
+    current_player: u8,
+    // Keys of players 2-4, player 1 uses the joypad
+    pub player_keys: [u8; 3],
+
+    screen: Vec<u32>,
+    frame: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SgbState {
+    commands_enabled: bool,
+    packet: [u8; PACKET_SIZE],
+    packet_bits: usize,
+    receiving: bool,
+    pulse_ready: bool,
+    command: Vec<u8>,
+    previous_lines: u8,
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>,
+    attributes: Vec<u8>,
+    attribute_files: Vec<u8>,
+    mask: Mask,
+    transfer: Option<(Transfer, u8)>,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+    player_count: u8,
+    current_player: u8,
+}
+
+impl Sgb {
+    /*
+    Super Game Boy, seen from the Game Boy side.
+    Commands are 1-7 packets of 16 bytes sent through the joypad register,
+    LSB first: a reset pulse (P14 and P15 low), 128 bits (P15 low = 1, P14 low = 0)
+    each followed by both lines high, then a 0 stop bit.
+    Byte 0 of the first packet: bits 3-7 command, bits 0-2 packet count.
+    The screen is colored per 8x8 cell with one of 4 palettes of 4 colors,
+    color 0 is shared by all of them.
+    https://gbdev.io/pandocs/SGB_Functions.html
+     */
+    pub fn new(commands_enabled: bool) -> Self {
+        // Shades of the DMG until the game sets its palettes
+        let gray = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+        Self {
+            commands_enabled,
+            packet: [0; PACKET_SIZE],
+            packet_bits: 0,
+            receiving: false,
+            pulse_ready: false,
+            command: Vec::new(),
+            previous_lines: 0x30,
+            palettes: [gray; 4],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attributes: vec![0; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            mask: Mask::None,
+            transfer: None,
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_map: vec![0; BORDER_DATA_SIZE],
+            player_count: 1,
+            current_player: 0,
+            player_keys: [0xFF; 3],
+            screen: vec![0xFF00_0000; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame: vec![0xFF00_0000; SGB_WIDTH * SGB_HEIGHT],
+        }
+    }
+
+    pub fn save_state(&self) -> SgbState {
+        SgbState {
+            commands_enabled: self.commands_enabled,
+            packet: self.packet,
+            packet_bits: self.packet_bits,
+            receiving: self.receiving,
+            pulse_ready: self.pulse_ready,
+            command: self.command.clone(),
+            previous_lines: self.previous_lines,
+            palettes: self.palettes,
+            system_palettes: self.system_palettes.clone(),
+            attributes: self.attributes.clone(),
+            attribute_files: self.attribute_files.clone(),
+            mask: self.mask,
+            transfer: self.transfer,
+            border_tiles: self.border_tiles.clone(),
+            border_map: self.border_map.clone(),
+            player_count: self.player_count,
+            current_player: self.current_player,
+        }
+    }
+    pub fn load_state(&mut self, state: SgbState) {
+        self.commands_enabled = state.commands_enabled;
+        self.packet = state.packet;
+        self.packet_bits = state.packet_bits;
+        self.receiving = state.receiving;
+        self.pulse_ready = state.pulse_ready;
+        self.command = state.command;
+        self.previous_lines = state.previous_lines;
+        self.palettes = state.palettes;
+        self.system_palettes = state.system_palettes;
+        self.attributes = state.attributes;
+        self.attribute_files = state.attribute_files;
+        self.mask = state.mask;
+        self.transfer = state.transfer;
+        self.border_tiles = state.border_tiles;
+        self.border_map = state.border_map;
+        self.player_count = state.player_count;
+        self.current_player = state.current_player;
+    }
+
+    pub fn commands_enabled(&self) -> bool {
+        self.commands_enabled
+    }
+    pub fn player_count(&self) -> u8 {
+        self.player_count
+    }
+    pub fn screen(&self) -> &[u32] {
+        &self.screen
+    }
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    pub fn read_joypad(&self, joypad: &Joypad) -> u8 {
+        // With no line selected the ID of the current player is returned
+        if self.player_count > 1 && joypad.register & 0x30 == 0x30 {
+            return 0xC0 | (0x0F - self.current_player);
+        }
+        match self.current_player {
+            0 => joypad.read(),
+            player => joypad.read_keys(self.player_keys[player as usize - 1]),
+        }
+    }
+
+    pub fn write_joypad(&mut self, value: u8) {
+        let lines = value & 0x30;
+        let previous_lines = std::mem::replace(&mut self.previous_lines, lines);
+        if !self.commands_enabled {
+            return;
+        }
+
+        // P15 going high selects the next player
+        if !self.receiving
+            && self.player_count > 1
+            && previous_lines & 0x20 == 0
+            && lines & 0x20 != 0
+        {
+            self.current_player = (self.current_player + 1) % self.player_count;
+        }
+
+        match lines {
+            0x00 => {
+                // Reset pulse, starts a packet. One cutting a packet short drops its command
+                if self.receiving && self.packet_bits > 0 {
+                    self.command.clear();
+                }
+                self.receiving = true;
+                self.pulse_ready = false;
+                self.packet = [0; PACKET_SIZE];
+                self.packet_bits = 0;
+            }
+            0x30 => self.pulse_ready = true,
+            _ if !self.receiving || !self.pulse_ready => {}
+            _ => {
+                self.pulse_ready = false;
+                let bit = lines == 0x10;
+                if self.packet_bits == PACKET_SIZE * 8 {
+                    // Stop bit
+                    self.receiving = false;
+                    if bit {
+                        self.command.clear();
+                    } else {
+                        self.receive_packet();
+                    }
+                    return;
+                }
+                if bit {
+                    self.packet[self.packet_bits / 8] |= 1 << (self.packet_bits % 8);
+                }
+                self.packet_bits += 1;
+            }
+        }
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let length = (self.command[0] & 0x07) as usize;
+        if length == 0 || self.command.len() >= length * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            if length > 0 {
+                self.execute(&command);
+            }
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            0x00 => self.set_palette_pair(data, 0, 1),
+            0x01 => self.set_palette_pair(data, 2, 3),
+            0x02 => self.set_palette_pair(data, 0, 3),
+            0x03 => self.set_palette_pair(data, 1, 2),
+            0x04 => self.attribute_block(data),
+            0x05 => self.attribute_line(data),
+            0x06 => self.attribute_divide(data),
+            0x07 => self.attribute_characters(data),
+            0x0A => self.palette_set(data),
+            0x0B => self.start_transfer(Transfer::Palettes),
+            0x11 => {
+                self.player_count = match data[1] & 0x03 {
+                    0x01 => 2,
+                    0x03 => 4,
+                    _ => 1,
+                };
+                self.current_player = 0;
+            }
+            0x13 => self.start_transfer(Transfer::BorderTiles((data[1] & 0x01) as usize)),
+            0x14 => self.start_transfer(Transfer::BorderMap),
+            0x15 => self.start_transfer(Transfer::Attributes),
+            0x16 => {
+                self.load_attribute_file((data[1] & 0x3F) as usize);
+                if data[1] & 0x40 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            0x17 => {
+                self.mask = match data[1] & 0x03 {
+                    0x01 => Mask::Freeze,
+                    0x02 => Mask::Black,
+                    0x03 => Mask::Color0,
+                    _ => Mask::None,
+                }
+            }
+            // Sound, SNES memory and the rest don't change what is shown
+            _ => {}
+        }
+    }
+
+    fn start_transfer(&mut self, transfer: Transfer) {
+        self.transfer = Some((transfer, TRANSFER_DELAY));
+    }
+
+    fn set_palette_pair(&mut self, data: &[u8], first: usize, second: usize) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]);
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        self.attributes[y * ATTRIBUTE_WIDTH + x] = palette & 0x03;
+    }
+
+    fn attribute_block(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for block in data[2..].chunks_exact(6).take(count) {
+            let control = block[0] & 0x07;
+            let inside = block[1] & 0x03;
+            let outside = (block[1] >> 4) & 0x03;
+            // Changing only the inside or the outside also changes the border
+            let (border, change_border) = match control {
+                0x01 => (inside, true),
+                0x04 => (outside, true),
+                _ => ((block[1] >> 2) & 0x03, control & 0x02 != 0),
+            };
+            let (x1, y1, x2, y2) = (
+                block[2] as usize,
+                block[3] as usize,
+                block[4] as usize,
+                block[5] as usize,
+            );
+            for y in 0..ATTRIBUTE_HEIGHT {
+                for x in 0..ATTRIBUTE_WIDTH {
+                    let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    if on_edge {
+                        if change_border {
+                            self.set_attribute(x, y, border);
+                        }
+                    } else if within {
+                        if control & 0x01 != 0 {
+                            self.set_attribute(x, y, inside);
+                        }
+                    } else if control & 0x04 != 0 {
+                        self.set_attribute(x, y, outside);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_line(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x03;
+            if line & 0x80 != 0 {
+                // Horizontal line, a row of cells
+                if number < ATTRIBUTE_HEIGHT {
+                    for x in 0..ATTRIBUTE_WIDTH {
+                        self.set_attribute(x, number, palette);
+                    }
+                }
+            } else if number < ATTRIBUTE_WIDTH {
+                for y in 0..ATTRIBUTE_HEIGHT {
+                    self.set_attribute(number, y, palette);
+                }
+            }
+        }
+    }
+
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = (data[1] >> 2) & 0x03;
+        let on_line = (data[1] >> 4) & 0x03;
+        let horizontal = data[1] & 0x40 != 0;
+        let line = data[2] as usize;
+        for y in 0..ATTRIBUTE_HEIGHT {
+            for x in 0..ATTRIBUTE_WIDTH {
+                let position = if horizontal { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = (u16::from_le_bytes([data[3], data[4]]) as usize)
+            .min(ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT);
+        let vertical = data[5] & 0x01 != 0;
+        for i in 0..count {
+            let Some(&byte) = data.get(6 + i / 4) else {
+                break;
+            };
+            if x >= ATTRIBUTE_WIDTH || y >= ATTRIBUTE_HEIGHT {
+                break;
+            }
+            // Bits 7-6 first
+            self.set_attribute(x, y, byte >> (6 - (i % 4) * 2));
+            if vertical {
+                y += 1;
+                if y == ATTRIBUTE_HEIGHT {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_WIDTH {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    fn palette_set(&mut self, data: &[u8]) {
+        for i in 0..4 {
+            let number = (u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) & 0x1FF) as usize;
+            self.palettes[i].copy_from_slice(&self.system_palettes[number * 4..number * 4 + 4]);
+        }
+        let color0 = self.palettes[0][0];
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        if data[9] & 0x80 != 0 {
+            self.load_attribute_file((data[9] & 0x3F) as usize);
+        }
+        if data[9] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    fn load_attribute_file(&mut self, file: usize) {
+        if file >= ATTRIBUTE_FILES {
+            return;
+        }
+        let start = file * ATTRIBUTE_FILE_SIZE;
+        for i in 0..ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT {
+            let byte = self.attribute_files[start + i / 4];
+            self.attributes[i] = (byte >> (6 - (i % 4) * 2)) & 0x03;
+        }
+    }
+
+    // Screen shades back to the 2bpp tiles the game displayed, in screen order
+    fn transfer_data(shades: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; TRANSFER_SIZE];
+        for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+            let (tile_x, tile_y) = (tile % ATTRIBUTE_WIDTH, tile / ATTRIBUTE_WIDTH);
+            for row in 0..8 {
+                let start = (tile_y * 8 + row) * SCREEN_WIDTH + tile_x * 8;
+                for (column, &shade) in shades[start..start + 8].iter().enumerate() {
+                    let bit = 7 - column;
+                    bytes[row * 2] |= (shade & 0x01) << bit;
+                    bytes[row * 2 + 1] |= ((shade >> 1) & 0x01) << bit;
+                }
+            }
+        }
+        data
+    }
+
+    fn finish_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        match transfer {
+            Transfer::Palettes => {
+                for (color, bytes) in self.system_palettes.iter_mut().zip(data.chunks_exact(2)) {
+                    *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+            }
+            Transfer::BorderTiles(half) => {
+                let start = half * BORDER_TILES_SIZE / 2;
+                self.border_tiles[start..start + BORDER_TILES_SIZE / 2]
+                    .copy_from_slice(&data[..BORDER_TILES_SIZE / 2]);
+            }
+            Transfer::BorderMap => self.border_map.copy_from_slice(&data[..BORDER_DATA_SIZE]),
+            Transfer::Attributes => {
+                let length = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..length]);
+            }
+        }
+    }
+
+    // Called at VBlank with the shades (0-3, after BGP/OBP) of the frame
+    pub fn end_frame(&mut self, shades: &[u8]) {
+        if let Some((transfer, delay)) = self.transfer {
+            if delay > 0 {
+                self.transfer = Some((transfer, delay - 1));
+            } else {
+                self.transfer = None;
+                self.finish_transfer(transfer, &Self::transfer_data(shades));
+            }
+        }
+
+        match self.mask {
+            Mask::None => {
+                for (i, pixel) in self.screen.iter_mut().enumerate() {
+                    let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+                    let palette = self.attributes[(y / 8) * ATTRIBUTE_WIDTH + x / 8] as usize;
+                    *pixel = rgb(self.palettes[palette][(shades[i] & 0x03) as usize]);
+                }
+            }
+            Mask::Freeze => {}
+            Mask::Black => self.screen.fill(0xFF00_0000),
+            Mask::Color0 => self.screen.fill(rgb(self.palettes[0][0])),
+        }
+        self.compose_frame();
+    }
+
+    fn border_color(&self, x: usize, y: usize) -> Option<u32> {
+        let entry = (y / 8) * 32 + x / 8;
+        let low = self.border_map[entry * 2];
+        let high = self.border_map[entry * 2 + 1];
+        let column = if high & 0x40 != 0 { x % 8 } else { 7 - x % 8 };
+        let row = if high & 0x80 != 0 { 7 - y % 8 } else { y % 8 };
+
+        // SNES 4bpp tile, planes 0-1 then 2-3
+        let tile = &self.border_tiles[low as usize * 32..low as usize * 32 + 32];
+        let color = [
+            tile[row * 2],
+            tile[row * 2 + 1],
+            tile[16 + row * 2],
+            tile[17 + row * 2],
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |color, (plane, byte)| {
+            color | ((byte >> column) & 0x01) << plane
+        }) as usize;
+        if color == 0 {
+            return None;
+        }
+        // Palettes 4-7 are the ones sent with the border
+        let palette = ((high >> 2) & 0x07).saturating_sub(4) as usize;
+        let offset = BORDER_MAP_SIZE + (palette * 16 + color) * 2;
+        Some(rgb(u16::from_le_bytes([
+            self.border_map[offset],
+            self.border_map[offset + 1],
+        ])))
+    }
+
+    fn compose_frame(&mut self) {
+        let backdrop = rgb(self.palettes[0][0]);
+        for y in 0..SGB_HEIGHT {
+            for x in 0..SGB_WIDTH {
+                let in_screen = (SCREEN_X..SCREEN_X + SCREEN_WIDTH).contains(&x)
+                    && (SCREEN_Y..SCREEN_Y + SCREEN_HEIGHT).contains(&y);
+                // The border is drawn over the game screen
+                self.frame[y * SGB_WIDTH + x] = match self.border_color(x, y) {
+                    Some(color) => color,
+                    None if in_screen => {
+                        self.screen[(y - SCREEN_Y) * SCREEN_WIDTH + (x - SCREEN_X)]
+                    }
+                    None => backdrop,
+                };
+            }
+        }
+    }
+}
+
+fn rgb(color: u16) -> u32 {
+    let [low, high] = color.to_le_bytes();
+    CgbRegisters::convert_color(low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gameboy::Gameboy, model::HardwareModel, test_util::PALETTE};
+
+    fn start_packet(sgb: &mut Sgb) {
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+    }
+
+    // Bits LSB first, each followed by both lines high
+    fn send_bits(sgb: &mut Sgb, packet: &[u8], count: usize) {
+        for i in 0..count {
+            let bit = packet[i / 8] & (1 << (i % 8)) != 0;
+            sgb.write_joypad(if bit { 0x10 } else { 0x20 });
+            sgb.write_joypad(0x30);
+        }
+    }
+
+    fn stop_bit(sgb: &mut Sgb, bit: bool) {
+        sgb.write_joypad(if bit { 0x10 } else { 0x20 });
+        sgb.write_joypad(0x30);
+    }
+
+    fn send(sgb: &mut Sgb, data: &[u8]) {
+        for packet in data.chunks(PACKET_SIZE) {
+            let mut bytes = [0; PACKET_SIZE];
+            bytes[..packet.len()].copy_from_slice(packet);
+            start_packet(sgb);
+            send_bits(sgb, &bytes, PACKET_SIZE * 8);
+            stop_bit(sgb, false);
+        }
+    }
+
+    // PAL01 with colors 1-3 of palette 0 set to 0x0001-0x0003, 4-6 of palette 1
+    fn pal01(color0: u16) -> Vec<u8> {
+        let mut data = vec![0x01];
+        for color in [color0, 1, 2, 3, 4, 5, 6] {
+            data.extend_from_slice(&u16::to_le_bytes(color));
+        }
+        data
+    }
+
+    // The shades that make transfer_data return `data`
+    fn transfer_shades(data: &[u8]) -> Vec<u8> {
+        let mut shades = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (tile, bytes) in data.chunks(16).enumerate() {
+            let (tile_x, tile_y) = (tile % ATTRIBUTE_WIDTH, tile / ATTRIBUTE_WIDTH);
+            for row in 0..8 {
+                for column in 0..8 {
+                    let bit = 7 - column;
+                    let shade =
+                        (bytes[row * 2] >> bit) & 0x01 | ((bytes[row * 2 + 1] >> bit) & 0x01) << 1;
+                    shades[(tile_y * 8 + row) * SCREEN_WIDTH + tile_x * 8 + column] = shade;
+                }
+            }
+        }
+        shades
+    }
+
+    #[test]
+    fn test_palette_packet() {
+        let mut sgb = Sgb::new(true);
+        send(&mut sgb, &pal01(0x7FFF));
+        assert_eq!(sgb.palettes[0], [0x7FFF, 1, 2, 3]);
+        assert_eq!(sgb.palettes[1], [0x7FFF, 4, 5, 6]);
+        // Color 0 is shared
+        assert_eq!(sgb.palettes[3], [0x7FFF, 0x56B5, 0x294A, 0x0000]);
+    }
+
+    #[test]
+    fn test_commands_disabled() {
+        let mut sgb = Sgb::new(false);
+        send(&mut sgb, &pal01(0x7FFF));
+        assert_eq!(sgb.palettes[0], [0x7FFF, 0x56B5, 0x294A, 0x0000]);
+    }
+
+    #[test]
+    fn test_multi_packet_command() {
+        let mut sgb = Sgb::new(true);
+        // ATTR_BLK, 2 packets: 3 blocks setting the inside of 1x1 areas
+        let mut data = vec![0x04 << 3 | 2, 3];
+        for (x, palette) in [(0u8, 1u8), (1, 2), (2, 3)] {
+            data.extend_from_slice(&[0x01, palette, x, 0, x, 0]);
+        }
+        send(&mut sgb, &data[..PACKET_SIZE]);
+        assert_eq!(sgb.attributes[..3], [0, 0, 0]);
+        assert_eq!(sgb.command.len(), PACKET_SIZE);
+        send(&mut sgb, &data[PACKET_SIZE..]);
+        assert_eq!(sgb.attributes[..4], [1, 2, 3, 0]);
+        assert!(sgb.command.is_empty());
+    }
+
+    #[test]
+    fn test_cut_short_packet_drops_the_command() {
+        let mut sgb = Sgb::new(true);
+        let mut data = vec![0x04 << 3 | 2, 1, 0x01, 0x03, 0, 0, 0, 0];
+        data.resize(PACKET_SIZE * 2, 0);
+        send(&mut sgb, &data[..PACKET_SIZE]);
+        // A reset pulse in the middle of the second packet
+        start_packet(&mut sgb);
+        send_bits(&mut sgb, &data[PACKET_SIZE..], 40);
+        send(&mut sgb, &pal01(0x1234));
+        // The new packet starts a command of its own
+        assert_eq!(sgb.palettes[0], [0x1234, 1, 2, 3]);
+        assert_eq!(sgb.attributes[0], 0);
+        assert!(sgb.command.is_empty());
+    }
+
+    #[test]
+    fn test_stop_bit_of_1_drops_the_command() {
+        let mut sgb = Sgb::new(true);
+        let mut data = vec![0x04 << 3 | 2, 1, 0x01, 0x03, 0, 0, 0, 0];
+        data.resize(PACKET_SIZE * 2, 0);
+        send(&mut sgb, &data[..PACKET_SIZE]);
+        start_packet(&mut sgb);
+        send_bits(&mut sgb, &data[PACKET_SIZE..], PACKET_SIZE * 8);
+        stop_bit(&mut sgb, true);
+        assert!(sgb.command.is_empty());
+        assert_eq!(sgb.attributes[0], 0);
+    }
+
+    #[test]
+    fn test_bits_need_both_lines_high_in_between() {
+        let mut sgb = Sgb::new(true);
+        start_packet(&mut sgb);
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x20);
+        assert_eq!(sgb.packet_bits, 1);
+        assert_eq!(sgb.packet[0], 0x01);
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let mut sgb = Sgb::new(true);
+        // MLT_REQ for 4 players
+        send(&mut sgb, &[0x11 << 3 | 1, 0x03]);
+        assert_eq!(sgb.player_count(), 4);
+        let mut joypad = Joypad::new();
+        joypad.write(0x30);
+        assert_eq!(sgb.read_joypad(&joypad), 0xCF);
+        // P15 going high moves on to the next player
+        for player in 1..=4 {
+            sgb.write_joypad(0x10);
+            sgb.write_joypad(0x30);
+            assert_eq!(sgb.read_joypad(&joypad), 0xCF - player % 4);
+        }
+    }
+
+    #[test]
+    fn test_palette_transfer() {
+        let mut sgb = Sgb::new(true);
+        let mut data = vec![0; TRANSFER_SIZE];
+        for (i, color) in [0x1111u16, 0x2222, 0x3333, 0x4444].iter().enumerate() {
+            // System palette 1
+            data[8 + i * 2..10 + i * 2].copy_from_slice(&color.to_le_bytes());
+        }
+        // PAL_TRN, then the screen two frames later is read
+        send(&mut sgb, &[0x0B << 3 | 1]);
+        let blank = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        sgb.end_frame(&blank);
+        sgb.end_frame(&blank);
+        sgb.end_frame(&transfer_shades(&data));
+        assert_eq!(sgb.system_palettes[4..8], [0x1111, 0x2222, 0x3333, 0x4444]);
+
+        // PAL_SET with system palette 1 for palettes 0-3
+        send(&mut sgb, &[0x0A << 3 | 1, 1, 0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(sgb.palettes, [[0x1111, 0x2222, 0x3333, 0x4444]; 4]);
+        sgb.end_frame(&vec![2; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        assert!(sgb.screen().iter().all(|&pixel| pixel == rgb(0x3333)));
+    }
+
+    #[test]
+    fn test_power_up_joypad_write_does_not_start_a_packet() {
+        let mut gameboy = Gameboy::new(PALETTE);
+        gameboy.set_model(Some(HardwareModel::Sgb));
+        let mut rom = vec![0; 0x8000];
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x33;
+        gameboy.load_rom(&rom);
+        let sgb = gameboy.bus.sgb.as_mut().unwrap();
+        assert!(sgb.commands_enabled());
+        assert!(!sgb.receiving);
+        // so the game's first packet comes through whole
+        send(sgb, &pal01(0x7FFF));
+        assert_eq!(sgb.palettes[0], [0x7FFF, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{bus::Bus, debugger::parse_number};
+
+/*
+Labels from RGBDS .sym and .map files for the debugger, disassembler and trace.
+Symbols are keyed by bank and address. Only ROMX, VRAM and WRAMX have banks
+that Bus::bank_at can tell apart, elsewhere the bank is ignored when looking
+one up, e.g. cartridge RAM labels match in any bank.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    by_location: BTreeMap<(u16, usize), String>,
+    by_name: HashMap<String, (usize, u16)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub bank: usize,
+    pub address: u16,
+}
+
+fn is_banked(address: u16) -> bool {
+    matches!(address, 0x4000..=0x9FFF | 0xD000..=0xDFFF)
+}
+
+// Start of the memory area an address is in, labels don't reach across areas
+fn area_start(address: u16) -> u16 {
+    match address {
+        0x0000..=0x3FFF => 0x0000,
+        0x4000..=0x7FFF => 0x4000,
+        0x8000..=0x9FFF => 0x8000,
+        0xA000..=0xBFFF => 0xA000,
+        0xC000..=0xCFFF => 0xC000,
+        0xD000..=0xDFFF => 0xD000,
+        0xE000..=0xFF7F => 0xE000,
+        _ => 0xFF80,
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.by_location.clear();
+        self.by_name.clear();
+    }
+    // Sorted by address, then bank
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.by_location
+            .iter()
+            .map(|(&(address, bank), name)| Symbol {
+                name: name.clone(),
+                bank,
+                address,
+            })
+            .collect()
+    }
+
+    // The first name given to a location is the one shown for it
+    pub fn add(&mut self, bank: usize, address: u16, name: &str) {
+        self.by_location
+            .entry((address, bank))
+            .or_insert_with(|| name.to_string());
+        self.by_name.insert(name.to_string(), (bank, address));
+    }
+
+    // "01:4000 Main.loop" per line, ; starts a comment. Returns how many were read
+    pub fn load_sym(&mut self, text: &str) -> usize {
+        let mut count = 0;
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            let Some((location, name)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            if let Ok((Some(bank), address)) = parse_bank_address(location) {
+                self.add(bank, address, name.trim());
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /*
+    rgblink -m output: "ROMX bank #1:" starts a bank, "$4000 = Main" lines
+    below its sections are the labels. Areas without a bank number (HRAM) are bank 0.
+    */
+    pub fn load_map(&mut self, text: &str) -> usize {
+        let mut count = 0;
+        let mut bank = 0;
+        for line in text.lines() {
+            if !line.starts_with(char::is_whitespace) && line.trim_end().ends_with(':') {
+                bank = line
+                    .split_once('#')
+                    .and_then(|(_, number)| {
+                        let digits = number.trim_start();
+                        let end = digits
+                            .find(|c: char| !c.is_ascii_digit())
+                            .unwrap_or(digits.len());
+                        digits[..end].parse().ok()
+                    })
+                    .unwrap_or(0);
+                continue;
+            }
+            let Some((address, name)) = line.trim().split_once(" = ") else {
+                continue;
+            };
+            if let Some(address) = address
+                .strip_prefix('$')
+                .and_then(|digits| u16::from_str_radix(digits, 16).ok())
+            {
+                self.add(bank, address, name.trim());
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<Symbol> {
+        self.by_name.get(name).map(|&(bank, address)| Symbol {
+            name: name.to_string(),
+            bank,
+            address,
+        })
+    }
+
+    pub fn name_at(&self, bank: usize, address: u16) -> Option<&str> {
+        let bank = is_banked(address).then_some(bank);
+        self.by_location
+            .range((address, 0)..=(address, usize::MAX))
+            .find(|((_, symbol_bank), _)| bank.is_none_or(|bank| bank == *symbol_bank))
+            .map(|(_, name)| name.as_str())
+    }
+
+    // "Main.loop", or the closest label before it in the same area, "Main+$12"
+    pub fn describe(&self, bank: usize, address: u16) -> Option<String> {
+        let bank = is_banked(address).then_some(bank);
+        self.by_location
+            .range((area_start(address), 0)..=(address, usize::MAX))
+            .rev()
+            .find(|((_, symbol_bank), _)| bank.is_none_or(|bank| bank == *symbol_bank))
+            .map(
+                |(&(symbol_address, _), name)| match address - symbol_address {
+                    0 => name.clone(),
+                    offset => format!("{}+${:X}", name, offset),
+                },
+            )
+    }
+
+    // Label at an address in the bank mapped right now
+    pub fn label(&self, bus: &Bus, address: u16) -> Option<&str> {
+        self.name_at(bus.bank_at(address), address)
+    }
+
+    /*
+    Where a breakpoint or trigger should go: a label, "01:4000" (hex, as in
+    .sym files) or a number for parse_number. The bank is only given where
+    Bus::bank_at can check it.
+    */
+    pub fn parse_location(&self, text: &str) -> Result<(Option<usize>, u16), String> {
+        let text = text.trim();
+        if let Some(symbol) = self.resolve(text) {
+            let bank = is_banked(symbol.address).then_some(symbol.bank);
+            return Ok((bank, symbol.address));
+        }
+        if text.contains(':') {
+            return parse_bank_address(text);
+        }
+        parse_number(text)
+            .map(|address| (None, address))
+            .map_err(|_| format!("Unknown label or address \"{}\"", text))
+    }
+}
+
+fn parse_bank_address(text: &str) -> Result<(Option<usize>, u16), String> {
+    let hex = |digits: &str| u16::from_str_radix(digits.trim_start_matches('$'), 16);
+    match text.split_once(':') {
+        Some((bank, address)) => match (hex(bank), hex(address)) {
+            (Ok(bank), Ok(address)) => Ok((Some(bank as usize), address)),
+            _ => Err(format!("Invalid address \"{}\"", text)),
+        },
+        None => Err(format!("Invalid address \"{}\"", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "; File generated by rgblink
+00:0150 Start
+01:4000 Main
+01:4010 Main.loop ; comment
+02:4000 Other
+00:C000 wBuffer
+00:FF80 hFrame
+not a symbol
+";
+
+    const MAP: &str = "ROM0 bank #0:
+  SECTION: $0000-$00FF ($0100 bytes) [\"Vectors\"]
+           $0000 = Reset
+ROMX bank #2:
+  SECTION: $4000-$4FFF ($1000 bytes) [\"Code\"]
+           $4000 = Engine
+           $4020 = Engine.update
+HRAM:
+  SECTION: $FF80-$FF81 ($0002 bytes) [\"HRAM\"]
+           $FF80 = hFrame
+";
+
+    #[test]
+    fn test_load_sym() {
+        let mut symbols = SymbolTable::new();
+        assert_eq!(symbols.load_sym(SYM), 6);
+        assert_eq!(symbols.len(), 6);
+        assert_eq!(symbols.name_at(1, 0x4010), Some("Main.loop"));
+        assert_eq!(symbols.name_at(2, 0x4000), Some("Other"));
+        assert_eq!(symbols.name_at(3, 0x4000), None);
+        // Unbanked areas match in any bank
+        assert_eq!(symbols.name_at(5, 0xC000), Some("wBuffer"));
+        assert_eq!(
+            symbols.resolve("Main"),
+            Some(Symbol {
+                name: "Main".to_string(),
+                bank: 1,
+                address: 0x4000,
+            })
+        );
+        let addresses: Vec<u16> = symbols.symbols().iter().map(|s| s.address).collect();
+        assert_eq!(addresses, [0x0150, 0x4000, 0x4000, 0x4010, 0xC000, 0xFF80]);
+    }
+
+    #[test]
+    fn test_load_map() {
+        let mut symbols = SymbolTable::new();
+        assert_eq!(symbols.load_map(MAP), 4);
+        assert_eq!(symbols.name_at(0, 0x0000), Some("Reset"));
+        assert_eq!(symbols.name_at(2, 0x4020), Some("Engine.update"));
+        assert_eq!(symbols.name_at(1, 0x4020), None);
+        assert_eq!(symbols.resolve("hFrame").unwrap().bank, 0);
+    }
+
+    #[test]
+    fn test_first_name_is_shown() {
+        let mut symbols = SymbolTable::new();
+        symbols.add(0, 0x150, "Start");
+        symbols.add(0, 0x150, "Entry");
+        assert_eq!(symbols.name_at(0, 0x150), Some("Start"));
+        assert_eq!(symbols.resolve("Entry").unwrap().address, 0x150);
+        symbols.clear();
+        assert!(symbols.is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut symbols = SymbolTable::new();
+        symbols.load_sym(SYM);
+        assert_eq!(symbols.describe(1, 0x4010), Some("Main.loop".to_string()));
+        assert_eq!(
+            symbols.describe(1, 0x4012),
+            Some("Main.loop+$2".to_string())
+        );
+        assert_eq!(symbols.describe(2, 0x4100), Some("Other+$100".to_string()));
+        assert_eq!(symbols.describe(0, 0x3FFF), Some("Start+$3EAF".to_string()));
+        // Labels don't reach into the next area or another bank
+        assert_eq!(symbols.describe(3, 0x4000), None);
+        assert_eq!(symbols.describe(0, 0x0100), None);
+        assert_eq!(symbols.describe(0, 0xD000), None);
+        assert_eq!(symbols.describe(0, 0xFF81), Some("hFrame+$1".to_string()));
+    }
+
+    #[test]
+    fn test_parse_location() {
+        let mut symbols = SymbolTable::new();
+        symbols.load_sym(SYM);
+        assert_eq!(symbols.parse_location("Main.loop"), Ok((Some(1), 0x4010)));
+        // The bank of an unbanked label can't be checked
+        assert_eq!(symbols.parse_location("wBuffer"), Ok((None, 0xC000)));
+        assert_eq!(symbols.parse_location("03:4000"), Ok((Some(3), 0x4000)));
+        assert_eq!(symbols.parse_location("$FF40"), Ok((None, 0xFF40)));
+        assert!(symbols.parse_location("01:XYZ").is_err());
+        assert!(symbols.parse_location("Missing").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    cartridge::cartridge_header::{CartridgeHeader, NINTENDO_LOGO},
+    gameboy::Gameboy,
+};
+
+pub const PALETTE: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+// 32 KiB ROM whose entry point jumps to the program at 0150
+pub fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // NOP; JP $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+pub fn gameboy_with_rom(rom: &[u8]) -> Gameboy {
+    let mut gameboy = Gameboy::new(PALETTE);
+    gameboy.load_rom(rom);
+    gameboy
+}
+
+pub fn gameboy_with_program(program: &[u8]) -> Gameboy {
+    gameboy_with_rom(&rom_with_program(program))
+}
+
+// Each 16 KiB bank starts with its number as a little-endian word
+pub fn banked_rom(banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * 0x4000];
+    for bank in 0..banks {
+        rom[bank * 0x4000..bank * 0x4000 + 2].copy_from_slice(&(bank as u16).to_le_bytes());
+    }
+    rom
+}
+
+// Nintendo logo, title and cartridge type of a header at the start of `rom`
+pub fn write_header(rom: &mut [u8], title: &str, cartridge_type: u8) {
+    let logo = CartridgeHeader::NintendoLogoStart as usize;
+    rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+    let title_start = CartridgeHeader::TitleStart as usize;
+    rom[title_start..title_start + title.len()].copy_from_slice(title.as_bytes());
+    rom[CartridgeHeader::CartridgeType as usize] = cartridge_type;
+}
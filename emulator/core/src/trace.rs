@@ -0,0 +1,320 @@
+use std::fmt::Write;
+
+use crate::{
+    bus::{Bus, MemoryInterface},
+    cpu::CPU,
+    disasm,
+    symbols::SymbolTable,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    // A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+    Doctor,
+    // A: 01 F: B0 B: 00 C: 13 D: 00 E: D8 H: 01 L: 4D SP: FFFE PC: 00:0100 (00 C3 13 02)
+    GameboyLogs,
+    // BGB/SameBoy style with bank and disassembly:
+    // 00:0150 F0 44     ldh a, [$FF44]         AF=01B0 BC=0013 DE=00D8 HL=014D SP=FFFE
+    Disassembly,
+}
+
+// When tracing starts or stops, PCs can be bank qualified like breakpoints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceTrigger {
+    Pc { bank: Option<usize>, address: u16 },
+    // Frames since the trace was set up
+    Frame(usize),
+}
+
+/*
+One line per executed instruction, with the CPU state before it runs.
+Lines are collected in memory and a frontend drains them with take_output,
+so the core doesn't need file access. max_bytes bounds the whole trace,
+tracing ends once the next line would go over it.
+*/
+#[derive(Clone, Debug)]
+pub struct TraceLogger {
+    pub format: TraceFormat,
+    pub start: Option<TraceTrigger>,
+    pub stop: Option<TraceTrigger>,
+    pub max_bytes: usize,
+    active: bool,
+    finished: bool,
+    frame: usize,
+    lines: u64,
+    bytes: usize,
+    output: String,
+}
+
+impl TraceFormat {
+    pub const ALL: [TraceFormat; 3] = [
+        TraceFormat::Doctor,
+        TraceFormat::GameboyLogs,
+        TraceFormat::Disassembly,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceFormat::Doctor => "doctor",
+            TraceFormat::GameboyLogs => "logs",
+            TraceFormat::Disassembly => "bgb",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl TraceTrigger {
+    // "frame:600", "pc:$0150", "pc:01:4000", "pc:Main.loop" or just "$0150"
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(frame) = text.strip_prefix("frame:") {
+            return frame
+                .parse()
+                .map(TraceTrigger::Frame)
+                .map_err(|_| format!("Invalid frame \"{}\"", frame));
+        }
+        let location = text.strip_prefix("pc:").unwrap_or(text);
+        let (bank, address) = symbols.parse_location(location)?;
+        Ok(TraceTrigger::Pc { bank, address })
+    }
+
+    fn matches(&self, frame: usize, pc: u16, bus: &Bus) -> bool {
+        match *self {
+            TraceTrigger::Frame(target) => frame >= target,
+            TraceTrigger::Pc { bank, address } => {
+                pc == address && bank.is_none_or(|bank| bank == bus.bank_at(pc))
+            }
+        }
+    }
+}
+
+impl TraceLogger {
+    pub fn new(format: TraceFormat, max_bytes: usize) -> Self {
+        Self {
+            format,
+            start: None,
+            stop: None,
+            max_bytes,
+            active: false,
+            finished: false,
+            frame: 0,
+            lines: 0,
+            bytes: 0,
+            output: String::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+    // Stopped by its trigger or the size limit, nothing more will be logged
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+    // Lines since the last call
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // Called before each instruction, halted cycles aren't instructions
+    pub(crate) fn log(&mut self, cpu: &CPU, bus: &Bus, symbols: &SymbolTable) {
+        if self.finished || cpu.halt {
+            return;
+        }
+        if !self.active {
+            self.active = self
+                .start
+                .is_none_or(|start| start.matches(self.frame, cpu.pc, bus));
+            if !self.active {
+                return;
+            }
+        }
+        if self
+            .stop
+            .is_some_and(|stop| stop.matches(self.frame, cpu.pc, bus))
+        {
+            self.finish();
+            return;
+        }
+
+        let start = self.output.len();
+        self.write_line(cpu, bus, symbols);
+        let length = self.output.len() - start;
+        if self.bytes + length > self.max_bytes {
+            self.output.truncate(start);
+            self.finish();
+            return;
+        }
+        self.bytes += length;
+        self.lines += 1;
+    }
+
+    fn finish(&mut self) {
+        self.active = false;
+        self.finished = true;
+    }
+
+    fn write_line(&mut self, cpu: &CPU, bus: &Bus, symbols: &SymbolTable) {
+        let pc = cpu.pc;
+        let memory = |offset: u16| bus.read_byte(pc.wrapping_add(offset));
+        let out = &mut self.output;
+        // Writing to a String can't fail
+        let _ = match self.format {
+            TraceFormat::Doctor => writeln!(
+                out,
+                "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                cpu.a, cpu.f.bits(), cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, pc,
+                memory(0), memory(1), memory(2), memory(3)
+            ),
+            TraceFormat::GameboyLogs => writeln!(
+                out,
+                "A: {:02X} F: {:02X} B: {:02X} C: {:02X} D: {:02X} E: {:02X} H: {:02X} L: {:02X} SP: {:04X} PC: {:02X}:{:04X} ({:02X} {:02X} {:02X} {:02X})",
+                cpu.a, cpu.f.bits(), cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp,
+                bus.bank_at(pc), pc, memory(0), memory(1), memory(2), memory(3)
+            ),
+            TraceFormat::Disassembly => {
+                let instruction = disasm::decode(bus, pc);
+                writeln!(
+                    out,
+                    "{} {:<8}  {:<22} AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X}",
+                    instruction.location(),
+                    instruction.bytes_text(),
+                    instruction.text_with_symbols(bus, symbols),
+                    cpu.a, cpu.f.bits(), cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp
+                )
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gameboy::Gameboy, test_util::gameboy_with_program};
+
+    fn gameboy(trace: TraceLogger) -> Gameboy {
+        // 0150: INC A; JR $0150
+        let mut gameboy = gameboy_with_program(&[0x3C, 0x18, 0xFD]);
+        gameboy.start_trace(trace);
+        gameboy
+    }
+
+    #[test]
+    fn test_parse_triggers() {
+        let mut symbols = SymbolTable::new();
+        symbols.add(1, 0x4000, "Main");
+        let parse = |text: &str| TraceTrigger::parse(text, &symbols);
+        assert_eq!(parse("frame:600"), Ok(TraceTrigger::Frame(600)));
+        let pc = |bank, address| Ok(TraceTrigger::Pc { bank, address });
+        assert_eq!(parse("pc:$0150"), pc(None, 0x150));
+        assert_eq!(parse(" 0150h "), pc(None, 0x150));
+        assert_eq!(parse("pc:01:4000"), pc(Some(1), 0x4000));
+        assert_eq!(parse("pc:Main"), pc(Some(1), 0x4000));
+        assert!(parse("frame:soon").is_err());
+        assert!(parse("pc:Missing").is_err());
+        assert_eq!(
+            TraceFormat::from_name("BGB"),
+            Some(TraceFormat::Disassembly)
+        );
+    }
+
+    #[test]
+    fn test_doctor_lines() {
+        let mut gameboy = gameboy(TraceLogger::new(TraceFormat::Doctor, usize::MAX));
+        for _ in 0..3 {
+            gameboy.tick();
+        }
+        let trace = gameboy.trace.as_mut().unwrap();
+        assert!(trace.is_active());
+        assert_eq!(trace.lines(), 3);
+        let output = trace.take_output();
+        let lines: Vec<&str> = output.lines().collect();
+        // DMG boot registers, H and C are clear for a header checksum of 0
+        assert_eq!(
+            lines[0],
+            "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01"
+        );
+        assert!(lines[2].ends_with("PC:0150 PCMEM:3C,18,FD,00"));
+        assert_eq!(trace.bytes(), output.len());
+        assert!(trace.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_other_formats() {
+        let mut gameboy = gameboy(TraceLogger::new(TraceFormat::GameboyLogs, usize::MAX));
+        gameboy.tick();
+        let output = gameboy.trace.as_mut().unwrap().take_output();
+        assert!(output.ends_with("SP: FFFE PC: 00:0100 (00 C3 50 01)\n"));
+
+        let mut gameboy = self::gameboy(TraceLogger::new(TraceFormat::Disassembly, usize::MAX));
+        gameboy.symbols.add(0, 0x150, "Loop");
+        for _ in 0..4 {
+            gameboy.tick();
+        }
+        let output = gameboy.trace.as_mut().unwrap().take_output();
+        let line = output.lines().nth(3).unwrap();
+        assert!(line.starts_with("00:0151 18 FD     jr Loop "), "{}", line);
+        assert!(line.ends_with("SP=FFFE"));
+    }
+
+    #[test]
+    fn test_triggers() {
+        let mut trace = TraceLogger::new(TraceFormat::Doctor, usize::MAX);
+        trace.start = Some(TraceTrigger::Pc {
+            bank: None,
+            address: 0x151,
+        });
+        trace.stop = Some(TraceTrigger::Frame(1));
+        let mut gameboy = gameboy(trace);
+        gameboy.tick();
+        gameboy.tick();
+        gameboy.tick();
+        assert!(!gameboy.trace.as_ref().unwrap().is_active());
+
+        gameboy.run_frame();
+        gameboy.run_frame();
+        let trace = gameboy.trace.as_mut().unwrap();
+        assert!(trace.is_finished());
+        assert!(!trace.is_active());
+        let output = trace.take_output();
+        assert!(output.lines().next().unwrap().contains("PC:0151"));
+        assert_eq!(output.lines().count() as u64, trace.lines());
+
+        // Nothing once finished
+        gameboy.tick();
+        assert_eq!(gameboy.trace.as_ref().unwrap().bytes(), output.len());
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let mut gameboy = gameboy(TraceLogger::new(TraceFormat::Doctor, usize::MAX));
+        gameboy.tick();
+        let line = gameboy.stop_trace().unwrap().take_output().len();
+
+        // The third line would go over
+        let mut gameboy = self::gameboy(TraceLogger::new(TraceFormat::Doctor, line * 3 - 1));
+        for _ in 0..10 {
+            gameboy.tick();
+        }
+        let trace = gameboy.trace.as_mut().unwrap();
+        assert!(trace.is_finished());
+        assert_eq!(trace.lines(), 2);
+        assert_eq!(trace.bytes(), line * 2);
+        assert_eq!(trace.take_output().len(), line * 2);
+    }
+}
@@ -0,0 +1,77 @@
+use gameboy_core::gameboy::Gameboy;
+use std::{
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+// Binary PGM (P5) with 8 bit samples, e.g. `convert photo.jpg -colorspace gray photo.pgm`
+pub fn load_pgm(path: &Path) -> io::Result<(Vec<u8>, usize, usize)> {
+    let data = std::fs::read(path)?;
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+
+    // Magic, width, height and max value, separated by whitespace and comments
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while position < data.len() && data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if data.get(position) == Some(&b'#') {
+            while position < data.len() && data[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("Truncated PGM header"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+    }
+    // A single whitespace byte before the pixels
+    position += 1;
+
+    if fields[0] != "P5" {
+        return Err(invalid("Only binary PGM (P5) images are supported"));
+    }
+    let number = |field: &str| {
+        field
+            .parse::<usize>()
+            .map_err(|_| invalid("Bad PGM header"))
+    };
+    let (width, height, max) = (
+        number(&fields[1])?,
+        number(&fields[2])?,
+        number(&fields[3])?,
+    );
+    if max == 0 || max > 255 {
+        return Err(invalid("Only 8 bit PGM images are supported"));
+    }
+    let pixels = data
+        .get(position..position + width * height)
+        .ok_or_else(|| invalid("Truncated PGM data"))?
+        .iter()
+        .map(|&value| (value as usize * 255 / max) as u8)
+        .collect();
+    Ok((pixels, width, height))
+}
+
+pub fn set_image_from_file(gameboy: &mut Gameboy, path: &Path) {
+    match load_pgm(path) {
+        Ok((pixels, width, height)) => gameboy.set_camera_image(&pixels, width, height),
+        Err(e) => println!("Failed to load camera image: {}", e),
+    }
+}
+
+// Writes photo-1.png, photo-2.png... for the photos in the camera album
+pub fn export_photos(gameboy: &Gameboy, directory: &Path) -> io::Result<usize> {
+    let photos = gameboy.camera_photos_png();
+    std::fs::create_dir_all(directory)?;
+    for (i, photo) in photos.iter().enumerate() {
+        std::fs::write(directory.join(format!("photo-{}.png", i + 1)), photo)?;
+    }
+    Ok(photos.len())
+}
@@ -8,12 +8,15 @@ use gameboy_core as GameboyCore;
 use GameboyCore::bus::io_address::IoRegister;
 use GameboyCore::cartridge::cartridge_header;
 use GameboyCore::cpu::CPU;
+use GameboyCore::disasm;
 use GameboyCore::ppu::PPUMode;
+use GameboyCore::symbols::SymbolTable;
 use GameboyCore::{bus, ppu};
 
 const WINDOW_WIDTH: usize = 800;
 const WINDOW_HEIGHT: usize = 600;
 const COLORS: [u32; 4] = [0x9bbc0f, 0x8bac0f, 0x306230, 0x0f380f];
+const DISASSEMBLY_LINES: usize = 10;
 
 struct CartridgeHeaderState {
     title: String,
@@ -49,6 +52,7 @@ pub struct DebugWindow {
     op: u8,
     ime: bool,
     halt: bool,
+    disassembly: Vec<String>,
     last_cycle: usize,
     io_registers: Vec<(String, u8)>,
     tile_data: [u8; 0x1800],
@@ -89,6 +93,7 @@ impl DebugWindow {
             op: 0,
             ime: false,
             halt: false,
+            disassembly: Vec::new(),
             io_registers: Vec::new(),
             tile_data: [0; 0x1800],
             bg_tilemap: [0; 0x800],
@@ -113,7 +118,14 @@ impl DebugWindow {
             .unwrap();
     }
 
-    pub fn update(&mut self, cpu: &CPU, bus: &bus::Bus, ppu: &ppu::PPU, frames: usize) {
+    pub fn update(
+        &mut self,
+        cpu: &CPU,
+        bus: &bus::Bus,
+        ppu: &ppu::PPU,
+        symbols: &SymbolTable,
+        frames: usize,
+    ) {
         // CPU registers
         self.cpu_registers = [
             cpu.a,
@@ -131,6 +143,18 @@ impl DebugWindow {
         self.op = bus.read_byte(cpu.pc);
         self.ime = cpu.ime;
         self.halt = cpu.halt;
+        // Labels get a line of their own, like in the source
+        self.disassembly.clear();
+        for instruction in disasm::disassemble(bus, cpu.pc, DISASSEMBLY_LINES) {
+            if let Some(label) = symbols.name_at(instruction.bank, instruction.address) {
+                self.disassembly.push(format!("{}:", label));
+            }
+            self.disassembly.push(format!(
+                "{} {}",
+                instruction.location(),
+                instruction.text_with_symbols(bus, symbols)
+            ));
+        }
         self.lcdc = bus.read_byte(IoRegister::Lcdc.address());
         self.window_y = bus.read_byte(IoRegister::Wy.address());
         self.window_x = bus.read_byte(IoRegister::Wx.address());
@@ -533,6 +557,22 @@ impl DebugWindow {
         text_font_renderer.draw_text(&mut buffer, 10, 205, "CYCLES");
         value_font_renderer.draw_text(&mut buffer, 60, 205, &format!("{:02X}", self.last_cycle));
 
+        // Disassembly from PC (middle column, below the cartridge header)
+        title_font_renderer.draw_text(&mut buffer, 150, 150, "Disassembly");
+        // The first line that isn't a label is the one at PC
+        let pc_line = self
+            .disassembly
+            .iter()
+            .position(|line| !line.ends_with(':'));
+        for (i, line) in self.disassembly.iter().enumerate() {
+            let renderer = if Some(i) == pc_line {
+                &value_font_renderer
+            } else {
+                &text_font_renderer
+            };
+            renderer.draw_text(&mut buffer, 150, 170 + i * 12, line);
+        }
+
         // IO Registers (left column, below CPU registers)
         let mut y_offset = 220;
         title_font_renderer.draw_text(&mut buffer, 10, y_offset, "IO Registers");
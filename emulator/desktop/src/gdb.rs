@@ -0,0 +1,705 @@
+use gameboy_core::{
+    bus::MemoryInterface,
+    cpu::{flags::Flags, CPU},
+    debugger::{StopReason, WatchKind},
+    gameboy::Gameboy,
+};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+// Largest packet we take, in bytes (advertised in hex)
+const PACKET_SIZE: usize = 0x1000;
+// How long a stopped target waits for packets each frame
+const STOPPED_WAIT: Duration = Duration::from_millis(15);
+
+// Register numbers are the order of this list, the same as in the g packet
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.sabiboy.sm83.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+enum Incoming {
+    Packet(String),
+    // Ctrl-C from the client
+    Interrupt,
+}
+
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+    no_ack: bool,
+}
+
+/*
+GDB remote serial protocol stub on localhost. Breakpoints and watchpoints go
+through the core debugger, memory through MemoryInterface. Addresses above
+$FFFF select a ROM bank for breakpoints, $014000 is bank 1 at $4000.
+The game waits for the first client, and runs freely once it detaches.
+*/
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    attached: bool,
+    running: bool,
+    // Debugger ids of what the client set, by Z packet type and address
+    points: HashMap<(u8, u32), u32>,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum)?;
+        self.stream.flush()
+    }
+
+    // Reads what arrived, waiting up to timeout. Ok(false) when nothing came
+    fn receive(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut buffer = [0; PACKET_SIZE];
+        let result = match timeout {
+            Some(timeout) => {
+                self.stream.set_read_timeout(Some(timeout))?;
+                self.stream.read(&mut buffer)
+            }
+            None => {
+                self.stream.set_nonblocking(true)?;
+                let result = self.stream.read(&mut buffer);
+                self.stream.set_nonblocking(false)?;
+                result
+            }
+        };
+        match result {
+            Ok(0) => Err(io::Error::new(ErrorKind::ConnectionAborted, "closed")),
+            Ok(count) => {
+                self.input.extend_from_slice(&buffer[..count]);
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // $data#checksum, acknowledged unless the client turned that off
+    fn next(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.input.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.input.remove(0);
+                    return Ok(Some(Incoming::Interrupt));
+                }
+                Some(b'$') => {
+                    let Some(end) = self.input.iter().position(|&byte| byte == b'#') else {
+                        return Ok(None);
+                    };
+                    if self.input.len() < end + 3 {
+                        return Ok(None);
+                    }
+                    let data = self.input[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok());
+                    self.input.drain(..end + 3);
+                    let valid = checksum
+                        == Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+                    if !self.no_ack {
+                        self.stream.write_all(if valid { b"+" } else { b"-" })?;
+                    }
+                    if valid {
+                        return Ok(Some(Incoming::Packet(
+                            String::from_utf8_lossy(&data).to_string(),
+                        )));
+                    }
+                }
+                // Acks and line noise
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+}
+
+impl GdbServer {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        println!("Waiting for GDB on 127.0.0.1:{}", port);
+        Ok(Self {
+            listener,
+            client: None,
+            attached: false,
+            running: false,
+            points: HashMap::new(),
+        })
+    }
+
+    // In place of Gameboy::run_frame, returns false when the game is stopped
+    pub fn run_frame(&mut self, gameboy: &mut Gameboy) -> bool {
+        self.accept();
+        if self.client.is_some() {
+            let wait = (!self.running).then_some(STOPPED_WAIT);
+            if let Err(e) = self.poll(gameboy, wait) {
+                println!("GDB client disconnected: {}", e);
+                self.detach(gameboy);
+            }
+        }
+        match &self.client {
+            None if !self.attached => {
+                std::thread::sleep(STOPPED_WAIT);
+                false
+            }
+            None => {
+                gameboy.run_frame();
+                true
+            }
+            Some(_) if !self.running => false,
+            Some(_) => {
+                let reason = gameboy.run_until_break();
+                if reason != StopReason::FrameComplete {
+                    self.running = false;
+                    let reply = self.stop_reply(gameboy, reason);
+                    self.send(gameboy, &reply);
+                }
+                true
+            }
+        }
+    }
+
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                println!("GDB client connected from {}", address);
+                // Accepted sockets may inherit nonblocking from the listener
+                if let Err(e) = stream
+                    .set_nonblocking(false)
+                    .and_then(|_| stream.set_nodelay(true))
+                {
+                    println!("GDB: {}", e);
+                }
+                self.client = Some(Client {
+                    stream,
+                    input: Vec::new(),
+                    no_ack: false,
+                });
+                self.attached = true;
+                self.running = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => println!("GDB accept failed: {}", e),
+        }
+    }
+
+    // Handles packets until the client continues, or the wait is over
+    fn poll(&mut self, gameboy: &mut Gameboy, wait: Option<Duration>) -> io::Result<()> {
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        loop {
+            let Some(client) = &mut self.client else {
+                return Ok(());
+            };
+            match client.next()? {
+                Some(Incoming::Interrupt) => {
+                    self.running = false;
+                    client.send("T02")?;
+                }
+                Some(Incoming::Packet(packet)) => {
+                    let reply = self.handle(gameboy, &packet);
+                    if let (Some(reply), Some(client)) = (reply, &mut self.client) {
+                        client.send(&reply)?;
+                        if packet == "QStartNoAckMode" {
+                            client.no_ack = true;
+                        }
+                    }
+                    if packet.starts_with('D') {
+                        self.detach(gameboy);
+                    }
+                    if self.running {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    let timeout = match deadline {
+                        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                            Some(remaining) if !remaining.is_zero() => Some(remaining),
+                            _ => return Ok(()),
+                        },
+                        None => None,
+                    };
+                    if !client.receive(timeout)? && timeout.is_none() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, gameboy: &mut Gameboy, reply: &str) {
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.send(reply) {
+                println!("GDB client disconnected: {}", e);
+                self.detach(gameboy);
+            }
+        }
+    }
+
+    // Drops the client and what it set, the game keeps running
+    fn detach(&mut self, gameboy: &mut Gameboy) {
+        for (_, id) in self.points.drain() {
+            gameboy.debugger.remove(id);
+        }
+        self.client = None;
+        self.running = false;
+    }
+
+    // The reply, None when there is none yet (continue) or ever (kill)
+    fn handle(&mut self, gameboy: &mut Gameboy, packet: &str) -> Option<String> {
+        let command = packet.chars().next()?;
+        let args = &packet[command.len_utf8()..];
+        let reply = match command {
+            '?' => "T05".to_string(),
+            'g' => hex(&read_registers(&gameboy.cpu)),
+            'G' => match from_hex(args) {
+                Some(bytes) if bytes.len() >= 12 => {
+                    for (index, value) in [0, 1, 2, 3, 4, 5, 6, 7, 8, 10].iter().enumerate() {
+                        write_register(&mut gameboy.cpu, index, &bytes[*value..]);
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            'p' => match usize::from_str_radix(args, 16) {
+                Ok(index) if index < 10 => {
+                    let registers = read_registers(&gameboy.cpu);
+                    let range = register_range(index);
+                    hex(&registers[range])
+                }
+                _ => "E01".to_string(),
+            },
+            'P' => {
+                let parsed = args.split_once('=').and_then(|(index, value)| {
+                    Some((usize::from_str_radix(index, 16).ok()?, from_hex(value)?))
+                });
+                match parsed {
+                    Some((index, bytes)) if index < 10 && !bytes.is_empty() => {
+                        write_register(&mut gameboy.cpu, index, &bytes);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            'm' => match parse_range(args) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0..length.min(PACKET_SIZE / 2))
+                        .map(|i| {
+                            gameboy
+                                .bus
+                                .read_byte((address as u16).wrapping_add(i as u16))
+                        })
+                        .collect();
+                    hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            'M' => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+                match parsed {
+                    Some(((address, _), bytes)) => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            gameboy
+                                .bus
+                                .write_byte((address as u16).wrapping_add(i as u16), *byte);
+                        }
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            'c' => {
+                if let Ok(address) = u32::from_str_radix(args, 16) {
+                    gameboy.cpu.pc = address as u16;
+                }
+                self.running = true;
+                return None;
+            }
+            's' => {
+                if let Ok(address) = u32::from_str_radix(args, 16) {
+                    gameboy.cpu.pc = address as u16;
+                }
+                let reason = gameboy.step_instruction();
+                self.stop_reply(gameboy, reason)
+            }
+            'Z' | 'z' => self.set_point(gameboy, command == 'Z', args),
+            'q' => query(args),
+            'Q' if args == "StartNoAckMode" => "OK".to_string(),
+            'H' | 'T' | 'D' => "OK".to_string(),
+            'k' => {
+                self.detach(gameboy);
+                return None;
+            }
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    // Z0/Z1 breakpoints, Z2 write, Z3 read and Z4 access watchpoints: "Z2,c000,2"
+    fn set_point(&mut self, gameboy: &mut Gameboy, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next().and_then(|kind| kind.parse::<u8>().ok());
+        let address = fields
+            .next()
+            .and_then(|text| u32::from_str_radix(text, 16).ok());
+        let length = fields
+            .next()
+            .and_then(|text| u16::from_str_radix(text, 16).ok())
+            .unwrap_or(1);
+        let (Some(kind), Some(address)) = (kind, address) else {
+            return "E01".to_string();
+        };
+        let watch_kind = match kind {
+            0 | 1 => None,
+            2 => Some(WatchKind::Write),
+            3 => Some(WatchKind::Read),
+            4 => Some(WatchKind::Access),
+            _ => return String::new(),
+        };
+
+        let debugger = &mut gameboy.debugger;
+        if !insert {
+            return match self.points.remove(&(kind, address)) {
+                Some(id) => {
+                    debugger.remove(id);
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            };
+        }
+        if self.points.contains_key(&(kind, address)) {
+            return "OK".to_string();
+        }
+        let start = address as u16;
+        let id = match watch_kind {
+            None => {
+                let bank = (address > 0xFFFF).then_some((address >> 16) as usize);
+                debugger.add_breakpoint(bank, start, None)
+            }
+            Some(watch_kind) => debugger.add_watchpoint(
+                start,
+                start.saturating_add(length.max(1) - 1),
+                watch_kind,
+                None,
+            ),
+        };
+        self.points.insert((kind, address), id);
+        "OK".to_string()
+    }
+
+    fn stop_reply(&self, gameboy: &Gameboy, reason: StopReason) -> String {
+        match reason {
+            StopReason::Watchpoint { id, address, .. } => {
+                let kind = gameboy
+                    .debugger
+                    .watchpoints
+                    .iter()
+                    .find(|watchpoint| watchpoint.id == id)
+                    .map(|watchpoint| watchpoint.kind);
+                let name = match kind {
+                    Some(WatchKind::Write) => "watch",
+                    Some(WatchKind::Read) => "rwatch",
+                    _ => "awatch",
+                };
+                format!("T05{}:{:x};", name, address)
+            }
+            _ => "T05".to_string(),
+        }
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return format!(
+            "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+            PACKET_SIZE
+        );
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_range(range) {
+            Some((offset, length)) => {
+                let offset = (offset as usize).min(TARGET_XML.len());
+                let end = (offset + length).min(TARGET_XML.len());
+                let prefix = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                format!("{}{}", prefix, &TARGET_XML[offset..end])
+            }
+            None => "E01".to_string(),
+        };
+    }
+    match args {
+        "Attached" => "1",
+        "C" => "QC1",
+        "fThreadInfo" => "m1",
+        "sThreadInfo" => "l",
+        "Symbol::" => "OK",
+        _ => "",
+    }
+    .to_string()
+}
+
+// a f b c d e h l, then sp and pc little endian
+fn read_registers(cpu: &CPU) -> Vec<u8> {
+    let mut registers = vec![
+        cpu.a,
+        cpu.f.bits(),
+        cpu.b,
+        cpu.c,
+        cpu.d,
+        cpu.e,
+        cpu.h,
+        cpu.l,
+    ];
+    registers.extend_from_slice(&cpu.sp.to_le_bytes());
+    registers.extend_from_slice(&cpu.pc.to_le_bytes());
+    registers
+}
+fn register_range(index: usize) -> std::ops::Range<usize> {
+    match index {
+        0..=7 => index..index + 1,
+        _ => 8 + (index - 8) * 2..10 + (index - 8) * 2,
+    }
+}
+fn write_register(cpu: &mut CPU, index: usize, bytes: &[u8]) {
+    let byte = bytes[0];
+    let word = u16::from_le_bytes([byte, bytes.get(1).copied().unwrap_or(0)]);
+    match index {
+        0 => cpu.a = byte,
+        1 => cpu.f = Flags::from_bits_truncate(byte & 0xF0),
+        2 => cpu.b = byte,
+        3 => cpu.c = byte,
+        4 => cpu.d = byte,
+        5 => cpu.e = byte,
+        6 => cpu.h = byte,
+        7 => cpu.l = byte,
+        8 => cpu.sp = word,
+        _ => cpu.pc = word,
+    }
+}
+
+// "addr,length" in hex
+fn parse_range(text: &str) -> Option<(u32, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0150: INC A; JR $0150
+    fn gameboy() -> Gameboy {
+        let mut gameboy = Gameboy::new([0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]);
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x150..0x153].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        gameboy.load_rom(&rom);
+        gameboy
+    }
+
+    fn connect(server: &mut GdbServer, gameboy: &mut Gameboy) -> TcpStream {
+        let address = server.listener.local_addr().unwrap();
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(!server.run_frame(gameboy));
+        assert!(server.client.is_some());
+        stream
+    }
+
+    fn send(stream: &mut TcpStream, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        // In one write, so the server sees the packet in the same frame
+        let packet = format!("${}#{:02x}", data, checksum);
+        stream.write_all(packet.as_bytes()).unwrap();
+    }
+
+    // Skips acks up to the next packet, checking its checksum
+    fn receive(stream: &mut TcpStream) -> String {
+        let mut byte = [0];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+            assert_eq!(byte[0], b'+');
+        }
+        let mut data = Vec::new();
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", sum)
+        );
+        String::from_utf8(data).unwrap()
+    }
+
+    // One packet and its reply, handled by a frame of the server
+    fn request(
+        server: &mut GdbServer,
+        gameboy: &mut Gameboy,
+        stream: &mut TcpStream,
+        data: &str,
+    ) -> String {
+        send(stream, data);
+        server.run_frame(gameboy);
+        receive(stream)
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut gameboy = gameboy();
+        let mut server = GdbServer::bind(0).unwrap();
+        let mut stream = connect(&mut server, &mut gameboy);
+        let mut request =
+            |gameboy: &mut Gameboy, data: &str| request(&mut server, gameboy, &mut stream, data);
+
+        assert!(request(&mut gameboy, "qSupported:multiprocess+").starts_with("PacketSize=1000;"));
+        assert_eq!(request(&mut gameboy, "?"), "T05");
+        assert_eq!(request(&mut gameboy, "g"), "0180001300d8014dfeff0001");
+        assert_eq!(request(&mut gameboy, "P0=42"), "OK");
+        assert_eq!(gameboy.cpu.a, 0x42);
+        assert_eq!(request(&mut gameboy, "p9"), "0001");
+        assert_eq!(request(&mut gameboy, "pa"), "E01");
+
+        assert_eq!(request(&mut gameboy, "m150,3"), "3c18fd");
+        assert_eq!(request(&mut gameboy, "Mc000,2:abcd"), "OK");
+        assert_eq!(gameboy.bus.read_byte(0xC000), 0xAB);
+        assert_eq!(gameboy.bus.read_byte(0xC001), 0xCD);
+        assert_eq!(request(&mut gameboy, "Mc000,1:xyz"), "E01");
+
+        let xml = request(&mut gameboy, "qXfer:features:read:target.xml:0,1000");
+        assert!(xml.starts_with("l<?xml"));
+        assert_eq!(request(&mut gameboy, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn test_bad_checksum_is_nacked() {
+        let mut gameboy = gameboy();
+        let mut server = GdbServer::bind(0).unwrap();
+        let mut stream = connect(&mut server, &mut gameboy);
+        stream.write_all(b"$g#00").unwrap();
+        server.run_frame(&mut gameboy);
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'-');
+
+        // Without acks only the reply comes back
+        assert_eq!(
+            request(&mut server, &mut gameboy, &mut stream, "QStartNoAckMode"),
+            "OK"
+        );
+        send(&mut stream, "?");
+        server.run_frame(&mut gameboy);
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'$');
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let mut gameboy = gameboy();
+        let mut server = GdbServer::bind(0).unwrap();
+        let mut stream = connect(&mut server, &mut gameboy);
+
+        assert_eq!(
+            request(&mut server, &mut gameboy, &mut stream, "Z0,151,1"),
+            "OK"
+        );
+        assert_eq!(gameboy.debugger.breakpoints.len(), 1);
+        // Continue has no reply until the target stops
+        send(&mut stream, "c");
+        assert!(server.run_frame(&mut gameboy));
+        assert_eq!(receive(&mut stream), "T05");
+        assert_eq!(gameboy.cpu.pc, 0x151);
+
+        assert_eq!(request(&mut server, &mut gameboy, &mut stream, "s"), "T05");
+        assert_eq!(gameboy.cpu.pc, 0x150);
+        assert_eq!(
+            request(&mut server, &mut gameboy, &mut stream, "z0,151,1"),
+            "OK"
+        );
+        assert!(gameboy.debugger.breakpoints.is_empty());
+        assert_eq!(
+            request(&mut server, &mut gameboy, &mut stream, "z0,151,1"),
+            "E01"
+        );
+
+        // Banked addresses break in that ROM bank only
+        request(&mut server, &mut gameboy, &mut stream, "Z0,14000,1");
+        assert_eq!(gameboy.debugger.breakpoints[0].bank, Some(1));
+        assert_eq!(gameboy.debugger.breakpoints[0].address, 0x4000);
+    }
+
+    #[test]
+    fn test_interrupt_and_detach() {
+        let mut gameboy = gameboy();
+        let mut server = GdbServer::bind(0).unwrap();
+        let mut stream = connect(&mut server, &mut gameboy);
+        request(&mut server, &mut gameboy, &mut stream, "Z2,c000,2");
+        assert_eq!(gameboy.debugger.watchpoints[0].end, 0xC001);
+
+        send(&mut stream, "c");
+        assert!(server.run_frame(&mut gameboy));
+        assert!(server.running);
+        stream.write_all(&[0x03]).unwrap();
+        server.run_frame(&mut gameboy);
+        assert_eq!(receive(&mut stream), "T02");
+        assert!(!server.running);
+
+        // Detaching removes what the client set and lets the game run
+        assert_eq!(request(&mut server, &mut gameboy, &mut stream, "D"), "OK");
+        assert!(server.client.is_none());
+        assert!(gameboy.debugger.watchpoints.is_empty());
+        assert!(server.run_frame(&mut gameboy));
+    }
+}
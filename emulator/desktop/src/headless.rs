@@ -0,0 +1,79 @@
+use gameboy_core::{apu::recorder::AudioRecorder, gameboy::Gameboy, gbs_player::GbsPlayer};
+use std::{io, path::PathBuf, time::Instant};
+
+use crate::{gdb::GdbServer, trace::TraceFile};
+
+pub struct HeadlessOptions {
+    pub frames: usize,
+    // WAV of the mix, plus one file per channel with stems
+    pub record_path: Option<PathBuf>,
+    pub record_stems: bool,
+    // VGM of the sound register writes, looping back to the given frame
+    pub vgm_path: Option<PathBuf>,
+    pub vgm_loop_frame: Option<usize>,
+    // Directory for the Game Boy Camera album as PNGs
+    pub photos_path: Option<PathBuf>,
+}
+
+// Runs without window or audio device as fast as possible, e.g. to render a soundtrack.
+// GBS songs with a length stop by themselves, including their fade out.
+pub fn run(
+    gameboy: &mut Gameboy,
+    mut gbs_player: Option<&mut GbsPlayer>,
+    mut trace: Option<&mut TraceFile>,
+    mut gdb: Option<&mut GdbServer>,
+    options: &HeadlessOptions,
+) -> io::Result<()> {
+    let mut recorder = match &options.record_path {
+        Some(path) => Some(AudioRecorder::start(
+            &mut gameboy.apu,
+            path,
+            options.record_stems,
+        )?),
+        None => None,
+    };
+    if options.vgm_path.is_some() {
+        gameboy.start_apu_log();
+    }
+
+    let frames = gbs_player
+        .as_ref()
+        .and_then(|player| player.total_frames())
+        .unwrap_or(options.frames);
+
+    let start = Instant::now();
+    for frame in 0..frames {
+        if options.vgm_loop_frame == Some(frame) {
+            gameboy.mark_apu_loop();
+        }
+        match (gdb.as_deref_mut(), gbs_player.as_deref_mut()) {
+            // Frames only count once the client lets the game run
+            (Some(gdb), _) => while !gdb.run_frame(gameboy) {},
+            (None, Some(player)) => player.run_frame(gameboy),
+            (None, None) => gameboy.run_frame(),
+        }
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.write(gameboy)?;
+        }
+        match &mut recorder {
+            Some(recorder) => recorder.record(&mut gameboy.apu)?,
+            // Nobody is listening
+            None => {
+                gameboy.apu.get_samples();
+            }
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.stop(&mut gameboy.apu)?;
+    }
+    if let (Some(path), Some(log)) = (&options.vgm_path, gameboy.stop_apu_log()) {
+        std::fs::write(path, log.to_vgm())?;
+    }
+    if let Some(path) = &options.photos_path {
+        let count = crate::camera::export_photos(gameboy, path)?;
+        println!("{} photos written to {}", count, path.display());
+    }
+    println!("Ran {} frames in {:.2?}", frames, start.elapsed());
+    Ok(())
+}
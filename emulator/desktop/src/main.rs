@@ -1,37 +1,198 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, SampleRate, StreamConfig};
-use gameboy_core::{self};
-use minifb::{Key, Window, WindowOptions};
-use std::sync::{Arc, Mutex};
+use gameboy_core::apu::{
+    recorder::AudioRecorder,
+    ring_buffer::{audio_ring_buffer, AudioProducer},
+};
+use gameboy_core::{
+    self,
+    gbs_player::GbsPlayer,
+    model::HardwareModel,
+    ppu::{
+        color::{ColorCorrection, FrameBlending},
+        compatibility::PaletteCombo,
+    },
+    sgb::FrameSize,
+};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
 use std::{
     env::Args,
-    time::{Duration, Instant},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+mod camera;
 mod debug_window;
+mod gdb;
+mod headless;
+mod rumble;
+mod trace;
 fn main() {
     // Parse command line arguments
     let debug_enabled = std::env::args().any(|arg| arg == "--debug" || arg == "-d");
     let turbo_mode = std::env::args().any(|arg| arg == "--turbo" || arg == "-t");
     let audio_disabled = std::env::args().any(|arg| arg == "--audio" || arg == "-a");
-    let mut window = set_up_window(turbo_mode);
-    let mut debug_window = if debug_enabled {
-        Some(debug_window::DebugWindow::new())
-    } else {
-        None
-    };
+    // Sleep based frame pacing instead of audio
+    let video_sync = std::env::args().any(|arg| arg == "--video-sync");
+    let sample_rate = arg_value("--sample-rate")
+        .and_then(|value| value.parse().ok())
+        .and_then(gameboy_core::apu::SampleRate::from_hz)
+        .unwrap_or(gameboy_core::apu::SampleRate::Hz48000);
+    // No window or audio device, runs --frames frames as fast as possible
+    let headless = std::env::args().any(|arg| arg == "--headless");
+    let frames = arg_value("--frames")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60 * 60);
+    // Record audio to a WAV file from the start, "--stems" adds one file per channel
+    let record_path = arg_value("--record").map(PathBuf::from);
+    let record_stems = std::env::args().any(|arg| arg == "--stems");
+    /*
+    "--model dmg0|dmg|mgb|sgb|sgb2|cgb|agb", from the cartridge header by default.
+    "--colorize" runs DMG games on a CGB for its colors when no model is given,
+    "--no-border" shows only the game screen on a SGB,
+    "--palette up-a" picks a boot ROM button combo for a DMG game on a CGB,
+    "--color-correction none|cgb|gba|modern" for CGB colors,
+    "--frame-blending none|mix|ghosting" for games that flicker sprites
+    */
+    let model = arg_value("--model").map(|name| {
+        HardwareModel::from_name(&name).unwrap_or_else(|| panic!("Unknown model {}", name))
+    });
 
     // Initialize GameBoy
     let palette: [u32; 4] = [0x9bbc0f, 0x8bac0f, 0x306230, 0x0f380f];
-    let mut gameboy = gameboy_core::gameboy::Gameboy::new(palette);
+    let mut gameboy = gameboy_core::gameboy::Gameboy::with_sample_rate(palette, sample_rate);
 
     if audio_disabled {
         gameboy.apu.toggle_audio();
     }
+    gameboy.set_model(model);
+    gameboy.set_colorization(std::env::args().any(|arg| arg == "--colorize"));
+    if let Some(name) = arg_value("--color-correction") {
+        match ColorCorrection::from_name(&name) {
+            Some(correction) => gameboy.ppu.set_color_correction(correction),
+            None => println!("Unknown color correction {}", name),
+        }
+    }
+    if let Some(name) = arg_value("--frame-blending") {
+        match FrameBlending::from_name(&name) {
+            Some(blending) => gameboy.ppu.set_frame_blending(blending),
+            None => println!("Unknown frame blending {}", name),
+        }
+    }
 
-    gameboy.load_rom(include_bytes!(
-        "../../../games/dr-mario/rom.gb" /*   "../../../games/tennis--1/rom.gb" */
-    ));
+    let rom_path = arg_value("--rom");
+    // Battery backed RAM lives next to the ROM
+    let battery_path = format!("{}.sav", rom_path.as_deref().unwrap_or("rom.gb"));
+    let cheats_path = format!("{}.cht", rom_path.as_deref().unwrap_or("rom.gb"));
+    let rom = match &rom_path {
+        Some(path) => std::fs::read(path).expect("Failed to read ROM"),
+        None => include_bytes!(
+            "../../../games/dr-mario/rom.gb" /*   "../../../games/tennis--1/rom.gb" */
+        )
+        .to_vec(),
+    };
+
+    // GBS soundtrack files are played instead of being run as a ROM
+    let mut gbs_player = if rom.starts_with(b"GBS") {
+        let mut player = GbsPlayer::load(&mut gameboy, &rom).expect("Failed to load GBS file");
+        // 1 based like the GBS header
+        if let Some(track) = arg_value("--track").and_then(|value| value.parse::<u8>().ok()) {
+            player.play_song(&mut gameboy, track.saturating_sub(1));
+        }
+        // Seconds, used to render songs to WAV
+        let length = arg_value("--length").and_then(|value| value.parse().ok());
+        let fade = arg_value("--fade")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0.0);
+        player.set_length(length, fade);
+        println!(
+            "{} - {} ({})",
+            player.header.title, player.header.author, player.header.copyright
+        );
+        print_gbs_song(&player);
+        Some(player)
+    } else {
+        gameboy.load_rom(&rom);
+        if let Some(name) = arg_value("--palette") {
+            match PaletteCombo::from_name(&name) {
+                Some(combo) => gameboy.set_compatibility_palette(&combo.palette()),
+                None => println!("Unknown palette {}", name),
+            }
+        }
+        if let Ok(battery) = std::fs::read(&battery_path) {
+            gameboy.load_battery(&battery);
+        }
+        // RGBDS labels for the debugger, "--symbols game.sym" or game.sym/game.map next to the ROM
+        load_symbols(&mut gameboy, arg_value("--symbols"), rom_path.as_deref());
+        // Cheats saved next to the ROM, "--cheat 01FF41C0,00A-17B-C49" adds a group
+        load_cheats(&mut gameboy, &cheats_path, arg_value("--cheat"));
+        // What the Game Boy Camera sees
+        if let Some(path) = arg_value("--camera-image") {
+            camera::set_image_from_file(&mut gameboy, Path::new(&path));
+        }
+        None
+    };
+
+    // Instruction trace to a file, see trace::TraceFile for the options
+    let mut trace = arg_value("--trace").and_then(|path| {
+        trace::TraceFile::start(
+            &mut gameboy,
+            Path::new(&path),
+            arg_value("--trace-format").as_deref(),
+            arg_value("--trace-start").as_deref(),
+            arg_value("--trace-stop").as_deref(),
+            arg_value("--trace-limit").and_then(|value| value.parse().ok()),
+        )
+        .map_err(|e| println!("Failed to start trace: {}", e))
+        .ok()
+    });
+
+    // GDB remote protocol on localhost, "--gdb 2345", the game waits for the client
+    let mut gdb = if std::env::args().any(|arg| arg == "--gdb") {
+        let port = arg_value("--gdb")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(2345);
+        gdb::GdbServer::bind(port)
+            .map_err(|e| println!("Failed to start GDB server: {}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    if headless {
+        let options = headless::HeadlessOptions {
+            frames,
+            record_path,
+            record_stems,
+            vgm_path: arg_value("--vgm").map(PathBuf::from),
+            vgm_loop_frame: arg_value("--vgm-loop").and_then(|value| value.parse().ok()),
+            photos_path: arg_value("--export-photos").map(PathBuf::from),
+        };
+        if let Err(e) = headless::run(
+            &mut gameboy,
+            gbs_player.as_mut(),
+            trace.as_mut(),
+            gdb.as_mut(),
+            &options,
+        ) {
+            println!("Headless run failed: {}", e);
+        }
+        stop_trace(&mut gameboy, trace);
+        save_battery(&gameboy, &battery_path);
+        save_cheats(&gameboy, &cheats_path);
+        return;
+    }
+
+    let (width, height) = gameboy.frame_dimensions(frame_size());
+    let mut window = set_up_window(turbo_mode, width, height);
+    let mut debug = DebugTools {
+        window: if debug_enabled {
+            Some(debug_window::DebugWindow::new())
+        } else {
+            None
+        },
+        gdb,
+    };
 
     /*  if let Ok(save_state) = std::fs::read("./rom.gb.state") {
            if let Err(e) = gameboy.load_state(save_state) {
@@ -40,25 +201,62 @@ fn main() {
        }
     */
     // Setup audio
-    let audio_output = match AudioOutput::new() {
+    let mut audio_output = match AudioOutput::new(sample_rate.hz()) {
         Ok(audio) => Some(audio),
         Err(e) => {
             println!("Audio disabled - couldn't initialize: {}", e);
             None
         }
     };
+    if audio_output.is_some() {
+        gameboy.apu.set_dynamic_rate_control(true);
+    }
+
+    // Audio drives the timing when available, the window must not throttle on its own
+    let pacing = if turbo_mode {
+        FramePacing::Turbo
+    } else if !video_sync && debug.gdb.is_none() && audio_output.is_some() && gameboy.apu.enabled {
+        window.limit_update_rate(None);
+        FramePacing::Audio
+    } else {
+        FramePacing::Video
+    };
+
+    let mut recording = Recording {
+        active: None,
+        stems: record_stems,
+        trace,
+    };
+    if let Some(path) = record_path {
+        recording.start(&mut gameboy, &path);
+    }
 
     run(
         &mut window,
         &mut gameboy,
-        &mut debug_window,
-        audio_output.as_ref(),
-        turbo_mode,
+        &mut debug,
+        audio_output.as_mut(),
+        pacing,
+        recording,
+        gbs_player.as_mut(),
     );
+    save_battery(&gameboy, &battery_path);
+    save_cheats(&gameboy, &cheats_path);
+}
+// Value following a flag, e.g. "--sample-rate 44100"
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg == name)?;
+    args.next()
 }
-fn set_up_window(turbo_mode: bool) -> Window {
-    let width = 160;
-    let height = 144;
+fn frame_size() -> FrameSize {
+    if std::env::args().any(|arg| arg == "--no-border") {
+        FrameSize::Screen
+    } else {
+        FrameSize::Sgb
+    }
+}
+fn set_up_window(turbo_mode: bool, width: usize, height: usize) -> Window {
     let window_options = WindowOptions {
         scale: minifb::Scale::X2,
         borderless: true,
@@ -74,13 +272,28 @@ fn set_up_window(turbo_mode: bool) -> Window {
     }
     window
 }
+struct DebugTools {
+    window: Option<debug_window::DebugWindow>,
+    gdb: Option<gdb::GdbServer>,
+}
+#[derive(Clone, Copy, PartialEq)]
+enum FramePacing {
+    // Sleep to 60 FPS
+    Video,
+    // Emulate as fast as the audio device consumes samples
+    Audio,
+    Turbo,
+}
 fn run(
     window: &mut Window,
     gameboy: &mut gameboy_core::gameboy::Gameboy,
-    debug_window: &mut Option<debug_window::DebugWindow>,
-    audio_output: Option<&AudioOutput>,
-    turbo_mode: bool,
+    debug: &mut DebugTools,
+    mut audio_output: Option<&mut AudioOutput>,
+    pacing: FramePacing,
+    mut recording: Recording,
+    mut gbs_player: Option<&mut GbsPlayer>,
 ) {
+    let turbo_mode = pacing == FramePacing::Turbo;
     let target_frame_time = if turbo_mode {
         Duration::from_micros(0)
     } else {
@@ -90,28 +303,37 @@ fn run(
     let mut last_fps_check = Instant::now();
     let mut frames = 0;
     let mut current_fps = 0;
-    let mut buffer = vec![0u32; 160 * 144];
+    let frame_size = frame_size();
+    let (width, height) = gameboy.frame_dimensions(frame_size);
+    let mut rumble = if gameboy.has_rumble() {
+        rumble::Rumble::new()
+            .map_err(|e| println!("Gamepad rumble unavailable: {}", e))
+            .ok()
+    } else {
+        None
+    };
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
         let frame_start_time = Instant::now();
 
-        // In turbo mode, run multiple frames per iteration
-        if turbo_mode {
+        // A GDB client decides when the game runs
+        if let Some(gdb) = &mut debug.gdb {
+            gdb.run_frame(gameboy);
+        } else if turbo_mode {
+            // In turbo mode, run multiple frames per iteration
             for _ in 0..4 {
                 // Run 4 frames at once for higher speed
                 gameboy.run_frame();
             }
+        } else if let (FramePacing::Audio, Some(audio)) = (pacing, audio_output.as_deref_mut()) {
+            run_audio_synced_frame(gameboy, audio);
         } else {
             gameboy.run_frame();
         }
 
-        // Get the frame buffer from PPU and convert colors
-        let gb_buffer = gameboy.ppu.get_frame_buffer();
-        buffer.copy_from_slice(gb_buffer);
-
         // Update the window with the new frame
         window
-            .update_with_buffer(&buffer, 160, 144)
+            .update_with_buffer(gameboy.frame_buffer(frame_size), width, height)
             .expect("Failed to update window");
 
         // FPS calculation and window title update
@@ -130,8 +352,8 @@ fn run(
             window.set_title(&title);
         }
 
-        // Frame timing (only if not in turbo mode)
-        if !turbo_mode {
+        // Frame timing (only if not in turbo mode or synced to audio)
+        if pacing == FramePacing::Video {
             let frame_time = frame_start_time.elapsed();
             if frame_time < target_frame_time {
                 std::thread::sleep(target_frame_time - frame_time);
@@ -141,15 +363,256 @@ fn run(
         // Update key input
         handle_input(window, gameboy);
 
+        if let Some(tone) = gameboy.take_cartridge_tone() {
+            println!("Cartridge tone {}", tone);
+        }
+        if gameboy.has_accelerometer() {
+            update_tilt(window, gameboy);
+        }
+        if let Some(rumble) = &mut rumble {
+            rumble.update(gameboy.rumble_strength());
+        }
+
+        // GBS song selection
+        if let Some(player) = gbs_player.as_deref_mut() {
+            if window.is_key_pressed(Key::Right, KeyRepeat::No) {
+                player.next_song(gameboy);
+                print_gbs_song(player);
+            }
+            if window.is_key_pressed(Key::Left, KeyRepeat::No) {
+                player.previous_song(gameboy);
+                print_gbs_song(player);
+            }
+        }
+
+        // Toggle VGM logging, L marks the loop point
+        if window.is_key_pressed(Key::V, KeyRepeat::No) {
+            toggle_vgm_log(gameboy);
+        }
+        if window.is_key_pressed(Key::L, KeyRepeat::No) && gameboy.bus.apu_log.is_some() {
+            gameboy.mark_apu_loop();
+            println!("VGM loop point marked");
+        }
+
+        // Switch all cheats off, or back on
+        if window.is_key_pressed(Key::C, KeyRepeat::No) && !gameboy.bus.cheats.is_empty() {
+            toggle_cheats(gameboy);
+        }
+
+        // Export the camera album
+        if window.is_key_pressed(Key::P, KeyRepeat::No) && gameboy.has_camera() {
+            let directory = PathBuf::from(format!("photos-{}", timestamp()));
+            match camera::export_photos(gameboy, &directory) {
+                Ok(count) => println!("{} photos written to {}", count, directory.display()),
+                Err(e) => println!("Failed to export photos: {}", e),
+            }
+        }
+
+        // Toggle audio recording
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            recording.toggle(gameboy);
+        }
+        recording.record(gameboy);
+
         // Update debug window
-        if let Some(debug_window) = debug_window {
-            debug_window.update(&gameboy.cpu, &gameboy.bus, &gameboy.ppu, current_fps);
+        if let Some(debug_window) = &mut debug.window {
+            debug_window.update(
+                &gameboy.cpu,
+                &gameboy.bus,
+                &gameboy.ppu,
+                &gameboy.symbols,
+                current_fps,
+            );
             debug_window.render();
         }
 
-        let samples = gameboy.apu.get_samples();
-        if let Some(audio) = audio_output {
-            audio.add_samples(&samples);
+        match audio_output.as_deref_mut() {
+            Some(audio) => audio.add_samples(&mut gameboy.apu),
+            None => {
+                gameboy.apu.get_samples();
+            }
+        }
+    }
+
+    // Make sure the WAV headers get written
+    recording.stop(gameboy);
+    stop_trace(gameboy, recording.trace.take());
+}
+
+fn save_battery(gameboy: &gameboy_core::gameboy::Gameboy, path: &str) {
+    if let Some(battery) = gameboy.save_battery() {
+        if let Err(e) = std::fs::write(path, battery) {
+            println!("Failed to write battery save: {}", e);
+        }
+    }
+}
+fn load_cheats(gameboy: &mut gameboy_core::gameboy::Gameboy, path: &str, codes: Option<String>) {
+    if let Ok(text) = std::fs::read_to_string(path) {
+        match gameboy.bus.cheats.load_text(&text) {
+            Ok(count) => println!("{} cheats loaded from {}", count, path),
+            Err(e) => println!("Failed to load cheats from {}: {}", path, e),
+        }
+    }
+    // Replaces the codes saved from the last run's --cheat
+    if let Some(codes) = codes {
+        if let Err(e) = gameboy.bus.cheats.add_group("Command line", &codes) {
+            println!("Invalid cheat: {}", e);
+        }
+    }
+}
+fn save_cheats(gameboy: &gameboy_core::gameboy::Gameboy, path: &str) {
+    let cheats = &gameboy.bus.cheats;
+    if cheats.is_empty() && !Path::new(path).exists() {
+        return;
+    }
+    if let Err(e) = std::fs::write(path, cheats.to_text()) {
+        println!("Failed to write cheats: {}", e);
+    }
+}
+fn toggle_cheats(gameboy: &mut gameboy_core::gameboy::Gameboy) {
+    let cheats = &mut gameboy.bus.cheats;
+    let enabled = !cheats.groups().iter().any(|group| group.enabled);
+    for index in 0..cheats.groups().len() {
+        cheats.set_enabled(index, enabled);
+    }
+    println!("Cheats {}", if enabled { "on" } else { "off" });
+}
+fn load_symbols(
+    gameboy: &mut gameboy_core::gameboy::Gameboy,
+    path: Option<String>,
+    rom_path: Option<&str>,
+) {
+    let rom_path = rom_path.map(Path::new);
+    let candidates = match (&path, rom_path) {
+        (Some(path), _) => vec![PathBuf::from(path)],
+        (None, Some(rom)) => vec![rom.with_extension("sym"), rom.with_extension("map")],
+        (None, None) => Vec::new(),
+    };
+    for candidate in candidates {
+        let Ok(text) = std::fs::read_to_string(&candidate) else {
+            if path.is_some() {
+                println!("Failed to read symbols from {}", candidate.display());
+            }
+            continue;
+        };
+        let count = if candidate
+            .extension()
+            .is_some_and(|extension| extension == "map")
+        {
+            gameboy.symbols.load_map(&text)
+        } else {
+            gameboy.symbols.load_sym(&text)
+        };
+        println!("{} symbols loaded from {}", count, candidate.display());
+        return;
+    }
+}
+fn stop_trace(gameboy: &mut gameboy_core::gameboy::Gameboy, trace: Option<trace::TraceFile>) {
+    if let Some(trace) = trace {
+        if let Err(e) = trace.stop(gameboy) {
+            println!("Failed to write trace: {}", e);
+        }
+    }
+}
+fn toggle_vgm_log(gameboy: &mut gameboy_core::gameboy::Gameboy) {
+    match gameboy.stop_apu_log() {
+        Some(log) => {
+            let path = format!("recording-{}.vgm", timestamp());
+            match std::fs::write(&path, log.to_vgm()) {
+                Ok(()) => println!("VGM written to {}", path),
+                Err(e) => println!("Failed to write VGM: {}", e),
+            }
+        }
+        None => {
+            gameboy.start_apu_log();
+            println!("VGM logging started");
+        }
+    }
+}
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+fn print_gbs_song(player: &GbsPlayer) {
+    println!(
+        "Song {}/{}",
+        player.current_song() as u16 + 1,
+        player.header.song_count
+    );
+}
+
+struct Recording {
+    active: Option<AudioRecorder>,
+    stems: bool,
+    // Instruction trace, written out every frame like the audio
+    trace: Option<trace::TraceFile>,
+}
+impl Recording {
+    fn start(&mut self, gameboy: &mut gameboy_core::gameboy::Gameboy, path: &Path) {
+        match AudioRecorder::start(&mut gameboy.apu, path, self.stems) {
+            Ok(recorder) => {
+                println!("Recording audio to {}", path.display());
+                self.active = Some(recorder);
+            }
+            Err(e) => println!("Failed to start recording: {}", e),
+        }
+    }
+    fn stop(&mut self, gameboy: &mut gameboy_core::gameboy::Gameboy) {
+        if let Some(recorder) = self.active.take() {
+            match recorder.stop(&mut gameboy.apu) {
+                Ok(()) => println!("Recording stopped"),
+                Err(e) => println!("Failed to finish recording: {}", e),
+            }
+        }
+    }
+    fn toggle(&mut self, gameboy: &mut gameboy_core::gameboy::Gameboy) {
+        if self.active.is_some() {
+            self.stop(gameboy);
+        } else {
+            self.start(
+                gameboy,
+                Path::new(&format!("recording-{}.wav", timestamp())),
+            );
+        }
+    }
+    fn record(&mut self, gameboy: &mut gameboy_core::gameboy::Gameboy) {
+        if let Some(recorder) = &mut self.active {
+            if let Err(e) = recorder.record(&mut gameboy.apu) {
+                println!("Recording failed: {}", e);
+                self.stop(gameboy);
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            if let Err(e) = trace.write(gameboy) {
+                println!("Failed to write trace: {}", e);
+                self.trace = None;
+            }
+        }
+    }
+}
+
+// Runs until the next frame is complete, only emulating while the audio buffer
+// is below its target fill, so the audio device clock paces emulation.
+fn run_audio_synced_frame(gameboy: &mut gameboy_core::gameboy::Gameboy, audio: &mut AudioOutput) {
+    let mut waiting_since = Instant::now();
+    loop {
+        let target = audio.target_fill();
+        let buffered = audio.buffered();
+        // Don't hang if the audio device stopped consuming
+        if buffered >= target && waiting_since.elapsed() < Duration::from_millis(100) {
+            std::thread::sleep(Duration::from_micros(500));
+            continue;
+        }
+        waiting_since = Instant::now();
+
+        let needed = target.saturating_sub(buffered).max(64) / 2;
+        let frame_completed = gameboy.run_for_samples(needed);
+        audio.add_samples(&mut gameboy.apu);
+        if frame_completed {
+            return;
         }
     }
 }
@@ -181,25 +644,51 @@ fn handle_input(window: &mut Window, gameboy: &mut gameboy_core::gameboy::Gamebo
     }
 }
 
+/*
+Tilt for MBC7 cartridges
+Numpad 4/6/8/2 tilt left/right/up/down, or hold the left mouse button
+and move away from the center of the screen
+*/
+fn update_tilt(window: &Window, gameboy: &mut gameboy_core::gameboy::Gameboy) {
+    let axis = |negative: Key, positive: Key| {
+        window.is_key_down(positive) as i8 as f32 - window.is_key_down(negative) as i8 as f32
+    };
+    let mut tilt = (
+        axis(Key::NumPad4, Key::NumPad6),
+        axis(Key::NumPad8, Key::NumPad2),
+    );
+    if window.get_mouse_down(MouseButton::Left) {
+        if let Some((x, y)) = window.get_unscaled_mouse_pos(MouseMode::Clamp) {
+            let (width, height) = window.get_size();
+            let (width, height) = (width as f32, height as f32);
+            tilt = (
+                (x - width / 2.0) / (width / 2.0),
+                (y - height / 2.0) / (height / 2.0),
+            );
+        }
+    }
+    gameboy.set_tilt(tilt.0, tilt.1);
+}
+
 pub struct AudioOutput {
     stream: cpal::Stream,
-    samples: Arc<Mutex<Vec<f32>>>,
+    producer: AudioProducer,
 }
 
 impl AudioOutput {
-    pub fn new() -> Result<Self, anyhow::Error> {
+    pub fn new(sample_rate: u32) -> Result<Self, anyhow::Error> {
         let host = cpal::default_host();
         let device = host
             .default_output_device()
             .expect("No output device available");
 
         // Configure stream parameters
-        let sample_rate = 48_000;
-        let buffer_size = 1600;
+        // One frame worth of stereo samples
+        let buffer_size = sample_rate / 60 * 2;
+        // 100ms of stereo samples between the emulator and the audio callback
+        let ring_capacity = (sample_rate / 10 * 2) as usize;
 
-        // Create thread-safe sample buffer
-        let samples = Arc::new(Mutex::new(Vec::new()));
-        let samples_clone = Arc::clone(&samples);
+        let (producer, mut consumer) = audio_ring_buffer(ring_capacity);
 
         // Configure stream
         let stream_config = StreamConfig {
@@ -211,19 +700,11 @@ impl AudioOutput {
         let stream = device.build_output_stream(
             &stream_config,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut samples_lock = samples_clone.lock().unwrap();
-
                 // Fill buffer with available samples or silence
-                let fill_len = data.len().min(samples_lock.len());
-                data[..fill_len].copy_from_slice(&samples_lock[..fill_len]);
-
-                // Fill remaining with silence if needed
+                let fill_len = consumer.pop_slice(data);
                 if fill_len < data.len() {
                     data[fill_len..].fill(0.0);
                 }
-
-                // Remove used samples
-                samples_lock.drain(0..fill_len);
             },
             |err| eprintln!("Audio stream error: {:?}", err),
             None,
@@ -232,12 +713,20 @@ impl AudioOutput {
         // Start the stream
         stream.play()?;
 
-        Ok(Self { stream, samples })
+        Ok(Self { stream, producer })
+    }
+
+    // Buffer fill level the emulator tries to keep
+    pub fn target_fill(&self) -> usize {
+        self.producer.capacity() / 2
+    }
+    pub fn buffered(&self) -> usize {
+        self.producer.len()
     }
 
     // Method to add samples from APU
-    pub fn add_samples(&self, new_samples: &[f32]) {
-        let mut samples_lock = self.samples.lock().unwrap();
-        samples_lock.extend_from_slice(new_samples);
+    pub fn add_samples(&mut self, apu: &mut gameboy_core::apu::APU) {
+        apu.update_rate_control(self.producer.len(), self.producer.capacity());
+        apu.push_samples(&mut self.producer);
     }
 }
@@ -0,0 +1,81 @@
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks},
+    GamepadId, Gilrs,
+};
+
+// Forwards the MBC5 rumble motor to every connected gamepad with force feedback.
+// A single looping effect is kept playing and its gain follows the motor strength.
+pub struct Rumble {
+    gilrs: Gilrs,
+    gamepads: Vec<GamepadId>,
+    effect: Option<Effect>,
+    strength: f32,
+}
+
+impl Rumble {
+    pub fn new() -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|e| e.to_string())?;
+        Ok(Self {
+            gilrs,
+            gamepads: Vec::new(),
+            effect: None,
+            strength: 0.0,
+        })
+    }
+
+    // Call once per frame with Gameboy::rumble_strength
+    pub fn update(&mut self, strength: f32) {
+        // Keeps the gamepad list up to date
+        while self.gilrs.next_event().is_some() {}
+
+        let gamepads: Vec<GamepadId> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+        if gamepads != self.gamepads {
+            self.gamepads = gamepads;
+            self.effect = self.create_effect();
+            self.strength = -1.0;
+        }
+
+        if (strength - self.strength).abs() < 0.05 {
+            return;
+        }
+        self.strength = strength;
+        if let Some(effect) = &self.effect {
+            if let Err(e) = effect.set_gain(strength) {
+                println!("Failed to update rumble: {}", e);
+            }
+        }
+    }
+
+    fn create_effect(&mut self) -> Option<Effect> {
+        if self.gamepads.is_empty() {
+            return None;
+        }
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: u16::MAX,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(50),
+                    ..Default::default()
+                },
+                envelope: Default::default(),
+            })
+            .gamepads(&self.gamepads)
+            .gain(0.0)
+            .finish(&mut self.gilrs)
+            .and_then(|effect| effect.play().map(|_| effect));
+        match effect {
+            Ok(effect) => Some(effect),
+            Err(e) => {
+                println!("Gamepad rumble unavailable: {}", e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use gameboy_core::{
+    gameboy::Gameboy,
+    trace::{TraceFormat, TraceLogger, TraceTrigger},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+// Default size limit in MB
+const DEFAULT_LIMIT_MB: usize = 256;
+
+// Writes the core's instruction trace to a file as it is produced
+pub struct TraceFile {
+    file: BufWriter<File>,
+    finished: bool,
+}
+
+impl TraceFile {
+    /*
+    "--trace-format doctor|logs|bgb", doctor by default,
+    "--trace-start"/"--trace-stop" with "frame:600", "pc:$0150", "pc:01:4000" or "pc:Main.loop",
+    "--trace-limit" in MB
+    */
+    pub fn start(
+        gameboy: &mut Gameboy,
+        path: &Path,
+        format: Option<&str>,
+        start: Option<&str>,
+        stop: Option<&str>,
+        limit_mb: Option<usize>,
+    ) -> Result<Self, String> {
+        let format = match format {
+            Some(name) => TraceFormat::from_name(name)
+                .ok_or_else(|| format!("Unknown trace format {}", name))?,
+            None => TraceFormat::Doctor,
+        };
+        let mut trace =
+            TraceLogger::new(format, limit_mb.unwrap_or(DEFAULT_LIMIT_MB) * 1024 * 1024);
+        let parse = |text: Option<&str>| {
+            text.map(|text| TraceTrigger::parse(text, &gameboy.symbols))
+                .transpose()
+        };
+        trace.start = parse(start)?;
+        trace.stop = parse(stop)?;
+
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        gameboy.start_trace(trace);
+        println!("Tracing to {}", path.display());
+        Ok(Self {
+            file: BufWriter::new(file),
+            finished: false,
+        })
+    }
+
+    // Once per frame, writes out what was traced since the last call
+    pub fn write(&mut self, gameboy: &mut Gameboy) -> io::Result<()> {
+        let Some(trace) = &mut gameboy.trace else {
+            return Ok(());
+        };
+        self.file.write_all(trace.take_output().as_bytes())?;
+        if trace.is_finished() && !self.finished {
+            self.finished = true;
+            self.file.flush()?;
+            println!("Trace finished after {} instructions", trace.lines());
+        }
+        Ok(())
+    }
+
+    pub fn stop(mut self, gameboy: &mut Gameboy) -> io::Result<()> {
+        self.write(gameboy)?;
+        gameboy.stop_trace();
+        self.file.flush()
+    }
+}
@@ -0,0 +1,102 @@
+/*
+Fixed block of interleaved stereo samples that the page reads in place through
+a view over wasm memory, so no Vec<f32> is allocated per frame.
+Positions only grow, the slot of a sample is its position modulo the capacity.
+*/
+pub struct AudioRing {
+    samples: Box<[f32]>,
+    read: usize,
+    write: usize,
+}
+
+impl AudioRing {
+    // Capacity in f32s, rounded up to whole stereo frames
+    pub fn new(capacity: usize) -> Self {
+        let capacity = (capacity.max(2) + 1) & !1;
+        Self {
+            samples: vec![0.0; capacity].into_boxed_slice(),
+            read: 0,
+            write: 0,
+        }
+    }
+
+    // Samples that don't fit are dropped so latency can't build up
+    pub fn push(&mut self, samples: impl Iterator<Item = f32>) -> usize {
+        let capacity = self.samples.len();
+        let mut written = 0;
+        for sample in samples {
+            if self.len() == capacity {
+                break;
+            }
+            self.samples[self.write % capacity] = sample;
+            self.write = self.write.wrapping_add(1);
+            written += 1;
+        }
+        written
+    }
+
+    // Marks samples as played, never more than are buffered
+    pub fn consume(&mut self, count: usize) {
+        self.read = self.read.wrapping_add(count.min(self.len()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.write.wrapping_sub(self.read)
+    }
+    pub fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+    // Slot of the oldest unread sample
+    pub fn read_index(&self) -> usize {
+        self.read % self.samples.len()
+    }
+    pub fn as_slice(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_is_whole_frames() {
+        assert_eq!(AudioRing::new(5).capacity(), 6);
+        assert_eq!(AudioRing::new(8).capacity(), 8);
+        assert_eq!(AudioRing::new(0).capacity(), 2);
+    }
+
+    #[test]
+    fn test_push_drops_when_full() {
+        let mut ring = AudioRing::new(4);
+        assert_eq!(ring.push([1.0, 2.0, 3.0].into_iter()), 3);
+        assert_eq!(ring.push([4.0, 5.0, 6.0].into_iter()), 1);
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.as_slice(), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_consume_is_capped() {
+        let mut ring = AudioRing::new(4);
+        ring.push([1.0, 2.0].into_iter());
+        ring.consume(10);
+        assert_eq!(ring.len(), 0);
+        assert_eq!(ring.read_index(), 2);
+        // Nothing buffered, nothing to consume
+        ring.consume(1);
+        assert_eq!(ring.read_index(), 2);
+    }
+
+    #[test]
+    fn test_wraps_around() {
+        let mut ring = AudioRing::new(4);
+        ring.push([1.0, 2.0, 3.0].into_iter());
+        ring.consume(3);
+        assert_eq!(ring.push([4.0, 5.0, 6.0].into_iter()), 3);
+        assert_eq!(ring.read_index(), 3);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.as_slice(), [5.0, 6.0, 3.0, 4.0]);
+        ring.consume(2);
+        assert_eq!(ring.read_index(), 1);
+    }
+}
@@ -1,10 +1,27 @@
+mod audio_ring;
+
 use std::collections::VecDeque;
 
+use audio_ring::AudioRing;
 use gameboy_core::{
     self as GameboyCore,
+    apu::SampleRate,
     bus::GameboyMode,
     cartridge::{self},
-    ppu::{fetcher::Fetcher, PPUMode},
+    cheats::Cheat,
+    debugger::{self, CallFrame, Condition, StopReason, WatchKind},
+    disasm::{self, Instruction},
+    gameboy::Gameboy,
+    model::HardwareModel,
+    ppu::{
+        color::{ColorCorrection, FrameBlending},
+        compatibility::PaletteCombo,
+        fetcher::Fetcher,
+        PPUMode,
+    },
+    ram_search::{Candidate, RamSearch, SearchFilter, ValueSize, FREEZE_GROUP},
+    sgb::FrameSize,
+    trace::{TraceFormat, TraceLogger, TraceTrigger},
 };
 use wasm_bindgen::convert::IntoWasmAbi;
 use wasm_bindgen::prelude::*;
@@ -13,19 +30,36 @@ use wasm_bindgen::prelude::*;
 pub struct GameboyWasm {
     gameboy: GameboyCore::gameboy::Gameboy,
     is_paused: bool,
+    frame_size: FrameSize,
+    rgba_output: bool,
+    rgba_frame: Vec<u8>,
+    audio_ring: AudioRing,
+    ram_search: Option<RamSearch>,
 }
 
+// Interleaved f32s, about 170ms of stereo audio at 48000 Hz
+const AUDIO_RING_CAPACITY: usize = 16384;
+
 #[wasm_bindgen]
 impl GameboyWasm {
     #[wasm_bindgen(constructor)]
-    pub fn new(palette: Vec<u32>) -> Self {
+    pub fn new(palette: Vec<u32>, sample_rate: Option<u32>) -> Self {
         let palette_array: [u32; 4] = match palette.len() {
             4 => [palette[0], palette[1], palette[2], palette[3]],
             _ => [0xA8D08D, 0x6A8E3C, 0x3A5D1D, 0x1F3C06], // Default green palette
         };
+        // 44100, 48000 or 96000 Hz, falls back to 48000 Hz
+        let sample_rate = sample_rate
+            .and_then(SampleRate::from_hz)
+            .unwrap_or(SampleRate::Hz48000);
         Self {
-            gameboy: GameboyCore::gameboy::Gameboy::new(palette_array),
+            gameboy: GameboyCore::gameboy::Gameboy::with_sample_rate(palette_array, sample_rate),
             is_paused: false,
+            frame_size: FrameSize::Sgb,
+            rgba_output: false,
+            rgba_frame: Vec::new(),
+            audio_ring: AudioRing::new(AUDIO_RING_CAPACITY),
+            ram_search: None,
         }
     }
 
@@ -81,14 +115,270 @@ impl GameboyWasm {
     }
 
     pub fn get_frame_buffer(&self) -> Vec<u32> {
-        self.gameboy.ppu.get_frame_buffer().to_vec()
+        self.gameboy.frame_buffer(self.frame_size).to_vec()
+    }
+    /*
+    Zero-copy frame access. The buffer stays in wasm memory and is read through
+    get_frame_buffer_ptr/get_frame_buffer_len or a view from get_frame_view.
+    Native byte order is the little endian 0xAARRGGBB words (B, G, R, A),
+    RGBA output converts in here so the bytes can go straight into ImageData.
+    Views and pointers are only valid until wasm memory grows, get them again every frame.
+    */
+    pub fn set_rgba_output(&mut self, enabled: bool) {
+        self.rgba_output = enabled;
+    }
+    pub fn get_frame_buffer_ptr(&mut self) -> *const u8 {
+        self.frame_bytes().as_ptr()
+    }
+    // In bytes, 4 per pixel
+    pub fn get_frame_buffer_len(&self) -> usize {
+        self.gameboy.frame_buffer(self.frame_size).len() * 4
+    }
+    pub fn get_frame_view(&mut self) -> js_sys::Uint8ClampedArray {
+        // Safety: the view is handed to JS right away, nothing allocates before it is used
+        unsafe { js_sys::Uint8ClampedArray::view(self.frame_bytes()) }
+    }
+    // Size of get_frame_buffer, 256x224 when showing the SGB border
+    pub fn get_frame_width(&self) -> usize {
+        self.gameboy.frame_dimensions(self.frame_size).0
+    }
+    pub fn get_frame_height(&self) -> usize {
+        self.gameboy.frame_dimensions(self.frame_size).1
+    }
+    // Game screen only, one u32 per pixel as in IndexedPixel::pack
+    pub fn set_indexed_output(&mut self, enabled: bool) {
+        self.gameboy.ppu.set_indexed_output(enabled);
+    }
+    pub fn get_indexed_buffer(&self) -> Vec<u32> {
+        self.gameboy
+            .ppu
+            .get_indexed_buffer()
+            .iter()
+            .map(|pixel| pixel.pack())
+            .collect()
     }
     pub fn handle_keys(&mut self, keys: u8) {
         self.gameboy.bus.joypad.update_keys(keys);
     }
+    // "dmg0", "dmg", "mgb", "sgb", "sgb2", "cgb" or "agb", call before init.
+    // None or an unknown name picks the model from the cartridge header
+    pub fn set_model(&mut self, name: Option<String>) -> bool {
+        let model = name.as_deref().and_then(HardwareModel::from_name);
+        self.gameboy.set_model(model);
+        name.is_none() || model.is_some()
+    }
+    pub fn get_model(&self) -> String {
+        self.gameboy.model().name().to_string()
+    }
+    pub fn is_sgb(&self) -> bool {
+        self.gameboy.is_sgb()
+    }
+    pub fn set_sgb_border(&mut self, enabled: bool) {
+        self.frame_size = if enabled {
+            FrameSize::Sgb
+        } else {
+            FrameSize::Screen
+        };
+    }
+    // GBC colorization of DMG games, call before init. A model from set_model wins
+    pub fn set_colorization(&mut self, enabled: bool) {
+        self.gameboy.set_colorization(enabled);
+    }
+    // DMG game running on a CGB or AGB
+    pub fn is_colorized(&self) -> bool {
+        self.gameboy.is_colorized()
+    }
+    // Button combo names of the CGB boot ROM: "up", "up-a", ... "right-b"
+    pub fn get_palette_combos(&self) -> Vec<String> {
+        PaletteCombo::ALL
+            .iter()
+            .map(|combo| combo.name().to_string())
+            .collect()
+    }
+    pub fn set_palette_combo(&mut self, name: &str) -> bool {
+        match PaletteCombo::from_name(name) {
+            Some(combo) => {
+                self.gameboy.set_compatibility_palette(&combo.palette());
+                true
+            }
+            None => false,
+        }
+    }
+    // "none", "cgb", "gba" or "modern", only CGB colors are corrected
+    pub fn set_color_correction(&mut self, name: &str) -> bool {
+        match ColorCorrection::from_name(name) {
+            Some(correction) => {
+                self.gameboy.ppu.set_color_correction(correction);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn get_color_correction(&self) -> String {
+        self.gameboy.ppu.color_correction().name().to_string()
+    }
+    // "none", "mix" or "ghosting", for games that flicker sprites every other frame
+    pub fn set_frame_blending(&mut self, name: &str) -> bool {
+        match FrameBlending::from_name(name) {
+            Some(blending) => {
+                self.gameboy.ppu.set_frame_blending(blending);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn get_frame_blending(&self) -> String {
+        self.gameboy.ppu.frame_blending().name().to_string()
+    }
+    // SGB multiplayer, players are 1 based
+    pub fn handle_player_keys(&mut self, player: usize, keys: u8) {
+        self.gameboy.set_player_keys(player, keys);
+    }
+    pub fn get_player_count(&self) -> u8 {
+        self.gameboy.player_count()
+    }
     pub fn get_audio_buffer(&mut self) -> Vec<f32> {
         self.gameboy.apu.get_samples()
     }
+    /*
+    Fixed audio ring, an alternative to get_audio_buffer that doesn't allocate.
+    update_audio_ring moves new samples in and returns how many are buffered,
+    they start at get_audio_read_index and wrap at get_audio_ring_capacity.
+    Call consume_audio once they are played.
+    */
+    pub fn update_audio_ring(&mut self) -> usize {
+        self.audio_ring.push(self.gameboy.apu.drain_samples());
+        self.audio_ring.len()
+    }
+    pub fn get_audio_ring_ptr(&self) -> *const f32 {
+        self.audio_ring.as_slice().as_ptr()
+    }
+    pub fn get_audio_ring_capacity(&self) -> usize {
+        self.audio_ring.capacity()
+    }
+    pub fn get_audio_read_index(&self) -> usize {
+        self.audio_ring.read_index()
+    }
+    pub fn get_audio_view(&self) -> js_sys::Float32Array {
+        // Safety: same as get_frame_view
+        unsafe { js_sys::Float32Array::view(self.audio_ring.as_slice()) }
+    }
+    pub fn consume_audio(&mut self, count: usize) {
+        self.audio_ring.consume(count);
+    }
+    // Audio as timing master, returns true when a frame was completed
+    pub fn run_for_samples(&mut self, count: usize) -> bool {
+        if self.is_paused {
+            return false;
+        }
+        self.gameboy.run_for_samples(count)
+    }
+    pub fn set_dynamic_rate_control(&mut self, enabled: bool) {
+        self.gameboy.apu.set_dynamic_rate_control(enabled);
+    }
+    // Buffered and capacity are in samples of the host audio queue
+    pub fn update_audio_rate_control(&mut self, buffered: usize, capacity: usize) {
+        self.gameboy.apu.update_rate_control(buffered, capacity);
+    }
+    // Titles of the games in a MBC1M compilation, empty otherwise
+    pub fn get_multicart_games(&self) -> Vec<String> {
+        self.gameboy
+            .bus
+            .mbc
+            .multicart_games()
+            .iter()
+            .map(|game| game.title.clone())
+            .collect()
+    }
+    pub fn has_rumble(&self) -> bool {
+        self.gameboy.has_rumble()
+    }
+    pub fn is_rumbling(&self) -> bool {
+        self.gameboy.is_rumbling()
+    }
+    /*
+    Milliseconds the motor was on since the last call, poll once per frame and pass
+    to navigator.vibrate (0 stops the vibration)
+    */
+    pub fn take_rumble_duration(&mut self, elapsed_ms: f64) -> u32 {
+        (self.gameboy.rumble_strength() as f64 * elapsed_ms).round() as u32
+    }
+    pub fn has_accelerometer(&self) -> bool {
+        self.gameboy.has_accelerometer()
+    }
+    /*
+    DeviceOrientationEvent angles in degrees, gamma is left/right and beta front/back.
+    Beta is relative to the angle the device is held at, e.g. 45 when held naturally
+    */
+    pub fn set_device_orientation(&mut self, beta: f64, gamma: f64, rest_beta: f64) {
+        let to_g = |degrees: f64| degrees.to_radians().sin().clamp(-1.0, 1.0) as f32;
+        self.gameboy.set_tilt(to_g(gamma), to_g(beta - rest_beta));
+    }
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.gameboy.set_tilt(x, y);
+    }
+    // IR link, the page relays the LED to the other side and reports light coming back
+    pub fn infrared_led(&self) -> bool {
+        self.gameboy.infrared_led()
+    }
+    pub fn set_infrared_receiving(&mut self, receiving: bool) {
+        self.gameboy.set_infrared_receiving(receiving);
+    }
+    // HuC3 speaker tone to play, poll once per frame
+    pub fn take_cartridge_tone(&mut self) -> Option<u8> {
+        self.gameboy.take_cartridge_tone()
+    }
+    pub fn has_camera(&self) -> bool {
+        self.gameboy.has_camera()
+    }
+    // RGBA pixels, e.g. ImageData of a video frame drawn to a canvas
+    pub fn set_camera_image(&mut self, rgba: &[u8], width: usize, height: usize) {
+        let gray: Vec<u8> = rgba
+            .chunks_exact(4)
+            .map(|pixel| {
+                let luma = 299 * pixel[0] as u32 + 587 * pixel[1] as u32 + 114 * pixel[2] as u32;
+                (luma / 1000) as u8
+            })
+            .collect();
+        self.gameboy.set_camera_image(&gray, width, height);
+    }
+    pub fn get_camera_photo_count(&self) -> usize {
+        self.gameboy.camera_photos_png().len()
+    }
+    // PNG file of a saved photo, empty if there is none
+    pub fn get_camera_photo_png(&self, index: usize) -> Vec<u8> {
+        self.gameboy
+            .camera_photos_png()
+            .into_iter()
+            .nth(index)
+            .unwrap_or_default()
+    }
+    pub fn has_battery(&self) -> bool {
+        self.gameboy.bus.has_battery
+    }
+    // Battery backed cartridge RAM to persist, empty when the cartridge has no battery
+    pub fn get_battery_save(&self) -> Vec<u8> {
+        self.gameboy.save_battery().unwrap_or_default()
+    }
+    pub fn load_battery_save(&mut self, data: Vec<u8>) {
+        self.gameboy.load_battery(&data);
+    }
+    pub fn start_apu_log(&mut self) {
+        self.gameboy.start_apu_log();
+    }
+    pub fn mark_apu_loop(&mut self) {
+        self.gameboy.mark_apu_loop();
+    }
+    // Stops logging and returns the .vgm file, empty when not logging
+    pub fn export_vgm(&mut self) -> Vec<u8> {
+        match self.gameboy.stop_apu_log() {
+            Some(log) => log.to_vgm(),
+            None => vec![],
+        }
+    }
+    pub fn get_sample_rate(&self) -> u32 {
+        self.gameboy.apu.sample_rate().hz()
+    }
     pub fn toggle_audio(&mut self) {
         self.gameboy.apu.toggle_audio();
     }
@@ -203,6 +493,287 @@ impl GameboyWasm {
     pub fn toggle_window_debug_mode(&mut self, enabled: bool) {
         self.gameboy.ppu.toggle_window_debug_mode(enabled);
     }
+    // Debugger. Conditions look like "a == $10", banks only matter for banked memory
+    pub fn add_breakpoint(
+        &mut self,
+        address: u16,
+        bank: Option<usize>,
+        condition: Option<String>,
+    ) -> Result<u32, String> {
+        let condition = condition.as_deref().map(Condition::parse).transpose()?;
+        Ok(self
+            .gameboy
+            .debugger
+            .add_breakpoint(bank, address, condition))
+    }
+    // At a label, "01:4000" or "$0150", a label's bank is checked where it is banked
+    pub fn add_breakpoint_at(
+        &mut self,
+        location: &str,
+        condition: Option<String>,
+    ) -> Result<u32, String> {
+        let (bank, address) = self.gameboy.symbols.parse_location(location)?;
+        self.add_breakpoint(address, bank, condition)
+    }
+    // kind is "read", "write" or "access", end is inclusive
+    pub fn add_watchpoint(
+        &mut self,
+        start: u16,
+        end: u16,
+        kind: &str,
+        condition: Option<String>,
+    ) -> Result<u32, String> {
+        let kind = WatchKind::from_name(kind).ok_or(format!("Unknown watch kind {}", kind))?;
+        let condition = condition.as_deref().map(Condition::parse).transpose()?;
+        Ok(self
+            .gameboy
+            .debugger
+            .add_watchpoint(start, end, kind, condition))
+    }
+    // length bytes from a label like "wPlayerX" or an address
+    pub fn add_watchpoint_at(
+        &mut self,
+        location: &str,
+        length: u16,
+        kind: &str,
+        condition: Option<String>,
+    ) -> Result<u32, String> {
+        let (_, start) = self.gameboy.symbols.parse_location(location)?;
+        let end = start.saturating_add(length.max(1) - 1);
+        self.add_watchpoint(start, end, kind, condition)
+    }
+    // IO register by its hardware.inc name, "LCDC" or "rLCDC"
+    pub fn add_io_watchpoint(&mut self, register: &str, kind: &str) -> Result<u32, String> {
+        let address = debugger::io_register_address(register)
+            .ok_or(format!("Unknown IO register {}", register))?;
+        self.add_watchpoint(address, address, kind, None)
+    }
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.gameboy.debugger.remove(id)
+    }
+    pub fn clear_breakpoints(&mut self) {
+        self.gameboy.debugger.clear();
+    }
+    pub fn set_breakpoint_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        self.gameboy.debugger.set_enabled(id, enabled)
+    }
+    pub fn set_breakpoint_ignore_count(&mut self, id: u32, count: u32) -> bool {
+        self.gameboy.debugger.set_ignore_count(id, count)
+    }
+    pub fn get_hit_count(&self, id: u32) -> Option<u32> {
+        self.gameboy.debugger.hit_count(id)
+    }
+    // Breakpoints and watchpoints together
+    pub fn get_breakpoints(&self) -> Vec<WasmBreakpoint> {
+        let debugger = &self.gameboy.debugger;
+        let breakpoints = debugger.breakpoints.iter().map(|b| WasmBreakpoint {
+            id: b.id,
+            kind: "execute".to_string(),
+            bank: b.bank,
+            start: b.address,
+            end: b.address,
+            condition: b.condition.map(|c| c.to_string()),
+            enabled: b.enabled,
+            hit_count: b.hit_count,
+            ignore_count: b.ignore_count,
+        });
+        let watchpoints = debugger.watchpoints.iter().map(|w| WasmBreakpoint {
+            id: w.id,
+            kind: w.kind.name().to_string(),
+            bank: None,
+            start: w.start,
+            end: w.end,
+            condition: w.condition.map(|c| c.to_string()),
+            enabled: w.enabled,
+            hit_count: w.hit_count,
+            ignore_count: w.ignore_count,
+        });
+        breakpoints.chain(watchpoints).collect()
+    }
+    // Runs to a breakpoint or the end of the frame
+    pub fn run_until_break(&mut self) -> WasmStopReason {
+        self.gameboy.run_until_break().into()
+    }
+    pub fn step_instruction(&mut self) -> WasmStopReason {
+        self.gameboy.step_instruction().into()
+    }
+    pub fn step_over(&mut self) -> WasmStopReason {
+        self.gameboy.step_over().into()
+    }
+    pub fn step_out(&mut self) -> WasmStopReason {
+        self.gameboy.step_out().into()
+    }
+    // Innermost call last
+    pub fn get_call_stack(&self) -> Vec<WasmCallFrame> {
+        self.gameboy
+            .debugger
+            .call_stack
+            .iter()
+            .map(|frame| WasmCallFrame::new(frame, &self.gameboy))
+            .collect()
+    }
+    // count instructions from address, in the banks mapped right now
+    pub fn disassemble(&self, address: u16, count: usize) -> Vec<WasmInstruction> {
+        disasm::disassemble(&self.gameboy.bus, address, count)
+            .iter()
+            .map(|instruction| WasmInstruction::new(instruction, &self.gameboy))
+            .collect()
+    }
+    // RGBDS .sym or, with map set, .map file contents. Returns how many labels were read
+    pub fn load_symbols(&mut self, text: &str, map: bool) -> usize {
+        if map {
+            self.gameboy.symbols.load_map(text)
+        } else {
+            self.gameboy.symbols.load_sym(text)
+        }
+    }
+    pub fn clear_symbols(&mut self) {
+        self.gameboy.symbols.clear();
+    }
+    // "Main.loop" or "Main+$12" for an address in the banks mapped now
+    pub fn get_symbol_at(&self, address: u16) -> Option<String> {
+        let bank = self.gameboy.bus.bank_at(address);
+        self.gameboy.symbols.describe(bank, address)
+    }
+    /*
+    Instruction trace: format "doctor", "logs" or "bgb", triggers like
+    "frame:600" or "pc:$0150", max_bytes bounds the whole trace
+    */
+    pub fn start_trace(
+        &mut self,
+        format: &str,
+        start: Option<String>,
+        stop: Option<String>,
+        max_bytes: usize,
+    ) -> Result<(), String> {
+        let format =
+            TraceFormat::from_name(format).ok_or(format!("Unknown trace format {}", format))?;
+        let mut trace = TraceLogger::new(format, max_bytes);
+        let symbols = &self.gameboy.symbols;
+        let parse = |text: Option<String>| {
+            text.map(|text| TraceTrigger::parse(&text, symbols))
+                .transpose()
+        };
+        trace.start = parse(start)?;
+        trace.stop = parse(stop)?;
+        self.gameboy.start_trace(trace);
+        Ok(())
+    }
+    // Lines traced since the last call
+    pub fn take_trace_output(&mut self) -> String {
+        self.gameboy
+            .trace
+            .as_mut()
+            .map(|trace| trace.take_output())
+            .unwrap_or_default()
+    }
+    pub fn is_trace_finished(&self) -> bool {
+        self.gameboy
+            .trace
+            .as_ref()
+            .is_some_and(|trace| trace.is_finished())
+    }
+    // Returns what wasn't taken yet
+    pub fn stop_trace(&mut self) -> String {
+        self.gameboy
+            .stop_trace()
+            .map(|mut trace| trace.take_output())
+            .unwrap_or_default()
+    }
+    // Snapshots all RAM, size "8" or "16" bits. Returns the candidate count
+    pub fn start_ram_search(&mut self, size: &str, signed: bool) -> Result<usize, String> {
+        let size = ValueSize::from_name(size).ok_or(format!("Unknown value size {}", size))?;
+        let search = RamSearch::new(&self.gameboy.bus, size, signed);
+        let count = search.len();
+        self.ram_search = Some(search);
+        Ok(count)
+    }
+    // "unchanged", "changed", "increased", "decreased" or a value like "99" or "$63"
+    pub fn filter_ram_search(&mut self, filter: &str) -> Result<usize, String> {
+        let filter = SearchFilter::parse(filter)?;
+        let search = self.ram_search.as_mut().ok_or("No RAM search running")?;
+        Ok(search.filter(&self.gameboy.bus, filter))
+    }
+    pub fn get_ram_search_count(&self) -> usize {
+        self.ram_search.as_ref().map_or(0, |search| search.len())
+    }
+    // The first limit candidates with their values right now
+    pub fn get_ram_search_results(&self, limit: usize) -> Vec<WasmSearchResult> {
+        let Some(search) = &self.ram_search else {
+            return Vec::new();
+        };
+        search
+            .candidates()
+            .iter()
+            .take(limit)
+            .map(|candidate| WasmSearchResult::new(candidate, search, &self.gameboy))
+            .collect()
+    }
+    pub fn stop_ram_search(&mut self) {
+        self.ram_search = None;
+    }
+    // Holds a search result at a value as a GameShark code in the "RAM search" group
+    pub fn freeze_ram_search_result(
+        &mut self,
+        bank: usize,
+        address: u16,
+        value: i32,
+    ) -> Result<(), String> {
+        let search = self.ram_search.as_ref().ok_or("No RAM search running")?;
+        let candidate = search
+            .candidates()
+            .iter()
+            .find(|candidate| candidate.bank == bank && candidate.address == address)
+            .ok_or("Not a search result")?;
+        for freeze in search.freeze(candidate, value) {
+            self.gameboy
+                .bus
+                .cheats
+                .add_cheat(FREEZE_GROUP, Cheat::from_freeze(&freeze));
+        }
+        Ok(())
+    }
+    // Game Genie or GameShark codes separated by spaces, commas or new lines
+    pub fn add_cheat_group(&mut self, name: &str, codes: &str) -> Result<usize, String> {
+        self.gameboy.bus.cheats.add_group(name, codes)
+    }
+    pub fn remove_cheat_group(&mut self, index: usize) -> bool {
+        self.gameboy.bus.cheats.remove_group(index)
+    }
+    pub fn remove_cheat(&mut self, group: usize, index: usize) -> bool {
+        self.gameboy.bus.cheats.remove_cheat(group, index)
+    }
+    pub fn set_cheat_group_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        self.gameboy.bus.cheats.set_enabled(index, enabled)
+    }
+    pub fn clear_cheats(&mut self) {
+        self.gameboy.bus.cheats.clear();
+    }
+    pub fn get_cheat_groups(&self) -> Vec<WasmCheatGroup> {
+        self.gameboy
+            .bus
+            .cheats
+            .groups()
+            .iter()
+            .map(|group| WasmCheatGroup {
+                name: group.name.clone(),
+                enabled: group.enabled,
+                codes: group
+                    .cheats
+                    .iter()
+                    .map(|cheat| cheat.code.clone())
+                    .collect(),
+            })
+            .collect()
+    }
+    // Text to keep per ROM and give back to import_cheats after init
+    pub fn export_cheats(&self) -> String {
+        self.gameboy.bus.cheats.to_text()
+    }
+    // Replaces all groups, returns how many codes were read
+    pub fn import_cheats(&mut self, text: &str) -> Result<usize, String> {
+        self.gameboy.bus.cheats.load_text(text)
+    }
     pub fn get_cartridge_info(&self) -> CartridgeHeaderState {
         let cartridge_data = self.gameboy.bus.read_cartridge_header();
         let title = cartridge::cartridge_header::get_title(&cartridge_data);
@@ -227,6 +798,25 @@ impl GameboyWasm {
     }
 }
 
+impl GameboyWasm {
+    // The current frame as bytes in the selected byte order
+    fn frame_bytes(&mut self) -> &[u8] {
+        let frame = self.gameboy.frame_buffer(self.frame_size);
+        if !self.rgba_output {
+            // Safety: u32 has no padding and wasm is little endian, so these are B, G, R, A
+            return unsafe {
+                std::slice::from_raw_parts(frame.as_ptr() as *const u8, frame.len() * 4)
+            };
+        }
+        self.rgba_frame.resize(frame.len() * 4, 0);
+        for (bytes, color) in self.rgba_frame.chunks_exact_mut(4).zip(frame) {
+            // DMG palettes have no alpha, the page always gets opaque pixels
+            bytes.copy_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, *color as u8, 0xFF]);
+        }
+        &self.rgba_frame
+    }
+}
+
 #[wasm_bindgen]
 #[derive(Clone, Debug)]
 pub struct CartridgeHeaderState {
@@ -275,6 +865,242 @@ impl CartridgeHeaderState {
     }
 }
 
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmBreakpoint {
+    pub id: u32,
+    // "execute", "read", "write" or "access"
+    kind: String,
+    pub bank: Option<usize>,
+    pub start: u16,
+    pub end: u16,
+    condition: Option<String>,
+    pub enabled: bool,
+    pub hit_count: u32,
+    pub ignore_count: u32,
+}
+#[wasm_bindgen]
+impl WasmBreakpoint {
+    #[wasm_bindgen(getter)]
+    pub fn kind(&self) -> String {
+        self.kind.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn condition(&self) -> Option<String> {
+        self.condition.clone()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmStopReason {
+    // "breakpoint", "watchpoint", "step", "frame" or "timeout"
+    reason: String,
+    // Breakpoint or watchpoint that stopped execution
+    pub id: Option<u32>,
+    pub bank: usize,
+    pub address: u16,
+    // Watchpoints: the byte read or written
+    pub value: u8,
+    pub write: bool,
+}
+#[wasm_bindgen]
+impl WasmStopReason {
+    #[wasm_bindgen(getter)]
+    pub fn reason(&self) -> String {
+        self.reason.clone()
+    }
+}
+impl From<StopReason> for WasmStopReason {
+    fn from(reason: StopReason) -> Self {
+        let plain = |reason: &str| WasmStopReason {
+            reason: reason.to_string(),
+            id: None,
+            bank: 0,
+            address: 0,
+            value: 0,
+            write: false,
+        };
+        match reason {
+            StopReason::Breakpoint { id, bank, address } => WasmStopReason {
+                id: Some(id),
+                bank,
+                address,
+                ..plain("breakpoint")
+            },
+            StopReason::Watchpoint {
+                id,
+                address,
+                value,
+                write,
+            } => WasmStopReason {
+                id: Some(id),
+                address,
+                value,
+                write,
+                ..plain("watchpoint")
+            },
+            StopReason::Step => plain("step"),
+            StopReason::FrameComplete => plain("frame"),
+            StopReason::Timeout => plain("timeout"),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmCallFrame {
+    pub target: u16,
+    pub bank: usize,
+    pub return_address: u16,
+    pub sp: u16,
+    pub interrupt: bool,
+    target_label: Option<String>,
+    return_label: Option<String>,
+}
+#[wasm_bindgen]
+impl WasmCallFrame {
+    #[wasm_bindgen(getter)]
+    pub fn target_label(&self) -> Option<String> {
+        self.target_label.clone()
+    }
+    // "Main+$12"
+    #[wasm_bindgen(getter)]
+    pub fn return_label(&self) -> Option<String> {
+        self.return_label.clone()
+    }
+}
+impl WasmCallFrame {
+    fn new(frame: &CallFrame, gameboy: &Gameboy) -> Self {
+        let return_bank = gameboy.bus.bank_at(frame.return_address);
+        WasmCallFrame {
+            target: frame.target,
+            bank: frame.bank,
+            return_address: frame.return_address,
+            sp: frame.sp,
+            interrupt: frame.interrupt,
+            target_label: gameboy.symbols.describe(frame.bank, frame.target),
+            return_label: gameboy.symbols.describe(return_bank, frame.return_address),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmCheatGroup {
+    name: String,
+    pub enabled: bool,
+    codes: Vec<String>,
+}
+#[wasm_bindgen]
+impl WasmCheatGroup {
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn codes(&self) -> Vec<String> {
+        self.codes.clone()
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmSearchResult {
+    pub bank: usize,
+    pub address: u16,
+    // At the last snapshot
+    pub previous: i32,
+    pub value: i32,
+    // "wPlayerHP" or "wParty+$12"
+    label: Option<String>,
+}
+#[wasm_bindgen]
+impl WasmSearchResult {
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> Option<String> {
+        self.label.clone()
+    }
+    // "02:D123"
+    #[wasm_bindgen(getter)]
+    pub fn location(&self) -> String {
+        disasm::format_location(self.bank, self.address)
+    }
+}
+impl WasmSearchResult {
+    fn new(candidate: &Candidate, search: &RamSearch, gameboy: &Gameboy) -> Self {
+        WasmSearchResult {
+            bank: candidate.bank,
+            address: candidate.address,
+            previous: candidate.previous,
+            value: search
+                .value(&gameboy.bus, candidate.bank, candidate.address)
+                .unwrap_or(candidate.previous),
+            label: gameboy.symbols.describe(candidate.bank, candidate.address),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WasmInstruction {
+    pub address: u16,
+    pub bank: usize,
+    bytes: Vec<u8>,
+    // RGBDS syntax with labels, "ld a, [wPlayerX]"
+    text: String,
+    // Label at the instruction itself
+    label: Option<String>,
+    mnemonic: String,
+    pub length: u8,
+    pub cycles: u8,
+    pub cycles_taken: Option<u8>,
+    pub target: Option<u16>,
+}
+#[wasm_bindgen]
+impl WasmInstruction {
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn text(&self) -> String {
+        self.text.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> Option<String> {
+        self.label.clone()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.clone()
+    }
+    // "01:4000"
+    #[wasm_bindgen(getter)]
+    pub fn location(&self) -> String {
+        disasm::format_location(self.bank, self.address)
+    }
+}
+impl WasmInstruction {
+    fn new(instruction: &Instruction, gameboy: &Gameboy) -> Self {
+        let symbols = &gameboy.symbols;
+        WasmInstruction {
+            address: instruction.address,
+            bank: instruction.bank,
+            bytes: instruction.bytes.clone(),
+            text: instruction.text_with_symbols(&gameboy.bus, symbols),
+            label: symbols
+                .name_at(instruction.bank, instruction.address)
+                .map(str::to_string),
+            mnemonic: instruction.mnemonic.to_string(),
+            length: instruction.length,
+            cycles: instruction.cycles,
+            cycles_taken: instruction.cycles_taken,
+            target: instruction.target,
+        }
+    }
+}
+
 #[wasm_bindgen]
 #[derive(Clone, Debug)]
 pub struct WasmCPUState {
@@ -2,12 +2,33 @@ import {
   createContext,
   useCallback,
   useContext,
+  useEffect,
   useMemo,
   useState,
 } from "react";
 import { GameboyWasm } from "../wasm/pkg/gameboy_wasm";
 import { TGameDetailsWithSaveState } from "../types";
 
+const LOCAL_STORAGE_CHEATS_KEY = "sabiboy-cheats";
+
+// Cheats are kept per ROM, by the header title and global checksum
+const cheatsStorageKey = (gameboy: GameboyWasm, romData: Uint8Array) => {
+  const checksum = ((romData[0x14e] << 8) | romData[0x14f])
+    .toString(16)
+    .padStart(4, "0");
+  const title = gameboy.get_cartridge_info().title;
+  return `${LOCAL_STORAGE_CHEATS_KEY}-${title}-${checksum}`;
+};
+
+const saveCheatsToStorage = (gameboy: GameboyWasm, key: string) => {
+  const cheats = gameboy.export_cheats();
+  if (cheats.trim()) {
+    localStorage.setItem(key, cheats);
+  } else {
+    localStorage.removeItem(key);
+  }
+};
+
 const GameboyContext = createContext<{
   gameboy: GameboyWasm | null;
   initGameboy: (
@@ -15,11 +36,14 @@ const GameboyContext = createContext<{
     palette: number[],
     saveStateData?: Uint8Array
   ) => void;
+  // Call after changing the cheats, they are also saved when the game is left
+  saveCheats: () => void;
   currentGame: TGameDetailsWithSaveState | null;
   setCurrentGame: (game: TGameDetailsWithSaveState | null) => void;
 }>({
   gameboy: null,
   initGameboy: () => {},
+  saveCheats: () => {},
   currentGame: null,
   setCurrentGame: () => {},
 });
@@ -28,6 +52,7 @@ export const GameboyProvider: React.FC<{ children: React.ReactNode }> = ({
   children,
 }) => {
   const [gameboy, setGameboy] = useState<GameboyWasm | null>(null);
+  const [cheatsKey, setCheatsKey] = useState<string | null>(null);
   const [currentGame, setCurrentGame] =
     useState<TGameDetailsWithSaveState | null>(null);
 
@@ -43,6 +68,17 @@ export const GameboyProvider: React.FC<{ children: React.ReactNode }> = ({
         const newGameboy = new GameboyWasm(paletteArray);
         await newGameboy.init(romData, saveStateData);
         console.log("Gameboy initialized successfully");
+
+        const key = cheatsStorageKey(newGameboy, romData);
+        const storedCheats = localStorage.getItem(key);
+        if (storedCheats) {
+          try {
+            newGameboy.import_cheats(storedCheats);
+          } catch (error) {
+            console.error("Failed to load cheats from localStorage:", error);
+          }
+        }
+        setCheatsKey(key);
         setGameboy(newGameboy);
       } catch (error) {
         console.error("Failed to initialize Gameboy:", error);
@@ -52,14 +88,30 @@ export const GameboyProvider: React.FC<{ children: React.ReactNode }> = ({
     []
   );
 
+  const saveCheats = useCallback(() => {
+    if (gameboy && cheatsKey) {
+      saveCheatsToStorage(gameboy, cheatsKey);
+    }
+  }, [gameboy, cheatsKey]);
+
+  // Saved when the page closes and before another game replaces this one
+  useEffect(() => {
+    window.addEventListener("beforeunload", saveCheats);
+    return () => {
+      window.removeEventListener("beforeunload", saveCheats);
+      saveCheats();
+    };
+  }, [saveCheats]);
+
   const value = useMemo(
     () => ({
       gameboy,
       initGameboy,
+      saveCheats,
       currentGame,
       setCurrentGame,
     }),
-    [gameboy, initGameboy, currentGame]
+    [gameboy, initGameboy, saveCheats, currentGame]
   );
 
   return (
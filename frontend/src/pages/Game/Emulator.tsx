@@ -210,10 +210,14 @@ export default function Emulator() {
     (audioContext: AudioContext, gainNode: GainNode) => {
       if (!isAudioEnabled || !gameboy) return;
 
-      const audioSamples = gameboy.get_audio_buffer();
-      if (audioSamples.length === 0) return;
-
-      const numSamples = audioSamples.length / 2;
+      const available = gameboy.update_audio_ring();
+      if (available === 0) return;
+
+      // Read in place from the wasm ring, samples wrap at its capacity
+      const ring = gameboy.get_audio_view();
+      const capacity = gameboy.get_audio_ring_capacity();
+      const readIndex = gameboy.get_audio_read_index();
+      const numSamples = available / 2;
       const sampleRate = 48000;
 
       const buffer = audioContext.createBuffer(2, numSamples, sampleRate);
@@ -221,9 +225,10 @@ export default function Emulator() {
       const rightChannel = buffer.getChannelData(1);
 
       for (let i = 0; i < numSamples; i++) {
-        leftChannel[i] = audioSamples[i * 2];
-        rightChannel[i] = audioSamples[i * 2 + 1];
+        leftChannel[i] = ring[(readIndex + i * 2) % capacity];
+        rightChannel[i] = ring[(readIndex + i * 2 + 1) % capacity];
       }
+      gameboy.consume_audio(available);
 
       const source = audioContext.createBufferSource();
       source.buffer = buffer;
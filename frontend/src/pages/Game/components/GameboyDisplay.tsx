@@ -87,11 +87,22 @@ export default function GameboyDisplay({
     const canvas = canvasRef.current!;
     const ctx = canvas.getContext("2d")!;
 
-    canvas.width = 160;
-    canvas.height = 144;
+    // 160x144, or 256x224 with a Super Game Boy border
+    const resizeFrame = () => {
+      const width = gameboy.get_frame_width();
+      const height = gameboy.get_frame_height();
+      const imageData = imageDataRef.current;
+      if (imageData?.width === width && imageData?.height === height) return;
+      canvas.width = width;
+      canvas.height = height;
+      imageDataRef.current = ctx.createImageData(width, height);
+    };
 
     contextRef.current = ctx;
-    imageDataRef.current = ctx.createImageData(160, 144);
+    imageDataRef.current = null;
+    resizeFrame();
+    // Frame bytes come out of wasm memory already in ImageData order
+    gameboy.set_rgba_output(true);
 
     handleCartridgeInfo();
 
@@ -140,19 +151,8 @@ export default function GameboyDisplay({
         frameTimeAccumulatorRef.current -= targetFrameTime;
       }
 
-      const frameBuffer = gameboy.get_frame_buffer();
-      const u8Buffer = new Uint8ClampedArray(frameBuffer.length * 4);
-      
-      for (let i = 0; i < frameBuffer.length; i++) {
-        const color = frameBuffer[i];
-        const offset = i * 4;
-        u8Buffer[offset] = (color >> 16) & 0xFF; // R
-        u8Buffer[offset + 1] = (color >> 8) & 0xFF;  // G
-        u8Buffer[offset + 2] = color & 0xFF;        // B
-        u8Buffer[offset + 3] = 255;                // A (Full alpha)
-      }
-      
-      imageDataRef.current.data.set(u8Buffer);
+      resizeFrame();
+      imageDataRef.current.data.set(gameboy.get_frame_view());
       ctx.putImageData(imageDataRef.current, 0, 0);
 
       const now = performance.now();